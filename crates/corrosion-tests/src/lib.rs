@@ -1,6 +1,6 @@
 use corro_api_types::{self as api, Statement};
 use corro_types::{self as types, actor::ActorId, pubsub::MatcherLoopConfig, updates::Handle as _};
-use corrosion::{persistent::mutator::BroadcastingTransactor, pubsub};
+use corrosion::{persistent::mutator::BroadcastingTransactor, pubsub, schema::Migration};
 use std::sync::Arc;
 
 pub use prettytable::Cell;
@@ -22,14 +22,14 @@ pub struct TestSubsDb {
 }
 
 impl TestSubsDb {
-    pub async fn new(schema: &str, _name: &str) -> Self {
+    pub async fn new(migrations: &[Migration], _name: &str) -> Self {
         let temp = tempfile::TempDir::new().expect("failed to create temp dir");
 
         let root = camino::Utf8Path::from_path(temp.path()).expect("non-utf8 path");
         let sub_path = root.join("subs");
         let db_path = root.join("db.db");
 
-        let db = corrosion::db::InitializedDb::setup(&db_path, schema, None)
+        let db = corrosion::db::InitializedDb::setup(&db_path, migrations, None)
             .await
             .expect("failed to initialize DB");
 
@@ -180,29 +180,25 @@ impl TestSubsDb {
 }
 
 pub async fn setup(
-    schema: &str,
+    migrations: &[Migration],
     pool: &types::agent::SplitPool,
 ) -> (types::schema::Schema, Arc<uhlc::HLC>) {
-    let mut schema = types::schema::parse_sql(schema).expect("failed to parse schema");
     let clock = Arc::new(uhlc::HLC::default());
 
-    {
+    let (schema, _version) = {
         let mut conn = pool
             .write_priority()
             .await
             .expect("failed to get DB connection");
         types::sqlite::setup_conn(&conn).expect("failed to setup connection");
         types::agent::migrate(clock.clone(), &mut conn).expect("failed to migrate");
-        let tx = conn.transaction().expect("failed to start transaction");
-        types::schema::apply_schema(&tx, &types::schema::Schema::default(), &mut schema)
-            .expect("failed to apply schema");
-        tx.commit().expect("failed to commit schema change");
-    }
+        corrosion::db::migrate(&mut conn, migrations).expect("failed to apply schema")
+    };
 
     (schema, clock)
 }
 
-pub async fn new_split_pool(name: &str, schema: &str) -> corro_types::agent::SplitPool {
+pub async fn new_split_pool(name: &str, migrations: &[Migration]) -> corro_types::agent::SplitPool {
     let sp = corro_types::agent::SplitPool::create_in_memory(
         name,
         std::sync::Arc::new(tokio::sync::Semaphore::new(1)),
@@ -211,7 +207,7 @@ pub async fn new_split_pool(name: &str, schema: &str) -> corro_types::agent::Spl
     .await
     .expect("failed to create split pool");
 
-    setup(schema, &sp).await;
+    setup(migrations, &sp).await;
     sp
 }
 
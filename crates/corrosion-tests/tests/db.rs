@@ -21,14 +21,12 @@ async fn exec_all<const N: usize>(v: &mut smallvec::SmallVec<[Statement; N]>, sp
 async fn read_server_row(id: usize, sp: &SplitPool) -> ServerRow {
     let conn = sp.read().await.unwrap();
     conn.query_row(
-        "SELECT endpoint,icao,tokens FROM servers WHERE rowid = ?",
+        "SELECT endpoint,icao,tokens,metadata,locality FROM servers WHERE rowid = ?",
         [id],
         |row| {
-            let v = vec![
-                row.get::<_, SqliteValue>(0).unwrap(),
-                row.get::<_, SqliteValue>(1).unwrap(),
-                row.get::<_, SqliteValue>(2).unwrap(),
-            ];
+            let v = (0..5)
+                .map(|i| row.get::<_, SqliteValue>(i).unwrap())
+                .collect::<Vec<_>>();
             Ok(ServerRow::from_sql(&v).unwrap())
         },
     )
@@ -52,13 +50,22 @@ fn make_row(i: u32) -> ServerRow {
         endpoint,
         icao: IcaoCode::new_testing(*b"BOOP"),
         tokens: [i.to_ne_bytes()].into(),
+        metadata: if i % 2 == 0 {
+            serde_json::json!({ "name": format!("gs-{i}"), "namespace": "default" })
+                .as_object()
+                .cloned()
+                .unwrap()
+        } else {
+            Default::default()
+        },
+        locality: (i % 2 == 0).then(|| format!("region-{i}:zone-{i}")),
     }
 }
 
 const PREP_PEER: SocketAddrV6 = SocketAddrV6::new(Ipv6Addr::from_bits(0xaaffeeff), 8999, 0, 0);
 
 async fn prep(name: &str, count: u32) -> SplitPool {
-    let sp = ct::new_split_pool(name, corrosion::schema::SCHEMA).await;
+    let sp = ct::new_split_pool(name, corrosion::schema::MIGRATIONS).await;
 
     const MAX: usize = 100;
 
@@ -72,7 +79,13 @@ async fn prep(name: &str, count: u32) -> SplitPool {
             exec_all(s.statements, &sp).await;
         }
 
-        s.upsert(&row.endpoint, row.icao, &row.tokens);
+        s.upsert(
+            &row.endpoint,
+            row.icao,
+            &row.tokens,
+            &row.metadata,
+            row.locality.as_deref(),
+        );
     }
 
     if !s.statements.is_empty() {
@@ -148,6 +161,8 @@ async fn collects_old_servers() {
             },
             IcaoCode::new_testing([b'V'; 4]),
             &[8888u64.to_ne_bytes()].into(),
+            &Default::default(),
+            None,
         );
 
         exec_all(s.statements, &sp).await;
@@ -223,7 +238,13 @@ async fn updates_servers() {
     // Update just the ICAO
     {
         let mut s = write::Server::for_peer(PREP_PEER, &mut v);
-        s.update(&ep, Some(IcaoCode::new_testing([b'Z'; 4])), None);
+        s.update(
+            &ep,
+            Some(IcaoCode::new_testing([b'Z'; 4])),
+            None,
+            None,
+            None,
+        );
         exec_all(s.statements, &sp).await;
     }
 
@@ -232,7 +253,7 @@ async fn updates_servers() {
     // Update just the tokenset
     {
         let mut s = write::Server::for_peer(PREP_PEER, &mut v);
-        s.update(&ep, None, Some(&[[b'Z'; 20]; 1].into()));
+        s.update(&ep, None, Some(&[[b'Z'; 20]; 1].into()), None, None);
         exec_all(s.statements, &sp).await;
     }
 
@@ -245,6 +266,8 @@ async fn updates_servers() {
             &ep,
             Some(IcaoCode::new_testing([b'Y'; 4])),
             Some(&[[b'Y'; 10]; 1].into()),
+            None,
+            None,
         );
         exec_all(s.statements, &sp).await;
     }
@@ -252,6 +275,50 @@ async fn updates_servers() {
     insta::assert_snapshot!("update_both_us", only_row().await);
 }
 
+/// Tests that server metadata and locality can be updated and cleared
+#[tokio::test]
+async fn updates_server_metadata_and_locality() {
+    let sp = prep("updates_server_metadata_and_locality", 1).await;
+    let mut expected = make_row(0);
+    assert_eq!(read_server_row(1, &sp).await, expected);
+
+    let mut v = smallvec::SmallVec::<[_; 2]>::new();
+
+    // Update just the metadata, the locality is unchanged
+    expected.metadata = serde_json::json!({ "name": "renamed" })
+        .as_object()
+        .cloned()
+        .unwrap();
+    {
+        let mut s = write::Server::for_peer(PREP_PEER, &mut v);
+        s.update(
+            &expected.endpoint,
+            None,
+            None,
+            Some(&expected.metadata),
+            None,
+        );
+        exec_all(s.statements, &sp).await;
+    }
+    assert_eq!(read_server_row(1, &sp).await, expected);
+
+    // Clear both
+    expected.metadata.clear();
+    expected.locality = None;
+    {
+        let mut s = write::Server::for_peer(PREP_PEER, &mut v);
+        s.update(
+            &expected.endpoint,
+            None,
+            None,
+            Some(&expected.metadata),
+            Some(""),
+        );
+        exec_all(s.statements, &sp).await;
+    }
+    assert_eq!(read_server_row(1, &sp).await, expected);
+}
+
 /// Tests that datacenters can be updated
 #[tokio::test]
 async fn updates_datacenters() {
@@ -303,3 +370,75 @@ async fn updates_datacenters() {
 
     insta::assert_snapshot!("update_both_ud", only_row().await);
 }
+
+#[tokio::test]
+async fn scoped_filters() {
+    use corrosion::db::FilterScope;
+
+    let sp = ct::new_split_pool("scoped_filters", corrosion::schema::MIGRATIONS).await;
+    let icao = IcaoCode::new_testing(*b"ABCD");
+
+    let mut v = smallvec::SmallVec::<[_; 2]>::new();
+    {
+        let mut f = write::Filter(&mut v);
+        f.upsert(&FilterScope::Global, "global");
+        f.upsert(&FilterScope::Icao(icao), "icao");
+        f.upsert(&FilterScope::Icao(IcaoCode::new_testing(*b"ZZZZ")), "other");
+        f.upsert(&FilterScope::Locality("us:east".into()), "zone");
+    }
+    exec_all(&mut v, &sp).await;
+
+    let scopes = FilterScope::candidates(icao, Some("us:east:1"));
+    assert_eq!(
+        scopes,
+        [
+            FilterScope::Locality("us:east:1".into()),
+            FilterScope::Locality("us:east".into()),
+            FilterScope::Locality("us".into()),
+            FilterScope::Icao(icao),
+            FilterScope::Global,
+        ]
+    );
+    for scope in &scopes {
+        assert_eq!(&scope.to_string().parse::<FilterScope>().unwrap(), scope);
+    }
+
+    let read_filters = |sp: SplitPool, scopes: Vec<FilterScope>| async move {
+        let conn = sp.read().await.unwrap();
+        let mut statement = conn
+            .prepare(&corrosion::pubsub::scoped_filter_query(&scopes))
+            .unwrap();
+        statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .unwrap()
+            .collect::<Result<std::collections::BTreeMap<_, _>, _>>()
+            .unwrap()
+    };
+
+    let filters = read_filters(sp.clone(), scopes.clone()).await;
+    assert_eq!(filters.len(), 3);
+    assert_eq!(filters["locality:us:east"], "zone");
+    assert_eq!(filters["icao:ABCD"], "icao");
+    assert_eq!(filters["*"], "global");
+
+    // The global chain is also written to the legacy table
+    {
+        let conn = sp.read().await.unwrap();
+        let legacy: String = conn
+            .query_row(corrosion::pubsub::FILTER_QUERY, [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(legacy, "global");
+    }
+
+    {
+        let mut f = write::Filter(&mut v);
+        f.remove(&FilterScope::Locality("us:east".into()));
+    }
+    exec_all(&mut v, &sp).await;
+
+    let filters = read_filters(sp.clone(), scopes).await;
+    assert_eq!(filters.len(), 2);
+    assert!(!filters.contains_key("locality:us:east"));
+}
@@ -44,7 +44,7 @@ fn other_error() -> ChangeError {
 }
 
 async fn make_transactor() -> corrosion_tests::TestSubsDb {
-    corrosion_tests::TestSubsDb::new(corrosion::schema::SCHEMA, "disk_full").await
+    corrosion_tests::TestSubsDb::new(corrosion::schema::MIGRATIONS, "disk_full").await
 }
 
 // ── is_disk_full ─────────────────────────────────────────────────────────────
@@ -215,7 +215,7 @@ async fn does_not_retry_non_disk_full_errors() {
 
 #[tokio::test(flavor = "multi_thread")]
 async fn recover_space_checkpoint_and_vacuum_succeed_on_empty_db() {
-    let sp = corrosion_tests::new_split_pool("recover_empty", corrosion::schema::SCHEMA).await;
+    let sp = corrosion_tests::new_split_pool("recover_empty", corrosion::schema::MIGRATIONS).await;
     db::recover_space(&sp, None)
         .await
         .expect("recover_space should not fail on an empty db");
@@ -223,7 +223,7 @@ async fn recover_space_checkpoint_and_vacuum_succeed_on_empty_db() {
 
 #[tokio::test(flavor = "multi_thread")]
 async fn recover_space_purges_oldest_entries_by_cont_update() {
-    let sp = corrosion_tests::new_split_pool("recover_purge", corrosion::schema::SCHEMA).await;
+    let sp = corrosion_tests::new_split_pool("recover_purge", corrosion::schema::MIGRATIONS).await;
 
     {
         let mut conn = sp.write_priority().await.unwrap();
@@ -261,7 +261,8 @@ async fn recover_space_purges_oldest_entries_by_cont_update() {
 
 #[tokio::test(flavor = "multi_thread")]
 async fn recover_space_purge_count_exceeding_rows_deletes_all() {
-    let sp = corrosion_tests::new_split_pool("recover_purge_all", corrosion::schema::SCHEMA).await;
+    let sp =
+        corrosion_tests::new_split_pool("recover_purge_all", corrosion::schema::MIGRATIONS).await;
 
     {
         let mut conn = sp.write_priority().await.unwrap();
@@ -308,7 +309,7 @@ async fn end_to_end_recovery_from_real_sqlite_full() {
     // budget for data rows.
     let db = InitializedDb::setup(
         &db_path,
-        corrosion::schema::SCHEMA,
+        corrosion::schema::MIGRATIONS,
         Some(DBMaintenance {
             limits: DBLimits {
                 max_page_count: Some(150),
@@ -350,6 +351,8 @@ async fn end_to_end_recovery_from_real_sqlite_full() {
                     &ep,
                     quilkin_types::IcaoCode::new_testing(*b"TEST"),
                     &[[0u8; 4]; 1].into(),
+                    &Default::default(),
+                    None,
                 );
                 write::exec_interruptible(tx, &v).map_err(|source| ChangeError::Rusqlite {
                     source,
@@ -386,6 +389,8 @@ async fn end_to_end_recovery_from_real_sqlite_full() {
                 },
                 quilkin_types::IcaoCode::new_testing(*b"RECV"),
                 &[[1u8; 4]; 1].into(),
+                &Default::default(),
+                None,
             );
             write::exec_interruptible(tx, &v).map_err(|source| ChangeError::Rusqlite {
                 source,
@@ -0,0 +1,200 @@
+//! Tests that databases created with previous schema versions are migrated
+
+use corro_api_types::SqliteValue;
+use corrosion::{
+    db::{
+        InitializedDb,
+        read::{FromSqlValue, ServerRow},
+        write,
+    },
+    pubsub,
+    schema::{MIGRATIONS, SchemaVersion, VERSION},
+};
+use quilkin_types::{AddressKind, Endpoint, IcaoCode};
+
+fn db_path(temp: &tempfile::TempDir) -> camino::Utf8PathBuf {
+    camino::Utf8Path::from_path(temp.path())
+        .unwrap()
+        .join("db.db")
+}
+
+async fn read_servers(db: &InitializedDb) -> Vec<ServerRow> {
+    let conn = db.pool.read().await.unwrap();
+    let mut statement = conn.prepare(pubsub::SERVER_QUERY).unwrap();
+    statement
+        .query_map([], |row| {
+            let v = (0..5)
+                .map(|i| row.get::<_, SqliteValue>(i).unwrap())
+                .collect::<Vec<_>>();
+            Ok(ServerRow::from_sql(&v).unwrap())
+        })
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+/// Tests that a database created before schema versioning, ie. with the initial
+/// schema but no recorded version, is migrated to the current version without
+/// losing data
+#[tokio::test(flavor = "multi_thread")]
+async fn migrates_unversioned_database() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let path = db_path(&temp);
+
+    {
+        let db = InitializedDb::setup(&path, &MIGRATIONS[..1], None)
+            .await
+            .unwrap();
+        assert_eq!(db.schema_version, 1);
+
+        let conn = db.pool.write_priority().await.unwrap();
+        conn.execute(
+            "INSERT INTO servers (endpoint,icao,tokens) VALUES ('|1.2.3.4:7777','ABCD','AQI')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "DELETE FROM __corro_state WHERE key = 'quilkin_schema_version'",
+            [],
+        )
+        .unwrap();
+    }
+
+    let db = InitializedDb::setup(&path, MIGRATIONS, None).await.unwrap();
+    assert_eq!(db.schema_version, VERSION);
+
+    let endpoint = Endpoint {
+        address: AddressKind::Ip(std::net::Ipv4Addr::new(1, 2, 3, 4).into()),
+        port: 7777,
+    };
+    let mut expected = ServerRow {
+        endpoint: endpoint.clone(),
+        icao: "ABCD".parse().unwrap(),
+        tokens: [[2u8]].into(),
+        metadata: Default::default(),
+        locality: None,
+    };
+    assert_eq!(read_servers(&db).await, [expected]);
+
+    // The columns added by later migrations can be written
+    expected = ServerRow {
+        endpoint,
+        icao: IcaoCode::new_testing([b'Z'; 4]),
+        tokens: [[3u8]].into(),
+        metadata: serde_json::json!({ "name": "gs-1" })
+            .as_object()
+            .cloned()
+            .unwrap(),
+        locality: Some("region:zone".into()),
+    };
+
+    let mut v = smallvec::SmallVec::<[_; 2]>::new();
+    let mut s = write::Server::for_peer(
+        std::net::SocketAddrV6::new(std::net::Ipv6Addr::LOCALHOST, 7000, 0, 0),
+        &mut v,
+    );
+    s.upsert(
+        &expected.endpoint,
+        expected.icao,
+        &expected.tokens,
+        &expected.metadata,
+        expected.locality.as_deref(),
+    );
+
+    {
+        let mut conn = db.pool.write_priority().await.unwrap();
+        let tx = conn.transaction().unwrap();
+        corrosion_tests::exec(&tx, v.iter()).unwrap();
+        tx.commit().unwrap();
+    }
+
+    assert_eq!(read_servers(&db).await, [expected]);
+}
+
+/// Tests that migrations are only applied once, and that a database with a
+/// newer schema version than is supported is rejected
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_newer_version() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let path = db_path(&temp);
+
+    {
+        let db = InitializedDb::setup(&path, MIGRATIONS, None).await.unwrap();
+        assert_eq!(db.schema_version, VERSION);
+    }
+
+    {
+        let db = InitializedDb::setup(&path, MIGRATIONS, None).await.unwrap();
+        assert_eq!(db.schema_version, VERSION);
+    }
+
+    let newest = MIGRATIONS.len() - 1;
+    assert!(
+        InitializedDb::setup(&path, &MIGRATIONS[..newest], None)
+            .await
+            .is_err()
+    );
+}
+
+/// Tests that the cluster id stored in the database is kept across migrations,
+/// rather than being tied to the schema version
+#[tokio::test(flavor = "multi_thread")]
+async fn keeps_cluster_id() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let path = db_path(&temp);
+
+    {
+        let db = InitializedDb::setup(&path, &MIGRATIONS[..1], None)
+            .await
+            .unwrap();
+        assert_eq!(db.cluster_id.0, 0);
+
+        let conn = db.pool.write_priority().await.unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO __corro_state (key, value) VALUES ('cluster_id', 7)",
+            [],
+        )
+        .unwrap();
+    }
+
+    let db = InitializedDb::setup(&path, MIGRATIONS, None).await.unwrap();
+    assert_eq!(db.schema_version, VERSION);
+    assert_eq!(db.cluster_id.0, 7);
+}
+
+/// Tests the schema version exchanged between gossip peers
+#[test]
+fn schema_version_compatibility() {
+    let current = SchemaVersion::CURRENT;
+    assert_eq!(SchemaVersion::decode(&current.encode()), Some(current));
+    assert_eq!(
+        SchemaVersion::decode(&[0; SchemaVersion::ENCODED_LEN]),
+        None
+    );
+    assert_eq!(SchemaVersion::decode(&current.encode()[1..]), None);
+
+    assert!(current.is_compatible(current));
+
+    // V1 peers would receive changes to `servers` columns they don't have
+    assert!(!current.is_compatible(SchemaVersion::LEGACY));
+    assert!(current.is_compatible(SchemaVersion {
+        version: 2,
+        min_compatible: 1,
+    }));
+
+    // A newer peer that requires at least our version
+    let newer = SchemaVersion {
+        version: VERSION + 1,
+        min_compatible: VERSION,
+    };
+    assert!(current.is_compatible(newer));
+    assert!(newer.is_compatible(current));
+
+    // A newer peer that can't gossip with our version
+    let breaking = SchemaVersion {
+        version: VERSION + 1,
+        min_compatible: VERSION + 1,
+    };
+    assert!(!current.is_compatible(breaking));
+    assert!(!breaking.is_compatible(current));
+}
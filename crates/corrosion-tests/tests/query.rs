@@ -0,0 +1,104 @@
+//! Tests ad-hoc queries are restricted to bounded, read-only statements
+
+use corrosion::db::{
+    SplitPoolReadExt as _,
+    query::{self, QueryError, QueryLimits},
+};
+use corrosion_tests as ct;
+use std::time::Duration;
+
+#[tokio::test]
+async fn read_only_bounded_queries() {
+    let sp = ct::new_split_pool("read_only_bounded_queries", corrosion::schema::MIGRATIONS).await;
+
+    {
+        let conn = sp.write_priority().await.unwrap();
+        for i in 0..10 {
+            conn.execute(
+                "INSERT INTO servers (endpoint,icao,tokens) VALUES (?,'ABCD','AQI')",
+                [format!("|1.2.3.{i}:7777")],
+            )
+            .unwrap();
+        }
+    }
+
+    let conn = sp.read_readonly().await.unwrap();
+
+    let output = query::query(
+        &conn,
+        "SELECT endpoint,icao,x'0102' FROM servers ORDER BY endpoint",
+        QueryLimits::new(None, Some(4)),
+    )
+    .unwrap();
+    assert_eq!(output.columns, ["endpoint", "icao", "x'0102'"]);
+    assert_eq!(output.rows.len(), 4);
+    assert!(output.truncated);
+    assert_eq!(
+        output.rows[0],
+        [
+            serde_json::json!("|1.2.3.0:7777"),
+            serde_json::json!("ABCD"),
+            serde_json::json!("AQI="),
+        ]
+    );
+
+    let output = query::query(
+        &conn,
+        "SELECT count(*) FROM servers",
+        QueryLimits::default(),
+    )
+    .unwrap();
+    assert_eq!(output.rows, [[serde_json::json!(10)]]);
+    assert!(!output.truncated);
+
+    assert!(matches!(
+        query::query(&conn, "DELETE FROM servers", QueryLimits::default()),
+        Err(QueryError::NotReadOnly)
+    ));
+    assert!(matches!(
+        query::query(
+            &conn,
+            "SELECT 1; DELETE FROM servers",
+            QueryLimits::default()
+        ),
+        Err(QueryError::MultipleStatements)
+    ));
+
+    let err = query::query(&conn, "SELECT nope FROM servers", QueryLimits::default()).unwrap_err();
+    assert!(err.is_invalid_query());
+
+    // Statements SQLite considers read-only, but that change the state of the
+    // connection, are rejected and leave no transaction open
+    for sql in [
+        "BEGIN",
+        "SAVEPOINT s",
+        "ATTACH DATABASE ':memory:' AS other",
+        "DETACH DATABASE main",
+        "PRAGMA table_info(servers)",
+    ] {
+        let err = query::query(&conn, sql, QueryLimits::default()).unwrap_err();
+        assert!(matches!(err, QueryError::NotReadOnly), "{sql}: {err}");
+        assert!(conn.is_autocommit(), "{sql} left a transaction open");
+    }
+
+    // The authorizer is removed once the statement is prepared
+    conn.prepare("PRAGMA table_info(servers)").unwrap();
+
+    // A query that would run for far longer than the timeout is interrupted
+    let err = query::query(
+        &conn,
+        "SELECT count(*) FROM servers a, servers b, servers c, servers d, servers e, servers f, servers g, servers h, servers i, servers j",
+        QueryLimits::new(Some(Duration::from_millis(100)), None),
+    )
+    .unwrap_err();
+    assert!(matches!(err, QueryError::Timeout(_)));
+
+    // Requested limits are capped
+    assert_eq!(
+        QueryLimits::new(Some(Duration::from_secs(3600)), Some(usize::MAX)),
+        QueryLimits {
+            timeout: QueryLimits::MAX_TIMEOUT,
+            max_rows: QueryLimits::MAX_ROWS,
+        }
+    );
+}
@@ -90,7 +90,13 @@ impl server::DbMutator for InstaPrinter {
                     p::ServerChange::Upsert(i) => {
                         let mut srv = db::write::Server::for_peer(peer, &mut v);
                         for i in i {
-                            srv.upsert(&i.endpoint, i.icao, &i.tokens);
+                            srv.upsert(
+                                &i.endpoint,
+                                i.icao,
+                                &i.tokens,
+                                &i.metadata,
+                                i.locality.as_deref(),
+                            );
                         }
                     }
                     p::ServerChange::Remove(r) => {
@@ -102,7 +108,13 @@ impl server::DbMutator for InstaPrinter {
                     p::ServerChange::Update(u) => {
                         let mut srv = db::write::Server::for_peer(peer, &mut v);
                         for u in u {
-                            srv.update(&u.endpoint, u.icao, u.tokens.as_ref());
+                            srv.update(
+                                &u.endpoint,
+                                u.icao,
+                                u.tokens.as_ref(),
+                                u.metadata.as_ref(),
+                                u.locality.as_deref(),
+                            );
                         }
                     }
                     p::ServerChange::UpdateMutator(mu) => {
@@ -144,7 +156,7 @@ impl server::DbMutator for InstaPrinter {
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
 async fn test_quic_stream() {
-    let db = ct::TestSubsDb::new(corrosion::schema::SCHEMA, "test_quic_stream").await;
+    let db = ct::TestSubsDb::new(corrosion::schema::MIGRATIONS, "test_quic_stream").await;
 
     let ip = InstaPrinter {
         db: db.pool.clone(),
@@ -310,6 +322,8 @@ async fn test_quic_stream() {
             },
             icao,
             tokens: [[20; 2]].into(),
+            metadata: Default::default(),
+            locality: None,
         },
         p::ServerUpsert {
             endpoint: Endpoint {
@@ -318,6 +332,8 @@ async fn test_quic_stream() {
             },
             icao,
             tokens: [[30; 3]].into(),
+            metadata: Default::default(),
+            locality: None,
         },
         p::ServerUpsert {
             endpoint: Endpoint {
@@ -326,6 +342,8 @@ async fn test_quic_stream() {
             },
             icao,
             tokens: [[40; 4]].into(),
+            metadata: Default::default(),
+            locality: None,
         },
         p::ServerUpsert {
             endpoint: Endpoint {
@@ -334,6 +352,8 @@ async fn test_quic_stream() {
             },
             icao,
             tokens: [[50; 5]].into(),
+            metadata: Default::default(),
+            locality: None,
         },
     ])])
     .await;
@@ -356,6 +376,8 @@ async fn test_quic_stream() {
             },
             icao: Some(IcaoCode::new_testing([b'X'; 4])),
             tokens: None,
+            metadata: None,
+            locality: None,
         }]),
     ])
     .await;
@@ -0,0 +1,76 @@
+//! Tests exporting and importing snapshots of the database
+
+use corrosion::{
+    db::{InitializedDb, offline},
+    schema::MIGRATIONS,
+};
+
+/// Tests that a snapshot can be imported into a new node, which gets its own
+/// actor id but retains the data and versions of the exporting node
+#[tokio::test(flavor = "multi_thread")]
+async fn export_and_import() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let root = camino::Utf8Path::from_path(temp.path()).unwrap();
+    let exporter_path = root.join("exporter/db.db");
+    let importer_path = root.join("importer/db.db");
+    let snapshot = root.join("snapshot.db");
+    std::fs::create_dir_all(exporter_path.parent().unwrap()).unwrap();
+
+    let exporter_id = {
+        let db = InitializedDb::setup(&exporter_path, MIGRATIONS, None)
+            .await
+            .unwrap();
+
+        let conn = db.pool.write_priority().await.unwrap();
+        conn.execute(
+            "INSERT INTO servers (endpoint,icao,tokens,locality) VALUES ('|1.2.3.4:7777','ABCD','AQI','region')",
+            [],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO filter (id,filter) VALUES (9,'[]')", [])
+            .unwrap();
+
+        db.actor_id
+    };
+
+    {
+        let conn = offline::open(&exporter_path).unwrap();
+        offline::export(&conn, &snapshot).unwrap();
+
+        // Exporting over an existing file is refused
+        assert!(offline::export(&conn, &snapshot).is_err());
+    }
+
+    offline::import(&snapshot, &importer_path).unwrap();
+    // As is importing over an existing database
+    assert!(offline::import(&snapshot, &importer_path).is_err());
+
+    let db = InitializedDb::setup(&importer_path, MIGRATIONS, None)
+        .await
+        .unwrap();
+    assert_ne!(db.actor_id, exporter_id);
+
+    let conn = offline::open(&importer_path).unwrap();
+    let servers = offline::dump(&conn, offline::Table::Servers).unwrap();
+    assert_eq!(
+        servers,
+        [serde_json::json!({
+            "endpoint": "1.2.3.4:7777",
+            "icao": "ABCD",
+            "tokens": ["Ag=="],
+            "metadata": {},
+            "locality": "region",
+            "contributors": null,
+            "cont_update": null,
+        })]
+    );
+    assert_eq!(
+        offline::dump(&conn, offline::Table::Filter).unwrap(),
+        [serde_json::json!({ "id": 9, "filter": [] })]
+    );
+
+    assert_eq!(
+        offline::bookkeeping(&conn).unwrap().actor_id,
+        db.actor_id.to_string()
+    );
+}
@@ -27,7 +27,7 @@ struct Server {
 async fn server_subscriptions() {
     qt::init_logging(qt::Level::DEBUG, "corro_types");
 
-    let mut pool = TestSubsDb::new(corrosion::schema::SCHEMA, "server_subscriptions").await;
+    let mut pool = TestSubsDb::new(corrosion::schema::MIGRATIONS, "server_subscriptions").await;
 
     let peer = corrosion::Peer::new(Ipv6Addr::from_bits(0xaabbccddeeff), 15111, 0, 0);
 
@@ -66,7 +66,7 @@ async fn server_subscriptions() {
         let mut s = write::Server::for_peer(peer, &mut states);
 
         for (ep, srv) in &server_set {
-            s.upsert(ep, srv.icao, &srv.tokens);
+            s.upsert(ep, srv.icao, &srv.tokens, &Default::default(), None);
         }
     }
 
@@ -121,7 +121,7 @@ async fn server_subscriptions() {
             },
         );
         let srv = server_set.get(&key).unwrap();
-        s.upsert(&key, srv.icao, &srv.tokens);
+        s.upsert(&key, srv.icao, &srv.tokens, &Default::default(), None);
     }
 
     pool.transaction(states.iter()).await;
@@ -157,7 +157,7 @@ async fn server_subscriptions() {
         };
         let srv = server_set.get_mut(&key).unwrap();
         srv.icao = IcaoCode::new_testing([b'Y'; 4]);
-        s.update(&key, Some(srv.icao), None);
+        s.update(&key, Some(srv.icao), None, None, None);
     }
 
     pool.transaction(states.iter()).await;
@@ -329,7 +329,7 @@ qt::trace_test!(
     single_sub, {
     //qt::init_logging(qt::Level::DEBUG, "corro_types");
 
-    let pool = TestSubsDb::new(corrosion::schema::SCHEMA, "single_sub").await;
+    let pool = TestSubsDb::new(corrosion::schema::MIGRATIONS, "single_sub").await;
     let ctx = pool.pubsub_ctx();
 
     let sub = ctx
@@ -387,7 +387,7 @@ qt::trace_test!(
                     },
                 );
                 let srv = server_set.get(&key).unwrap();
-                s.upsert(&key, srv.icao, &srv.tokens);
+                s.upsert(&key, srv.icao, &srv.tokens, &Default::default(), None);
             }
 
             pool.broadcast_changes(&mut states).await;
@@ -402,6 +402,8 @@ qt::trace_test!(
                         icao,
                         endpoint: key.clone(),
                         tokens: tokens.clone(),
+                        metadata: Default::default(),
+                        locality: None,
                     },
                     cur_cid.into(),
                 ),
@@ -415,7 +417,7 @@ qt::trace_test!(
                 let mut s = write::Server::for_peer(peer, &mut states);
 
                 server_set.get_mut(&key).unwrap().icao = new_icao;
-                s.update(&key, Some(new_icao), None);
+                s.update(&key, Some(new_icao), None, None, None);
             }
 
             pool.broadcast_changes(&mut states).await;
@@ -430,6 +432,8 @@ qt::trace_test!(
                         icao: new_icao,
                         endpoint: key.clone(),
                         tokens: tokens.clone(),
+                        metadata: Default::default(),
+                        locality: None,
                     },
                     cur_cid.into(),
                 ),
@@ -458,6 +462,8 @@ qt::trace_test!(
                         icao: new_icao,
                         endpoint: key.clone(),
                         tokens: tokens.clone(),
+                        metadata: Default::default(),
+                        locality: None,
                     },
                     cur_cid.into(),
                 ),
@@ -472,7 +478,7 @@ qt::trace_test!(
 async fn multiple_subs() {
     qt::init_logging(qt::Level::TRACE, "corrosion");
 
-    let pool = TestSubsDb::new(corrosion::schema::SCHEMA, "multiple_subs").await;
+    let pool = TestSubsDb::new(corrosion::schema::MIGRATIONS, "multiple_subs").await;
     let ctx = pool.pubsub_ctx();
     let mut cur_cid = 0;
 
@@ -560,7 +566,7 @@ async fn multiple_subs() {
                         },
                     );
                     let srv = server_set.get(&key).unwrap();
-                    s.upsert(&key, srv.icao, &srv.tokens);
+                    s.upsert(&key, srv.icao, &srv.tokens, &Default::default(), None);
                 }
 
                 pool.broadcast_changes(&mut states).await;
@@ -573,6 +579,8 @@ async fn multiple_subs() {
                         icao,
                         endpoint: key.clone(),
                         tokens: tokens.clone(),
+                        metadata: Default::default(),
+                        locality: None,
                     },
                     cur_cid.into(),
                 ))
@@ -585,7 +593,7 @@ async fn multiple_subs() {
                     let mut s = write::Server::for_peer(peer, &mut states);
 
                     server_set.get_mut(&key).unwrap().icao = new_icao;
-                    s.update(&key, Some(new_icao), None);
+                    s.update(&key, Some(new_icao), None, None, None);
                 }
 
                 pool.broadcast_changes(&mut states).await;
@@ -598,6 +606,8 @@ async fn multiple_subs() {
                         icao: new_icao,
                         endpoint: key.clone(),
                         tokens: tokens.clone(),
+                        metadata: Default::default(),
+                        locality: None,
                     },
                     cur_cid.into(),
                 ))
@@ -623,6 +633,8 @@ async fn multiple_subs() {
                         icao: new_icao,
                         endpoint: key.clone(),
                         tokens: tokens.clone(),
+                        metadata: Default::default(),
+                        locality: None,
                     },
                     cur_cid.into(),
                 ))
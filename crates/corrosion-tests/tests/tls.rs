@@ -0,0 +1,141 @@
+//! Tests for mutual TLS between a persistent server and its clients
+//!
+//! This is a separate test binary as the persistent metrics are a process wide
+//! singleton
+
+use corrosion::{
+    persistent::{client, server},
+    tls::TlsConfig,
+};
+use corrosion_tests as ct;
+use quilkin_types::IcaoCode;
+
+struct Ca {
+    cert: rcgen::Certificate,
+    issuer: rcgen::Issuer<'static, rcgen::KeyPair>,
+}
+
+impl Ca {
+    fn new() -> Self {
+        let mut params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+
+        Self {
+            cert,
+            issuer: rcgen::Issuer::new(params, key),
+        }
+    }
+
+    /// Issues a certificate for `name`, trusting `trusted` as the CA
+    fn issue(&self, name: &str, trusted: &Self) -> TlsConfig {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec![name.to_owned()])
+            .unwrap()
+            .signed_by(&key, &self.issuer)
+            .unwrap();
+
+        TlsConfig::from_pem(
+            cert.pem().as_bytes(),
+            key.serialize_pem().as_bytes(),
+            trusted.cert.pem().as_bytes(),
+        )
+        .unwrap()
+    }
+}
+
+fn failed_handshakes(registry: &prometheus::Registry, cause: &str) -> u64 {
+    registry
+        .gather()
+        .iter()
+        .filter(|mf| mf.name() == "corrosion_failed_handshakes")
+        .flat_map(|mf| mf.get_metric())
+        .filter(|m| m.get_label().iter().any(|l| l.value() == cause))
+        .map(|m| m.get_counter().value() as u64)
+        .sum()
+}
+
+async fn wait_for_failure(registry: &prometheus::Registry, cause: &str) {
+    tokio::time::timeout(std::time::Duration::from_secs(2), async {
+        while failed_handshakes(registry, cause) == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no handshake failure with cause '{cause}' was recorded"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn mutual_tls() {
+    let db = ct::TestSubsDb::new(corrosion::schema::MIGRATIONS, "mutual_tls").await;
+
+    static REG: std::sync::OnceLock<prometheus::Registry> = std::sync::OnceLock::new();
+    let reg = REG.get_or_init(prometheus::Registry::new);
+    let metrics = || corrosion::persistent::Metrics::new(reg);
+
+    let ca = Ca::new();
+    let untrusted_ca = Ca::new();
+
+    let server = server::Server::new(
+        (std::net::Ipv6Addr::LOCALHOST, 0).into(),
+        db.btx.clone(),
+        db.pubsub_ctx(),
+        metrics(),
+        &ca.issue("relay.quilkin.test", &ca)
+            .with_allowed_peers(vec!["agent.quilkin.test".into()]),
+    )
+    .unwrap();
+    let addr = server.local_addr();
+    let icao = IcaoCode::new_testing([b'T'; 4]);
+
+    // An allowed agent with a certificate from the trusted CA
+    let agent = ca
+        .issue("agent.quilkin.test", &ca)
+        .with_server_name(Some("relay.quilkin.test".into()));
+    let client = client::Client::connect(addr, metrics(), &agent)
+        .await
+        .unwrap();
+    let mutator = client::MutationClient::connect(client, 2001, icao)
+        .await
+        .unwrap();
+    mutator.shutdown().await;
+
+    // The server's certificate must be valid for the name the client expects
+    let wrong_name = ca
+        .issue("agent.quilkin.test", &ca)
+        .with_server_name(Some("other.quilkin.test".into()));
+    assert!(
+        client::Client::connect(addr, metrics(), &wrong_name)
+            .await
+            .is_err()
+    );
+
+    // A certificate from a CA the server doesn't trust is rejected during the handshake
+    let untrusted = untrusted_ca
+        .issue("agent.quilkin.test", &ca)
+        .with_server_name(Some("relay.quilkin.test".into()));
+    if let Ok(client) = client::Client::connect(addr, metrics(), &untrusted).await {
+        assert!(
+            client::MutationClient::connect(client, 2001, icao)
+                .await
+                .is_err()
+        );
+    }
+    wait_for_failure(reg, "unknown_ca").await;
+
+    // A valid certificate for an identity that isn't allowed is rejected after the handshake
+    let rogue = ca
+        .issue("rogue.quilkin.test", &ca)
+        .with_server_name(Some("relay.quilkin.test".into()));
+    if let Ok(client) = client::Client::connect(addr, metrics(), &rogue).await {
+        assert!(
+            client::MutationClient::connect(client, 2001, icao)
+                .await
+                .is_err()
+        );
+    }
+    wait_for_failure(reg, "peer_not_allowed").await;
+
+    server.shutdown("test complete").await;
+}
@@ -0,0 +1,115 @@
+//! Tests for replacing the certificate of a running persistent server
+//!
+//! This is a separate test binary as the persistent metrics are a process wide
+//! singleton
+
+use corrosion::{
+    persistent::{client, server},
+    tls::TlsConfig,
+};
+use corrosion_tests as ct;
+use quilkin_types::IcaoCode;
+
+struct Ca {
+    cert: rcgen::Certificate,
+    issuer: rcgen::Issuer<'static, rcgen::KeyPair>,
+}
+
+impl Ca {
+    fn new() -> Self {
+        let mut params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+
+        Self {
+            cert,
+            issuer: rcgen::Issuer::new(params, key),
+        }
+    }
+
+    /// Issues a PEM encoded certificate and key for `name`
+    fn issue_pem(&self, name: &str) -> (String, String) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec![name.to_owned()])
+            .unwrap()
+            .signed_by(&key, &self.issuer)
+            .unwrap();
+
+        (cert.pem(), key.serialize_pem())
+    }
+
+    /// Issues a certificate for `name`, trusting this CA
+    fn issue(&self, name: &str) -> TlsConfig {
+        let (cert, key) = self.issue_pem(name);
+        TlsConfig::from_pem(cert.as_bytes(), key.as_bytes(), self.cert.pem().as_bytes()).unwrap()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn reloads_certificate() {
+    let db = ct::TestSubsDb::new(corrosion::schema::MIGRATIONS, "reloads_certificate").await;
+
+    static REG: std::sync::OnceLock<prometheus::Registry> = std::sync::OnceLock::new();
+    let reg = REG.get_or_init(prometheus::Registry::new);
+    let metrics = || corrosion::persistent::Metrics::new(reg);
+
+    let ca = Ca::new();
+    let tls = ca.issue("old.quilkin.test");
+
+    let server = server::Server::new(
+        (std::net::Ipv6Addr::LOCALHOST, 0).into(),
+        db.btx.clone(),
+        db.pubsub_ctx(),
+        metrics(),
+        &tls,
+    )
+    .unwrap();
+    let addr = server.local_addr();
+    let icao = IcaoCode::new_testing([b'T'; 4]);
+
+    let agent = ca
+        .issue("agent.quilkin.test")
+        .with_server_name(Some("relay.quilkin.test".into()));
+
+    // The server's original certificate isn't valid for the name the client expects
+    assert!(
+        client::Client::connect(addr, metrics(), &agent)
+            .await
+            .is_err()
+    );
+
+    // Once replaced, new connections are made with the new certificate
+    let (cert, key) = ca.issue_pem("relay.quilkin.test");
+    tls.reload_pem(cert.as_bytes(), key.as_bytes()).unwrap();
+
+    let client = client::Client::connect(addr, metrics(), &agent)
+        .await
+        .unwrap();
+    let mutator = client::MutationClient::connect(client, 2001, icao)
+        .await
+        .unwrap();
+    mutator.shutdown().await;
+
+    // An invalid certificate is rejected, keeping the current one
+    assert!(
+        tls.reload_pem(b"not a certificate", key.as_bytes())
+            .is_err()
+    );
+
+    // As is a certificate that doesn't belong to the key
+    let (mismatched, _key) = ca.issue_pem("relay.quilkin.test");
+    assert!(
+        tls.reload_pem(mismatched.as_bytes(), key.as_bytes())
+            .is_err()
+    );
+    let client = client::Client::connect(addr, metrics(), &agent)
+        .await
+        .unwrap();
+    let mutator = client::MutationClient::connect(client, 2001, icao)
+        .await
+        .unwrap();
+    mutator.shutdown().await;
+
+    server.shutdown("test complete").await;
+}
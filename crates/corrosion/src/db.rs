@@ -6,9 +6,87 @@ use corro_types::{
 };
 use std::{sync::Arc, time::Duration};
 
+pub mod offline;
+pub mod query;
 pub mod read;
 pub mod write;
 
+/// Additional metadata stored for a server, beyond its ICAO and tokens
+pub type Metadata = serde_json::Map<String, serde_json::Value>;
+
+/// The scope a filter chain in the `filters` table applies to
+///
+/// A proxy uses the chain of the most specific scope that matches it, see
+/// [`FilterScope::candidates`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FilterScope {
+    /// The default chain used by proxies with no more specific chain
+    Global,
+    /// The chain used by proxies with the ICAO code
+    Icao(quilkin_types::IcaoCode),
+    /// The chain used by proxies in the locality, in `region[:zone[:sub_zone]]`
+    /// form, a proxy matches if its locality is equal to or within it
+    Locality(String),
+}
+
+impl FilterScope {
+    const GLOBAL: &str = "*";
+    const ICAO: &str = "icao:";
+    const LOCALITY: &str = "locality:";
+
+    /// The scopes that apply to a proxy with the specified ICAO code and
+    /// locality, ordered from the most to least specific
+    pub fn candidates(icao: quilkin_types::IcaoCode, locality: Option<&str>) -> Vec<Self> {
+        let mut scopes = Vec::new();
+
+        if let Some(mut locality) = locality.filter(|l| !l.is_empty()) {
+            loop {
+                scopes.push(Self::Locality(locality.to_owned()));
+                let Some((parent, _)) = locality.rsplit_once(':') else {
+                    break;
+                };
+                locality = parent;
+            }
+        }
+
+        scopes.push(Self::Icao(icao));
+        scopes.push(Self::Global);
+        scopes
+    }
+}
+
+impl std::fmt::Display for FilterScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Global => f.write_str(Self::GLOBAL),
+            Self::Icao(icao) => write!(f, "{}{icao}", Self::ICAO),
+            Self::Locality(locality) => write!(f, "{}{locality}", Self::LOCALITY),
+        }
+    }
+}
+
+impl std::str::FromStr for FilterScope {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == Self::GLOBAL {
+            Ok(Self::Global)
+        } else if let Some(icao) = s.strip_prefix(Self::ICAO) {
+            Ok(Self::Icao(icao.parse()?))
+        } else if let Some(locality) = s.strip_prefix(Self::LOCALITY) {
+            eyre::ensure!(!locality.is_empty(), "locality filter scope is empty");
+            Ok(Self::Locality(locality.to_owned()))
+        } else {
+            eyre::bail!(
+                "invalid filter scope '{s}', expected '{}', '{}<ICAO>', or '{}<region[:zone[:sub_zone]]>'",
+                Self::GLOBAL,
+                Self::ICAO,
+                Self::LOCALITY
+            )
+        }
+    }
+}
+
 /// Wraps [`SplitPool::read`] to enforce `temp_store = MEMORY` and `query_only = ON`.
 /// Use this instead of [`SplitPool::read`] directly.
 pub trait SplitPoolReadExt {
@@ -139,6 +217,8 @@ pub struct InitializedDb {
     pub clock: crate::Clock,
     /// The parsed and verified schema
     pub schema: Arc<Schema>,
+    /// The version of the schema, see [`crate::schema::MIGRATIONS`]
+    pub schema_version: u16,
     /// The unique actor ID for this database connection, distinguishing it from
     /// other actors that gossip DB changes
     pub actor_id: ActorId,
@@ -150,17 +230,16 @@ pub struct InitializedDb {
 }
 
 impl InitializedDb {
-    /// Attempts to initialize a CRSQL database with the specified schema, creating
-    /// it if it doesn't exist
+    /// Attempts to initialize a CRSQL database, creating it if it doesn't exist,
+    /// and applying any of the specified migrations that haven't already been
+    /// applied to it
     ///
     /// If `maintenance` is specified, spawns a task to maintain the WAL and vacuum the DB
     pub async fn setup(
         db_path: &crate::Path,
-        schema: &str,
+        migrations: &[crate::schema::Migration],
         maintenance: Option<DBMaintenance>,
     ) -> eyre::Result<Self> {
-        let partial_schema = corro_types::schema::parse_sql(schema)?;
-
         let actor_id = {
             // we need to set auto_vacuum before any tables are created
             let db_conn = rusqlite::Connection::open(db_path)?;
@@ -186,25 +265,16 @@ impl InitializedDb {
                 .build(),
         ));
 
-        let schema = {
+        let (schema, schema_version) = {
             let mut conn = pool.write_priority().await?;
 
-            let old_schema = {
-                corro_types::agent::migrate(clock.0.clone(), &mut conn)?;
-                let mut schema = corro_types::schema::init_schema(&conn)?;
-                schema.constrain()?;
-
-                schema
-            };
-
-            let schema = tokio::task::block_in_place(|| {
-                update_schema(&mut conn, old_schema, partial_schema)
-            })?;
+            corro_types::agent::migrate(clock.0.clone(), &mut conn)?;
+            let migrated = tokio::task::block_in_place(|| migrate(&mut conn, migrations))?;
 
             let limits = maintenance.as_ref().map(|m| &m.limits);
             tokio::task::block_in_place(|| run_startup_checks(&conn, limits))?;
 
-            schema
+            migrated
         };
 
         let cluster_id = {
@@ -250,17 +320,72 @@ impl InitializedDb {
             booked,
             clock,
             schema: Arc::new(schema),
+            schema_version,
             actor_id,
             cluster_id,
         })
     }
 }
 
+/// The `__corro_state` key the schema version is stored under
+const SCHEMA_VERSION_KEY: &str = "quilkin_schema_version";
+
+/// Applies each migration newer than the current schema version of the DB, in
+/// order, returning the resulting schema and its version
+///
+/// Fails if the DB has a schema version newer than the latest migration, as
+/// that means it was created by a newer version of quilkin
+pub fn migrate(
+    conn: &mut WriteConn,
+    migrations: &[crate::schema::Migration],
+) -> eyre::Result<(Schema, u16)> {
+    debug_assert!(
+        migrations.windows(2).all(|w| w[0].version < w[1].version),
+        "migrations must be ordered by version"
+    );
+
+    let mut schema = corro_types::schema::init_schema(conn)?;
+    schema.constrain()?;
+
+    let mut version = match conn.query_row(
+        "SELECT value FROM __corro_state WHERE key = ?",
+        [SCHEMA_VERSION_KEY],
+        |row| row.get(0),
+    ) {
+        Ok(version) => version,
+        // Databases created before schema versioning was introduced have the
+        // initial schema, but no recorded version
+        Err(rusqlite::Error::QueryReturnedNoRows) => u16::from(!schema.tables.is_empty()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let latest = migrations.last().map_or(0, |m| m.version);
+    eyre::ensure!(
+        version <= latest,
+        "database schema version {version} is newer than the latest supported version {latest}"
+    );
+
+    for migration in migrations.iter().filter(|m| m.version > version) {
+        let partial = corro_types::schema::parse_sql(migration.sql)?;
+        schema = update_schema(conn, schema, partial, migration.version)?;
+        tracing::info!(
+            from = version,
+            to = migration.version,
+            description = migration.description,
+            "applied schema migration"
+        );
+        version = migration.version;
+    }
+
+    Ok((schema, version))
+}
+
 /// We currently only support updating the schema at startup
 fn update_schema(
     conn: &mut WriteConn,
     old_schema: Schema,
     new_schema: Schema,
+    version: u16,
 ) -> eyre::Result<Schema> {
     // clone the previous schema and apply
     let mut new_schema = {
@@ -285,6 +410,11 @@ fn update_schema(
         tracing::info!("Updated {n} rows in __corro_schema for table {tbl_name}");
     }
 
+    tx.execute(
+        "INSERT OR REPLACE INTO __corro_state (key, value) VALUES (?, ?)",
+        rusqlite::params![SCHEMA_VERSION_KEY, version],
+    )?;
+
     tx.commit()?;
     Ok(new_schema)
 }
@@ -0,0 +1,315 @@
+//! Offline inspection, export, and import of a corrosion database
+//!
+//! These operate directly on the database file rather than through a running
+//! node. Inspection and export only read the database so are safe to use while
+//! a node is running, but importing must only be done before a node starts.
+
+use super::read;
+use corro_types::{actor::ActorId, sqlite::CrConn};
+use eyre::WrapErr as _;
+
+/// A table in the corrosion schema that can be dumped
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Table {
+    Servers,
+    Datacenters,
+    Filter,
+    ScopedFilters,
+}
+
+impl Table {
+    pub const ALL: [Self; 4] = [
+        Self::Servers,
+        Self::Datacenters,
+        Self::Filter,
+        Self::ScopedFilters,
+    ];
+
+    /// The name of the table in the schema
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Servers => "servers",
+            Self::Datacenters => "dc",
+            Self::Filter => "filter",
+            Self::ScopedFilters => "filters",
+        }
+    }
+}
+
+/// Opens the database at `db_path` for offline use
+pub fn open(db_path: &crate::Path) -> eyre::Result<CrConn> {
+    eyre::ensure!(db_path.exists(), "database '{db_path}' does not exist");
+
+    let conn = CrConn::init(rusqlite::Connection::open(db_path)?)
+        .wrap_err_with(|| format!("failed to open database '{db_path}'"))?;
+    Ok(conn)
+}
+
+/// Dumps every row of the table as a JSON object
+pub fn dump(conn: &rusqlite::Connection, table: Table) -> eyre::Result<Vec<serde_json::Value>> {
+    use serde_json::json;
+
+    let json_col = |v: Option<String>| -> serde_json::Value {
+        v.and_then(|v| serde_json::from_str(&v).ok())
+            .unwrap_or_default()
+    };
+
+    // Filters are usually JSON, but keep them as a string if they aren't
+    let filter_col = |v: Option<String>| -> serde_json::Value {
+        v.as_deref()
+            .and_then(|f| serde_json::from_str::<serde_json::Value>(f).ok())
+            .or_else(|| v.map(serde_json::Value::String))
+            .unwrap_or_default()
+    };
+
+    let rows = match table {
+        Table::Servers => {
+            let mut statement = conn.prepare(
+                "SELECT endpoint,icao,tokens,metadata,locality,json(contributors),cont_update FROM servers",
+            )?;
+            let mut rows = statement.query([])?;
+            let mut dumped = Vec::new();
+
+            while let Some(row) = rows.next()? {
+                let endpoint = read::parse_endpoint(&row.get::<_, String>(0)?)?;
+                let tokens =
+                    read::deserialize_token_set(row.get::<_, Option<String>>(2)?.as_deref())?;
+
+                dumped.push(json!({
+                    "endpoint": endpoint.to_string(),
+                    "icao": row.get::<_, String>(1)?,
+                    "tokens": tokens
+                        .iter()
+                        .map(|tok| data_encoding::BASE64.encode(tok))
+                        .collect::<Vec<_>>(),
+                    "metadata": read::deserialize_metadata(row.get::<_, Option<String>>(3)?.as_deref())?,
+                    "locality": row.get::<_, Option<String>>(4)?,
+                    "contributors": json_col(row.get(5)?),
+                    "cont_update": row.get::<_, Option<i64>>(6)?,
+                }));
+            }
+
+            dumped
+        }
+        Table::Datacenters => {
+            let mut statement = conn.prepare("SELECT ip,port,icao,json(servers) FROM dc")?;
+            let mut rows = statement.query([])?;
+            let mut dumped = Vec::new();
+
+            while let Some(row) = rows.next()? {
+                dumped.push(json!({
+                    "ip": row.get::<_, String>(0)?,
+                    "qcmp_port": row.get::<_, i64>(1)?,
+                    "icao": row.get::<_, String>(2)?,
+                    "servers": json_col(row.get(3)?),
+                }));
+            }
+
+            dumped
+        }
+        Table::Filter => {
+            let mut statement = conn.prepare("SELECT id,filter FROM filter")?;
+            let mut rows = statement.query([])?;
+            let mut dumped = Vec::new();
+
+            while let Some(row) = rows.next()? {
+                dumped.push(json!({
+                    "id": row.get::<_, i64>(0)?,
+                    "filter": filter_col(row.get(1)?),
+                }));
+            }
+
+            dumped
+        }
+        Table::ScopedFilters => {
+            let mut statement = conn.prepare("SELECT scope,filter FROM filters")?;
+            let mut rows = statement.query([])?;
+            let mut dumped = Vec::new();
+
+            while let Some(row) = rows.next()? {
+                dumped.push(json!({
+                    "scope": row.get::<_, String>(0)?,
+                    "filter": filter_col(row.get(1)?),
+                }));
+            }
+
+            dumped
+        }
+    };
+
+    Ok(rows)
+}
+
+/// The bookkeeping state of a single actor
+#[derive(serde::Serialize, Debug)]
+pub struct ActorBookkeeping {
+    pub actor_id: String,
+    /// Whether this is the actor of the database itself
+    pub local: bool,
+    /// The latest version known for the actor
+    pub last_version: Option<u64>,
+    /// The inclusive ranges of versions that are known to be missing
+    pub needed: Vec<(u64, u64)>,
+    /// The number of versions that have only been partially received
+    pub incomplete_partials: usize,
+}
+
+/// The bookkeeping and clock state of a database
+#[derive(serde::Serialize, Debug)]
+pub struct Bookkeeping {
+    /// The actor id of the database itself
+    pub actor_id: String,
+    /// The current `cr-sqlite` database version
+    pub db_version: i64,
+    /// The schema version, see [`crate::schema::MIGRATIONS`]
+    pub schema_version: Option<u16>,
+    pub actors: Vec<ActorBookkeeping>,
+}
+
+/// Retrieves the bookkeeping state for every actor known to the database
+pub fn bookkeeping(conn: &rusqlite::Connection) -> eyre::Result<Bookkeeping> {
+    use rusqlite::OptionalExtension as _;
+
+    let local: ActorId = conn.query_row("SELECT crsql_site_id()", [], |row| row.get(0))?;
+    let db_version = conn.query_row("SELECT crsql_db_version()", [], |row| row.get(0))?;
+    let schema_version = conn
+        .query_row(
+            "SELECT value FROM __corro_state WHERE key = ?",
+            [super::SCHEMA_VERSION_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let all_booked = corro_types::agent::BookedVersions::load_all_from_conn(conn)
+        .wrap_err("failed to load booked versions")?;
+    let bookie = corro_types::agent::Bookie::new(all_booked);
+    let guard = bookie.owned_guard();
+
+    let mut actors = Vec::new();
+    for (actor_id, booked) in bookie.iter(&guard) {
+        let booked = booked.read();
+
+        actors.push(ActorBookkeeping {
+            actor_id: actor_id.to_string(),
+            local: *actor_id == local,
+            last_version: booked.last().map(|v| v.0),
+            needed: booked
+                .needed()
+                .iter()
+                .map(|r| (r.start().0, r.end().0))
+                .collect(),
+            incomplete_partials: booked
+                .partials
+                .values()
+                .filter(|p| !p.is_complete())
+                .count(),
+        });
+    }
+
+    actors.sort_by(|a, b| {
+        b.local
+            .cmp(&a.local)
+            .then_with(|| a.actor_id.cmp(&b.actor_id))
+    });
+
+    Ok(Bookkeeping {
+        actor_id: local.to_string(),
+        db_version,
+        schema_version,
+        actors,
+    })
+}
+
+/// Writes a consistent snapshot of the database to `output`
+///
+/// The snapshot is detached from the local actor, all of the changes made by
+/// it are attributed to it as a remote actor instead, so that a node importing
+/// the snapshot generates its own actor id, while still knowing every version
+/// contained in the snapshot so that it only needs to sync newer changes.
+pub fn export(conn: &rusqlite::Connection, output: &crate::Path) -> eyre::Result<()> {
+    eyre::ensure!(!output.exists(), "snapshot '{output}' already exists");
+
+    conn.execute("VACUUM INTO ?", [output.as_str()])
+        .wrap_err("failed to write snapshot")?;
+
+    // The snapshot is modified without the cr-sqlite extension, so that it
+    // doesn't generate a new local site id when opened
+    let mut snapshot = rusqlite::Connection::open(output)?;
+    let tx = snapshot.transaction()?;
+
+    let site_id: Vec<u8> = tx.query_row(
+        "SELECT site_id FROM crsql_site_id WHERE ordinal = 0",
+        [],
+        |row| row.get(0),
+    )?;
+    tx.execute("DELETE FROM crsql_site_id WHERE ordinal = 0", [])?;
+    let ordinal: i64 = tx.query_row(
+        "INSERT INTO crsql_site_id (site_id) VALUES (?) RETURNING ordinal",
+        [&site_id],
+        |row| row.get(0),
+    )?;
+
+    // Local changes are recorded in the clock tables with the site ordinal 0
+    let clock_tables = tx
+        .prepare(
+            "SELECT name FROM sqlite_schema WHERE type = 'table' AND name LIKE '%__crsql_clock'",
+        )?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for table in clock_tables {
+        tx.execute(
+            &format!("UPDATE \"{table}\" SET site_id = ? WHERE site_id = 0"),
+            [ordinal],
+        )?;
+    }
+
+    tx.commit()?;
+    snapshot.execute("VACUUM", [])?;
+    Ok(())
+}
+
+/// Imports a snapshot written by [`export`] as the database at `db_path`
+///
+/// The database must not already exist, ie. this is only used to bootstrap a
+/// new node, which will still gossip any changes made after the snapshot was
+/// exported.
+pub fn import(snapshot: &crate::Path, db_path: &crate::Path) -> eyre::Result<()> {
+    use rusqlite::OptionalExtension as _;
+
+    eyre::ensure!(
+        !db_path.exists(),
+        "database '{db_path}' already exists, a snapshot can only be imported into a new node"
+    );
+
+    {
+        let conn = rusqlite::Connection::open_with_flags(
+            snapshot,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .wrap_err_with(|| format!("failed to open snapshot '{snapshot}'"))?;
+
+        let check: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+        eyre::ensure!(check == "ok", "snapshot is corrupt: {check}");
+
+        let local = conn
+            .query_row(
+                "SELECT site_id FROM crsql_site_id WHERE ordinal = 0",
+                [],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+        eyre::ensure!(
+            local.is_none(),
+            "'{snapshot}' has a local actor, it must be created with `quilkin corrosion export`"
+        );
+    }
+
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::copy(snapshot, db_path)
+        .wrap_err_with(|| format!("failed to copy snapshot to '{db_path}'"))?;
+    Ok(())
+}
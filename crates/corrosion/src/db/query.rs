@@ -0,0 +1,216 @@
+//! Bounded, read-only ad-hoc queries against the local database
+//!
+//! These are used by operators to inspect the state of a node, so unlike the
+//! fixed queries used by subscribers, the SQL is arbitrary and must be checked
+//! to not modify the database, and limited so that it can't starve the
+//! connection pool of the node.
+
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use std::time::{Duration, Instant};
+
+/// The limits applied to an ad-hoc query
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// The maximum time the query can run for before it is interrupted
+    pub timeout: Duration,
+    /// The maximum number of rows returned, any further rows are not read
+    pub max_rows: usize,
+}
+
+impl QueryLimits {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+    pub const MAX_TIMEOUT: Duration = Duration::from_secs(30);
+    pub const DEFAULT_MAX_ROWS: usize = 1000;
+    pub const MAX_ROWS: usize = 10_000;
+
+    /// Creates limits from the requested values, which are capped at
+    /// [`Self::MAX_TIMEOUT`] and [`Self::MAX_ROWS`]
+    pub fn new(timeout: Option<Duration>, max_rows: Option<usize>) -> Self {
+        Self {
+            timeout: timeout
+                .unwrap_or(Self::DEFAULT_TIMEOUT)
+                .min(Self::MAX_TIMEOUT),
+            max_rows: max_rows
+                .unwrap_or(Self::DEFAULT_MAX_ROWS)
+                .min(Self::MAX_ROWS),
+        }
+    }
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum QueryError {
+    #[error("only a single statement can be queried")]
+    MultipleStatements,
+    #[error("only read-only statements can be queried")]
+    NotReadOnly,
+    #[error("the query was interrupted after exceeding the timeout of {0:?}")]
+    Timeout(Duration),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("failed to spawn query timeout thread: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl QueryError {
+    /// Whether the error was caused by the query itself rather than the database
+    #[inline]
+    pub fn is_invalid_query(&self) -> bool {
+        match self {
+            Self::MultipleStatements | Self::NotReadOnly => true,
+            // Syntax errors, unknown tables or columns, etc are all reported
+            // as the generic `SQLITE_ERROR`
+            Self::Sqlite(err) => err.sqlite_error_code() == Some(rusqlite::ErrorCode::Unknown),
+            Self::Timeout(_) | Self::Io(_) => false,
+        }
+    }
+}
+
+/// The result of an ad-hoc query
+#[derive(serde::Serialize, Debug)]
+pub struct QueryOutput {
+    /// The names of each column in the rows
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// Whether there were more rows than [`QueryLimits::max_rows`]
+    pub truncated: bool,
+    /// How long the query took to run, in milliseconds
+    pub elapsed_ms: f64,
+}
+
+/// Only allows statements that select and read from tables, and call
+/// functions, so that statements such as `BEGIN` or `ATTACH`, which SQLite
+/// considers read-only, can't change the state of the connection
+fn authorize(ctx: AuthContext<'_>) -> Authorization {
+    match ctx.action {
+        AuthAction::Select | AuthAction::Read { .. } | AuthAction::Function { .. } => {
+            Authorization::Allow
+        }
+        _ => Authorization::Deny,
+    }
+}
+
+/// Prepares the SQL, ensuring it is a single statement that makes no changes
+/// to the database
+pub fn prepare<'conn>(
+    conn: &'conn rusqlite::Connection,
+    sql: &str,
+) -> Result<rusqlite::Statement<'conn>, QueryError> {
+    // The connection is shared, so the authorizer is only installed while
+    // the statement is prepared, which is when SQLite checks it
+    conn.authorizer(Some(authorize));
+    let statement = conn.prepare(sql);
+    conn.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+
+    let statement = statement.map_err(|err| match err {
+        rusqlite::Error::MultipleStatement => QueryError::MultipleStatements,
+        err if err.sqlite_error_code()
+            == Some(rusqlite::ErrorCode::AuthorizationForStatementDenied) =>
+        {
+            QueryError::NotReadOnly
+        }
+        err => QueryError::Sqlite(err),
+    })?;
+
+    // Statements that don't return rows, eg. `BEGIN`, have nothing to query
+    if !statement.readonly() || statement.column_count() == 0 {
+        return Err(QueryError::NotReadOnly);
+    }
+
+    Ok(statement)
+}
+
+/// Runs a read-only query, returning at most [`QueryLimits::max_rows`] rows
+///
+/// If the query takes longer than [`QueryLimits::timeout`] it is interrupted
+pub fn query(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    limits: QueryLimits,
+) -> Result<QueryOutput, QueryError> {
+    let start = Instant::now();
+    let mut statement = prepare(conn, sql)?;
+
+    let columns = statement
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    // Interrupt the query from another thread if it exceeds the timeout, the
+    // sender is dropped when the query finishes, waking the thread early
+    let (done, done_rx) = std::sync::mpsc::channel::<()>();
+    let interrupt = conn.get_interrupt_handle();
+    let timeout = limits.timeout;
+    std::thread::Builder::new()
+        .name("corrosion-query-timeout".into())
+        .spawn(move || {
+            if let Err(std::sync::mpsc::RecvTimeoutError::Timeout) = done_rx.recv_timeout(timeout) {
+                interrupt.interrupt();
+            }
+        })?;
+
+    let result = (|| -> Result<_, rusqlite::Error> {
+        let mut rows = statement.query([])?;
+        let mut output = Vec::new();
+        let mut truncated = false;
+
+        while let Some(row) = rows.next()? {
+            if output.len() == limits.max_rows {
+                truncated = true;
+                break;
+            }
+
+            output.push(
+                (0..columns.len())
+                    .map(|i| row.get_ref(i).map(to_json))
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+
+        Ok((output, truncated))
+    })();
+
+    drop(done);
+    drop(statement);
+
+    // Nothing should be able to start a transaction, but if one was, it's
+    // rolled back rather than left open on the connection, pinning the WAL
+    if !conn.is_autocommit() {
+        drop(conn.execute_batch("ROLLBACK"));
+        return Err(QueryError::NotReadOnly);
+    }
+
+    let (rows, truncated) = result.map_err(|err| {
+        if err.sqlite_error_code() == Some(rusqlite::ErrorCode::OperationInterrupted) {
+            QueryError::Timeout(timeout)
+        } else {
+            QueryError::Sqlite(err)
+        }
+    })?;
+
+    Ok(QueryOutput {
+        columns,
+        rows,
+        truncated,
+        elapsed_ms: start.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+/// Converts a SQLite value to JSON, blobs are base64 encoded
+fn to_json(value: rusqlite::types::ValueRef<'_>) -> serde_json::Value {
+    use rusqlite::types::ValueRef;
+
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).into(),
+        ValueRef::Blob(b) => data_encoding::BASE64.encode(b).into(),
+    }
+}
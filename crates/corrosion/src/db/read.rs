@@ -1,5 +1,6 @@
 //! Deserialization of changes sent from a corrosion agent
 
+use crate::db::Metadata;
 pub use corro_api_types::{QueryEvent, SqliteValue};
 use eyre::{ContextCompat as _, WrapErr as _};
 use quilkin_types::{AddressKind, Endpoint, IcaoCode, TokenSet};
@@ -23,6 +24,8 @@ pub struct ServerRow {
     pub endpoint: Endpoint,
     pub icao: IcaoCode,
     pub tokens: TokenSet,
+    pub metadata: Metadata,
+    pub locality: Option<String>,
 }
 
 pub fn deserialize_token_set(s: Option<&str>) -> eyre::Result<TokenSet> {
@@ -72,6 +75,17 @@ pub fn parse_endpoint(addr: &str) -> eyre::Result<Endpoint> {
     }
 }
 
+/// Parses the JSON object of additional server metadata
+#[inline]
+pub fn deserialize_metadata(s: Option<&str>) -> eyre::Result<Metadata> {
+    match s {
+        Some(s) if !s.is_empty() => {
+            serde_json::from_str(s).wrap_err("server metadata was not a JSON object")
+        }
+        _ => Ok(Metadata::new()),
+    }
+}
+
 macro_rules! get_column {
     ($index:expr, $name:literal, $v:expr) => {
         $v.get($index)
@@ -105,10 +119,21 @@ impl FromSqlValue for ServerRow {
             get_column!(1, "icao", values).map_or(Ok(IcaoCode::default()), IcaoCode::from_str)?;
         let tokens = deserialize_token_set(get_column!(2, "tokens", values))?;
 
+        // The metadata and locality columns are optional so that queries that
+        // only select the original columns can still be parsed
+        let metadata = deserialize_metadata(values.get(3).and_then(|v| v.as_str()))?;
+        let locality = values
+            .get(4)
+            .and_then(|v| v.as_str())
+            .filter(|l| !l.is_empty())
+            .map(String::from);
+
         Ok(Self {
             endpoint,
             icao,
             tokens,
+            metadata,
+            locality,
         })
     }
 }
@@ -135,6 +160,16 @@ impl<'de> Deserialize<'de> for ServerRow {
                 let icao = get_json!("icao", IcaoCode::from_str, seq);
                 let tokens = get_json!("tokens", deserialize_token_set, seq);
 
+                // Unlike the preceding columns, metadata and locality are
+                // optional, and may contain escaped characters so can't be borrowed
+                let metadata = seq.next_element::<Option<String>>()?.flatten();
+                let metadata =
+                    deserialize_metadata(metadata.as_deref()).map_err(de::Error::custom)?;
+                let locality = seq
+                    .next_element::<Option<String>>()?
+                    .flatten()
+                    .filter(|l| !l.is_empty());
+
                 // Ignore the rest of the elements, if we don't we'll leave
                 // the deserializer with tokens that will cause an error
                 while let Some(Ignore) = seq.next_element()? {}
@@ -143,6 +178,8 @@ impl<'de> Deserialize<'de> for ServerRow {
                     endpoint,
                     icao,
                     tokens,
+                    metadata,
+                    locality,
                 })
             }
         }
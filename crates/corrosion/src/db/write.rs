@@ -3,6 +3,7 @@
 use crate::{
     Peer,
     api::{SqliteParam, Statement},
+    db::{FilterScope, Metadata},
 };
 use quilkin_types::{AddressKind, Endpoint, IcaoCode, TokenSet};
 use rusqlite::Transaction;
@@ -73,6 +74,31 @@ impl ToSqlParam for TokenSet {
     }
 }
 
+impl ToSqlParam for Metadata {
+    /// Converts metadata to a JSON object string, or `NULL` if there is none
+    fn to_sql(&self) -> SqliteParam {
+        if self.is_empty() {
+            return SqliteParam::Null;
+        }
+
+        SqliteParam::Text(
+            serde_json::to_string(self)
+                .expect("JSON object serialization is infallible")
+                .into(),
+        )
+    }
+}
+
+/// Converts an optional locality to a SQL parameter, empty localities are
+/// stored as `NULL`
+#[inline]
+fn locality_to_sql(locality: Option<&str>) -> SqliteParam {
+    match locality {
+        Some(l) if !l.is_empty() => SqliteParam::Text(l.into()),
+        _ => SqliteParam::Null,
+    }
+}
+
 impl ToSqlParam for IcaoCode {
     fn to_sql(&self) -> SqliteParam {
         SqliteParam::Text(self.as_ref().into())
@@ -130,25 +156,38 @@ impl<'s, const N: usize> Server<'s, N> {
 
     /// Create a statement to insert a new server
     #[inline]
-    pub fn upsert(&mut self, endpoint: &Endpoint, icao: IcaoCode, tokens: &TokenSet) {
+    pub fn upsert(
+        &mut self,
+        endpoint: &Endpoint,
+        icao: IcaoCode,
+        tokens: &TokenSet,
+        metadata: &Metadata,
+        locality: Option<&str>,
+    ) {
         let peer_ip = self.peer.ip().to_string();
         let server = to_compact_str(endpoint);
 
         self.statements.push(Statement::WithParams(
-            "INSERT INTO servers (endpoint,icao,tokens,contributors,cont_update) VALUES (?,?,?,jsonb(json_object(?,json_object())),unixepoch('now'))
+            "INSERT INTO servers (endpoint,icao,tokens,metadata,locality,contributors,cont_update) VALUES (?,?,?,?,?,jsonb(json_object(?,json_object())),unixepoch('now'))
             ON CONFLICT(endpoint) DO UPDATE SET
                 contributors = jsonb_patch(contributors,json_object(?,json_object())),
                 cont_update = unixepoch('now'),
                 icao = ?,
-                tokens = ?".into(),
+                tokens = ?,
+                metadata = ?,
+                locality = ?".into(),
             vec![
                 endpoint.to_sql(),
                 icao.to_sql(),
                 tokens.to_sql(),
+                metadata.to_sql(),
+                locality_to_sql(locality),
                 peer_ip.clone().into(),
                 peer_ip.clone().into(),
                 icao.to_sql(),
                 tokens.to_sql(),
+                metadata.to_sql(),
+                locality_to_sql(locality),
             ],
         ));
 
@@ -213,34 +252,50 @@ impl<'s, const N: usize> Server<'s, N> {
     }
 
     /// Create a statement to update one or more server columns
+    ///
+    /// An empty locality clears the locality of the server
     pub fn update(
         &mut self,
         endpoint: &Endpoint,
         icao: Option<IcaoCode>,
         tokens: Option<&TokenSet>,
+        metadata: Option<&Metadata>,
+        locality: Option<&str>,
     ) {
-        if icao.is_none() && tokens.is_none() {
-            tracing::warn!("must update either icao or token set");
+        if icao.is_none() && tokens.is_none() && metadata.is_none() && locality.is_none() {
+            tracing::warn!("must update at least one of icao, token set, metadata, or locality");
             return;
         }
 
         let mut query = String::with_capacity(128);
         query.push_str("UPDATE servers SET ");
 
-        let mut params = Vec::with_capacity(3);
+        let mut params = Vec::with_capacity(5);
+
+        let mut set = |column: &str, param: SqliteParam| {
+            if !params.is_empty() {
+                query.push_str(", ");
+            }
+
+            query.push_str(column);
+            query.push_str(" = ?");
+            params.push(param);
+        };
 
         if let Some(icao) = icao {
-            query.push_str("icao = ?");
-            params.push(SqliteParam::Text(icao.as_ref().into()));
+            set("icao", SqliteParam::Text(icao.as_ref().into()));
         }
 
         if let Some(ts) = tokens {
-            if !params.is_empty() {
-                query.push_str(", ");
-            }
+            set("tokens", ts.to_sql());
+        }
 
-            query.push_str("tokens = ?");
-            params.push(ts.to_sql());
+        if let Some(md) = metadata {
+            set("metadata", md.to_sql());
+        }
+
+        if locality.is_some() {
+            set("locality", locality_to_sql(locality));
         }
 
         // We know we are only updating one row, so ideally we would just stick
@@ -352,11 +407,32 @@ impl<const N: usize> Datacenter<'_, N> {
 pub struct Filter<'s, const N: usize>(pub &'s mut smallvec::SmallVec<[Statement; N]>);
 
 impl<const N: usize> Filter<'_, N> {
+    /// Sets the filter chain for the scope
+    ///
+    /// The [`FilterScope::Global`] chain is also written to the legacy single
+    /// row `filter` table for proxies that predate scoped filter chains
+    #[inline]
+    pub fn upsert(&mut self, scope: &FilterScope, filter: &str) {
+        if *scope == FilterScope::Global {
+            self.0.push(Statement::WithParams(
+                "INSERT INTO filter (id,filter) VALUES (9999,?) ON CONFLICT(id) DO UPDATE SET filter = excluded.filter".into(),
+                vec![SqliteParam::Text(filter.into())]
+            ));
+        }
+
+        self.0.push(Statement::WithParams(
+            "INSERT INTO filters (scope,filter) VALUES (?,?) ON CONFLICT(scope) DO UPDATE SET filter = excluded.filter".into(),
+            vec![SqliteParam::Text(scope.to_string().into()), SqliteParam::Text(filter.into())]
+        ));
+    }
+
+    /// Removes the filter chain for the scope, proxies in the scope will use
+    /// the chain of the next most specific scope instead
     #[inline]
-    pub fn upsert(&mut self, filter: &str) {
+    pub fn remove(&mut self, scope: &FilterScope) {
         self.0.push(Statement::WithParams(
-            "INSERT INTO filter (id,filter) VALUES (9999,?) ON CONFLICT(id) DO UPDATE SET filter = excluded.filter".into(),
-            vec![SqliteParam::Text(filter.into())]
+            "DELETE FROM filters WHERE scope = ?".into(),
+            vec![SqliteParam::Text(scope.to_string().into())],
         ));
     }
 }
@@ -1,6 +1,7 @@
 pub mod changes;
 pub mod handler;
 pub mod metrics;
+pub mod status;
 pub mod swim;
 pub mod sync;
 pub mod transport;
@@ -63,10 +64,13 @@ pub struct GossipContext {
     pub changes_tx: Sender<Change>,
     pub bookie: corro_types::bookie::Bookie,
     pub cluster_id: corro_types::actor::ClusterId,
+    /// Our schema version, sent to peers when they connect
+    pub schema: crate::schema::SchemaVersion,
     pub actor_id: corro_types::actor::ActorId,
     pub clock: crate::Clock,
     pub sync_permits: Arc<tokio::sync::Semaphore>,
     pub pool: corro_types::agent::SplitPool,
+    pub peer_syncs: status::PeerSyncs,
 }
 
 struct StreamMetrics {
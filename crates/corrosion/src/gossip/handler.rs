@@ -8,11 +8,43 @@ use tripwire::{Outcome, PreemptibleFutureExt as _, TimeoutFutureExt as _, Tripwi
 
 /// Spawn a tree of tasks that handles incoming gossip server connections, streams, and their respective payloads.
 pub fn spawn_gossipserver_unencrypted(
+    ctx: GossipContext,
+    tripwire: Tripwire,
+    gossip_server_endpoint: SocketAddr,
+) -> std::io::Result<()> {
+    spawn_gossipserver_with(
+        ctx,
+        tripwire,
+        gossip_server_endpoint,
+        quinn_plaintext::server_config(),
+        None,
+    )
+}
+
+/// Spawns a gossip server that requires peers to authenticate with a certificate
+/// signed by the CA in `tls`, see [`spawn_gossipserver_unencrypted`]
+pub fn spawn_gossipserver(
+    ctx: GossipContext,
+    tripwire: Tripwire,
+    gossip_server_endpoint: SocketAddr,
+    tls: &crate::tls::TlsConfig,
+) -> std::io::Result<()> {
+    spawn_gossipserver_with(
+        ctx,
+        tripwire,
+        gossip_server_endpoint,
+        tls.server_config(),
+        Some(tls.clone()),
+    )
+}
+
+fn spawn_gossipserver_with(
     ctx: GossipContext,
     mut tripwire: Tripwire,
     gossip_server_endpoint: SocketAddr,
+    mut cfg: quinn::ServerConfig,
+    tls: Option<crate::tls::TlsConfig>,
 ) -> std::io::Result<()> {
-    let mut cfg = quinn_plaintext::server_config();
     let mut tcfg = quinn::TransportConfig::default();
 
     // Note this is the same as the current default quinn idle timeout, but we specify it here again in case the default
@@ -32,7 +64,12 @@ pub fn spawn_gossipserver_unencrypted(
                 Outcome::Preempted(_) => break,
             };
 
-            spawn_incoming_connection_handlers(ctx.clone(), tripwire.clone(), incoming);
+            spawn_incoming_connection_handlers(
+                ctx.clone(),
+                tripwire.clone(),
+                incoming,
+                tls.clone(),
+            );
         }
 
         // graceful shutdown
@@ -67,6 +104,7 @@ fn spawn_incoming_connection_handlers(
     ctx: GossipContext,
     mut tripwire: Tripwire,
     connecting: quinn::Incoming,
+    tls: Option<crate::tls::TlsConfig>,
 ) {
     let remote_addr = connecting.remote_address();
 
@@ -83,6 +121,14 @@ fn spawn_incoming_connection_handlers(
                 }
             };
 
+            if let Some(tls) = &tls
+                && let Err(error) = tls.verify_peer(&conn)
+            {
+                tracing::warn!(%error, "rejected gossip connection");
+                ctx.metrics.server_peer_rejected_inc();
+                return;
+            }
+
             tracing::trace!("accepted a gossip connection");
             ctx.metrics.server_conns_inc();
 
@@ -96,7 +142,7 @@ fn spawn_incoming_connection_handlers(
                         process_broadcast_stream(ctx.clone(), uni, remote_addr);
                     }
                     bi = conn.accept_bi() => {
-                        process_sync_stream(ctx.clone(), bi, remote_addr);
+                        process_sync_stream(ctx.clone(), conn.clone(), bi, remote_addr);
                     }
                     _ = &mut tripwire => {
                         tracing::debug!("connection cancelled");
@@ -191,10 +237,11 @@ fn process_broadcast_stream(
 
 fn process_sync_stream(
     ctx: GossipContext,
+    conn: quinn::Connection,
     stream: Result<(quinn::SendStream, quinn::RecvStream), quinn::ConnectionError>,
     remote_addr: std::net::SocketAddr,
 ) {
-    let (send, recv) = match stream {
+    let (mut send, recv) = match stream {
         Ok(r) => r,
         Err(error) => {
             ctx.metrics
@@ -237,6 +284,35 @@ fn process_sync_stream(
 
                 stream_metrics.incoming(&frame, super::transport::TrafficClass::Sync);
 
+                // Peers send their schema version on a separate stream when
+                // they connect, which we respond to with our own
+                if let Some(peer) = crate::schema::SchemaVersion::decode(&frame) {
+                    let compatible = ctx.schema.is_compatible(peer);
+                    if !compatible {
+                        tracing::warn!(local = ?ctx.schema, ?peer, "rejecting peer with an incompatible schema version");
+                    }
+
+                    let hello = ctx.schema.encode();
+                    if let Err(error) = send.write_all(&(hello.len() as u32).to_be_bytes()).await {
+                        tracing::debug!(%error, "failed to send schema version");
+                        return;
+                    }
+                    if let Err(error) = send.write_all(&hello).await {
+                        tracing::debug!(%error, "failed to send schema version");
+                        return;
+                    }
+                    let _dont_care = send.finish();
+
+                    // Let the peer read our version so it knows why, then
+                    // close the connection so that we neither send changes
+                    // to nor receive changes from it
+                    if !compatible {
+                        let _dont_care = tokio::time::timeout(Duration::from_secs(5), send.stopped()).await;
+                        conn.close(1u32.into(), b"incompatible schema version");
+                    }
+                    break;
+                }
+
                 match broadcast::BiPayload::read_from_buffer(&frame) {
                     Ok(broadcast::BiPayload::V1 {
                         data:
@@ -247,7 +323,11 @@ fn process_sync_stream(
                         cluster_id,
                     }) => {
                         let span = tracing::trace_span!("sync", local_actor = %ctx.actor_id, remote_actor = %actor_id);
-                        if let Err(error) = super::sync::serve_sync(ctx, stream_metrics, actor_id, cluster_id, trace_ctx, framed, send).instrument(span).await {
+                        let peer_syncs = ctx.peer_syncs.clone();
+                        let res = super::sync::serve_sync(ctx, stream_metrics, actor_id, cluster_id, trace_ctx, framed, send).instrument(span).await;
+                        peer_syncs.record(actor_id, &res);
+
+                        if let Err(error) = res {
                             tracing::warn!(%error, "failed to complete sync");
                         }
 
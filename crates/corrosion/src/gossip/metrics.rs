@@ -465,7 +465,14 @@ impl GossipMetrics {
     #[inline]
     pub fn server_handshakes_failed_inc(&self, error: &quinn::ConnectionError) {
         self.failed_handshakes
-            .with_label_values(&[connection_error_to_string(error)])
+            .with_label_values(&[crate::tls::handshake_failure_cause(error)])
+            .inc();
+    }
+
+    #[inline]
+    pub fn server_peer_rejected_inc(&self) {
+        self.failed_handshakes
+            .with_label_values(&["peer_not_allowed"])
             .inc();
     }
 
@@ -0,0 +1,260 @@
+//! Introspection of the SWIM membership and sync state of the local node
+//!
+//! The [`GossipMetrics`](super::GossipMetrics) give an aggregate view of the
+//! cluster, this instead gives the current state of each individual member,
+//! which is needed to debug eg. split-brain or slow convergence
+
+use corro_types::{
+    actor::{Actor, ActorId, ClusterId},
+    broadcast::{FocaCmd, FocaInput},
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+/// The SWIM state of a cluster member
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberState {
+    Alive,
+    Suspect,
+    Down,
+}
+
+impl From<foca::State> for MemberState {
+    fn from(state: foca::State) -> Self {
+        match state {
+            foca::State::Alive => Self::Alive,
+            foca::State::Suspect => Self::Suspect,
+            foca::State::Down => Self::Down,
+        }
+    }
+}
+
+impl std::fmt::Display for MemberState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Alive => "alive",
+            Self::Suspect => "suspect",
+            Self::Down => "down",
+        })
+    }
+}
+
+/// A member of the cluster, as known by the local node
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct MemberStatus {
+    pub actor_id: ActorId,
+    pub address: SocketAddr,
+    pub state: MemberState,
+    pub incarnation: u16,
+    /// Whether the member is in the closest ring, local broadcasts are sent
+    /// to every member in it
+    pub ring0: bool,
+    /// The minimum of the recently sampled round trip times, in milliseconds
+    pub rtt_min_ms: Option<u64>,
+    /// The mean of the recently sampled round trip times, in milliseconds
+    pub rtt_avg_ms: Option<f64>,
+}
+
+/// The sync state for an actor, either a member of the cluster or an actor
+/// whose changes have been received from another member
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct SyncStatus {
+    pub actor_id: ActorId,
+    /// The latest version known for the actor
+    pub head: Option<u64>,
+    /// The number of versions from the actor, up to `head`, that have not
+    /// been received yet
+    pub needed_versions: u64,
+    /// The number of versions that have only been partially received
+    pub incomplete_partials: usize,
+    /// The last time the actor synced from the local node
+    pub last_served: Option<ServedSync>,
+}
+
+/// A sync the local node served to a peer
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ServedSync {
+    /// How long ago the sync finished, in seconds
+    pub secs_ago: f64,
+    /// The number of versions the peer was missing and requested
+    pub requested_versions: usize,
+    /// The error the sync failed with, if any
+    pub error: Option<String>,
+}
+
+/// The membership and sync state of the local node
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct GossipStatus {
+    pub actor_id: ActorId,
+    pub cluster_id: ClusterId,
+    pub members: Vec<MemberStatus>,
+    pub sync: Vec<SyncStatus>,
+}
+
+#[derive(Clone)]
+struct PeerSync {
+    at: Instant,
+    requested_versions: usize,
+    error: Option<String>,
+}
+
+/// Tracks the last sync served to each peer
+#[derive(Clone, Default)]
+pub struct PeerSyncs(Arc<parking_lot::Mutex<BTreeMap<ActorId, PeerSync>>>);
+
+impl PeerSyncs {
+    /// Records the outcome of a sync served to `actor_id`
+    pub fn record(&self, actor_id: ActorId, result: &Result<usize, super::sync::SyncError>) {
+        let (requested_versions, error) = match result {
+            Ok(count) => (*count, None),
+            Err(error) => (0, Some(error.to_string())),
+        };
+
+        self.0.lock().insert(
+            actor_id,
+            PeerSync {
+                at: Instant::now(),
+                requested_versions,
+                error,
+            },
+        );
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StatusError {
+    #[error("the SWIM loop is not running")]
+    SwimStopped,
+    #[error("timed out waiting for the SWIM membership states")]
+    Timeout,
+}
+
+/// A handle used to retrieve the [`GossipStatus`] of the running gossip
+/// service
+#[derive(Clone)]
+pub struct Introspect {
+    pub foca_tx: mpsc::Sender<FocaInput>,
+    pub members: super::Members,
+    pub bookie: corro_types::bookie::Bookie,
+    pub peer_syncs: PeerSyncs,
+    pub actor_id: ActorId,
+    pub cluster_id: ClusterId,
+}
+
+impl Introspect {
+    /// The maximum time to wait for the SWIM loop to report the membership
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Retrieves the current membership and sync state
+    pub async fn status(&self) -> Result<GossipStatus, StatusError> {
+        let (tx, mut rx) = mpsc::channel(64);
+        self.foca_tx
+            .send(FocaInput::Cmd(FocaCmd::MembershipStates(tx)))
+            .await
+            .map_err(|_| StatusError::SwimStopped)?;
+
+        // The sender is dropped by the SWIM loop once every member is sent
+        let states = tokio::time::timeout(Self::TIMEOUT, async move {
+            // Foca can know of multiple identities for the same actor if it
+            // has rejoined, only the latest one is relevant
+            let mut states = Vec::<foca::Member<Actor>>::new();
+            while let Some(member) = rx.recv().await {
+                match states
+                    .iter_mut()
+                    .find(|existing| existing.id().id() == member.id().id())
+                {
+                    Some(existing) if existing.id().ts() < member.id().ts() => *existing = member,
+                    Some(_) => {}
+                    None => states.push(member),
+                }
+            }
+            states
+        })
+        .await
+        .map_err(|_| StatusError::Timeout)?;
+
+        let mut members = {
+            let members = self.members.0.read();
+            let ring0 = members.ring0(self.cluster_id).collect::<HashSet<_>>();
+
+            states
+                .into_iter()
+                .map(|member| {
+                    let address = member.id().addr();
+                    let rtts = members.rtts.get(&address);
+
+                    MemberStatus {
+                        actor_id: member.id().id(),
+                        address,
+                        state: member.state().into(),
+                        incarnation: member.incarnation(),
+                        ring0: ring0.contains(&address),
+                        rtt_min_ms: rtts.and_then(|rtts| rtts.buf.iter().min().copied()),
+                        rtt_avg_ms: rtts.filter(|rtts| !rtts.buf.is_empty()).map(|rtts| {
+                            rtts.buf.iter().sum::<u64>() as f64 / rtts.buf.len() as f64
+                        }),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        members.sort_by_key(|member| member.actor_id);
+
+        let mut sync = BTreeMap::<ActorId, SyncStatus>::new();
+
+        {
+            let guard = self.bookie.owned_guard();
+            for (&actor_id, booked) in self.bookie.iter(&guard) {
+                let booked = booked.read();
+
+                sync.insert(
+                    actor_id,
+                    SyncStatus {
+                        actor_id,
+                        head: booked.last().map(|v| v.0),
+                        needed_versions: booked
+                            .needed()
+                            .iter()
+                            .map(|r| r.end().0 - r.start().0 + 1)
+                            .sum(),
+                        incomplete_partials: booked
+                            .partials
+                            .values()
+                            .filter(|p| !p.is_complete())
+                            .count(),
+                        last_served: None,
+                    },
+                );
+            }
+        }
+
+        for (actor_id, ps) in self.peer_syncs.0.lock().iter() {
+            sync.entry(*actor_id)
+                .or_insert_with(|| SyncStatus {
+                    actor_id: *actor_id,
+                    head: None,
+                    needed_versions: 0,
+                    incomplete_partials: 0,
+                    last_served: None,
+                })
+                .last_served = Some(ServedSync {
+                secs_ago: ps.at.elapsed().as_secs_f64(),
+                requested_versions: ps.requested_versions,
+                error: ps.error.clone(),
+            });
+        }
+
+        Ok(GossipStatus {
+            actor_id: self.actor_id,
+            cluster_id: self.cluster_id,
+            members,
+            sync: sync.into_values().collect(),
+        })
+    }
+}
@@ -1,4 +1,5 @@
 use super::GossipMetrics;
+use crate::schema::SchemaVersion;
 use bytes::Bytes;
 use quinn::{self as q, Connection};
 use std::{
@@ -17,6 +18,8 @@ struct TransportInner {
     conns: RwLock<HashMap<SocketAddr, Arc<Mutex<Option<Connection>>>>>,
     rtt_tx: mpsc::Sender<(SocketAddr, Duration)>,
     metrics: &'static GossipMetrics,
+    tls: Option<crate::tls::TlsConfig>,
+    schema: SchemaVersion,
 }
 
 #[derive(Clone, Copy)]
@@ -51,6 +54,15 @@ pub enum TransportError {
     TimedOut(#[from] tokio::time::error::Elapsed),
     #[error(transparent)]
     Stopped(#[from] q::StoppedError),
+    #[error(transparent)]
+    Tls(#[from] crate::tls::TlsError),
+    #[error(transparent)]
+    Read(#[from] q::ReadToEndError),
+    #[error("peer schema version {peer:?} is incompatible with the local version {local:?}")]
+    IncompatibleSchema {
+        local: SchemaVersion,
+        peer: SchemaVersion,
+    },
 }
 
 impl Transport {
@@ -58,10 +70,42 @@ impl Transport {
     pub fn new_insecure(
         metrics: &'static GossipMetrics,
         rtt_tx: mpsc::Sender<(SocketAddr, Duration)>,
+        schema: SchemaVersion,
     ) -> eyre::Result<Self> {
-        let mut endpoint = q::Endpoint::client((std::net::Ipv6Addr::UNSPECIFIED, 0).into())?;
+        Self::with_config(
+            quinn_plaintext::client_config(),
+            None,
+            metrics,
+            rtt_tx,
+            schema,
+        )
+    }
+
+    /// Creates a new transport that authenticates itself, and the peers it
+    /// connects to, with the certificates in `tls`
+    pub fn new(
+        metrics: &'static GossipMetrics,
+        rtt_tx: mpsc::Sender<(SocketAddr, Duration)>,
+        schema: SchemaVersion,
+        tls: &crate::tls::TlsConfig,
+    ) -> eyre::Result<Self> {
+        Self::with_config(
+            tls.client_config(),
+            Some(tls.clone()),
+            metrics,
+            rtt_tx,
+            schema,
+        )
+    }
 
-        let mut cfg = quinn_plaintext::client_config();
+    fn with_config(
+        mut cfg: q::ClientConfig,
+        tls: Option<crate::tls::TlsConfig>,
+        metrics: &'static GossipMetrics,
+        rtt_tx: mpsc::Sender<(SocketAddr, Duration)>,
+        schema: SchemaVersion,
+    ) -> eyre::Result<Self> {
+        let mut endpoint = q::Endpoint::client((std::net::Ipv6Addr::UNSPECIFIED, 0).into())?;
 
         static TRANSPORT_CONFIG: std::sync::OnceLock<Arc<quinn::TransportConfig>> =
             std::sync::OnceLock::new();
@@ -83,6 +127,8 @@ impl Transport {
             conns: Default::default(),
             rtt_tx,
             metrics,
+            tls,
+            schema,
         })))
     }
 
@@ -177,11 +223,31 @@ impl Transport {
         .await
         {
             Ok(Ok(conn)) => {
+                if let Some(tls) = &self.0.tls
+                    && let Err(error) = tls.verify_peer(&conn)
+                {
+                    self.0
+                        .metrics
+                        .client_connect_error(traffic, "peer_not_allowed");
+                    return Err(error.into());
+                }
+
+                if let Err(error) = self.exchange_schema_version(&conn).await {
+                    let kind = if matches!(error, TransportError::IncompatibleSchema { .. }) {
+                        "incompatible_schema"
+                    } else {
+                        "schema_exchange"
+                    };
+                    self.0.metrics.client_connect_error(traffic, kind);
+                    conn.close(INCOMPATIBLE_SCHEMA, b"incompatible schema version");
+                    return Err(error);
+                }
+
                 self.0.metrics.client_connect_time(start.elapsed(), traffic);
                 Ok(conn)
             }
             Ok(Err(e)) => {
-                let err_str = match &e {
+                let err_str = crate::tls::tls_alert(&e).unwrap_or(match &e {
                     q::ConnectionError::VersionMismatch => "version_mismatch",
                     q::ConnectionError::ApplicationClosed(_) => "application_closed",
                     q::ConnectionError::ConnectionClosed(_) => "connection_closed",
@@ -190,7 +256,7 @@ impl Transport {
                     q::ConnectionError::Reset => "reset",
                     q::ConnectionError::TimedOut => "quic_timed_out",
                     q::ConnectionError::CidsExhausted => "cids_exhausted",
-                };
+                });
 
                 self.0.metrics.client_connect_error(traffic, err_str);
 
@@ -203,6 +269,48 @@ impl Transport {
         }
     }
 
+    /// Sends our schema version to the peer, and fails if the version it
+    /// responds with is incompatible with ours
+    ///
+    /// Peers that predate the exchange close the stream without responding,
+    /// and are assumed to be on [`SchemaVersion::LEGACY`]
+    async fn exchange_schema_version(&self, conn: &Connection) -> Result<(), TransportError> {
+        let local = self.0.schema;
+
+        let peer = tokio::time::timeout(Duration::from_secs(5), async {
+            let (mut send, mut recv) = conn.open_bi().await?;
+
+            // The server reads the stream with a length delimited codec
+            let hello = local.encode();
+            send.write_all(&(hello.len() as u32).to_be_bytes()).await?;
+            send.write_all(&hello).await?;
+            let _dont_care = send.finish();
+
+            let response = recv.read_to_end(64).await?;
+            Ok::<_, TransportError>(
+                response
+                    .get(4..)
+                    .and_then(SchemaVersion::decode)
+                    .unwrap_or(SchemaVersion::LEGACY),
+            )
+        })
+        .await??;
+
+        if !local.is_compatible(peer) {
+            return Err(TransportError::IncompatibleSchema { local, peer });
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn server_name(&self, addr: SocketAddr) -> String {
+        self.0
+            .tls
+            .as_ref()
+            .map_or_else(|| addr.ip().to_string(), |tls| tls.server_name(addr))
+    }
+
     // this shouldn't block for long...
     async fn get_lock(&self, addr: SocketAddr) -> Arc<Mutex<Option<Connection>>> {
         {
@@ -238,7 +346,7 @@ impl Transport {
         *lock = None;
 
         let conn = self
-            .measured_connect(addr, addr.ip().to_string(), traffic)
+            .measured_connect(addr, self.server_name(addr), traffic)
             .await?;
         *lock = Some(conn.clone());
         Ok(conn)
@@ -246,6 +354,8 @@ impl Transport {
 }
 
 const NO_ERROR: q::VarInt = q::VarInt::from_u32(0);
+/// The code a connection is closed with when the peer's schema is incompatible
+pub(crate) const INCOMPATIBLE_SCHEMA: q::VarInt = q::VarInt::from_u32(1);
 
 fn datagram_error_kind(e: &q::SendDatagramError) -> &'static str {
     match e {
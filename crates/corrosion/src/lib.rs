@@ -9,6 +9,7 @@ pub mod metrics;
 pub mod persistent;
 pub mod pubsub;
 pub mod schema;
+pub mod tls;
 
 pub use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
 
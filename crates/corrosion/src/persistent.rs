@@ -17,6 +17,7 @@ pub struct Metrics {
     tx_bytes: prometheus::IntCounterVec,
     rx_count: prometheus::IntCounterVec,
     rx_bytes: prometheus::IntCounterVec,
+    failed_handshakes: prometheus::IntCounterVec,
 }
 
 impl Metrics {
@@ -69,6 +70,15 @@ impl Metrics {
                 registry,
             }
             .unwrap();
+            let failed_handshakes = prometheus::register_int_counter_vec_with_registry! {
+                prometheus::opts! {
+                    "corrosion_failed_handshakes",
+                    "Number of connections that failed to be established, by cause",
+                },
+                &["cause"],
+                registry,
+            }
+            .unwrap();
 
             Self {
                 active,
@@ -76,10 +86,16 @@ impl Metrics {
                 tx_bytes,
                 rx_count,
                 rx_bytes,
+                failed_handshakes,
             }
         })
         .clone()
     }
+
+    #[inline]
+    fn handshake_failed(&self, cause: &str) {
+        self.failed_handshakes.with_label_values(&[cause]).inc();
+    }
 }
 
 pub struct SubMetrics {
@@ -73,6 +73,8 @@ pub enum ConnectError {
     Write(#[from] StreamError),
     #[error(transparent)]
     Proto(#[from] proto::Error),
+    #[error(transparent)]
+    Tls(#[from] crate::tls::TlsError),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -105,16 +107,56 @@ impl Client {
         addr: SocketAddr,
         local: SocketAddr,
         metrics: super::Metrics,
+    ) -> Result<Self, ConnectError> {
+        Self::connect_with(
+            addr,
+            local,
+            quinn_plaintext::client_config(),
+            &addr.ip().to_string(),
+            metrics,
+        )
+        .await
+    }
+
+    /// Connects using a mutually authenticated TLS session
+    pub async fn connect(
+        addr: SocketAddr,
+        metrics: super::Metrics,
+        tls: &crate::tls::TlsConfig,
+    ) -> Result<Self, ConnectError> {
+        let this = Self::connect_with(
+            addr,
+            (std::net::Ipv6Addr::UNSPECIFIED, 0).into(),
+            tls.client_config(),
+            &tls.server_name(addr),
+            metrics.clone(),
+        )
+        .await?;
+
+        if let Err(error) = tls.verify_peer(&this.conn) {
+            metrics.handshake_failed("peer_not_allowed");
+            return Err(error.into());
+        }
+
+        Ok(this)
+    }
+
+    async fn connect_with(
+        addr: SocketAddr,
+        local: SocketAddr,
+        config: quinn::ClientConfig,
+        server_name: &str,
+        metrics: super::Metrics,
     ) -> Result<Self, ConnectError> {
         let ep = quinn::Endpoint::client(local)?;
 
-        let conn = ep
-            .connect_with(
-                quinn_plaintext::client_config(),
-                addr,
-                &addr.ip().to_string(),
-            )?
-            .await?;
+        let conn = match ep.connect_with(config, addr, server_name)?.await {
+            Ok(conn) => conn,
+            Err(error) => {
+                metrics.handshake_failed(crate::tls::handshake_failure_cause(&error));
+                return Err(error.into());
+            }
+        };
 
         let local_addr = ep.local_addr()?;
 
@@ -311,7 +311,13 @@ impl super::server::DbMutator for BroadcastingTransactor {
                 p::ServerChange::Upsert(i) => {
                     let mut srv = db::write::Server::for_peer(peer, &mut v);
                     for i in i {
-                        srv.upsert(&i.endpoint, i.icao, &i.tokens);
+                        srv.upsert(
+                            &i.endpoint,
+                            i.icao,
+                            &i.tokens,
+                            &i.metadata,
+                            i.locality.as_deref(),
+                        );
                     }
                 }
                 p::ServerChange::Remove(r) => {
@@ -323,7 +329,13 @@ impl super::server::DbMutator for BroadcastingTransactor {
                 p::ServerChange::Update(u) => {
                     let mut srv = db::write::Server::for_peer(peer, &mut v);
                     for u in u {
-                        srv.update(&u.endpoint, u.icao, u.tokens.as_ref());
+                        srv.update(
+                            &u.endpoint,
+                            u.icao,
+                            u.tokens.as_ref(),
+                            u.metadata.as_ref(),
+                            u.locality.as_deref(),
+                        );
                     }
                 }
                 p::ServerChange::UpdateMutator(mu) => {
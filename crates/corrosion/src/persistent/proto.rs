@@ -204,6 +204,16 @@ pub mod v1 {
         /// The server's token set
         #[serde(rename = "t")]
         pub tokens: TokenSet,
+        /// Additional metadata for the server
+        #[serde(
+            rename = "m",
+            default,
+            skip_serializing_if = "serde_json::Map::is_empty"
+        )]
+        pub metadata: crate::db::Metadata,
+        /// The locality of the server, in `region:zone:sub_zone` form
+        #[serde(rename = "l", default, skip_serializing_if = "Option::is_none")]
+        pub locality: Option<String>,
     }
 
     /// A DB mutation request to update a server
@@ -218,6 +228,12 @@ pub mod v1 {
         /// If present, updates the server's token set
         #[serde(rename = "t", default, skip_serializing_if = "Option::is_none")]
         pub tokens: Option<TokenSet>,
+        /// If present, replaces the server's metadata
+        #[serde(rename = "m", default, skip_serializing_if = "Option::is_none")]
+        pub metadata: Option<crate::db::Metadata>,
+        /// If present, updates the server's locality, an empty locality clears it
+        #[serde(rename = "l", default, skip_serializing_if = "Option::is_none")]
+        pub locality: Option<String>,
     }
 
     /// Updates the details for the mutator connected to the server
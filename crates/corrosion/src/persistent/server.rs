@@ -132,7 +132,43 @@ impl Server {
         subs: PubsubContext,
         metrics: super::Metrics,
     ) -> std::io::Result<Self> {
-        let mut sc = quinn_plaintext::server_config();
+        Self::spawn(
+            quinn_plaintext::server_config(),
+            None,
+            addr,
+            mutator,
+            subs,
+            metrics,
+        )
+    }
+
+    /// Creates a server that requires clients to authenticate with a
+    /// certificate signed by the CA in `tls`
+    pub fn new(
+        addr: SocketAddr,
+        mutator: impl DbMutator + 'static,
+        subs: PubsubContext,
+        metrics: super::Metrics,
+        tls: &crate::tls::TlsConfig,
+    ) -> std::io::Result<Self> {
+        Self::spawn(
+            tls.server_config(),
+            Some(tls.clone()),
+            addr,
+            mutator,
+            subs,
+            metrics,
+        )
+    }
+
+    fn spawn(
+        mut sc: quinn::ServerConfig,
+        tls: Option<crate::tls::TlsConfig>,
+        addr: SocketAddr,
+        mutator: impl DbMutator + 'static,
+        subs: PubsubContext,
+        metrics: super::Metrics,
+    ) -> std::io::Result<Self> {
         sc.transport_config(std::sync::Arc::new(Self::transport_config()));
 
         let endpoint = quinn::Endpoint::server(sc, addr)?;
@@ -152,6 +188,7 @@ impl Server {
                 let mutator = mutator.clone();
                 let usbs = subs.clone();
                 let metrics = metrics.clone();
+                let tls = tls.clone();
 
                 tokio::spawn(async move {
                     let peer = match inc.remote_address().ip() {
@@ -166,10 +203,19 @@ impl Server {
                         Ok(c) => c,
                         Err(error) => {
                             tracing::warn!(%error, "failed to establish connection from remote peer");
+                            metrics.handshake_failed(crate::tls::handshake_failure_cause(&error));
                             return;
                         }
                     };
 
+                    if let Some(tls) = &tls
+                        && let Err(error) = tls.verify_peer(&connection)
+                    {
+                        tracing::warn!(%error, "rejected connection from remote peer");
+                        metrics.handshake_failed("peer_not_allowed");
+                        return;
+                    }
+
                     metrics.active.with_label_values::<&str>(&[]).inc();
 
                     // Periodically update the tx/rx stats of this connection
@@ -69,10 +69,27 @@ pub enum CatchUpError {
 
 pub type BodySender = mpsc::Sender<Bytes>;
 
-pub const SERVER_QUERY: &str = "SELECT endpoint,icao,tokens FROM servers";
+pub const SERVER_QUERY: &str = "SELECT endpoint,icao,tokens,metadata,locality FROM servers";
 pub const DC_QUERY: &str = "SELECT ip,port,icao FROM dc";
+/// The query for the single global filter chain, used by proxies that predate
+/// [`scoped_filter_query`]
 pub const FILTER_QUERY: &str = "SELECT filter FROM filter";
 
+/// The query for the filter chains of the specified scopes
+pub fn scoped_filter_query(scopes: &[crate::db::FilterScope]) -> String {
+    let mut query = String::from("SELECT scope,filter FROM filters WHERE scope IN (");
+    for (i, scope) in scopes.iter().enumerate() {
+        if i > 0 {
+            query.push(',');
+        }
+        query.push('\'');
+        query.push_str(&scope.to_string().replace('\'', "''"));
+        query.push('\'');
+    }
+    query.push(')');
+    query
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SubParamsv1 {
     /// The query being subscribed to
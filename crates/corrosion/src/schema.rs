@@ -1,4 +1,114 @@
-pub const SCHEMA: &str = r#"
+//! The versioned schema of the corrosion database
+//!
+//! Each [`Migration`] contains the full definitions of the tables it creates or
+//! changes, which are applied on top of the schema of the previous version.
+//! Migrations are applied in order at startup via `cr-sqlite`'s alter support,
+//! so they are limited to the changes it supports, eg. adding tables, nullable
+//! columns, and indices.
+
+/// A single schema migration
+#[derive(Copy, Clone, Debug)]
+pub struct Migration {
+    /// The schema version after this migration has been applied
+    pub version: u16,
+    /// A short description of the changes in this migration
+    pub description: &'static str,
+    /// The full definitions of the tables created or changed in this version
+    pub sql: &'static str,
+}
+
+/// The ordered set of migrations, the last of which is the current version
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: V1,
+    },
+    Migration {
+        version: 2,
+        description: "add metadata and locality to servers",
+        sql: V2,
+    },
+    Migration {
+        version: 3,
+        description: "add filter chains scoped by ICAO code or locality",
+        sql: V3,
+    },
+];
+
+/// The current schema version
+pub const VERSION: u16 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// The oldest schema version that nodes on the [`VERSION`] can gossip with
+///
+/// This should only be bumped by migrations that older versions can't apply
+/// the changes of, eg. ones that change the meaning of an existing column, or
+/// add columns to a table that older versions would receive changes for
+///
+/// V2 added the `metadata` and `locality` columns to `servers`
+pub const MIN_COMPATIBLE_VERSION: u16 = 2;
+
+/// The schema version of a gossip node, exchanged when connecting to a peer
+/// so that nodes with incompatible schemas don't gossip with each other
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SchemaVersion {
+    /// The schema version of the node
+    pub version: u16,
+    /// The oldest schema version the node can gossip with
+    pub min_compatible: u16,
+}
+
+impl SchemaVersion {
+    /// The schema version of this build
+    pub const CURRENT: Self = Self {
+        version: VERSION,
+        min_compatible: MIN_COMPATIBLE_VERSION,
+    };
+
+    /// The schema version assumed for peers that predate the exchange
+    pub const LEGACY: Self = Self {
+        version: 1,
+        min_compatible: 1,
+    };
+
+    /// The size of an encoded schema version
+    pub const ENCODED_LEN: usize = Self::MAGIC.len() + 4;
+
+    /// Distinguishes the schema version from the payloads of other streams
+    const MAGIC: [u8; 4] = *b"QSV\x01";
+
+    /// Returns true if each version is at least the minimum compatible
+    /// version of the other
+    #[inline]
+    pub fn is_compatible(self, peer: Self) -> bool {
+        peer.version >= self.min_compatible && self.version >= peer.min_compatible
+    }
+
+    #[inline]
+    pub fn encode(self) -> [u8; Self::ENCODED_LEN] {
+        let mut buf = [0; Self::ENCODED_LEN];
+        buf[..4].copy_from_slice(&Self::MAGIC);
+        buf[4..6].copy_from_slice(&self.version.to_be_bytes());
+        buf[6..].copy_from_slice(&self.min_compatible.to_be_bytes());
+        buf
+    }
+
+    /// Decodes a schema version, returning `None` if `buf` is not one
+    #[inline]
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let buf: &[u8; Self::ENCODED_LEN] = buf.try_into().ok()?;
+        if buf[..4] != Self::MAGIC {
+            return None;
+        }
+
+        Some(Self {
+            version: u16::from_be_bytes([buf[4], buf[5]]),
+            min_compatible: u16::from_be_bytes([buf[6], buf[7]]),
+        })
+    }
+}
+
+const V1: &str = r#"
 CREATE TABLE servers (
     -- hostname or IP + port
     endpoint varchar(264) not null primary key,
@@ -30,3 +140,33 @@ CREATE TABLE filter (
     filter text
 );
 "#;
+
+const V2: &str = r#"
+CREATE TABLE servers (
+    -- hostname or IP + port
+    endpoint varchar(264) not null primary key,
+    -- icao code
+    icao char(4) not null default 'XXXX',
+    -- Token set. Since SQLite does not support arrays, we use a base64 encoded binary blob
+    tokens text,
+    -- The JSONB set of peers that contributed this server
+    contributors blob,
+    -- The timestamp of the last contributors update, either insertion or deletion
+    cont_update timestamp,
+    -- JSON object of additional endpoint metadata, eg. the name and namespace
+    -- of the Agones GameServer
+    metadata text,
+    -- The locality of the endpoint, in `region:zone:sub_zone` form
+    locality text
+);
+"#;
+
+const V3: &str = r#"
+CREATE TABLE filters (
+    -- the scope the filter chain applies to, `*` for the global default,
+    -- `icao:<ICAO>`, or `locality:<region[:zone[:sub_zone]]>`
+    scope text not null primary key,
+    -- the filter chain
+    filter text
+);
+"#;
@@ -0,0 +1,312 @@
+//! Mutual TLS configuration for the QUIC connections used by the persistent
+//! (agent/proxy) and gossip transports
+
+use quilkin_types::tls::{certified_key, crypto_provider};
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use rustls::{
+    pki_types::{CertificateDer, pem::PemObject as _},
+    sign::CertifiedKey,
+};
+use std::sync::Arc;
+
+/// The error code a connection is closed with when the peer's certificate is
+/// valid, but its identity is not in the allowed list
+pub const PEER_NOT_ALLOWED: u32 = 0x5051;
+
+#[derive(thiserror::Error, Debug)]
+pub enum TlsError {
+    #[error("failed to read {kind} from {path:?}: {error}")]
+    Io {
+        kind: &'static str,
+        path: std::path::PathBuf,
+        error: std::io::Error,
+    },
+    #[error("failed to parse PEM {kind}: {error}")]
+    Pem {
+        kind: &'static str,
+        error: rustls::pki_types::pem::Error,
+    },
+    #[error("no certificates found in the PEM {0}")]
+    NoCertificates(&'static str),
+    #[error(transparent)]
+    Key(#[from] quilkin_types::tls::KeyError),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+    #[error(transparent)]
+    Verifier(#[from] rustls::server::VerifierBuilderError),
+    #[error(transparent)]
+    CipherSuite(#[from] quinn::crypto::rustls::NoInitialCipherSuite),
+    #[error("peer identity {0:?} is not allowed")]
+    PeerNotAllowed(Vec<String>),
+}
+
+/// The identity of this node, and the CA(s) used to authenticate peers
+///
+/// Both sides of every connection present a certificate, which must be signed
+/// by one of the configured CAs. Additionally, if any allowed peers are set,
+/// the DNS or URI SANs, or the common name, of the peer certificate must match
+/// one of them.
+///
+/// The certificate can be replaced with [`Self::reload_pem`], which applies to
+/// every connection established afterwards, including those of servers and
+/// clients created from a clone of this configuration.
+#[derive(Clone)]
+pub struct TlsConfig {
+    server: quinn::ServerConfig,
+    client: quinn::ClientConfig,
+    certificate: Arc<Certificate>,
+    server_name: Option<String>,
+    allowed_peers: Arc<[String]>,
+}
+
+impl TlsConfig {
+    /// Creates a configuration from a PEM encoded certificate chain, private
+    /// key, and CA bundle
+    pub fn from_pem(cert: &[u8], key: &[u8], ca: &[u8]) -> Result<Self, TlsError> {
+        let provider = crypto_provider();
+        let certificate = Arc::new(Certificate(parking_lot::RwLock::new(certified_key(
+            cert, key, &provider,
+        )?)));
+
+        let mut roots = rustls::RootCertStore::empty();
+        for ca in CertificateDer::pem_slice_iter(ca) {
+            roots.add(ca.map_err(|error| TlsError::Pem {
+                kind: "CA bundle",
+                error,
+            })?)?;
+        }
+        if roots.is_empty() {
+            return Err(TlsError::NoCertificates("CA bundle"));
+        }
+        let roots = Arc::new(roots);
+
+        let verifier = rustls::server::WebPkiClientVerifier::builder_with_provider(
+            roots.clone(),
+            provider.clone(),
+        )
+        .build()?;
+
+        let server = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .with_client_cert_verifier(verifier)
+            .with_cert_resolver(certificate.clone());
+
+        let client = rustls::ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .with_root_certificates(roots)
+            .with_client_cert_resolver(certificate.clone());
+
+        Ok(Self {
+            server: quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(server)?)),
+            client: quinn::ClientConfig::new(Arc::new(QuicClientConfig::try_from(client)?)),
+            certificate,
+            server_name: None,
+            allowed_peers: Arc::new([]),
+        })
+    }
+
+    /// Replaces the certificate chain and private key presented to peers with
+    /// new PEM encoded ones, keeping the current ones if they are invalid
+    pub fn reload_pem(&self, cert: &[u8], key: &[u8]) -> Result<(), TlsError> {
+        let certified = certified_key(cert, key, &crypto_provider())?;
+        *self.certificate.0.write() = certified;
+        Ok(())
+    }
+
+    /// Creates a configuration from the paths of a PEM encoded certificate
+    /// chain, private key, and CA bundle
+    pub fn from_files(
+        cert: &std::path::Path,
+        key: &std::path::Path,
+        ca: &std::path::Path,
+    ) -> Result<Self, TlsError> {
+        let read = |kind, path: &std::path::Path| {
+            std::fs::read(path).map_err(|error| TlsError::Io {
+                kind,
+                path: path.to_owned(),
+                error,
+            })
+        };
+
+        Self::from_pem(
+            &read("certificate", cert)?,
+            &read("private key", key)?,
+            &read("CA bundle", ca)?,
+        )
+    }
+
+    /// Sets the name used to verify the certificate of servers this node
+    /// connects to, by default the IP address of the server is used, which
+    /// requires the server's certificate to have an IP SAN
+    pub fn with_server_name(mut self, name: Option<String>) -> Self {
+        self.server_name = name;
+        self
+    }
+
+    /// Sets the identities peers are allowed to have, if empty any peer with a
+    /// certificate signed by the CA is allowed
+    pub fn with_allowed_peers(mut self, peers: Vec<String>) -> Self {
+        self.allowed_peers = peers.into();
+        self
+    }
+
+    #[inline]
+    pub fn server_config(&self) -> quinn::ServerConfig {
+        self.server.clone()
+    }
+
+    #[inline]
+    pub fn client_config(&self) -> quinn::ClientConfig {
+        self.client.clone()
+    }
+
+    /// The name to verify the certificate of the server at `addr` against
+    #[inline]
+    pub fn server_name(&self, addr: std::net::SocketAddr) -> String {
+        self.server_name
+            .clone()
+            .unwrap_or_else(|| addr.ip().to_string())
+    }
+
+    /// Checks the identity of the peer of an established connection against
+    /// the allowed peers, closing the connection if it is not allowed
+    pub fn verify_peer(&self, conn: &quinn::Connection) -> Result<(), TlsError> {
+        if self.allowed_peers.is_empty() {
+            return Ok(());
+        }
+
+        let identities = peer_identities(conn);
+        if identities
+            .iter()
+            .any(|id| self.allowed_peers.iter().any(|allowed| allowed == id))
+        {
+            return Ok(());
+        }
+
+        conn.close(PEER_NOT_ALLOWED.into(), b"peer not allowed");
+        Err(TlsError::PeerNotAllowed(identities))
+    }
+}
+
+/// The certificate this node presents, shared by the server and client
+/// configurations so it can be replaced without recreating either
+#[derive(Debug)]
+struct Certificate(parking_lot::RwLock<Arc<CertifiedKey>>);
+
+impl rustls::server::ResolvesServerCert for Certificate {
+    fn resolve(&self, _client_hello: rustls::server::ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.read().clone())
+    }
+}
+
+impl rustls::client::ResolvesClientCert for Certificate {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        _sigschemes: &[rustls::SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.read().clone())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("server_name", &self.server_name)
+            .field("allowed_peers", &self.allowed_peers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Retrieves the DNS and URI SANs, and the common name, of the leaf certificate
+/// the peer presented
+pub fn peer_identities(conn: &quinn::Connection) -> Vec<String> {
+    use x509_parser::extensions::GeneralName;
+
+    let Some(chain) = conn
+        .peer_identity()
+        .and_then(|id| id.downcast::<Vec<CertificateDer<'static>>>().ok())
+    else {
+        return Vec::new();
+    };
+    let Some(Ok((_, leaf))) = chain
+        .first()
+        .map(|leaf| x509_parser::parse_x509_certificate(leaf.as_ref()))
+    else {
+        return Vec::new();
+    };
+
+    let mut identities = Vec::new();
+    if let Ok(Some(san)) = leaf.subject_alternative_name() {
+        for name in &san.value.general_names {
+            if let GeneralName::DNSName(name) | GeneralName::URI(name) = name {
+                identities.push((*name).to_owned());
+            }
+        }
+    }
+    identities.extend(
+        leaf.subject()
+            .iter_common_name()
+            .filter_map(|cn| cn.as_str().ok().map(String::from)),
+    );
+    identities
+}
+
+/// Maps a connection error to the cause of a failed handshake, used as a
+/// metric label
+pub fn handshake_failure_cause(error: &quinn::ConnectionError) -> &'static str {
+    use quinn::ConnectionError as Ce;
+
+    if let Some(alert) = tls_alert(error) {
+        return alert;
+    }
+
+    match error {
+        Ce::ApplicationClosed(ac) if ac.error_code == PEER_NOT_ALLOWED.into() => "peer_not_allowed",
+        Ce::ApplicationClosed(_) => "application_closed",
+        Ce::TransportError(_) => "transport_error",
+        Ce::ConnectionClosed(_) => "connection_closed",
+        Ce::VersionMismatch => "version_mismatch",
+        Ce::Reset => "reset",
+        Ce::TimedOut => "timed_out",
+        Ce::LocallyClosed => "locally_closed",
+        Ce::CidsExhausted => "cids_exhausted",
+    }
+}
+
+/// The name of the TLS alert that caused the connection to fail, if any
+///
+/// TLS alerts are reported as QUIC crypto errors, `0x100` plus the alert
+/// code, the alerts relevant to certificate verification are reported by name.
+pub fn tls_alert(error: &quinn::ConnectionError) -> Option<&'static str> {
+    let code = match error {
+        quinn::ConnectionError::TransportError(te) => te.code,
+        quinn::ConnectionError::ConnectionClosed(cc) => cc.error_code,
+        _ => return None,
+    };
+
+    let code = u64::from(code);
+    if !(0x100..0x200).contains(&code) {
+        return None;
+    }
+
+    Some(match code - 0x100 {
+        40 => "handshake_failure",
+        42 => "bad_certificate",
+        43 => "unsupported_certificate",
+        44 => "certificate_revoked",
+        45 => "certificate_expired",
+        46 => "certificate_unknown",
+        48 => "unknown_ca",
+        49 => "access_denied",
+        51 => "decrypt_error",
+        70 => "protocol_version",
+        116 => "certificate_required",
+        120 => "no_application_protocol",
+        _ => "tls_alert",
+    })
+}
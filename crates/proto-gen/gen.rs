@@ -207,6 +207,7 @@ fn execute(which: &str) {
                 "envoy/config/listener/v3/listener_components",
                 "envoy/service/discovery/v3/ads",
                 "envoy/service/discovery/v3/discovery",
+                "envoy/config/endpoint/v3/endpoint",
                 "envoy/config/endpoint/v3/endpoint_components",
             ],
         ),
@@ -230,6 +231,7 @@ fn execute(which: &str) {
                 "filters/local_rate_limit/v1alpha1/local_rate_limit",
                 "filters/match/v1alpha1/match",
                 "filters/pass/v1alpha1/pass",
+                "filters/proxy_protocol/v1alpha1/proxy_protocol",
                 "filters/token_router/v1alpha1/token_router",
                 "filters/timestamp/v1alpha1/timestamp",
                 "pprof",
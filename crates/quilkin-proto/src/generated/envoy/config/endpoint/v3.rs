@@ -1,4 +1,13 @@
 // This file is @generated by prost-build.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ClusterLoadAssignment {
+    #[prost(string, tag = "1")]
+    pub cluster_name: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "2")]
+    pub endpoints: ::prost::alloc::vec::Vec<LocalityLbEndpoints>,
+    #[prost(map = "string, message", tag = "5")]
+    pub named_endpoints: ::std::collections::HashMap<::prost::alloc::string::String, Endpoint>,
+}
 #[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
 pub struct Endpoint {
     #[prost(message, optional, tag = "1")]
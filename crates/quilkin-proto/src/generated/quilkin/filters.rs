@@ -8,5 +8,6 @@ pub mod load_balancer;
 pub mod local_rate_limit;
 pub mod matches;
 pub mod pass;
+pub mod proxy_protocol;
 pub mod timestamp;
 pub mod token_router;
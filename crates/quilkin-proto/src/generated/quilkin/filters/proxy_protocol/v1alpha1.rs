@@ -0,0 +1,74 @@
+// This file is @generated by prost-build.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ProxyProtocol {
+    #[prost(enumeration = "proxy_protocol::Format", tag = "1")]
+    pub format: i32,
+    #[prost(enumeration = "proxy_protocol::Frequency", tag = "2")]
+    pub frequency: i32,
+    #[prost(message, repeated, tag = "3")]
+    pub tlvs: ::prost::alloc::vec::Vec<proxy_protocol::Tlv>,
+    #[prost(bool, tag = "4")]
+    pub strip_replies: bool,
+}
+/// Nested message and enum types in `ProxyProtocol`.
+pub mod proxy_protocol {
+    #[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+    pub struct Tlv {
+        #[prost(string, tag = "1")]
+        pub key: ::prost::alloc::string::String,
+        #[prost(uint32, tag = "2")]
+        pub tlv_type: u32,
+    }
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Format {
+        V2 = 0,
+        Compact = 1,
+    }
+    impl Format {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::V2 => "V2",
+                Self::Compact => "Compact",
+            }
+        }
+        /// Creates an enum from field names used in the ProtoBuf definition.
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "V2" => Some(Self::V2),
+                "Compact" => Some(Self::Compact),
+                _ => None,
+            }
+        }
+    }
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Frequency {
+        EveryPacket = 0,
+        FirstPacket = 1,
+    }
+    impl Frequency {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::EveryPacket => "EveryPacket",
+                Self::FirstPacket => "FirstPacket",
+            }
+        }
+        /// Creates an enum from field names used in the ProtoBuf definition.
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "EveryPacket" => Some(Self::EveryPacket),
+                "FirstPacket" => Some(Self::FirstPacket),
+                _ => None,
+            }
+        }
+    }
+}
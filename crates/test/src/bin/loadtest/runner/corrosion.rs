@@ -9,7 +9,7 @@ use corrosion::{
         server::Server,
     },
     pubsub::{PubsubContext, Trip},
-    schema::SCHEMA,
+    schema::MIGRATIONS,
 };
 use quilkin_types::{AddressKind, Endpoint, IcaoCode, TokenSet};
 use std::{
@@ -87,7 +87,7 @@ impl CorrosionHandle {
             ..DBMaintenance::default()
         });
 
-        let db = InitializedDb::setup(&db_path, SCHEMA, maintenance).await?;
+        let db = InitializedDb::setup(&db_path, MIGRATIONS, maintenance).await?;
         let subs = SubsManager::default();
         let btx = BroadcastingTransactor::new(
             db.actor_id,
@@ -238,6 +238,8 @@ async fn run_writer(
                     endpoint,
                     icao,
                     tokens: TokenSet::default(),
+                    metadata: Default::default(),
+                    locality: None,
                 }
             })
             .collect();
@@ -29,6 +29,7 @@ pub fn default_xdp_state(
             local_ipv4: std::net::Ipv4Addr::new(1, 1, 1, 1),
             local_ipv6: std::net::Ipv6Addr::from_bits(u128::from_ne_bytes([1; 16])),
             last_receive: quilkin::time::UtcTimestamp::now(),
+            dscp: Default::default(),
         },
         cfg_state,
     )
@@ -0,0 +1,245 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Tests that the xDS and relay servers only accept clients with a certificate
+//! signed by the client CA, that are allowed by the client policies
+
+use qt::*;
+use quilkin_xds::{
+    core::Node,
+    discovery::{
+        DeltaDiscoveryRequest, DeltaDiscoveryResponse,
+        aggregated_discovery_service_client::AggregatedDiscoveryServiceClient,
+    },
+    generated::quilkin::relay::v1alpha1::aggregated_control_plane_discovery_service_client::AggregatedControlPlaneDiscoveryServiceClient,
+    server::{ControlPlane, TlsIdentity},
+    tls::{ClientAuth, ClientPolicy, ClientTls},
+};
+use std::{sync::Arc, time::Duration};
+use tokio_stream::StreamExt as _;
+
+const SERVER_NAME: &str = "server.quilkin.test";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Ca {
+    cert: rcgen::Certificate,
+    issuer: rcgen::Issuer<'static, rcgen::KeyPair>,
+}
+
+impl Ca {
+    fn new() -> Self {
+        let mut params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+
+        Self {
+            cert,
+            issuer: rcgen::Issuer::new(params, key),
+        }
+    }
+
+    fn pem(&self) -> String {
+        self.cert.pem()
+    }
+
+    /// Issues a certificate for `name`, returning the PEM encoded certificate and key
+    fn issue(&self, name: &str) -> (String, String) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec![name.to_owned()])
+            .unwrap()
+            .signed_by(&key, &self.issuer)
+            .unwrap();
+
+        (cert.pem(), key.serialize_pem())
+    }
+
+    /// The TLS config of a client presenting a certificate for `name`, that
+    /// trusts the server certificate issued by `server_ca`
+    fn client(&self, name: &str, server_ca: &Self) -> ClientTls {
+        let (cert, key) = self.issue(name);
+        ClientTls::from_pem(server_ca.pem())
+            .with_identity(cert, key)
+            .with_domain_name(SERVER_NAME)
+    }
+}
+
+fn endpoint(port: u16, tls: &ClientTls) -> tonic::transport::Endpoint {
+    tls.apply(vec![
+        tonic::transport::Endpoint::from_shared(format!("https://[::1]:{port}")).unwrap(),
+    ])
+    .unwrap()
+    .remove(0)
+    .connect_timeout(TIMEOUT)
+}
+
+fn subscription(node_id: &str) -> DeltaDiscoveryRequest {
+    DeltaDiscoveryRequest {
+        node: Some(Node {
+            id: node_id.into(),
+            ..Default::default()
+        }),
+        type_url: quilkin::xds::CLUSTER_TYPE.into(),
+        ..Default::default()
+    }
+}
+
+/// Subscribes to the clusters of the xDS server, resolving with the initial response
+async fn subscribe_xds(port: u16, tls: &ClientTls, node_id: &str) -> Result<(), tonic::Status> {
+    let channel = endpoint(port, tls)
+        .connect()
+        .await
+        .map_err(|error| tonic::Status::unavailable(error.to_string()))?;
+    let mut client = AggregatedDiscoveryServiceClient::new(channel);
+
+    let requests = tokio_stream::once(subscription(node_id)).chain(tokio_stream::pending());
+    let mut responses = tokio::time::timeout(TIMEOUT, client.delta_aggregated_resources(requests))
+        .await
+        .expect("timed out subscribing")?
+        .into_inner();
+
+    tokio::time::timeout(TIMEOUT, responses.message())
+        .await
+        .expect("timed out waiting for response")?
+        .ok_or_else(|| tonic::Status::aborted("stream was closed"))
+        .map(drop)
+}
+
+/// Subscribes to the clusters of the relay server
+async fn subscribe_relay(port: u16, tls: &ClientTls, node_id: &str) -> Result<(), tonic::Status> {
+    let channel = endpoint(port, tls)
+        .connect()
+        .await
+        .map_err(|error| tonic::Status::unavailable(error.to_string()))?;
+    let mut client = AggregatedControlPlaneDiscoveryServiceClient::new(channel);
+
+    let requests = tokio_stream::once(subscription(node_id)).chain(tokio_stream::pending());
+    tokio::time::timeout(TIMEOUT, client.subscribe_delta_resources(requests))
+        .await
+        .expect("timed out subscribing")
+        .map(drop)
+}
+
+/// Starts pushing resources to the relay server
+async fn push_relay(port: u16, tls: &ClientTls) -> Result<(), tonic::Status> {
+    let channel = endpoint(port, tls)
+        .connect()
+        .await
+        .map_err(|error| tonic::Status::unavailable(error.to_string()))?;
+    let mut client = AggregatedControlPlaneDiscoveryServiceClient::new(channel);
+
+    let responses = tokio_stream::pending::<DeltaDiscoveryResponse>();
+    tokio::time::timeout(TIMEOUT, client.delta_aggregated_resources(responses))
+        .await
+        .expect("timed out pushing")
+        .map(drop)
+}
+
+fn assert_denied(result: Result<(), tonic::Status>) {
+    let status = result.expect_err("request should have been denied");
+    assert_eq!(status.code(), tonic::Code::PermissionDenied, "{status:?}");
+}
+
+trace_test!(client_certificates, {
+    let ca = Ca::new();
+    let untrusted_ca = Ca::new();
+
+    let (server_cert, server_key) = ca.issue(SERVER_NAME);
+    let policies = [(
+        "proxy.quilkin.test".to_owned(),
+        ClientPolicy {
+            identifiers: vec!["proxy-1".into()],
+            subscribe: vec![quilkin_xds::tls::ANY.into()],
+            push: false,
+        },
+    )]
+    .into();
+    let identity = TlsIdentity::from_raw(server_cert.as_bytes(), server_key.as_bytes())
+        .unwrap()
+        .with_client_auth(Some(ClientAuth::from_pem(ca.pem()).with_policies(policies)));
+
+    let mut svc = quilkin::Service::default().udp().udp_port(0);
+    let config = Arc::new(quilkin::Config::new(
+        Some("mtls".into()),
+        Default::default(),
+        &Default::default(),
+        &mut svc,
+    ));
+
+    let (_shutdown_tx, shutdown_rx) = quilkin::signal::channel();
+    let control_plane = ControlPlane::from_arc(config, Duration::from_secs(30), shutdown_rx);
+
+    let listener = quilkin_xds::net::TcpListener::bind(None).unwrap();
+    let xds_port = listener.port();
+    tokio::spawn(
+        control_plane
+            .clone()
+            .management_server(listener, Some(identity.clone()))
+            .unwrap(),
+    );
+
+    let listener = quilkin_xds::net::TcpListener::bind(None).unwrap();
+    let relay_port = listener.port();
+    tokio::spawn(
+        control_plane
+            .relay_server(listener, Some(identity))
+            .unwrap(),
+    );
+
+    let allowed = ca.client("proxy.quilkin.test", &ca);
+    subscribe_xds(xds_port, &allowed, "proxy-1").await.unwrap();
+    subscribe_relay(relay_port, &allowed, "proxy-1")
+        .await
+        .unwrap();
+
+    // A client without a certificate fails the handshake
+    let anonymous = ClientTls::from_pem(ca.pem()).with_domain_name(SERVER_NAME);
+    assert!(
+        subscribe_xds(xds_port, &anonymous, "proxy-1")
+            .await
+            .is_err()
+    );
+    assert!(
+        subscribe_relay(relay_port, &anonymous, "proxy-1")
+            .await
+            .is_err()
+    );
+
+    // A client with a certificate from a CA the servers don't trust fails the handshake
+    let untrusted = untrusted_ca.client("proxy.quilkin.test", &ca);
+    assert!(
+        subscribe_xds(xds_port, &untrusted, "proxy-1")
+            .await
+            .is_err()
+    );
+    assert!(
+        subscribe_relay(relay_port, &untrusted, "proxy-1")
+            .await
+            .is_err()
+    );
+
+    // A trusted certificate with an identity that has no policy
+    let unknown = ca.client("other.quilkin.test", &ca);
+    assert_denied(subscribe_xds(xds_port, &unknown, "proxy-1").await);
+    assert_denied(subscribe_relay(relay_port, &unknown, "proxy-1").await);
+
+    // The policy of the certificate doesn't allow the identifier
+    assert_denied(subscribe_xds(xds_port, &allowed, "proxy-2").await);
+    assert_denied(subscribe_relay(relay_port, &allowed, "proxy-2").await);
+
+    // The policy of the certificate doesn't allow pushing to the relay
+    assert_denied(push_relay(relay_port, &allowed).await);
+});
@@ -0,0 +1,103 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Tests exporting metrics to a stand-in for an OpenTelemetry collector
+
+use opentelemetry_proto::tonic::{
+    collector::metrics::v1::{
+        ExportMetricsServiceRequest, ExportMetricsServiceResponse,
+        metrics_service_server::{MetricsService, MetricsServiceServer},
+    },
+    common::v1::any_value,
+    metrics::v1::metric::Data,
+};
+use qt::*;
+use std::time::Duration;
+
+/// Forwards every export request it receives
+struct Collector(tokio::sync::mpsc::UnboundedSender<ExportMetricsServiceRequest>);
+
+#[tonic::async_trait]
+impl MetricsService for Collector {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportMetricsServiceRequest>,
+    ) -> Result<tonic::Response<ExportMetricsServiceResponse>, tonic::Status> {
+        drop(self.0.send(request.into_inner()));
+        Ok(tonic::Response::new(ExportMetricsServiceResponse::default()))
+    }
+}
+
+trace_test!(exports_metrics, {
+    let counter = quilkin::metrics::register(
+        prometheus::IntCounter::with_opts(quilkin::metrics::opts(
+            "otlp_test_total",
+            "otlp",
+            "A counter to test exporting",
+        ))
+        .unwrap(),
+    );
+    counter.inc_by(3);
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let listener = quilkin_xds::net::TcpListener::bind(None).unwrap();
+    let port = listener.port();
+
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(MetricsServiceServer::new(Collector(tx)))
+            .serve_with_incoming(listener.into_stream().unwrap()),
+    );
+
+    let mut exporter = quilkin::otlp::MetricsExporter::new(
+        format!("http://[::1]:{port}").try_into().unwrap(),
+        "otlp-test".into(),
+    );
+    exporter.export().await.unwrap();
+
+    let request = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let resource_metrics = &request.resource_metrics[0];
+
+    let instance_id = resource_metrics
+        .resource
+        .as_ref()
+        .unwrap()
+        .attributes
+        .iter()
+        .find(|kv| kv.key == "service.instance.id")
+        .and_then(|kv| kv.value.as_ref()?.value.clone());
+    assert_eq!(
+        instance_id,
+        Some(any_value::Value::StringValue("otlp-test".into()))
+    );
+
+    let metric = resource_metrics.scope_metrics[0]
+        .metrics
+        .iter()
+        .find(|metric| metric.name == "quilkin_otlp_otlp_test_total")
+        .expect("counter was not exported");
+    let Some(Data::Sum(sum)) = &metric.data else {
+        panic!("expected a sum, got {:?}", metric.data);
+    };
+    assert!(sum.is_monotonic);
+    assert_eq!(
+        sum.data_points[0].value,
+        Some(opentelemetry_proto::tonic::metrics::v1::number_data_point::Value::AsDouble(3.0))
+    );
+});
@@ -133,8 +133,10 @@ trace_test!(uring_receiver, {
             usize::MAX,
             backend,
             4,
+            Default::default(),
         ),
         backend,
+        socket_options: Default::default(),
     }
     .spawn_io_loop(
         pending_sends,
@@ -152,6 +154,116 @@ trace_test!(uring_receiver, {
     assert_eq!(msg, sb.timeout(200, packet_rx.recv()).await.0.unwrap());
 });
 
+// The QoS options must be set on the sockets the workers actually receive and
+// send downstream traffic on, rather than only the socket used to pick the port
+trace_test!(
+    #[cfg(target_os = "linux")]
+    worker_socket_options,
+    {
+        use std::os::fd::BorrowedFd;
+
+        /// Every UDP socket in this process that is bound to `port`
+        fn sockets_bound_to(port: u16) -> Vec<std::os::fd::OwnedFd> {
+            std::fs::read_dir("/proc/self/fd")
+                .unwrap()
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let link = std::fs::read_link(entry.path()).ok()?;
+                    if !link.to_string_lossy().starts_with("socket:") {
+                        return None;
+                    }
+
+                    let fd: i32 = entry.file_name().to_str()?.parse().ok()?;
+                    // SAFETY: the descriptor is only borrowed long enough to
+                    // duplicate it, and a socket being closed concurrently
+                    // just means the duplication fails
+                    let fd = unsafe { BorrowedFd::borrow_raw(fd) }
+                        .try_clone_to_owned()
+                        .ok()?;
+                    let socket = socket2::SockRef::from(&fd);
+                    (socket.r#type().ok()? == socket2::Type::DGRAM
+                        && socket.local_addr().ok()?.as_socket()?.port() == port)
+                        .then_some(fd)
+                })
+                .collect()
+        }
+
+        let mut sc = qt::sandbox_config!();
+
+        sc.push("server", ServerPailConfig::default(), &[]);
+        let mut sb = sc.spinup().await;
+
+        let (_packet_rx, endpoint) = sb.server("server");
+
+        let mut service = quilkin::Service::builder().udp();
+        let config = std::sync::Arc::new(quilkin::Config::new(
+            None,
+            Default::default(),
+            &Default::default(),
+            &mut service,
+        ));
+        config
+            .dyn_cfg
+            .clusters()
+            .unwrap()
+            .modify(|clusters| clusters.insert_default([endpoint.into()].into()));
+
+        let (ws, addr) = sb.socket();
+        let backend = quilkin::net::io::UdpBackend::probe_user_space();
+        let pending_sends = net::queue(1, backend).unwrap();
+
+        let dscp = net::qos::Dscp::EF;
+        quilkin::net::io::Listener {
+            worker_id: 1,
+            port: addr.port(),
+            config: config.clone(),
+            sessions: quilkin::net::sessions::SessionPool::new(
+                vec![pending_sends.0.clone()],
+                config.dyn_cfg.cached_filter_chain().unwrap(),
+                usize::MAX,
+                backend,
+                4,
+                Default::default(),
+            ),
+            backend,
+            socket_options: net::qos::SocketOptions {
+                dscp: Some(dscp),
+                priority: Some(5),
+                ..Default::default()
+            },
+        }
+        .spawn_io_loop(
+            pending_sends,
+            config.dyn_cfg.cached_filter_chain().unwrap(),
+            4,
+        )
+        .expect("failed to spawn task");
+
+        // Only the worker's socket is left bound to the port
+        drop(ws);
+
+        let (sockets, _) = sb
+            .timeout(1000, async {
+                loop {
+                    let sockets = sockets_bound_to(addr.port());
+                    if !sockets.is_empty() {
+                        break sockets;
+                    }
+
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                }
+            })
+            .await;
+
+        for fd in sockets {
+            let socket = socket2::SockRef::from(&fd);
+            assert_eq!(socket.tos_v4().unwrap(), u32::from(dscp.tos()));
+            assert_eq!(socket.tclass_v6().unwrap(), u32::from(dscp.tos()));
+            assert_eq!(socket.priority().unwrap(), 5);
+        }
+    }
+);
+
 trace_test!(
     #[ignore]
     recv_from,
@@ -199,13 +311,22 @@ trace_test!(
             usize::MAX,
             backend,
             64,
+            Default::default(),
         );
 
         const WORKER_COUNT: usize = 3;
 
         let (socket, addr) = sb.socket();
-        net::packet::spawn_receivers(config, socket, pending_sends, &sessions, backend, 64)
-            .unwrap();
+        net::packet::spawn_receivers(
+            config,
+            socket,
+            pending_sends,
+            &sessions,
+            backend,
+            64,
+            Default::default(),
+        )
+        .unwrap();
 
         let socket = std::sync::Arc::new(sb.client());
         let msg = "recv-from";
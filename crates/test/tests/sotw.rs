@@ -0,0 +1,258 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Tests state of the world (SotW) xDS streams, both against a management
+//! server that doesn't support delta xDS, and against our own control plane
+
+use qt::*;
+use quilkin::net::{cluster::EndpointSet, endpoint::Endpoint};
+use quilkin_xds::discovery::{
+    DeltaDiscoveryRequest, DeltaDiscoveryResponse, DiscoveryRequest, DiscoveryResponse,
+    aggregated_discovery_service_server::{
+        AggregatedDiscoveryService, AggregatedDiscoveryServiceServer,
+    },
+};
+use std::{
+    collections::BTreeSet,
+    net::Ipv6Addr,
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const SUBS: &[(&str, &[(&str, Vec<String>)])] = &[(
+    "9",
+    &[
+        (quilkin::xds::CLUSTER_TYPE, Vec::new()),
+        (quilkin::xds::DATACENTER_TYPE, Vec::new()),
+        (quilkin::xds::FILTER_CHAIN_TYPE, Vec::new()),
+    ],
+)];
+
+/// A stand-in for a management server that only supports SotW streams, which
+/// responds to each subscription with a fixed set of resources
+struct SotwOnly {
+    clusters: Vec<prost_types::Any>,
+    requests: tokio::sync::mpsc::UnboundedSender<DiscoveryRequest>,
+}
+
+#[tonic::async_trait]
+impl AggregatedDiscoveryService for SotwOnly {
+    type StreamAggregatedResourcesStream =
+        tokio_stream::wrappers::ReceiverStream<Result<DiscoveryResponse, tonic::Status>>;
+    type DeltaAggregatedResourcesStream =
+        tokio_stream::wrappers::ReceiverStream<Result<DeltaDiscoveryResponse, tonic::Status>>;
+
+    async fn stream_aggregated_resources(
+        &self,
+        request: tonic::Request<tonic::Streaming<DiscoveryRequest>>,
+    ) -> Result<tonic::Response<Self::StreamAggregatedResourcesStream>, tonic::Status> {
+        let mut requests = request.into_inner();
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        let clusters = self.clusters.clone();
+        let forward = self.requests.clone();
+
+        tokio::spawn(async move {
+            while let Ok(Some(request)) = requests.message().await {
+                // Only subscriptions are responded to, the rest are ACKs/NACKs
+                if request.response_nonce.is_empty() {
+                    let resources = if request.type_url == quilkin::xds::CLUSTER_TYPE {
+                        clusters.clone()
+                    } else {
+                        Vec::new()
+                    };
+
+                    let response = DiscoveryResponse {
+                        version_info: "1".into(),
+                        resources,
+                        type_url: request.type_url.clone(),
+                        nonce: format!("{}-1", request.type_url),
+                        ..Default::default()
+                    };
+
+                    if tx.send(Ok(response)).await.is_err() {
+                        break;
+                    }
+                }
+
+                drop(forward.send(request));
+            }
+        });
+
+        Ok(tonic::Response::new(
+            tokio_stream::wrappers::ReceiverStream::new(rx),
+        ))
+    }
+
+    async fn delta_aggregated_resources(
+        &self,
+        _request: tonic::Request<tonic::Streaming<DeltaDiscoveryRequest>>,
+    ) -> Result<tonic::Response<Self::DeltaAggregatedResourcesStream>, tonic::Status> {
+        Err(tonic::Status::unimplemented(
+            "only state of the world streams are supported",
+        ))
+    }
+}
+
+fn proxy_config(id: &str) -> Arc<quilkin::Config> {
+    let mut svc = quilkin::Service::default().udp().udp_port(0);
+    Arc::new(quilkin::Config::new(
+        Some(id.into()),
+        Default::default(),
+        &Default::default(),
+        &mut svc,
+    ))
+}
+
+/// Waits for the resources of `type_url` to be applied
+async fn wait_for(rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>, type_url: &str) {
+    loop {
+        let applied = tokio::time::timeout(TIMEOUT, rx.recv())
+            .await
+            .expect("timed out waiting for resources")
+            .expect("notifier was dropped");
+
+        if applied == type_url {
+            return;
+        }
+    }
+}
+
+trace_test!(sotw_only_management_server, {
+    let endpoint = Endpoint::new((Ipv6Addr::LOCALHOST, 4321).into());
+    let clusters = vec![
+        EndpointSet::new([endpoint.clone()].into())
+            .encoded_cluster(&None)
+            .unwrap(),
+    ];
+
+    let (requests_tx, mut requests_rx) = tokio::sync::mpsc::unbounded_channel();
+    let listener = quilkin_xds::net::TcpListener::bind(None).unwrap();
+    let port = listener.port();
+
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(AggregatedDiscoveryServiceServer::new(SotwOnly {
+                clusters,
+                requests: requests_tx,
+            }))
+            .serve_with_incoming(listener.into_stream().unwrap()),
+    );
+
+    let config = proxy_config("sotw-proxy");
+    let (notifier, mut applied) = tokio::sync::mpsc::unbounded_channel();
+
+    // The delta stream is rejected, so the client falls back to SotW
+    let _stream = quilkin_xds::subscribe(
+        config.clone(),
+        "sotw-proxy".into(),
+        vec![format!("http://[::1]:{port}").try_into().unwrap()],
+        Arc::new(AtomicBool::new(false)),
+        Some(notifier),
+        SUBS.iter()
+            .map(|(version, subs)| (*version, subs.to_vec()))
+            .collect(),
+    )
+    .await
+    .unwrap();
+
+    wait_for(&mut applied, quilkin::xds::CLUSTER_TYPE).await;
+    assert_eq!(
+        config.dyn_cfg.clusters().unwrap().read().endpoints(),
+        [endpoint]
+    );
+
+    // Each of the subscribed types is requested, then the response ACKed
+    let mut acked = BTreeSet::new();
+    while acked.len() < SUBS[0].1.len() {
+        let request = tokio::time::timeout(TIMEOUT, requests_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        if request.response_nonce.is_empty() {
+            assert_eq!(request.node.unwrap().id, "sotw-proxy");
+            continue;
+        }
+
+        assert!(request.error_detail.is_none(), "{:?}", request.error_detail);
+        assert_eq!(request.version_info, "1");
+        assert_eq!(request.response_nonce, format!("{}-1", request.type_url));
+        acked.insert(request.type_url);
+    }
+});
+
+trace_test!(sotw_control_plane, {
+    static SOTW_SUBS: &[(&str, Vec<String>)] = &[
+        (quilkin::xds::CLUSTER_TYPE, Vec::new()),
+        (quilkin::xds::FILTER_CHAIN_TYPE, Vec::new()),
+    ];
+
+    let server_config = proxy_config("sotw-server");
+    let first = Endpoint::new((Ipv6Addr::LOCALHOST, 4321).into());
+    server_config
+        .dyn_cfg
+        .clusters()
+        .unwrap()
+        .modify(|clusters| clusters.insert_default([first.clone()].into()));
+
+    let (_shutdown_tx, shutdown_rx) = quilkin::signal::channel();
+    let listener = quilkin_xds::net::TcpListener::bind(None).unwrap();
+    let port = listener.port();
+    let server = quilkin_xds::server::ControlPlane::from_arc(
+        server_config.clone(),
+        Duration::from_secs(30),
+        shutdown_rx,
+    )
+    .management_server(listener, None)
+    .unwrap();
+    tokio::spawn(server);
+
+    let client_config = proxy_config("sotw-client");
+    let (notifier, mut applied) = tokio::sync::mpsc::unbounded_channel();
+
+    let _stream = quilkin_xds::sotw_subscribe(
+        client_config.clone(),
+        "sotw-client".into(),
+        vec![format!("http://[::1]:{port}").try_into().unwrap()],
+        Arc::new(AtomicBool::new(false)),
+        Some(notifier),
+        SOTW_SUBS.to_vec(),
+    )
+    .await
+    .unwrap();
+
+    wait_for(&mut applied, quilkin::xds::CLUSTER_TYPE).await;
+    assert_eq!(
+        client_config.dyn_cfg.clusters().unwrap().read().endpoints(),
+        [first.clone()]
+    );
+
+    // Changes are pushed as a new full set of resources
+    let second = Endpoint::new((Ipv6Addr::LOCALHOST, 4322).into());
+    server_config
+        .dyn_cfg
+        .clusters()
+        .unwrap()
+        .modify(|clusters| clusters.insert_default([first.clone(), second.clone()].into()));
+
+    wait_for(&mut applied, quilkin::xds::CLUSTER_TYPE).await;
+    assert_eq!(
+        client_config.dyn_cfg.clusters().unwrap().read().endpoints(),
+        [first, second]
+    );
+});
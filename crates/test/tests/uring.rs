@@ -167,8 +167,10 @@ trace_test!(requeues_recv, {
             usize::MAX,
             backend,
             4,
+            Default::default(),
         ),
         backend,
+        socket_options: Default::default(),
     }
     .spawn_io_loop(
         pending_sends,
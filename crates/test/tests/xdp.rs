@@ -48,6 +48,7 @@ async fn simple_forwarding() {
         local_ipv4: *PROXY.ip(),
         local_ipv6: Ipv6Addr::from_bits(0),
         last_receive: UtcTimestamp::now(),
+        dscp: Default::default(),
     };
 
     let data = [0xf0u8; 11];
@@ -130,6 +131,7 @@ async fn changes_ip_version() {
         local_ipv4: *PROXY4.ip(),
         local_ipv6: *PROXY6.ip(),
         last_receive: UtcTimestamp::now(),
+        dscp: Default::default(),
     };
 
     let data = [0xf1u8; 11];
@@ -267,6 +269,7 @@ async fn packet_manipulation() {
             local_ipv4: *PROXY.ip(),
             local_ipv6: Ipv6Addr::from_bits(0),
             last_receive: UtcTimestamp::now(),
+            dscp: Default::default(),
         };
 
         let data = [0xf1u8; 11];
@@ -323,6 +326,7 @@ async fn packet_manipulation() {
             local_ipv4: *PROXY.ip(),
             local_ipv6: Ipv6Addr::from_bits(0),
             last_receive: UtcTimestamp::now(),
+            dscp: Default::default(),
         };
 
         let data = [0xf1u8; 11];
@@ -386,6 +390,7 @@ async fn packet_manipulation() {
             local_ipv4: *PROXY.ip(),
             local_ipv6: Ipv6Addr::from_bits(0),
             last_receive: UtcTimestamp::now(),
+            dscp: Default::default(),
         };
 
         let mut client_packet = unsafe { umem.alloc().unwrap() };
@@ -486,6 +491,7 @@ async fn multiple_servers() {
         local_ipv4: Ipv4Addr::from_bits(0),
         local_ipv6: *PROXY.ip(),
         last_receive: UtcTimestamp::now(),
+        dscp: Default::default(),
     };
 
     let mut umem = xdp::Umem::map(
@@ -557,6 +563,7 @@ async fn many_sessions() {
         local_ipv4: *PROXY.ip(),
         local_ipv6: Ipv6Addr::from_bits(0),
         last_receive: UtcTimestamp::now(),
+        dscp: Default::default(),
     };
 
     let data = [0xf0u8; 11];
@@ -671,6 +678,7 @@ async fn frees_dropped_packets() {
         local_ipv4: *PROXY4.ip(),
         local_ipv6: *PROXY6.ip(),
         last_receive: UtcTimestamp::now(),
+        dscp: Default::default(),
     };
 
     let data = [0xf0u8; 11];
@@ -767,6 +775,88 @@ async fn frees_dropped_packets() {
     }
 }
 
+/// Ensures that while draining, packets for existing sessions are still
+/// forwarded, but packets that would create a new session are dropped
+#[tokio::test]
+async fn rejects_new_sessions_while_draining() {
+    const SERVER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(1, 1, 1, 1), 1111);
+    const PROXY: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(2, 2, 2, 2), 7777);
+    const CLIENT: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(5, 5, 5, 5), 8888);
+    const NEW_CLIENT: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(6, 6, 6, 6), 9999);
+
+    let mut cfg_state = make_config(
+        qt::filter_chain!([
+            Capture => filters::capture::Config::with_strategy(filters::capture::Suffix {
+                size: 1,
+                remove: false,
+            }),
+            TokenRouter => None,
+        ]),
+        endpoints(&[(SERVER.into(), &[&[0xf0]])]),
+    );
+
+    let mut state = process::State {
+        external_port: PROXY.port().into(),
+        qcmp_port: 0.into(),
+        destinations: Vec::with_capacity(1),
+        addr_to_asn: Default::default(),
+        sessions: Arc::new(Default::default()),
+        local_ipv4: *PROXY.ip(),
+        local_ipv6: Ipv6Addr::from_bits(0),
+        last_receive: UtcTimestamp::now(),
+        dscp: Default::default(),
+    };
+
+    let data = [0xf0u8; 11];
+
+    let mut umem = xdp::Umem::map(
+        xdp::umem::UmemCfgBuilder {
+            frame_size: xdp::umem::FrameSize::TwoK,
+            head_room: 0,
+            frame_count: 1,
+            ..Default::default()
+        }
+        .build()
+        .unwrap(),
+    )
+    .unwrap();
+
+    let mut rx_slab = LittleSlab::new();
+    let mut tx_slab = LittleSlab::new();
+
+    let mut send = |client: SocketAddrV4, state: &mut process::State| {
+        let mut client_packet = unsafe { umem.alloc().expect("umem has no available packets") };
+
+        etherparse::PacketBuilder::ethernet2([3, 3, 3, 3, 3, 3], [4, 4, 4, 4, 4, 4])
+            .ipv4(client.ip().octets(), PROXY.ip().octets(), 64)
+            .udp(client.port(), PROXY.port())
+            .write(&mut client_packet, &data)
+            .unwrap();
+
+        rx_slab.push_front(client_packet);
+        process::process_packets(&mut rx_slab, &mut umem, &mut tx_slab, &mut cfg_state, state);
+
+        let forwarded = tx_slab.pop_back();
+        let was_forwarded = forwarded.is_some();
+        if let Some(server_packet) = forwarded {
+            umem.free_packet(server_packet);
+        }
+        was_forwarded
+    };
+
+    assert!(send(CLIENT, &mut state));
+    assert_eq!(state.sessions.count(), 1);
+
+    state.sessions.drain();
+
+    assert!(send(CLIENT, &mut state), "existing session was dropped");
+    assert!(
+        !send(NEW_CLIENT, &mut state),
+        "new session was created while draining"
+    );
+    assert_eq!(state.sessions.count(), 1);
+}
+
 /// Validates we can process QCMP packets with the same loop as regular packets
 #[tokio::test]
 async fn qcmp() {
@@ -786,6 +876,7 @@ async fn qcmp() {
         local_ipv4: *PROXY.ip(),
         local_ipv6: Ipv6Addr::from_bits(0),
         last_receive: UtcTimestamp::now(),
+        dscp: Default::default(),
     };
 
     let mut umem = xdp::Umem::map(
@@ -954,6 +1045,7 @@ async fn trims_ethernet_padding() {
         local_ipv4: *PROXY.ip(),
         local_ipv6: Ipv6Addr::from_bits(0),
         last_receive: UtcTimestamp::now(),
+        dscp: Default::default(),
     };
 
     // The size of a QCMP ping, which pads to the minimum frame size
@@ -1023,6 +1115,7 @@ async fn drops_unparsable_packets() {
         local_ipv4: *PROXY.ip(),
         local_ipv6: Ipv6Addr::from_bits(0),
         last_receive: UtcTimestamp::now(),
+        dscp: Default::default(),
     };
 
     let mut umem = xdp::Umem::map(
@@ -1143,6 +1236,7 @@ async fn drops_packets_filters_cant_modify() {
             local_ipv4: *PROXY.ip(),
             local_ipv6: Ipv6Addr::from_bits(0),
             last_receive: UtcTimestamp::now(),
+            dscp: Default::default(),
         };
 
         // If this fails, the previously dropped packet wasn't freed
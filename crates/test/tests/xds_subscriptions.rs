@@ -0,0 +1,100 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Tests that the control plane only sends the cluster resources a client has
+//! subscribed to the names of
+
+use qt::*;
+use quilkin::net::endpoint::{Endpoint, Locality};
+use quilkin_xds::{config::ClientState, discovery::DeltaDiscoveryRequest};
+use std::{collections::BTreeSet, net::Ipv6Addr};
+
+fn names(res: &quilkin_xds::config::DeltaDiscoveryRes) -> BTreeSet<String> {
+    res.resources.iter().map(|r| r.name.clone()).collect()
+}
+
+trace_test!(named_locality_subscriptions, {
+    let mut svc = quilkin::Service::default().udp().udp_port(0);
+    let config = quilkin::Config::new(
+        Some("subscriptions".into()),
+        Default::default(),
+        &Default::default(),
+        &mut svc,
+    );
+
+    let clusters = config.dyn_cfg.clusters().unwrap();
+    let endpoint = |port: u16| Endpoint::new((Ipv6Addr::LOCALHOST, port).into());
+    clusters.insert(
+        None,
+        Some(Locality::new("eu", "north", "")),
+        [endpoint(1)].into(),
+    );
+    clusters.insert(
+        None,
+        Some(Locality::new("eu", "south", "")),
+        [endpoint(2)].into(),
+    );
+    clusters.insert(
+        None,
+        Some(Locality::with_region("us")),
+        [endpoint(3)].into(),
+    );
+    clusters.insert(None, None, [endpoint(4)].into());
+
+    // Not subscribing to any names is a wildcard subscription
+    let mut client_state = ClientState::new(quilkin::xds::CLUSTER_TYPE.into());
+    let res = config.delta_discovery_request(&client_state).unwrap();
+    assert_eq!(
+        names(&res),
+        ["", "eu:north", "eu:south", "us"].map(String::from).into()
+    );
+
+    // A region subscribes to every locality within it
+    client_state.update(DeltaDiscoveryRequest {
+        resource_names_subscribe: vec!["eu".into()],
+        ..Default::default()
+    });
+    let res = config.delta_discovery_request(&client_state).unwrap();
+    assert_eq!(
+        names(&res),
+        ["eu:north", "eu:south"].map(String::from).into()
+    );
+    assert!(res.removed.is_empty());
+
+    for resource in res.resources {
+        client_state
+            .versions
+            .insert(resource.name, resource.version);
+    }
+
+    // Narrowing the subscription removes the localities no longer subscribed to
+    client_state.update(DeltaDiscoveryRequest {
+        resource_names_subscribe: vec!["eu:north".into(), String::new()],
+        resource_names_unsubscribe: vec!["eu".into()],
+        ..Default::default()
+    });
+    let res = config.delta_discovery_request(&client_state).unwrap();
+    assert_eq!(names(&res), ["", "eu:north"].map(String::from).into());
+    assert_eq!(res.removed, ["eu:south".to_owned()].into());
+
+    // Names that aren't localities are rejected
+    client_state.update(DeltaDiscoveryRequest {
+        resource_names_subscribe: vec!["eu:north:a:b".into()],
+        ..Default::default()
+    });
+    let error = config.delta_discovery_request(&client_state).unwrap_err();
+    assert!(error.to_string().contains("eu:north:a:b"), "{error}");
+});
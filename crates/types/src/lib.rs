@@ -1,5 +1,7 @@
 mod endpoint;
 mod icao;
+#[cfg(feature = "tls")]
+pub mod tls;
 mod tokens;
 
 pub use endpoint::{AddressKind, Endpoint};
@@ -0,0 +1,75 @@
+//! The parts of TLS configuration shared by the xDS and gossip transports
+
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject as _},
+    sign::CertifiedKey,
+};
+use std::{fmt, sync::Arc};
+
+#[derive(Debug)]
+pub enum KeyError {
+    Pem {
+        kind: &'static str,
+        error: rustls::pki_types::pem::Error,
+    },
+    NoCertificates,
+    Rustls(rustls::Error),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pem { kind, error } => write!(f, "failed to parse PEM {kind}: {error}"),
+            Self::NoCertificates => f.write_str("no certificates found in the PEM certificate"),
+            Self::Rustls(error) => fmt::Display::fmt(error, f),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Pem { error, .. } => Some(error),
+            Self::NoCertificates => None,
+            Self::Rustls(error) => Some(error),
+        }
+    }
+}
+
+/// The process wide default crypto provider if one was installed, otherwise
+/// the `ring` provider
+#[inline]
+pub fn crypto_provider() -> Arc<rustls::crypto::CryptoProvider> {
+    rustls::crypto::CryptoProvider::get_default()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(rustls::crypto::ring::default_provider()))
+}
+
+/// Parses a PEM encoded certificate chain and the private key of its leaf
+/// certificate
+pub fn certified_key(
+    cert: &[u8],
+    key: &[u8],
+    provider: &rustls::crypto::CryptoProvider,
+) -> Result<Arc<CertifiedKey>, KeyError> {
+    let chain = CertificateDer::pem_slice_iter(cert)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| KeyError::Pem {
+            kind: "certificate",
+            error,
+        })?;
+    if chain.is_empty() {
+        return Err(KeyError::NoCertificates);
+    }
+
+    let key = PrivateKeyDer::from_pem_slice(key).map_err(|error| KeyError::Pem {
+        kind: "private key",
+        error,
+    })?;
+
+    // Unlike `CertifiedKey::new`, this also checks that the key belongs to
+    // the certificate
+    CertifiedKey::from_der(chain, key, provider)
+        .map(Arc::new)
+        .map_err(KeyError::Rustls)
+}
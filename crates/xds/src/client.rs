@@ -163,6 +163,16 @@ impl<C: ServiceClient> Client<C> {
         })
     }
 
+    /// Connects to the management servers, verifying their certificates, and
+    /// presenting our own if the servers require client authentication
+    pub async fn connect_with_tls(
+        identifier: String,
+        management_servers: Vec<Endpoint>,
+        tls: &crate::tls::ClientTls,
+    ) -> Result<Self> {
+        Self::connect(identifier, tls.apply(management_servers)?).await
+    }
+
     async fn connect_with_backoff(management_servers: &[Endpoint]) -> Result<(C, Endpoint)> {
         use crate::config::{
             BACKOFF_INITIAL_DELAY, BACKOFF_MAX_DELAY, BACKOFF_MAX_JITTER, CONNECTION_TIMEOUT,
@@ -307,7 +317,7 @@ impl MdsClient {
                         });
 
                         tokio::select! {
-                            res = control_plane.delta_aggregated_resources(stream) => {
+                            res = control_plane.delta_aggregated_resources(stream, None) => {
                                 match res {
                                     Ok(mut stream) => {
                                         loop {
@@ -558,7 +568,7 @@ pub async fn delta_subscribe<C: crate::config::Configuration>(
     endpoints: Vec<Endpoint>,
     health: impl HealthState + Send + 'static,
     notifier: Option<tokio::sync::mpsc::UnboundedSender<String>>,
-    resources: &'static [(&'static str, &'static [(&'static str, Vec<String>)])],
+    resources: Vec<(&'static str, Vec<(&'static str, Vec<String>)>)>,
 ) -> eyre::Result<tokio::task::JoinHandle<Result<()>>> {
     let (mut client, mut response_stream, mut connected_endpoint) =
         DeltaClientStream::connect(&endpoints, identifier.clone())
@@ -571,10 +581,11 @@ pub async fn delta_subscribe<C: crate::config::Configuration>(
                 );
             })?;
 
+    #[allow(clippy::type_complexity)]
     async fn handle_first_response(
         stream: &mut tonic::Streaming<DeltaDiscoveryResponse>,
-        resources: &'static [(&'static str, &'static [(&'static str, Vec<String>)])],
-    ) -> eyre::Result<(String, &'static [(&'static str, Vec<String>)])> {
+        resources: &[(&'static str, Vec<(&'static str, Vec<String>)>)],
+    ) -> eyre::Result<(String, Vec<(&'static str, Vec<String>)>)> {
         const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
 
         match tokio::time::timeout(TIMEOUT, stream.message()).await {
@@ -601,7 +612,9 @@ pub async fn delta_subscribe<C: crate::config::Configuration>(
 
                 resources
                     .iter()
-                    .find_map(|(vers, subs)| (*vers == first.system_version_info).then_some(*subs))
+                    .find_map(|(vers, subs)| {
+                        (*vers == first.system_version_info).then(|| subs.clone())
+                    })
                     .map(|rs| (control_plane_identifier, rs))
                     .with_context(|| {
                         crate::metrics::errors_total(KIND_CLIENT, "no_resource").inc();
@@ -616,7 +629,7 @@ pub async fn delta_subscribe<C: crate::config::Configuration>(
 
     let (mut control_plane, resource_subscriptions) = match handle_first_response(
         &mut response_stream,
-        resources,
+        &resources,
     )
     .await
     {
@@ -725,7 +738,7 @@ pub async fn delta_subscribe<C: crate::config::Configuration>(
                         }
                     }
 
-                    match handle_first_response(&mut response_stream, resources).await {
+                    match handle_first_response(&mut response_stream, &resources).await {
                         Ok((id, rs)) => {
                             control_plane = id;
                             resource_subscriptions = rs;
@@ -754,6 +767,209 @@ pub async fn delta_subscribe<C: crate::config::Configuration>(
     Ok(handle)
 }
 
+pub(crate) struct SotwClientStream {
+    req_tx: tokio::sync::mpsc::UnboundedSender<DiscoveryRequest>,
+}
+
+impl SotwClientStream {
+    /// Connects to a management server and subscribes to each of the resource
+    /// types, the subscriptions are sent before the stream is established as
+    /// management servers can wait for the first request before responding
+    #[inline]
+    async fn connect(
+        endpoints: &[Endpoint],
+        identifier: &str,
+        subscriptions: &[(&'static str, Vec<String>)],
+    ) -> Result<(Self, tonic::Streaming<DiscoveryResponse>, Endpoint)> {
+        crate::metrics::actions_total(KIND_CLIENT, "connect").inc();
+        let (mut client, ep) = AdsClient::connect_with_backoff(endpoints).await?;
+
+        let (req_tx, requests_rx) = tokio::sync::mpsc::unbounded_channel();
+        let scs = Self { req_tx };
+
+        for (rt, names) in subscriptions {
+            scs.send_request(DiscoveryRequest {
+                node: Some(Node {
+                    id: identifier.to_owned(),
+                    user_agent_name: "quilkin".into(),
+                    ..Node::default()
+                }),
+                type_url: (*rt).to_owned(),
+                resource_names: names.clone(),
+                ..Default::default()
+            })?;
+        }
+
+        let stream = client
+            .stream_aggregated_resources(tokio_stream::wrappers::UnboundedReceiverStream::new(
+                requests_rx,
+            ))
+            .in_current_span()
+            .await?;
+        Ok((scs, stream.into_inner(), ep))
+    }
+
+    #[inline]
+    fn send_request(&self, request: DiscoveryRequest) -> Result<()> {
+        crate::metrics::actions_total(KIND_CLIENT, "send_request").inc();
+        self.req_tx.send(request)?;
+        Ok(())
+    }
+}
+
+/// Starts a new state of the world (SotW) stream to the xDS management server,
+/// for management servers that don't support delta xDS
+///
+/// Every response contains the full set of resources of a type, which is
+/// compared against the resources already received to only apply the ones that
+/// have been added, changed, or removed.
+pub async fn sotw_subscribe<C: crate::config::Configuration>(
+    config: Arc<C>,
+    identifier: String,
+    endpoints: Vec<Endpoint>,
+    health: impl HealthState + Send + 'static,
+    notifier: Option<tokio::sync::mpsc::UnboundedSender<String>>,
+    subscriptions: Vec<(&'static str, Vec<String>)>,
+) -> eyre::Result<tokio::task::JoinHandle<Result<()>>> {
+    let (mut client, mut response_stream, mut connected_endpoint) =
+        SotwClientStream::connect(&endpoints, &identifier, &subscriptions)
+            .await
+            .inspect_err(|error| {
+                crate::metrics::errors_total(KIND_CLIENT, "connect").inc();
+                tracing::error!(
+                    ?error,
+                    "failed to acquire aggregated state of the world stream from management server"
+                );
+            })?;
+
+    // The local versions are kept across reconnections, so that only the
+    // resources that changed while disconnected are applied
+    let local = Arc::new(crate::config::LocalVersions::new(
+        subscriptions.iter().map(|(s, _)| *s),
+    ));
+
+    let client_id = identifier.clone();
+    let handle = tokio::task::spawn(
+        async move {
+            tracing::trace!("starting xDS state of the world stream task");
+
+            health.set_healthy();
+            loop {
+                let mut ack_request_stream = crate::config::handle_sotw_discovery_responses(
+                    connected_endpoint.uri().to_string(),
+                    response_stream,
+                    config.clone(),
+                    local.clone(),
+                    subscriptions.clone(),
+                    notifier.clone(),
+                );
+
+                tracing::info!(endpoint = %connected_endpoint.uri(), "entering xDS state of the world stream loop");
+                loop {
+                    match ack_request_stream.next().await {
+                        Some(Ok(ack_request)) => {
+                            if let Err(error) = client.send_request(ack_request) {
+                                crate::metrics::errors_total(KIND_CLIENT, "ack_failed").inc();
+                                tracing::error!(%error, "failed to ack state of the world response");
+                            }
+                        }
+                        Some(Err(error)) => {
+                            if crate::is_broken_pipe(&error) {
+                                crate::metrics::actions_total(KIND_CLIENT, "remote_terminate")
+                                    .inc();
+                                tracing::info!(
+                                    endpoint = %connected_endpoint.uri(),
+                                    "remote terminated the connection",
+                                );
+                            } else {
+                                crate::metrics::errors_total(KIND_CLIENT, "unknown").inc();
+                                tracing::warn!(%error, "xds stream error");
+                            }
+                            break;
+                        }
+                        None => {
+                            crate::metrics::actions_total(KIND_CLIENT, "terminate").inc();
+                            tracing::warn!("xDS stream terminated");
+                            break;
+                        }
+                    }
+                }
+
+                health.set_unhealthy("sotw_subscribe: connection lost");
+
+                loop {
+                    tracing::info!("Lost connection to xDS, retrying");
+                    match SotwClientStream::connect(&endpoints, &identifier, &subscriptions).await {
+                        Ok(res) => {
+                            (client, response_stream, connected_endpoint) = res;
+                            break;
+                        }
+                        Err(error) => {
+                            crate::metrics::errors_total(KIND_CLIENT, "connect").inc();
+                            tracing::error!(%error, "failed to establish connection");
+                        }
+                    }
+                }
+
+                tracing::info!("xDS connection refreshed");
+                health.set_healthy();
+            }
+        }
+        .instrument(tracing::trace_span!("xds_client_sotw_stream", client_id)),
+    );
+
+    Ok(handle)
+}
+
+/// Subscribes to the xDS management server, using a delta stream if the
+/// management server supports it, falling back to a state of the world stream
+/// otherwise
+///
+/// The state of the world stream subscribes to the first, ie. latest, set of
+/// `resources`, since the management server can't report which it supports.
+pub async fn subscribe<C: crate::config::Configuration>(
+    config: Arc<C>,
+    identifier: String,
+    endpoints: Vec<Endpoint>,
+    health: impl HealthState + Clone + Send + 'static,
+    notifier: Option<tokio::sync::mpsc::UnboundedSender<String>>,
+    resources: Vec<(&'static str, Vec<(&'static str, Vec<String>)>)>,
+) -> eyre::Result<tokio::task::JoinHandle<Result<()>>> {
+    let error = match delta_subscribe(
+        config.clone(),
+        identifier.clone(),
+        endpoints.clone(),
+        health.clone(),
+        notifier.clone(),
+        resources.clone(),
+    )
+    .await
+    {
+        Ok(handle) => return Ok(handle),
+        Err(error) => error,
+    };
+
+    let unsupported = error
+        .downcast_ref::<tonic::Status>()
+        .is_some_and(|status| status.code() == tonic::Code::Unimplemented);
+    let Some((_, subscriptions)) = resources.into_iter().next().filter(|_| unsupported) else {
+        return Err(error);
+    };
+
+    tracing::info!(
+        "management server does not support delta xDS, falling back to state of the world"
+    );
+    sotw_subscribe(
+        config,
+        identifier,
+        endpoints,
+        health,
+        notifier,
+        subscriptions,
+    )
+    .await
+}
+
 #[derive(Debug, thiserror::Error)]
 enum RpcSessionError {
     #[error("No successful connections to {0} server(s) established")]
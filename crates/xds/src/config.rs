@@ -1,4 +1,6 @@
-use crate::discovery::{DeltaDiscoveryRequest, DeltaDiscoveryResponse, Resource};
+use crate::discovery::{
+    DeltaDiscoveryRequest, DeltaDiscoveryResponse, DiscoveryRequest, DiscoveryResponse, Resource,
+};
 use std::{
     collections::{BTreeSet, HashMap},
     sync::Arc,
@@ -55,6 +57,9 @@ impl LocalVersions {
     }
 }
 
+/// The resource name that explicitly subscribes to every resource of a type
+pub const WILDCARD: &str = "*";
+
 pub struct ClientState {
     pub resource_type: String,
     pub versions: VersionMap,
@@ -104,6 +109,14 @@ impl ClientState {
         }
     }
 
+    /// Checks if the client is subscribed to every resource of the type, either
+    /// explicitly with [`WILDCARD`], or implicitly by not subscribing to any
+    /// particular names
+    #[inline]
+    pub fn is_wildcard(&self) -> bool {
+        self.subscribed.is_empty() || self.subscribed.contains(WILDCARD)
+    }
+
     pub fn version_matches(&self, key: &str, value: &str) -> bool {
         self.versions
             .get(key)
@@ -169,8 +182,9 @@ impl ClientTracker {
             eyre::bail!("unknown type url");
         };
 
+        // The client is still subscribed to removed resources, and should
+        // receive them again if they are added back
         for removed in ack_state.removed {
-            cs.subscribed.remove(&removed);
             cs.versions.remove(&removed);
         }
 
@@ -179,6 +193,63 @@ impl ClientTracker {
     }
 }
 
+/// The state of a client's state of the world (SotW) subscription to a
+/// resource type
+///
+/// Unlike delta subscriptions, every response contains the full set of
+/// resources, so rather than the version of each resource, only the version
+/// of the whole set last sent to the client is tracked
+pub struct SotwState {
+    /// The subscription, the versions are always empty so that every resource
+    /// is included when generating a response
+    pub client_state: ClientState,
+    /// The version of the last response sent to the client
+    pub version_info: Option<String>,
+    /// The nonce of the last response sent to the client, requests with any
+    /// other nonce are stale and ignored
+    pub nonce: Option<uuid::Uuid>,
+}
+
+impl SotwState {
+    pub fn new(resource_type: String, resource_names: Vec<String>) -> Self {
+        let mut client_state = ClientState::new(resource_type);
+        client_state.subscribed.extend(resource_names);
+
+        Self {
+            client_state,
+            version_info: None,
+            nonce: None,
+        }
+    }
+
+    /// Replaces the subscribed resource names, returning true if they changed
+    pub fn subscribe(&mut self, resource_names: Vec<String>) -> bool {
+        let subscribed = resource_names.into_iter().collect::<BTreeSet<_>>();
+        if subscribed == self.client_state.subscribed {
+            return false;
+        }
+
+        self.client_state.subscribed = subscribed;
+        true
+    }
+}
+
+/// Computes the version of a full set of resources sent in a SotW response,
+/// which only changes if a resource is added, removed, or changes version
+pub fn sotw_version(resources: &[Resource]) -> String {
+    use std::hash::{Hash as _, Hasher as _};
+
+    let mut versions = resources
+        .iter()
+        .map(|res| (res.name.as_str(), res.version.as_str()))
+        .collect::<Vec<_>>();
+    versions.sort_unstable();
+
+    let mut hasher = std::hash::DefaultHasher::new();
+    versions.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
 pub trait Configuration: Send + Sync + Sized + 'static {
     fn identifier(&self) -> String;
 
@@ -201,6 +272,19 @@ pub trait Configuration: Send + Sync + Sized + 'static {
         client_state: &ClientState,
     ) -> crate::Result<DeltaDiscoveryRes>;
 
+    /// Converts a resource received in a SotW response, which only contains
+    /// the resource payload, into a named and versioned resource so that it
+    /// can be applied the same as one received in a delta response
+    ///
+    /// Resources wrapped in a [`Resource`] by the management server are
+    /// already named and versioned, so they are not passed to this
+    fn sotw_resource(&self, resource: prost_types::Any) -> crate::Result<Resource> {
+        eyre::bail!(
+            "state of the world resources of type '{}' are not supported",
+            resource.type_url
+        )
+    }
+
     fn on_changed(
         &self,
         subscribed: crate::server::ControlPlane<Self>,
@@ -312,3 +396,159 @@ pub fn handle_delta_discovery_responses<C: Configuration>(
         }
     })
 }
+
+/// The type URL of resources that are wrapped in a [`Resource`], which
+/// management servers can send in SotW responses to provide the name and
+/// version of each resource
+const RESOURCE_WRAPPER_TYPE: &str = "type.googleapis.com/envoy.service.discovery.v3.Resource";
+
+/// Applies the difference between the full set of resources in a SotW response
+/// and the resources that are already known locally
+fn apply_sotw_response<C: Configuration>(
+    config: &C,
+    local: &LocalVersions,
+    type_url: &str,
+    resources: Vec<prost_types::Any>,
+) -> crate::Result<()> {
+    use prost::Message as _;
+
+    let resources = resources
+        .into_iter()
+        .map(|any| {
+            if any.type_url == RESOURCE_WRAPPER_TYPE {
+                Ok(Resource::decode(&*any.value)?)
+            } else {
+                config.sotw_resource(any)
+            }
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+
+    let (changed, removed) = {
+        let lock = local.get(type_url);
+
+        let removed = lock
+            .keys()
+            .filter(|name| !resources.iter().any(|res| &res.name == *name))
+            .cloned()
+            .collect::<Vec<_>>();
+        let changed = resources
+            .into_iter()
+            .filter(|res| lock.get(&res.name) != Some(&res.version))
+            .collect::<Vec<_>>();
+
+        (changed, removed)
+    };
+
+    if changed.is_empty() && removed.is_empty() {
+        tracing::trace!(kind = type_url, "resources are unchanged");
+        return Ok(());
+    }
+
+    tracing::trace!(
+        changed = changed.len(),
+        removed = removed.len(),
+        kind = type_url,
+        "applying state of the world resources"
+    );
+
+    let version_map: Vec<_> = changed
+        .iter()
+        .map(|res| (res.name.clone(), res.version.clone()))
+        .collect();
+
+    config.apply_delta(type_url, changed, &removed, None)?;
+
+    let mut lock = local.get(type_url);
+    for removed in removed {
+        lock.remove(&removed);
+    }
+
+    for (k, v) in version_map {
+        lock.insert(k, v);
+    }
+
+    Ok(())
+}
+
+/// Processes SotW responses from a management server, yielding the ACK/NACK
+/// requests to send back to it
+#[tracing::instrument(skip_all, fields(identifier))]
+pub fn handle_sotw_discovery_responses<C: Configuration>(
+    identifier: String,
+    stream: impl futures::Stream<Item = tonic::Result<DiscoveryResponse>> + 'static + Send,
+    config: Arc<C>,
+    local: Arc<LocalVersions>,
+    subscriptions: Vec<(&'static str, Vec<String>)>,
+    mut notifier: Option<tokio::sync::mpsc::UnboundedSender<String>>,
+) -> std::pin::Pin<Box<dyn futures::Stream<Item = tonic::Result<DiscoveryRequest>> + Send>> {
+    Box::pin(async_stream::try_stream! {
+        let _stream_metrics = crate::metrics::StreamConnectionMetrics::new(identifier.clone());
+        // The version of the last response accepted for each type, which is
+        // sent back when NACKing a response
+        let mut accepted = HashMap::<String, String>::new();
+
+        tracing::trace!("awaiting state of the world response");
+        for await response in stream
+        {
+            let response = match response {
+                Ok(response) => response,
+                Err(error) => {
+                    yield Err(error)?;
+                    break;
+                }
+            };
+
+            let control_plane_identifier = response.control_plane.as_ref().map(|cp| cp.identifier.as_str()).unwrap_or_default();
+
+            crate::metrics::discovery_responses(control_plane_identifier, &response.type_url).inc();
+            tracing::trace!(
+                version = &*response.version_info,
+                r#type = &*response.type_url,
+                nonce = &*response.nonce,
+                "received state of the world response"
+            );
+
+            let type_url = response.type_url;
+            let Some((_, resource_names)) = subscriptions.iter().find(|(ty, _)| *ty == type_url) else {
+                crate::metrics::errors_total(crate::metrics::KIND_CLIENT, "unexpected").inc();
+                tracing::warn!(r#type = type_url, "ignoring response for unsubscribed resource type");
+                continue;
+            };
+
+            let result = apply_sotw_response(&*config, &local, &type_url, response.resources);
+
+            if let Some(note) = &notifier
+                && note.send(type_url.clone()).is_err() {
+                    notifier = None;
+                }
+
+            let (version_info, error_detail) = match result {
+                Ok(()) => {
+                    crate::metrics::acks(control_plane_identifier, &type_url).inc();
+                    accepted.insert(type_url.clone(), response.version_info.clone());
+                    (response.version_info, None)
+                }
+                Err(error) => {
+                    crate::metrics::nacks(control_plane_identifier, &type_url).inc();
+                    (
+                        accepted.get(&type_url).cloned().unwrap_or_default(),
+                        Some(quilkin_proto::generated::google::rpc::Status {
+                            code: 3,
+                            message: error.to_string(),
+                            ..Default::default()
+                        }),
+                    )
+                }
+            };
+
+            yield DiscoveryRequest {
+                version_info,
+                resource_names: resource_names.clone(),
+                type_url,
+                response_nonce: response.nonce,
+                error_detail,
+                ..Default::default()
+            }
+        }
+    })
+}
@@ -20,8 +20,9 @@ pub mod locality;
 pub mod metrics;
 pub mod net;
 pub mod server;
+pub mod tls;
 
-pub use client::{Client, delta_subscribe};
+pub use client::{Client, delta_subscribe, sotw_subscribe, subscribe};
 
 pub use generated::envoy::{
     config::core::v3::{self as core, socket_address},
@@ -92,6 +92,17 @@ impl Locality {
             (o < self.buffer.len()).then(|| &self.buffer[o..])
         })
     }
+
+    /// Checks if `other` is this locality, or is within it, eg. the region
+    /// `eu` contains `eu:north` and `eu:north:a`
+    #[inline]
+    pub fn contains(&self, other: &Self) -> bool {
+        self.region() == other.region()
+            && self.zone().is_none_or(|zone| other.zone() == Some(zone))
+            && self
+                .sub_zone()
+                .is_none_or(|sub_zone| other.sub_zone() == Some(sub_zone))
+    }
 }
 
 impl std::fmt::Display for Locality {
@@ -276,4 +287,20 @@ mod test {
 
         assert!("region::".parse::<Locality>().unwrap().zone().is_none());
     }
+
+    #[test]
+    fn contains() {
+        let region: Locality = "eu".parse().unwrap();
+        let zone: Locality = "eu:north".parse().unwrap();
+        let sub_zone: Locality = "eu:north:a".parse().unwrap();
+
+        assert!(region.contains(&region));
+        assert!(region.contains(&zone));
+        assert!(region.contains(&sub_zone));
+        assert!(zone.contains(&sub_zone));
+        assert!(!zone.contains(&region));
+        assert!(!sub_zone.contains(&zone));
+        assert!(!zone.contains(&"eu:south:a".parse().unwrap()));
+        assert!(!region.contains(&"us:north".parse().unwrap()));
+    }
 }
@@ -92,6 +92,38 @@ pub(crate) fn delta_discovery_responses(
     DELTA_DISCOVERY_RESPONSES.with_label_values(&[control_plane, type_url])
 }
 
+pub(crate) fn discovery_requests(client_id: &str, type_url: &str) -> prometheus::IntCounter {
+    static DISCOVERY_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+        prometheus::register_int_counter_vec_with_registry! {
+            prometheus::opts! {
+                "xds_discovery_requests",
+                "Total number of xDS state of the world discovery requests",
+            },
+            &[CLIENT_ID_LABEL, TYPE_LABEL],
+            crate::metrics::registry(),
+        }
+        .unwrap()
+    });
+
+    DISCOVERY_REQUESTS.with_label_values(&[client_id, type_url])
+}
+
+pub(crate) fn discovery_responses(control_plane: &str, type_url: &str) -> prometheus::IntCounter {
+    static DISCOVERY_RESPONSES: Lazy<IntCounterVec> = Lazy::new(|| {
+        prometheus::register_int_counter_vec_with_registry! {
+            prometheus::opts! {
+                "xds_discovery_responses",
+                "Total number of xDS state of the world discovery responses",
+            },
+            &[CONTROL_PLANE_LABEL, TYPE_LABEL],
+            crate::metrics::registry(),
+        }
+        .unwrap()
+    });
+
+    DISCOVERY_RESPONSES.with_label_values(&[control_plane, type_url])
+}
+
 pub(crate) fn acks(control_plane: &str, type_url: &str) -> prometheus::IntCounter {
     static ACKS: Lazy<IntCounterVec> = Lazy::new(|| {
         prometheus::register_int_counter_vec_with_registry! {
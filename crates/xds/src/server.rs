@@ -33,6 +33,7 @@ use crate::{
     },
     metrics::{self, KIND_SERVER},
     net::TcpListener,
+    tls::{ClientAuth, ClientPolicy, ServerCertificate},
 };
 
 const FORWARDED: &str = "forwarded";
@@ -77,26 +78,81 @@ fn get_external_remote_addr<T>(request: &tonic::Request<T>) -> Option<std::net::
 
 #[derive(Clone)]
 pub struct TlsIdentity {
-    identity: tonic::transport::Identity,
+    certificate: ServerCertificate,
+    client_auth: Option<ClientAuth>,
 }
 
 impl TlsIdentity {
-    pub fn from_raw(cert: &[u8], key: &[u8]) -> Self {
-        Self {
-            identity: tonic::transport::Identity::from_pem(cert, key),
-        }
+    pub fn from_raw(cert: &[u8], key: &[u8]) -> eyre::Result<Self> {
+        Ok(Self {
+            certificate: ServerCertificate::from_pem(cert, key)?,
+            client_auth: None,
+        })
     }
 
     pub fn from_files(cert: &std::path::Path, key: &std::path::Path) -> eyre::Result<Self> {
         use eyre::WrapErr as _;
-        let cert = std::fs::read(cert)
+        let cert_pem = std::fs::read(cert)
             .with_context(|| format!("failed to read PEM certificate from {cert:?}"))?;
-        let key = std::fs::read(key).with_context(|| format!("failed to read key from {key:?}"))?;
+        let key_pem =
+            std::fs::read(key).with_context(|| format!("failed to read key from {key:?}"))?;
 
-        Ok(Self {
-            identity: tonic::transport::Identity::from_pem(cert, key),
-        })
+        Self::from_raw(&cert_pem, &key_pem)
+            .with_context(|| format!("failed to load certificate from {cert:?} and {key:?}"))
     }
+
+    /// Requires clients to present a certificate, authorizing them with the
+    /// policy of its identity
+    pub fn with_client_auth(mut self, client_auth: Option<ClientAuth>) -> Self {
+        self.client_auth = client_auth;
+        self
+    }
+
+    /// The certificate presented to clients, which can be replaced while the
+    /// servers using this identity are running
+    #[inline]
+    pub fn certificate(&self) -> &ServerCertificate {
+        &self.certificate
+    }
+
+    fn server_config(&self) -> eyre::Result<Arc<rustls::ServerConfig>> {
+        let provider = quilkin_types::tls::crypto_provider();
+        let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let builder = if let Some(client_auth) = &self.client_auth {
+            builder.with_client_cert_verifier(client_auth.verifier(provider)?)
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let mut config = builder.with_cert_resolver(Arc::new(self.certificate.clone()));
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        Ok(Arc::new(config))
+    }
+}
+
+/// Serves `router` on `listener` until `shutdown` is signalled, over TLS if
+/// `tls` is set
+fn serve(
+    router: tonic::transport::server::Router,
+    listener: TcpListener,
+    tls: Option<&TlsIdentity>,
+    mut shutdown: ShutdownSignal,
+) -> eyre::Result<impl std::future::Future<Output = crate::Result<()>>> {
+    let incoming = listener.into_stream()?;
+    let signal = async move {
+        drop(shutdown.changed().await);
+    };
+
+    let server = if let Some(tls) = tls {
+        let incoming = crate::tls::accept(incoming, tls.server_config()?);
+        futures::future::Either::Left(router.serve_with_incoming_shutdown(incoming, signal))
+    } else {
+        futures::future::Either::Right(router.serve_with_incoming_shutdown(incoming, signal))
+    };
+
+    Ok(server.map_err(From::from))
 }
 
 const RESPONSE_PROPAGATION_INTERVAL: Duration = Duration::from_millis(100);
@@ -148,6 +204,7 @@ pub struct ControlPlane<C> {
     pub idle_request_interval: Duration,
     tx: tokio::sync::broadcast::Sender<&'static str>,
     pub shutdown: ShutdownSignal,
+    client_auth: Option<ClientAuth>,
 }
 
 impl<C> Clone for ControlPlane<C> {
@@ -157,6 +214,7 @@ impl<C> Clone for ControlPlane<C> {
             idle_request_interval: self.idle_request_interval,
             tx: self.tx.clone(),
             shutdown: self.shutdown.clone(),
+            client_auth: self.client_auth.clone(),
         }
     }
 }
@@ -174,9 +232,21 @@ impl<C: crate::config::Configuration> ControlPlane<C> {
             idle_request_interval,
             tx,
             shutdown,
+            client_auth: None,
         }
     }
 
+    /// Retrieves the policy of the client that sent the request, `None` if
+    /// clients are not authenticated, or the client is not restricted
+    fn authorize<T>(
+        &self,
+        request: &tonic::Request<T>,
+    ) -> Result<Option<ClientPolicy>, tonic::Status> {
+        self.client_auth
+            .as_ref()
+            .map_or(Ok(None), |client_auth| client_auth.authorize(request))
+    }
+
     fn server_builder() -> tonic::transport::Server {
         tonic::transport::Server::builder()
             .http2_keepalive_interval(Some(crate::HTTP2_KEEPALIVE_INTERVAL))
@@ -184,65 +254,43 @@ impl<C: crate::config::Configuration> ControlPlane<C> {
     }
 
     pub fn management_server(
-        self,
+        mut self,
         listener: TcpListener,
         tls: Option<TlsIdentity>,
     ) -> eyre::Result<impl std::future::Future<Output = crate::Result<()>>> {
+        self.client_auth = tls.as_ref().and_then(|tls| tls.client_auth.clone());
         let srx = self.shutdown.clone();
         tokio::spawn({
             let this = self.clone();
             self.config.on_changed(this, srx)
         });
 
-        let mut shutdown = self.shutdown.clone();
+        let shutdown = self.shutdown.clone();
         let server = AggregatedDiscoveryServiceServer::new(self)
             .max_encoding_message_size(crate::config::max_grpc_message_size());
-        let builder = Self::server_builder();
-
-        let mut builder = if let Some(tls) = tls {
-            builder.tls_config(tonic::transport::ServerTlsConfig::new().identity(tls.identity))?
-        } else {
-            builder
-        };
-
-        let server = builder.add_service(server);
+        let server = Self::server_builder().add_service(server);
         tracing::info!("serving management server on port `{}`", listener.port());
-        Ok(server
-            .serve_with_incoming_shutdown(listener.into_stream()?, async move {
-                drop(shutdown.changed().await);
-            })
-            .map_err(From::from))
+        serve(server, listener, tls.as_ref(), shutdown)
     }
 
     pub fn relay_server(
-        self,
+        mut self,
         listener: TcpListener,
         tls: Option<TlsIdentity>,
     ) -> eyre::Result<impl std::future::Future<Output = crate::Result<()>>> {
+        self.client_auth = tls.as_ref().and_then(|tls| tls.client_auth.clone());
         let srx = self.shutdown.clone();
         tokio::spawn({
             let this = self.clone();
             self.config.on_changed(this, srx)
         });
 
-        let mut shutdown = self.shutdown.clone();
+        let shutdown = self.shutdown.clone();
         let server = AggregatedControlPlaneDiscoveryServiceServer::new(self)
             .max_encoding_message_size(crate::config::max_grpc_message_size());
-        let builder = Self::server_builder();
-
-        let mut builder = if let Some(tls) = tls {
-            builder.tls_config(tonic::transport::ServerTlsConfig::new().identity(tls.identity))?
-        } else {
-            builder
-        };
-
-        let server = builder.add_service(server);
+        let server = Self::server_builder().add_service(server);
         tracing::info!("serving relay server on port `{}`", listener.port());
-        Ok(server
-            .serve_with_incoming_shutdown(listener.into_stream()?, async move {
-                drop(shutdown.changed().await);
-            })
-            .map_err(From::from))
+        serve(server, listener, tls.as_ref(), shutdown)
     }
 
     #[inline]
@@ -261,6 +309,7 @@ impl<C: crate::config::Configuration> ControlPlane<C> {
     pub async fn delta_aggregated_resources<S>(
         &self,
         mut streaming: S,
+        policy: Option<ClientPolicy>,
     ) -> Result<
         impl Stream<Item = Result<DeltaDiscoveryResponse, tonic::Status>> + Send + use<S, C>,
         tonic::Status,
@@ -284,6 +333,10 @@ impl<C: crate::config::Configuration> ControlPlane<C> {
             return Err(tonic::Status::invalid_argument("Node identifier required"));
         };
 
+        if let Some(policy) = &policy {
+            policy.check_identifier(&node_id)?;
+        }
+
         let mut rx = self.tx.subscribe();
         let id = self.config.identifier();
 
@@ -316,6 +369,9 @@ impl<C: crate::config::Configuration> ControlPlane<C> {
                 let cs = if let Some(cs) = client_tracker.get_state(type_url) {
                     cs
                 } else if cfg.allow_request_processing(type_url) {
+                    if let Some(policy) = &policy {
+                        policy.check_subscribe(type_url)?;
+                    }
                     client_tracker.track_state(type_url.into())
                 } else {
                     return Err(tonic::Status::invalid_argument(format!(
@@ -493,7 +549,7 @@ impl<C: crate::config::Configuration> ControlPlane<C> {
 
                         let type_url = client_request.type_url.clone();
 
-                        let Some(response) = responder(Some(client_request), &type_url, &mut client_tracker).unwrap() else { continue; };
+                        let Some(response) = responder(Some(client_request), &type_url, &mut client_tracker)? else { continue; };
                         yield response;
                     }
                     _ = shutdown.changed() => {
@@ -513,6 +569,245 @@ impl<C: crate::config::Configuration> ControlPlane<C> {
     }
 }
 
+impl<C: crate::config::Configuration> ControlPlane<C> {
+    /// Serves a state of the world (SotW) stream, where every response
+    /// contains the full set of resources of a type rather than only the ones
+    /// that have changed, for clients that don't support delta xDS
+    pub async fn stream_aggregated_resources<S>(
+        &self,
+        mut streaming: S,
+        policy: Option<ClientPolicy>,
+    ) -> Result<
+        impl Stream<Item = Result<DiscoveryResponse, tonic::Status>> + Send + use<S, C>,
+        tonic::Status,
+    >
+    where
+        S: Stream<Item = Result<DiscoveryRequest, tonic::Status>>
+            + Send
+            + std::marker::Unpin
+            + 'static,
+    {
+        tracing::debug!("starting state of the world stream");
+        let message = streaming.next().await.ok_or_else(|| {
+            tracing::error!("No message found");
+            tonic::Status::invalid_argument("No message found")
+        })??;
+
+        let node_id = if let Some(node) = &message.node {
+            node.id.clone()
+        } else {
+            tracing::error!("Node identifier was not found");
+            return Err(tonic::Status::invalid_argument("Node identifier required"));
+        };
+
+        if let Some(policy) = &policy {
+            policy.check_identifier(&node_id)?;
+        }
+
+        let mut rx = self.tx.subscribe();
+        let id = self.config.identifier();
+
+        tracing::debug!(
+            id,
+            client = node_id,
+            count = self.tx.receiver_count(),
+            "subscribed to config updates"
+        );
+
+        let control_plane = Some(crate::core::ControlPlane {
+            identifier: id.clone(),
+        });
+
+        use crate::config::SotwState;
+        let mut states = std::collections::HashMap::<String, SotwState>::new();
+
+        let cfg = self.config.clone();
+        let mut shutdown = self.shutdown.clone();
+
+        // Generates a response containing every resource of the type, unless
+        // the resources haven't changed since the last response and it isn't
+        // forced by a new subscription
+        let responder = move |type_url: &str,
+                              states: &mut std::collections::HashMap<String, SotwState>,
+                              force: bool|
+              -> Result<Option<DiscoveryResponse>, tonic::Status> {
+            let Some(state) = states.get_mut(type_url) else {
+                return Ok(None);
+            };
+
+            let res = cfg
+                .delta_discovery_request(&state.client_state)
+                .map_err(|error| tonic::Status::internal(error.to_string()))?;
+
+            let version_info = crate::config::sotw_version(&res.resources);
+            if !force && state.version_info.as_deref() == Some(version_info.as_str()) {
+                return Ok(None);
+            }
+
+            let nonce = uuid::Uuid::new_v4();
+            state.version_info = Some(version_info.clone());
+            state.nonce = Some(nonce);
+
+            let response = DiscoveryResponse {
+                version_info,
+                resources: res
+                    .resources
+                    .into_iter()
+                    .filter_map(|res| res.resource)
+                    .collect(),
+                canary: false,
+                type_url: type_url.into(),
+                nonce: nonce.to_string(),
+                control_plane: control_plane.clone(),
+            };
+
+            tracing::trace!(
+                r#type = &*response.type_url,
+                version = &*response.version_info,
+                nonce = &*response.nonce,
+                "state of the world discovery response"
+            );
+
+            Ok(Some(response))
+        };
+
+        let client = node_id.clone();
+        let allowed = self.config.clone();
+
+        // Handles a request from the client, which is either a new or changed
+        // subscription, which is always responded to, or an ACK/NACK of a
+        // previous response
+        let handle_request = move |req: DiscoveryRequest,
+                                   states: &mut std::collections::HashMap<String, SotwState>|
+              -> Result<Option<bool>, tonic::Status> {
+            let type_url = req.type_url;
+            metrics::discovery_requests(&client, &type_url).inc();
+
+            let Some(state) = states.get_mut(&type_url) else {
+                if !allowed.allow_request_processing(&type_url) {
+                    return Err(tonic::Status::invalid_argument(format!(
+                        "resource type '{type_url}' is not allowed by this stream"
+                    )));
+                }
+
+                if let Some(policy) = &policy {
+                    policy.check_subscribe(&type_url)?;
+                }
+
+                states.insert(
+                    type_url.clone(),
+                    SotwState::new(type_url, req.resource_names),
+                );
+                return Ok(Some(true));
+            };
+
+            if !req.response_nonce.is_empty() {
+                if uuid::Uuid::parse_str(&req.response_nonce).ok() != state.nonce {
+                    tracing::trace!(nonce = %req.response_nonce, "ignoring stale request");
+                    return Ok(None);
+                }
+
+                if let Some(error) = &req.error_detail {
+                    metrics::nacks(&client, &type_url).inc();
+                    tracing::error!(nonce = %req.response_nonce, ?error, "NACK");
+                } else {
+                    tracing::trace!(nonce = %req.response_nonce, "ACK");
+                }
+
+                // An ACK or NACK can also change the subscription
+                return Ok(state.subscribe(req.resource_names).then_some(true));
+            }
+
+            // A request without a nonce is the client (re)requesting the resources
+            state.subscribe(req.resource_names);
+            Ok(Some(true))
+        };
+
+        let nid = node_id.clone();
+
+        let first_type_url = message.type_url.clone();
+        let response = match handle_request(message, &mut states)? {
+            Some(force) => responder(&first_type_url, &mut states, force)?,
+            None => None,
+        };
+
+        let stream = async_stream::try_stream! {
+            if let Some(response) = response {
+                yield response;
+            }
+
+            let buffer = ResponseBroadcastPropagationBuffer::default();
+            let mut lag_amount: u64 = 0;
+            let mut propagation_interval = tokio::time::interval(RESPONSE_PROPAGATION_INTERVAL);
+            propagation_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    _ = propagation_interval.tick() => {
+                        let mut resources = buffer.flush();
+                        // If we've been lagging on updates, check everything instead
+                        if lag_amount > 0 {
+                            tracing::warn!(lag_amount, "lagged while receiving response broadcasts");
+                            resources = states.keys().cloned().collect();
+                        }
+                        lag_amount = 0;
+                        for rt in resources {
+                            match responder(&rt, &mut states, false) {
+                                Ok(Some(res)) => yield res,
+                                Ok(None) => {},
+                                Err(error) => {
+                                    crate::metrics::errors_total(KIND_SERVER, "respond").inc();
+                                    tracing::error!(%error, "responder failed to generate response");
+                                }
+                            }
+                        }
+                    }
+                    res = rx.recv() => {
+                        match buffer.ingest(res) {
+                            Ok(_) => {},
+                            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                            Err(tokio::sync::broadcast::error::RecvError::Lagged(amount)) => {
+                                lag_amount += amount;
+                            },
+                        }
+                    }
+                    client_request = streaming.next() => {
+                        let client_request = match client_request.transpose() {
+                            Ok(Some(value)) => value,
+                            Ok(None) => break,
+                            Err(error) => {
+                                crate::metrics::errors_total(KIND_SERVER, "receive").inc();
+                                tracing::error!(%error, "error receiving state of the world request");
+                                continue;
+                            }
+                        };
+
+                        tracing::trace!(resource_type = client_request.type_url, "new state of the world message");
+
+                        let type_url = client_request.type_url.clone();
+                        if let Some(force) = handle_request(client_request, &mut states)?
+                            && let Some(response) = responder(&type_url, &mut states, force)?
+                        {
+                            yield response;
+                        }
+                    }
+                    _ = shutdown.changed() => {
+                        break;
+                    }
+                }
+            }
+
+            tracing::info!("terminating stream");
+        };
+
+        Ok(Box::pin(stream.instrument(tracing::info_span!(
+            "xds_server_sotw_stream",
+            id,
+            client = nid
+        ))))
+    }
+}
+
 #[tonic::async_trait]
 impl<C: crate::config::Configuration> AggregatedDiscoveryService for ControlPlane<C> {
     type StreamAggregatedResourcesStream =
@@ -523,11 +818,14 @@ impl<C: crate::config::Configuration> AggregatedDiscoveryService for ControlPlan
     #[tracing::instrument(skip_all)]
     async fn stream_aggregated_resources(
         &self,
-        _request: tonic::Request<tonic::Streaming<DiscoveryRequest>>,
+        request: tonic::Request<tonic::Streaming<DiscoveryRequest>>,
     ) -> Result<tonic::Response<Self::StreamAggregatedResourcesStream>, tonic::Status> {
-        Err(tonic::Status::unimplemented(
-            "only delta streams are supported",
-        ))
+        let policy = self.authorize(&request)?;
+        Ok(tonic::Response::new(Box::pin(
+            self.stream_aggregated_resources(request.into_inner(), policy)
+                .in_current_span()
+                .await?,
+        )))
     }
 
     #[tracing::instrument(skip_all)]
@@ -535,8 +833,9 @@ impl<C: crate::config::Configuration> AggregatedDiscoveryService for ControlPlan
         &self,
         request: tonic::Request<tonic::Streaming<DeltaDiscoveryRequest>>,
     ) -> Result<tonic::Response<Self::DeltaAggregatedResourcesStream>, tonic::Status> {
+        let policy = self.authorize(&request)?;
         Ok(tonic::Response::new(Box::pin(
-            self.delta_aggregated_resources(request.into_inner())
+            self.delta_aggregated_resources(request.into_inner(), policy)
                 .in_current_span()
                 .await?,
         )))
@@ -569,6 +868,10 @@ impl<C: crate::config::Configuration> AggregatedControlPlaneDiscoveryService for
     ) -> Result<tonic::Response<Self::DeltaAggregatedResourcesStream>, tonic::Status> {
         let remote_addr = get_external_remote_addr(&responses)
             .ok_or_else(|| tonic::Status::invalid_argument("no remote address available"))?;
+        let policy = self.authorize(&responses)?;
+        if let Some(policy) = &policy {
+            policy.check_push()?;
+        }
 
         tracing::info!("control plane discovery delta stream attempt");
         let mut responses = responses.into_inner();
@@ -591,6 +894,9 @@ impl<C: crate::config::Configuration> AggregatedControlPlaneDiscoveryService for
             .ok_or_else(|| tonic::Status::cancelled("received empty first response"))??;
 
         let (identifier, server_version) = handle_first_response(first_response)?;
+        if let Some(policy) = &policy {
+            policy.check_identifier(&identifier)?;
+        }
 
         tracing::info!(identifier, "new control plane delta discovery stream");
         let config = self.config.clone();
@@ -704,6 +1010,7 @@ impl<C: crate::config::Configuration> AggregatedControlPlaneDiscoveryService for
         requests: tonic::Request<tonic::Streaming<DeltaDiscoveryRequest>>,
     ) -> Result<tonic::Response<Self::SubscribeDeltaResourcesStream>, tonic::Status> {
         tracing::debug!("starting delta stream");
+        let policy = self.authorize(&requests)?;
         let mut requests = requests.into_inner();
         let message = requests.next().await.ok_or_else(|| {
             tracing::error!("No message found");
@@ -717,6 +1024,10 @@ impl<C: crate::config::Configuration> AggregatedControlPlaneDiscoveryService for
             return Err(tonic::Status::invalid_argument("Node identifier required"));
         };
 
+        if let Some(policy) = &policy {
+            policy.check_identifier(&node_id)?;
+        }
+
         let mut rx = self.tx.subscribe();
         let id = self.config.identifier();
         let mut shutdown = self.shutdown.clone();
@@ -748,6 +1059,9 @@ impl<C: crate::config::Configuration> AggregatedControlPlaneDiscoveryService for
                 let cs = if let Some(cs) = client_tracker.get_state(type_url) {
                     cs
                 } else if cfg.allow_request_processing(type_url) {
+                    if let Some(policy) = &policy {
+                        policy.check_subscribe(type_url)?;
+                    }
                     client_tracker.track_state(type_url.into())
                 } else {
                     return Err(tonic::Status::invalid_argument(format!(
@@ -923,7 +1237,7 @@ impl<C: crate::config::Configuration> AggregatedControlPlaneDiscoveryService for
 
                         let type_url = client_request.type_url.clone();
 
-                        let Some(response) = responder(Some(client_request), &type_url, &mut client_tracker).unwrap() else { continue; };
+                        let Some(response) = responder(Some(client_request), &type_url, &mut client_tracker)? else { continue; };
                         yield response;
                     }
                     _ = shutdown.changed() => {
@@ -0,0 +1,405 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Mutual TLS for the xDS and relay servers and their clients
+//!
+//! Servers can require clients to present a certificate signed by a CA, with
+//! the identity of the certificate, its DNS or URI SANs, or its common name,
+//! mapped to a [`ClientPolicy`] which limits the
+//! [`Configuration::identifier`](crate::config::Configuration::identifier)s
+//! the client can use, the resource types it can subscribe to, and whether it
+//! can push resources to a relay.
+//!
+//! The certificate servers present can be replaced while they are running
+//! with [`ServerCertificate::reload_pem`], each connection is accepted with
+//! the certificate current at the time.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use eyre::WrapErr as _;
+use quilkin_types::tls::{certified_key, crypto_provider};
+use rustls::{
+    pki_types::{CertificateDer, pem::PemObject as _},
+    sign::CertifiedKey,
+};
+use tokio_stream::StreamExt as _;
+
+/// The resource type or identifier that matches any other
+pub const ANY: &str = "*";
+
+/// What a client with a particular certificate identity is allowed to do
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientPolicy {
+    /// The identifiers the client can use, ie. the node id of a subscriber, or
+    /// the control plane identifier of an agent pushing to a relay
+    #[serde(default)]
+    pub identifiers: Vec<String>,
+    /// The resource types the client can subscribe to
+    #[serde(default)]
+    pub subscribe: Vec<String>,
+    /// Whether the client can push resources to a relay
+    #[serde(default)]
+    pub push: bool,
+}
+
+impl ClientPolicy {
+    #[inline]
+    fn matches(allowed: &[String], value: &str) -> bool {
+        allowed
+            .iter()
+            .any(|allowed| allowed == ANY || allowed == value)
+    }
+
+    /// Checks the client is allowed to use the identifier
+    pub fn check_identifier(&self, identifier: &str) -> Result<(), tonic::Status> {
+        if Self::matches(&self.identifiers, identifier) {
+            Ok(())
+        } else {
+            Err(tonic::Status::permission_denied(format!(
+                "client is not allowed to use the identifier '{identifier}'"
+            )))
+        }
+    }
+
+    /// Checks the client is allowed to subscribe to the resource type
+    pub fn check_subscribe(&self, type_url: &str) -> Result<(), tonic::Status> {
+        if Self::matches(&self.subscribe, type_url) {
+            Ok(())
+        } else {
+            Err(tonic::Status::permission_denied(format!(
+                "client is not allowed to subscribe to '{type_url}'"
+            )))
+        }
+    }
+
+    /// Checks the client is allowed to push resources
+    pub fn check_push(&self) -> Result<(), tonic::Status> {
+        if self.push {
+            Ok(())
+        } else {
+            Err(tonic::Status::permission_denied(
+                "client is not allowed to push resources",
+            ))
+        }
+    }
+}
+
+/// Requires clients of a server to present a certificate signed by a CA, and
+/// authorizes them with the policy of the certificate's identity
+///
+/// If no policies are set, any client with a certificate signed by the CA is
+/// allowed to do anything.
+#[derive(Clone)]
+pub struct ClientAuth {
+    ca: tonic::transport::Certificate,
+    policies: Arc<BTreeMap<String, ClientPolicy>>,
+}
+
+impl ClientAuth {
+    pub fn from_pem(ca: impl AsRef<[u8]>) -> Self {
+        Self {
+            ca: tonic::transport::Certificate::from_pem(ca),
+            policies: Default::default(),
+        }
+    }
+
+    pub fn from_file(ca: &std::path::Path) -> eyre::Result<Self> {
+        let ca = std::fs::read(ca)
+            .with_context(|| format!("failed to read PEM CA bundle from {ca:?}"))?;
+        Ok(Self::from_pem(ca))
+    }
+
+    /// Sets the policies of each certificate identity
+    pub fn with_policies(mut self, policies: BTreeMap<String, ClientPolicy>) -> Self {
+        self.policies = Arc::new(policies);
+        self
+    }
+
+    /// Creates the verifier requiring clients to present a certificate signed
+    /// by the CA
+    pub(crate) fn verifier(
+        &self,
+        provider: Arc<rustls::crypto::CryptoProvider>,
+    ) -> eyre::Result<Arc<dyn rustls::server::danger::ClientCertVerifier>> {
+        let mut roots = rustls::RootCertStore::empty();
+        for ca in CertificateDer::pem_slice_iter(self.ca.get_ref()) {
+            roots.add(ca.context("failed to parse PEM CA bundle")?)?;
+        }
+        eyre::ensure!(
+            !roots.is_empty(),
+            "no certificates found in the PEM CA bundle"
+        );
+
+        Ok(
+            rustls::server::WebPkiClientVerifier::builder_with_provider(roots.into(), provider)
+                .build()?,
+        )
+    }
+
+    /// Retrieves the policy of the client that sent the request, `None` if the
+    /// client is not restricted
+    pub fn authorize<T>(
+        &self,
+        request: &tonic::Request<T>,
+    ) -> Result<Option<ClientPolicy>, tonic::Status> {
+        if self.policies.is_empty() {
+            return Ok(None);
+        }
+
+        let Some(chain) = request.peer_certs() else {
+            return Err(tonic::Status::unauthenticated(
+                "a client certificate is required",
+            ));
+        };
+
+        let identities = chain
+            .first()
+            .map(|leaf| certificate_identities(leaf.as_ref()))
+            .unwrap_or_default();
+
+        identities
+            .iter()
+            .find_map(|identity| self.policies.get(identity))
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| {
+                tracing::warn!(?identities, "client certificate identity not allowed");
+                tonic::Status::permission_denied("client certificate identity is not allowed")
+            })
+    }
+}
+
+impl std::fmt::Debug for ClientAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientAuth")
+            .field("policies", &self.policies)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Retrieves the DNS and URI SANs, and the common name, of a DER encoded
+/// certificate
+pub fn certificate_identities(der: &[u8]) -> Vec<String> {
+    use x509_parser::extensions::GeneralName;
+
+    let Ok((_, cert)) = x509_parser::parse_x509_certificate(der) else {
+        return Vec::new();
+    };
+
+    let mut identities = Vec::new();
+    if let Ok(Some(san)) = cert.subject_alternative_name() {
+        for name in &san.value.general_names {
+            if let GeneralName::DNSName(name) | GeneralName::URI(name) = name {
+                identities.push((*name).to_owned());
+            }
+        }
+    }
+    identities.extend(
+        cert.subject()
+            .iter_common_name()
+            .filter_map(|cn| cn.as_str().ok().map(String::from)),
+    );
+    identities
+}
+
+/// Retrieves the time the first certificate of a PEM encoded chain expires,
+/// as seconds since the UNIX epoch
+pub fn certificate_expiry(cert: &[u8]) -> Option<i64> {
+    let leaf = CertificateDer::pem_slice_iter(cert).next()?.ok()?;
+    let (_, leaf) = x509_parser::parse_x509_certificate(leaf.as_ref()).ok()?;
+    Some(leaf.validity().not_after.timestamp())
+}
+
+/// The certificate chain and private key a server presents to clients
+///
+/// Clones share the same certificate, so replacing it with [`Self::reload_pem`]
+/// applies to every server it was given to.
+#[derive(Clone, Debug)]
+pub struct ServerCertificate(Arc<parking_lot::RwLock<Arc<CertifiedKey>>>);
+
+impl ServerCertificate {
+    /// Creates a certificate from a PEM encoded certificate chain and private key
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> eyre::Result<Self> {
+        let certified = certified_key(cert, key, &crypto_provider())?;
+        Ok(Self(Arc::new(parking_lot::RwLock::new(certified))))
+    }
+
+    /// Replaces the certificate with a new PEM encoded certificate chain and
+    /// private key, keeping the current one if they are invalid
+    pub fn reload_pem(&self, cert: &[u8], key: &[u8]) -> eyre::Result<()> {
+        let certified = certified_key(cert, key, &crypto_provider())?;
+        *self.0.write() = certified;
+        Ok(())
+    }
+}
+
+impl rustls::server::ResolvesServerCert for ServerCertificate {
+    fn resolve(&self, _client_hello: rustls::server::ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.read().clone())
+    }
+}
+
+/// How long a client has to complete the TLS handshake before its connection
+/// is dropped
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Performs the TLS handshake of every connection accepted from `incoming`
+/// concurrently, yielding those that complete it
+pub(crate) fn accept(
+    mut incoming: tokio_stream::wrappers::TcpListenerStream,
+    config: Arc<rustls::ServerConfig>,
+) -> impl futures::Stream<Item = std::io::Result<tokio_rustls::server::TlsStream<tokio::net::TcpStream>>>
++ Send
++ Unpin {
+    let acceptor = tokio_rustls::TlsAcceptor::from(config);
+
+    Box::pin(async_stream::stream! {
+        let mut handshakes = futures::stream::FuturesUnordered::new();
+
+        loop {
+            let accepted = tokio::select! {
+                stream = incoming.next() => match stream {
+                    Some(Ok(stream)) => {
+                        handshakes.push(tokio::time::timeout(
+                            HANDSHAKE_TIMEOUT,
+                            acceptor.accept(stream),
+                        ));
+                        continue;
+                    }
+                    Some(Err(error)) => Err(error),
+                    None => break,
+                },
+                Some(handshake) = handshakes.next() => match handshake {
+                    Ok(Ok(stream)) => Ok(stream),
+                    Ok(Err(error)) => {
+                        tracing::debug!(%error, "TLS handshake failed");
+                        continue;
+                    }
+                    Err(_) => {
+                        tracing::debug!("TLS handshake timed out");
+                        continue;
+                    }
+                },
+            };
+
+            yield accepted;
+        }
+    })
+}
+
+/// The CA used to verify the certificate of xDS and relay servers, and the
+/// certificate this client presents to them
+#[derive(Clone, Debug)]
+pub struct ClientTls {
+    config: tonic::transport::ClientTlsConfig,
+}
+
+impl ClientTls {
+    pub fn from_pem(ca: impl AsRef<[u8]>) -> Self {
+        Self {
+            config: tonic::transport::ClientTlsConfig::new()
+                .ca_certificate(tonic::transport::Certificate::from_pem(ca)),
+        }
+    }
+
+    pub fn from_file(ca: &std::path::Path) -> eyre::Result<Self> {
+        let ca = std::fs::read(ca)
+            .with_context(|| format!("failed to read PEM CA bundle from {ca:?}"))?;
+        Ok(Self::from_pem(ca))
+    }
+
+    /// Sets the certificate and key presented to servers that require client
+    /// authentication
+    pub fn with_identity(mut self, cert: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> Self {
+        self.config = self
+            .config
+            .identity(tonic::transport::Identity::from_pem(cert, key));
+        self
+    }
+
+    /// Sets the identity from the paths of a PEM encoded certificate and key
+    pub fn with_identity_files(
+        self,
+        cert: &std::path::Path,
+        key: &std::path::Path,
+    ) -> eyre::Result<Self> {
+        let cert = std::fs::read(cert)
+            .with_context(|| format!("failed to read PEM certificate from {cert:?}"))?;
+        let key = std::fs::read(key).with_context(|| format!("failed to read key from {key:?}"))?;
+        Ok(self.with_identity(cert, key))
+    }
+
+    /// Sets the name the server certificates are verified against, by default
+    /// the host of the endpoint is used
+    pub fn with_domain_name(mut self, name: impl Into<String>) -> Self {
+        self.config = self.config.domain_name(name);
+        self
+    }
+
+    /// Applies the configuration to each of the endpoints
+    pub fn apply(
+        &self,
+        endpoints: Vec<tonic::transport::Endpoint>,
+    ) -> eyre::Result<Vec<tonic::transport::Endpoint>> {
+        endpoints
+            .into_iter()
+            .map(|endpoint| {
+                let uri = endpoint.uri().clone();
+                endpoint
+                    .tls_config(self.config.clone())
+                    .with_context(|| format!("failed to configure TLS for {uri}"))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn policy_checks() {
+        let policy: ClientPolicy = serde_json::from_value(serde_json::json!({
+            "identifiers": ["proxy-1"],
+            "subscribe": ["type.googleapis.com/quilkin.config.v1alpha1.Cluster"],
+        }))
+        .unwrap();
+
+        assert!(policy.check_identifier("proxy-1").is_ok());
+        assert!(policy.check_identifier("proxy-2").is_err());
+        assert!(
+            policy
+                .check_subscribe("type.googleapis.com/quilkin.config.v1alpha1.Cluster")
+                .is_ok()
+        );
+        assert!(
+            policy
+                .check_subscribe("type.googleapis.com/quilkin.config.v1alpha1.FilterChain")
+                .is_err()
+        );
+        assert!(policy.check_push().is_err());
+
+        let agent = ClientPolicy {
+            identifiers: vec![ANY.into()],
+            subscribe: Vec::new(),
+            push: true,
+        };
+        assert!(agent.check_identifier("eu").is_ok());
+        assert!(agent.check_push().is_ok());
+        assert!(agent.check_subscribe("anything").is_err());
+    }
+}
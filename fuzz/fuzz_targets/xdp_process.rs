@@ -102,6 +102,7 @@ impl Harness {
                 local_ipv4: Ipv4Addr::new(2, 2, 2, 2),
                 local_ipv6: Ipv6Addr::new(2, 2, 2, 2, 2, 2, 2, 2),
                 last_receive: quilkin::time::UtcTimestamp::now(),
+                dscp: Default::default(),
             },
             cfg: process::ConfigState {
                 filters: quilkin::config::filter::FilterChainConfig::new(chain).cached(),
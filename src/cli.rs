@@ -21,10 +21,16 @@ use clap::crate_version;
 
 use strum_macros::{Display, EnumString};
 
-pub use self::{generate_config_schema::GenerateConfigSchema, qcmp::Qcmp};
+pub use self::{
+    config::ConfigCommand, corrosion::Corrosion, generate_config_schema::GenerateConfigSchema,
+    qcmp::Qcmp, simulate::Simulate,
+};
 
+pub mod config;
+pub mod corrosion;
 pub mod generate_config_schema;
 pub mod qcmp;
+pub mod simulate;
 
 #[derive(Debug, clap::Parser)]
 #[command(next_help_heading = "Administration Options")]
@@ -43,6 +49,10 @@ pub struct AdminCli {
     /// The address to bind for the admin server.
     #[clap(long = "admin.address", env = "QUILKIN_ADMIN_ADDRESS")]
     pub address: Option<std::net::SocketAddr>,
+    /// Allows modifying the filters and clusters through the `/config/{resource}`
+    /// routes of the admin server.
+    #[clap(long = "admin.config-writes", env = "QUILKIN_ADMIN_CONFIG_WRITES")]
+    pub config_writes: bool,
 }
 
 #[derive(Debug, clap::Parser)]
@@ -97,7 +107,8 @@ impl LocalityCli {
 #[command(version)]
 #[non_exhaustive]
 pub struct Cli {
-    /// The path to the configuration file for the Quilkin instance.
+    /// The path to the configuration file, or directory of files, for the
+    /// Quilkin instance.
     #[clap(short, long, env = "QUILKIN_CONFIG", default_value = "quilkin.yaml")]
     pub config: PathBuf,
     /// Whether Quilkin will report any results to stdout/stderr.
@@ -127,6 +138,10 @@ pub struct Cli {
     #[command(flatten)]
     pub locality: LocalityCli,
     #[command(flatten)]
+    pub metrics: crate::metrics::MetricsCli,
+    #[command(flatten)]
+    pub otlp: crate::otlp::OtlpCli,
+    #[command(flatten)]
     pub providers: crate::Providers,
     #[command(flatten)]
     pub service: crate::service::Service,
@@ -153,8 +168,14 @@ impl LogFormats {
         self,
         quiet: bool,
         file_writer: Option<tracing_appender::non_blocking::NonBlocking>,
+        otlp_tracer: Option<opentelemetry_sdk::trace::Tracer>,
     ) {
-        use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriterExt};
+        use tracing_subscriber::{
+            Layer as _,
+            fmt::writer::{BoxMakeWriter, MakeWriterExt},
+            layer::SubscriberExt as _,
+            util::SubscriberInitExt as _,
+        };
 
         let env_filter = tracing_subscriber::EnvFilter::builder()
             .with_default_directive(tracing_subscriber::filter::LevelFilter::INFO.into())
@@ -177,25 +198,30 @@ impl LogFormats {
             }
         };
 
-        let subscriber = tracing_subscriber::fmt()
+        let fmt = tracing_subscriber::fmt::layer()
             .with_file(true)
             .with_thread_ids(true)
-            .with_env_filter(env_filter)
             .with_writer(mk_writer);
 
-        match self {
+        let fmt = match self {
             LogFormats::Auto => {
                 use std::io::IsTerminal;
                 if !std::io::stdout().is_terminal() {
-                    subscriber.with_ansi(false).json().init();
+                    fmt.with_ansi(false).json().boxed()
                 } else {
-                    subscriber.init();
+                    fmt.boxed()
                 }
             }
-            LogFormats::Json => subscriber.with_ansi(false).json().init(),
-            LogFormats::Plain => subscriber.init(),
-            LogFormats::Pretty => subscriber.pretty().init(),
-        }
+            LogFormats::Json => fmt.with_ansi(false).json().boxed(),
+            LogFormats::Plain => fmt.boxed(),
+            LogFormats::Pretty => fmt.pretty().boxed(),
+        };
+
+        tracing_subscriber::registry()
+            .with(fmt)
+            .with(otlp_tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+            .with(env_filter)
+            .init();
     }
 }
 
@@ -205,6 +231,11 @@ pub enum Commands {
     GenerateConfigSchema(GenerateConfigSchema),
     #[clap(subcommand)]
     Qcmp(Qcmp),
+    #[clap(subcommand)]
+    Corrosion(Corrosion),
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+    Simulate(Simulate),
 }
 
 impl Cli {
@@ -228,8 +259,13 @@ impl Cli {
             None => (None, None),
         };
 
+        // _otlp_guard should be kept in scope, and will flush any remaining spans when dropped
+        let (otlp_tracer, _otlp_guard) = self.otlp.tracer()?.unzip();
         self.log_format
-            .init_tracing_subscriber(self.quiet, file_writer);
+            .init_tracing_subscriber(self.quiet, file_writer, otlp_tracer);
+
+        // The labels of the packet metrics need to be set before they are created
+        self.metrics.apply();
 
         tracing::info!(
             version = crate_version!(),
@@ -241,6 +277,9 @@ impl Cli {
         // are executed here.
         match self.command {
             Some(Commands::Qcmp(Qcmp::Ping(ping))) => return ping.run().await,
+            Some(Commands::Corrosion(corrosion)) => return corrosion.run().await,
+            Some(Commands::Config(config)) => return config.run(),
+            Some(Commands::Simulate(simulate)) => return simulate.run(),
             Some(Commands::GenerateConfigSchema(generator)) => {
                 return generator.generate_config_schema();
             }
@@ -272,6 +311,8 @@ impl Cli {
         crate::metrics::with_mut_registry(|mut registry| {
             crate::metrics::register_metrics(&mut registry, config.id());
         });
+        self.otlp
+            .spawn_metrics_exporter(config.id(), shutdown_handler.shutdown_rx());
 
         let ready = Arc::<std::sync::atomic::AtomicBool>::default();
         if self.admin.enabled {
@@ -280,6 +321,7 @@ impl Cli {
                 ready.clone(),
                 shutdown_handler.shutdown_tx(),
                 self.admin.address,
+                self.admin.config_writes,
             );
         }
 
@@ -379,6 +421,12 @@ impl std::str::FromStr for Duration {
     }
 }
 
+impl From<std::time::Duration> for Duration {
+    fn from(duration: std::time::Duration) -> Self {
+        Self(duration)
+    }
+}
+
 impl std::ops::Deref for Duration {
     type Target = std::time::Duration;
 
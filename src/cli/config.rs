@@ -0,0 +1,725 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::de::{self, DeserializeSeed as _, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+
+use crate::{
+    config::loader,
+    filters::{CreateFilterArgs, CreationError, FilterRegistry},
+    net::endpoint::{Endpoint, EndpointAddress},
+};
+
+/// The entry of a Kubernetes `ConfigMap` holding the configuration, the same
+/// one read by the Agones provider.
+const CONFIGMAP_KEY: &str = "quilkin.yaml";
+
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum ConfigCommand {
+    Validate(Validate),
+}
+
+impl ConfigCommand {
+    pub fn run(&self) -> crate::Result<()> {
+        match self {
+            Self::Validate(validate) => validate.run(),
+        }
+    }
+}
+
+/// Validates configuration files without starting any services, printing
+/// every problem found along with its line and column, and failing if there
+/// were any.
+///
+/// Kubernetes manifests are also accepted, in which case the `quilkin.yaml`
+/// entry of every `ConfigMap` is validated, and any other resource ignored.
+/// Directories are merged the same way as when they're read, and problems
+/// reported by the field they are in.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Validate {
+    /// The configuration files or directories to validate.
+    #[clap(required = true, num_args = 1..)]
+    pub files: Vec<PathBuf>,
+}
+
+impl Validate {
+    pub fn run(&self) -> crate::Result<()> {
+        let mut problems = 0;
+
+        for path in &self.files {
+            if path.is_dir() {
+                let issues = validate_directory(path);
+                for issue in &issues {
+                    println!("{}: {issue}", path.display());
+                }
+                problems += issues.len();
+                continue;
+            }
+
+            let text = std::fs::read_to_string(path)
+                .map_err(|error| eyre::eyre!("failed to read {}: {error}", path.display()))?;
+            let issues = validate(&text, path.parent().unwrap_or(Path::new(".")));
+            for issue in &issues {
+                println!("{}:{issue}", path.display());
+            }
+            problems += issues.len();
+        }
+
+        if problems > 0 {
+            eyre::bail!("found {problems} problem(s) in the configuration");
+        }
+
+        tracing::info!(files = self.files.len(), "configuration is valid");
+        Ok(())
+    }
+}
+
+/// A problem found in a configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// The line the problem is on, starting from 1.
+    pub line: usize,
+    /// The column the problem is on, starting from 1.
+    pub column: usize,
+    pub message: String,
+}
+
+impl Issue {
+    fn new(location: Option<&serde_yaml::Location>, message: impl fmt::Display) -> Self {
+        Self {
+            line: location.map_or(1, serde_yaml::Location::line),
+            column: location.map_or(1, serde_yaml::Location::column),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Validates every document of a configuration file, with any includes
+/// relative to `dir`, returning the problems found ordered by where they are
+/// in the file.
+pub fn validate(text: &str, dir: &Path) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let probe = loader::expression_probe(text);
+    let mut probes = serde_yaml::Deserializer::from_str(&probe);
+
+    for (index, document) in serde_yaml::Deserializer::from_str(text).enumerate() {
+        let probe = probes
+            .next()
+            .and_then(|probe| <serde_yaml::Value as serde::Deserialize>::deserialize(probe).ok());
+        let value = match <serde_yaml::Value as serde::Deserialize>::deserialize(document) {
+            Ok(value) => value,
+            Err(error) => {
+                // The parser can't recover from syntax errors, so there's
+                // nothing left to check
+                issues.push(Issue::new(error.location().as_ref(), &error));
+                break;
+            }
+        };
+
+        if value.is_null() {
+            continue;
+        }
+
+        let is_resource = value.get("apiVersion").is_some() && value.get("kind").is_some();
+        if !is_resource {
+            issues.extend(validate_config(text, index, value, probe.as_ref(), dir));
+            continue;
+        }
+
+        let embedded = value
+            .get("data")
+            .and_then(|data| data.get(CONFIGMAP_KEY))
+            .and_then(serde_yaml::Value::as_str);
+        let is_configmap = value["kind"].as_str() == Some("ConfigMap");
+        if let Some(embedded) = embedded.filter(|_| is_configmap) {
+            issues.extend(validate_embedded(text, index, embedded, dir));
+        }
+    }
+
+    issues.sort_by_key(|issue| (issue.line, issue.column));
+    issues
+}
+
+/// Validates the configuration of a `ConfigMap`, moving the problems found to
+/// where they are in the manifest.
+fn validate_embedded(text: &str, document: usize, embedded: &str, dir: &Path) -> Vec<Issue> {
+    let path = [Segment::from("data"), Segment::from(CONFIGMAP_KEY)];
+    let location = locate(text, document, &path);
+
+    // Only literal block scalars keep the lines of the configuration as they
+    // are in the manifest, anything else is reported at the entry itself
+    let block = location.as_ref().and_then(|location| {
+        let mut lines = text.lines().skip(location.line() - 1);
+        let header: String = lines.next()?.chars().skip(location.column() - 1).collect();
+        let indent = lines
+            .find(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start().len())?;
+        header.starts_with('|').then_some((location.line(), indent))
+    });
+
+    validate(embedded, dir)
+        .into_iter()
+        .map(|issue| match block {
+            Some((line, indent)) => Issue {
+                line: line + issue.line,
+                column: indent + issue.column,
+                message: issue.message,
+            },
+            None => Issue::new(location.as_ref(), format_args!("{CONFIGMAP_KEY}:{issue}")),
+        })
+        .collect()
+}
+
+/// Validates a single configuration document, in the same way as
+/// [`crate::Config::read_config`] reads it.
+fn validate_config(
+    text: &str,
+    document: usize,
+    mut value: serde_yaml::Value,
+    probe: Option<&serde_yaml::Value>,
+    dir: &Path,
+) -> Vec<Issue> {
+    let mut problems = Vec::new();
+    resolve_includes(&mut value, probe, dir, &mut Vec::new(), &mut problems);
+
+    if problems.is_empty() {
+        match serde_yaml::from_value(value) {
+            Ok(map) => problems = check(map),
+            Err(error) => problems.push(Problem::new(Vec::new(), error)),
+        }
+    }
+
+    problems
+        .into_iter()
+        .map(|problem| {
+            let location = locate(text, document, &problem.path);
+            Issue::new(location.as_ref(), problem.message)
+        })
+        .collect()
+}
+
+/// Validates a directory by the configuration merged from its files, which
+/// has no lines to refer to, so problems are reported by their field instead.
+fn validate_directory(dir: &Path) -> Vec<String> {
+    match loader::load(dir) {
+        Ok(loaded) => check(loaded.config)
+            .into_iter()
+            .map(|problem| format!("{}: {}", field(&problem.path), problem.message))
+            .collect(),
+        Err(error) => vec![format!("{error:#}")],
+    }
+}
+
+/// Substitutes environment variables into every string of `value`, and
+/// replaces every `!include` with the file it refers to, relative to `dir`,
+/// recording a problem for any that can't be substituted or read. `probe` is
+/// the same value parsed from [`loader::expression_probe`].
+fn resolve_includes(
+    value: &mut serde_yaml::Value,
+    probe: Option<&serde_yaml::Value>,
+    dir: &Path,
+    path: &mut Vec<Segment>,
+    problems: &mut Vec<Problem>,
+) {
+    if matches!(
+        value,
+        serde_yaml::Value::String(_) | serde_yaml::Value::Tagged(_)
+    ) {
+        let lookup = |name: &str| std::env::var(name).ok();
+        match loader::substitute_value(std::mem::take(value), probe, &lookup) {
+            Ok(substituted) => *value = substituted,
+            Err(error) => {
+                problems.push(Problem::new(path.clone(), error));
+                return;
+            }
+        }
+    }
+
+    match value {
+        serde_yaml::Value::Tagged(tagged) => {
+            let included = match &tagged.value {
+                serde_yaml::Value::String(file) if tagged.tag == loader::INCLUDE_TAG => {
+                    loader::include(&dir.join(file)).map_err(|error| format!("{error:#}"))
+                }
+                _ => Err(format!("unsupported tag `{}`", tagged.tag)),
+            };
+
+            match included {
+                Ok(included) => *value = included,
+                Err(error) => problems.push(Problem::new(path.clone(), error)),
+            }
+        }
+        serde_yaml::Value::Sequence(items) => {
+            let mut probes = probe
+                .and_then(serde_yaml::Value::as_sequence)
+                .into_iter()
+                .flatten();
+            for (index, item) in items.iter_mut().enumerate() {
+                path.push(index.into());
+                resolve_includes(item, probes.next(), dir, path, problems);
+                path.pop();
+            }
+        }
+        serde_yaml::Value::Mapping(entries) => {
+            let mut probes = probe
+                .and_then(serde_yaml::Value::as_mapping)
+                .into_iter()
+                .flat_map(serde_yaml::Mapping::values);
+            for (key, item) in entries.iter_mut() {
+                path.push(key.as_str().unwrap_or_default().into());
+                resolve_includes(item, probes.next(), dir, path, problems);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A step along the path to a value of a configuration document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl From<&str> for Segment {
+    fn from(key: &str) -> Self {
+        Self::Key(key.into())
+    }
+}
+
+impl From<usize> for Segment {
+    fn from(index: usize) -> Self {
+        Self::Index(index)
+    }
+}
+
+/// Formats `path` as the field it leads to, such as `filters[1].label`.
+fn field(path: &[Segment]) -> String {
+    let mut field = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(key) if field.is_empty() => field.push_str(key),
+            Segment::Key(key) => {
+                field.push('.');
+                field.push_str(key);
+            }
+            Segment::Index(index) => {
+                field.push('[');
+                field.push_str(&index.to_string());
+                field.push(']');
+            }
+        }
+    }
+    field
+}
+
+/// Extends `path` with further segments.
+fn join<const N: usize>(path: &[Segment], segments: [Segment; N]) -> Vec<Segment> {
+    path.iter().cloned().chain(segments).collect()
+}
+
+/// A problem with the value at `path`.
+#[derive(Debug)]
+struct Problem {
+    path: Vec<Segment>,
+    message: String,
+}
+
+impl Problem {
+    fn new(path: Vec<Segment>, message: impl fmt::Display) -> Self {
+        Self {
+            path,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Checks the configuration, applying each field to a fresh [`crate::Config`]
+/// so that anything not explicitly checked fails the same way as when the
+/// file is read by a running instance.
+fn check(map: serde_json::Map<String, Value>) -> Vec<Problem> {
+    let providers = crate::Providers::default();
+    let mut service = crate::Service::builder().udp().qcmp();
+    let config = crate::Config::new(None, Default::default(), &providers, &mut service);
+    let mut problems = Vec::new();
+
+    for (key, value) in map {
+        let path = vec![Segment::from(key.as_str())];
+        let found = problems.len();
+
+        match key.as_str() {
+            "filters" => check_filters(&path, &value, &mut problems),
+            "clusters" => check_clusters(&path, &value, &mut problems),
+            "qcmp_port" => check_port(&path, value.as_u64(), &mut problems),
+            _ => {}
+        }
+
+        if problems.len() > found {
+            continue;
+        }
+
+        let mut field = serde_json::Map::new();
+        field.insert(key, value);
+        if let Err(error) = config.apply_json(field, None) {
+            problems.push(Problem::new(path, format_args!("{error:#}")));
+        }
+    }
+
+    problems
+}
+
+fn check_port(path: &[Segment], port: Option<u64>, problems: &mut Vec<Problem>) {
+    if !matches!(port, Some(1..=65535)) {
+        problems.push(Problem::new(
+            path.to_vec(),
+            "port must be between 1 and 65535",
+        ));
+    }
+}
+
+/// Instantiates every filter through the [`FilterRegistry`], and checks that
+/// no two filters share a label.
+fn check_filters(path: &[Segment], value: &Value, problems: &mut Vec<Problem>) {
+    let Some(filters) = value.as_array() else {
+        return;
+    };
+
+    let mut labels = HashSet::new();
+    for (index, filter) in filters.iter().enumerate() {
+        let path = join(path, [index.into()]);
+        let filter: crate::config::filter::Filter = match serde_json::from_value(filter.clone()) {
+            Ok(filter) => filter,
+            Err(error) => {
+                problems.push(Problem::new(path, error));
+                continue;
+            }
+        };
+
+        let duplicate = filter
+            .label
+            .as_ref()
+            .filter(|label| !labels.insert((*label).clone()));
+        if let Some(label) = duplicate {
+            problems.push(Problem::new(
+                join(&path, ["label".into()]),
+                format_args!("duplicate filter label `{label}`"),
+            ));
+        }
+
+        let args = CreateFilterArgs::fixed(filter.config);
+        if let Err(error) = FilterRegistry::get(&filter.name, args) {
+            let path = match &error {
+                CreationError::NotFound(_) => join(&path, ["name".into()]),
+                CreationError::FieldInvalid { field, .. } => {
+                    join(&path, ["config".into(), field.as_str().into()])
+                }
+                _ => join(&path, ["config".into()]),
+            };
+            problems.push(Problem::new(path, error));
+        }
+    }
+}
+
+fn check_clusters(path: &[Segment], value: &Value, problems: &mut Vec<Problem>) {
+    let Some(clusters) = value.as_array() else {
+        return;
+    };
+
+    for (index, cluster) in clusters.iter().enumerate() {
+        let path = join(path, [index.into()]);
+        let found = problems.len();
+
+        let endpoints = cluster.get("endpoints").and_then(Value::as_array);
+        for (index, endpoint) in endpoints.into_iter().flatten().enumerate() {
+            check_endpoint(
+                &join(&path, ["endpoints".into(), index.into()]),
+                endpoint,
+                problems,
+            );
+        }
+
+        if problems.len() > found {
+            continue;
+        }
+
+        if let Err(error) =
+            serde_json::from_value::<crate::net::cluster::EndpointWithLocality>(cluster.clone())
+        {
+            problems.push(Problem::new(path, error));
+        }
+    }
+}
+
+/// Checks the address and the encoding of the tokens of an endpoint.
+fn check_endpoint(path: &[Segment], endpoint: &Value, problems: &mut Vec<Problem>) {
+    let found = problems.len();
+
+    if let Some(address) = endpoint.get("address").and_then(Value::as_str) {
+        let path = join(path, ["address".into()]);
+        match address.parse::<EndpointAddress>() {
+            Ok(address) => check_port(&path, Some(address.port.into()), problems),
+            Err(error) => problems.push(Problem::new(
+                path,
+                format_args!("invalid address `{address}`: {error}"),
+            )),
+        }
+    }
+
+    let key = crate::net::endpoint::metadata::KEY;
+    let tokens = endpoint
+        .get("metadata")
+        .and_then(|metadata| metadata.get(key))
+        .and_then(|known| known.get("tokens"))
+        .and_then(Value::as_array);
+
+    let mut decoded = HashSet::new();
+    for (index, token) in tokens.into_iter().flatten().enumerate() {
+        let path = join(
+            path,
+            ["metadata".into(), key.into(), "tokens".into(), index.into()],
+        );
+
+        match token.as_str().map(crate::codec::base64::decode) {
+            Some(Ok(token)) => {
+                if !decoded.insert(token) {
+                    problems.push(Problem::new(path, "duplicate token"));
+                }
+            }
+            Some(Err(error)) => {
+                problems.push(Problem::new(path, format_args!("invalid base64: {error}")));
+            }
+            None => problems.push(Problem::new(path, "tokens must be base64 encoded strings")),
+        }
+    }
+
+    if problems.len() > found {
+        return;
+    }
+
+    if let Err(error) = serde_json::from_value::<Endpoint>(endpoint.clone()) {
+        problems.push(Problem::new(path.to_vec(), error));
+    }
+}
+
+/// Finds where the value at `path` is in the `document`th document of `text`,
+/// or the closest value containing it if it can't be found.
+fn locate(text: &str, document: usize, path: &[Segment]) -> Option<serde_yaml::Location> {
+    let document = serde_yaml::Deserializer::from_str(text).nth(document)?;
+    Locator(path).deserialize(document).err()?.location()
+}
+
+/// Walks a document along a path, failing at the value it leads to, so that
+/// the error carries the location of that value.
+struct Locator<'path>(&'path [Segment]);
+
+const LOCATED: &str = "located";
+
+impl<'de> de::DeserializeSeed<'de> for Locator<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Locator<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(LOCATED)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        if let [Segment::Key(key), rest @ ..] = self.0 {
+            while let Some(next) = map.next_key::<serde_yaml::Value>()? {
+                if next.as_str() == Some(key.as_str()) {
+                    return map.next_value_seed(Locator(rest));
+                }
+
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+
+        Err(de::Error::custom(LOCATED))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        if let [Segment::Index(index), rest @ ..] = self.0 {
+            for _ in 0..*index {
+                if seq.next_element::<IgnoredAny>()?.is_none() {
+                    return Err(de::Error::custom(LOCATED));
+                }
+            }
+
+            seq.next_element_seed(Locator(rest))?;
+        }
+
+        Err(de::Error::custom(LOCATED))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locations(issues: &[Issue]) -> Vec<(usize, usize)> {
+        issues
+            .iter()
+            .map(|issue| (issue.line, issue.column))
+            .collect()
+    }
+
+    #[test]
+    fn valid_config() {
+        let text = [
+            "version: v1alpha1",
+            "id: validate",
+            "qcmp_port: 7600",
+            "filters:",
+            "  - name: quilkin.filters.debug.v1alpha1.Debug",
+            "    label: debug",
+            "    config:",
+            "      id: validate",
+            "  - name: quilkin.filters.token_router.v1alpha1.TokenRouter",
+            "clusters:",
+            "  - endpoints:",
+            "      - address: 127.0.0.1:26000",
+            "        metadata:",
+            "          quilkin.dev:",
+            "            tokens:",
+            "              - MXg3aWp5Ng==",
+        ]
+        .join("\n");
+
+        assert_eq!(validate(&text, Path::new(".")), Vec::new());
+    }
+
+    #[test]
+    fn reports_locations() {
+        let text = [
+            "qcmp_port: 0",
+            "filters:",
+            "  - name: quilkin.filters.debug.v1alpha1.Debug",
+            "    label: debug",
+            "  - name: quilkin.filters.debug.v1alpha1.Debug",
+            "    label: debug",
+            "  - name: not.a.Filter",
+            "clusters:",
+            "  - endpoints:",
+            "      - address: \"127.0.0.1:0\"",
+            "        metadata:",
+            "          quilkin.dev:",
+            "            tokens:",
+            "              - \"not base64!\"",
+            "unknown: true",
+        ]
+        .join("\n");
+
+        let issues = validate(&text, Path::new("."));
+        assert_eq!(
+            locations(&issues),
+            [(1, 12), (6, 12), (7, 11), (10, 18), (14, 17), (15, 10)],
+            "{issues:#?}"
+        );
+        assert!(issues[1].message.contains("duplicate filter label"));
+        assert!(issues[2].message.contains("not.a.Filter"));
+        assert!(issues[5].message.contains("unknown"));
+    }
+
+    #[test]
+    fn syntax_error() {
+        let issues = validate("filters:\n  - name: [\n", Path::new("."));
+        assert_eq!(issues.len(), 1, "{issues:#?}");
+        assert!(issues[0].line > 1, "{issues:#?}");
+    }
+
+    #[test]
+    fn configmap() {
+        let text = [
+            "apiVersion: v1",
+            "kind: Service",
+            "metadata:",
+            "  name: quilkin",
+            "---",
+            "apiVersion: v1",
+            "kind: ConfigMap",
+            "metadata:",
+            "  name: quilkin-config",
+            "data:",
+            "  quilkin.yaml: |",
+            "    version: v1alpha1",
+            "    filters:",
+            "      - name: not.a.Filter",
+        ]
+        .join("\n");
+
+        let issues = validate(&text, Path::new("."));
+        assert_eq!(locations(&issues), [(14, 15)], "{issues:#?}");
+    }
+    #[test]
+    fn includes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("filters.yaml"),
+            "- name: quilkin.filters.debug.v1alpha1.Debug\n",
+        )
+        .unwrap();
+
+        let text = [
+            "filters: !include filters.yaml",
+            "clusters: !include missing.yaml",
+        ]
+        .join("\n");
+
+        let issues = validate(&text, dir.path());
+        assert_eq!(locations(&issues), [(2, 11)], "{issues:#?}");
+    }
+
+    #[test]
+    fn unset_variables() {
+        let text = [
+            "# ${QUILKIN_TEST_UNSET_COMMENT}",
+            "version: v1alpha1",
+            "id: ${QUILKIN_TEST_UNSET_ID}",
+        ]
+        .join("\n");
+
+        let issues = validate(&text, Path::new("."));
+        assert_eq!(locations(&issues), [(3, 5)], "{issues:#?}");
+    }
+}
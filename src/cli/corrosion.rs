@@ -0,0 +1,377 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use corrosion::db::offline;
+
+/// Offline inspection and snapshotting of the corrosion database
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum Corrosion {
+    Dump(Dump),
+    Export(Export),
+    Import(Import),
+    Bookkeeping(Bookkeeping),
+    Query(Query),
+    Members(Members),
+}
+
+impl Corrosion {
+    pub async fn run(&self) -> crate::Result<()> {
+        match self {
+            Self::Dump(dump) => dump.run(),
+            Self::Export(export) => export.run(),
+            Self::Import(import) => import.run(),
+            Self::Bookkeeping(bk) => bk.run(),
+            Self::Query(query) => query.run().await,
+            Self::Members(members) => members.run().await,
+        }
+    }
+}
+
+/// The location of the corrosion database
+#[derive(clap::Args, Clone, Debug)]
+pub struct DbPath {
+    /// Path to the root directory where the `SQLite` databases are stored, the
+    /// same as `--service.corrosion.db-path`
+    ///
+    /// If not specified, defaults to `$TMPDIR/quilkin_db`
+    #[clap(long = "db-path", env = "QUILKIN_SERVICE_CORROSION_DB_PATH")]
+    pub db_path: Option<camino::Utf8PathBuf>,
+}
+
+impl DbPath {
+    /// The path of the database file itself
+    fn db_file(&self) -> crate::Result<camino::Utf8PathBuf> {
+        let root = match &self.db_path {
+            Some(root) => root.clone(),
+            None => {
+                let Ok(mut root) = camino::Utf8PathBuf::from_path_buf(std::env::temp_dir()) else {
+                    eyre::bail!("$TEMP_DIR was not utf-8");
+                };
+                root.push("quilkin_db");
+                root
+            }
+        };
+
+        Ok(root.join("db.db"))
+    }
+}
+
+#[derive(clap::ValueEnum, Copy, Clone, Debug)]
+pub enum Table {
+    Servers,
+    Dc,
+    Filter,
+    Filters,
+}
+
+impl From<Table> for offline::Table {
+    fn from(table: Table) -> Self {
+        match table {
+            Table::Servers => Self::Servers,
+            Table::Dc => Self::Datacenters,
+            Table::Filter => Self::Filter,
+            Table::Filters => Self::ScopedFilters,
+        }
+    }
+}
+
+/// Prints the contents of the database tables as JSON
+#[derive(clap::Args, Clone, Debug)]
+pub struct Dump {
+    #[clap(flatten)]
+    pub db: DbPath,
+    /// The table(s) to dump, defaults to all of them
+    #[clap(short, long, value_enum)]
+    pub table: Vec<Table>,
+}
+
+impl Dump {
+    pub fn run(&self) -> crate::Result<()> {
+        let conn = offline::open(&self.db.db_file()?)?;
+
+        let tables = if self.table.is_empty() {
+            offline::Table::ALL.to_vec()
+        } else {
+            self.table.iter().map(|t| (*t).into()).collect()
+        };
+
+        let mut dumped = serde_json::Map::new();
+        for table in tables {
+            dumped.insert(
+                table.name().into(),
+                serde_json::Value::Array(offline::dump(&conn, table)?),
+            );
+        }
+
+        println!("{}", serde_json::to_string_pretty(&dumped)?);
+        Ok(())
+    }
+}
+
+/// Writes a consistent snapshot of the database to a file, which can be
+/// imported into a new node with `quilkin corrosion import`
+#[derive(clap::Args, Clone, Debug)]
+pub struct Export {
+    #[clap(flatten)]
+    pub db: DbPath,
+    /// The path to write the snapshot to, which must not already exist
+    pub output: camino::Utf8PathBuf,
+}
+
+impl Export {
+    pub fn run(&self) -> crate::Result<()> {
+        let conn = offline::open(&self.db.db_file()?)?;
+        offline::export(&conn, &self.output)?;
+
+        tracing::info!(snapshot = %self.output, "exported corrosion snapshot");
+        Ok(())
+    }
+}
+
+/// Bootstraps the database of a new node from a snapshot written by
+/// `quilkin corrosion export`, so that it only needs to gossip the changes made
+/// since the snapshot, rather than performing a full sync
+///
+/// This must be done before the node is started for the first time.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Import {
+    #[clap(flatten)]
+    pub db: DbPath,
+    /// The path of the snapshot to import
+    pub snapshot: camino::Utf8PathBuf,
+}
+
+impl Import {
+    pub fn run(&self) -> crate::Result<()> {
+        let db_file = self.db.db_file()?;
+        offline::import(&self.snapshot, &db_file)?;
+
+        tracing::info!(snapshot = %self.snapshot, db = %db_file, "imported corrosion snapshot");
+        Ok(())
+    }
+}
+
+/// Prints the bookkeeping and clock state of each actor known to the database
+/// as JSON
+#[derive(clap::Args, Clone, Debug)]
+pub struct Bookkeeping {
+    #[clap(flatten)]
+    pub db: DbPath,
+}
+
+impl Bookkeeping {
+    pub fn run(&self) -> crate::Result<()> {
+        let conn = offline::open(&self.db.db_file()?)?;
+        let bookkeeping = offline::bookkeeping(&conn)?;
+
+        println!("{}", serde_json::to_string_pretty(&bookkeeping)?);
+        Ok(())
+    }
+}
+
+/// Runs a read-only SQL query against the corrosion database of a running
+/// node, via its admin server, printing the result as JSON
+#[derive(clap::Args, Clone, Debug)]
+pub struct Query {
+    /// The read-only SQL statement to run
+    pub sql: String,
+    /// The address of the node's admin server
+    #[clap(long, default_value = "http://localhost:8000")]
+    pub admin_address: String,
+    /// The maximum number of rows, or events if subscribing, to return
+    #[clap(short, long)]
+    pub limit: Option<usize>,
+    /// The maximum time the query can run for
+    #[clap(short, long)]
+    pub timeout: Option<crate::cli::Duration>,
+    /// Subscribes to the query, printing the initial rows and then each change
+    /// to them as newline delimited JSON until the limit is reached
+    #[clap(short, long)]
+    pub subscribe: bool,
+}
+
+impl Query {
+    pub async fn run(&self) -> crate::Result<()> {
+        use http_body_util::BodyExt as _;
+        use std::io::Write as _;
+
+        let path = if self.subscribe { "subscribe" } else { "query" };
+        let body = serde_json::json!({
+            "sql": self.sql,
+            "limit": self.limit,
+            "timeout_ms": self.timeout.map(|t| t.0.as_millis() as u64),
+        });
+
+        let mut body = admin_request(&self.admin_address, path, Some(body)).await?;
+
+        if self.subscribe {
+            while let Some(frame) = body.frame().await {
+                if let Ok(data) = frame?.into_data() {
+                    std::io::stdout().write_all(&data)?;
+                }
+            }
+        } else {
+            let output: serde_json::Value =
+                serde_json::from_slice(&body.collect().await?.to_bytes())?;
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+
+        Ok(())
+    }
+}
+
+/// Prints the gossip membership of a running node, and how far behind it is
+/// in syncing changes from each actor, via its admin server
+#[derive(clap::Args, Clone, Debug)]
+pub struct Members {
+    /// The address of the node's admin server
+    #[clap(long, default_value = "http://localhost:8000")]
+    pub admin_address: String,
+    /// Prints the raw JSON rather than tables
+    #[clap(long)]
+    pub json: bool,
+}
+
+impl Members {
+    pub async fn run(&self) -> crate::Result<()> {
+        use corrosion::gossip::status::GossipStatus;
+        use http_body_util::BodyExt as _;
+
+        let body = admin_request(&self.admin_address, "gossip", None).await?;
+        let status: GossipStatus = serde_json::from_slice(&body.collect().await?.to_bytes())?;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&status)?);
+            return Ok(());
+        }
+
+        println!(
+            "actor {} in cluster {}",
+            status.actor_id, status.cluster_id.0
+        );
+        println!();
+
+        let mut members = vec![[
+            "ACTOR".to_owned(),
+            "ADDRESS".into(),
+            "STATE".into(),
+            "INCARNATION".into(),
+            "RING0".into(),
+            "RTT MIN".into(),
+            "RTT AVG".into(),
+        ]];
+        members.extend(status.members.iter().map(|m| {
+            [
+                m.actor_id.to_string(),
+                m.address.to_string(),
+                m.state.to_string(),
+                m.incarnation.to_string(),
+                m.ring0.to_string(),
+                m.rtt_min_ms.map_or("-".into(), |rtt| format!("{rtt}ms")),
+                m.rtt_avg_ms.map_or("-".into(), |rtt| format!("{rtt:.1}ms")),
+            ]
+        }));
+        print_table(&members);
+        println!();
+
+        let mut sync = vec![[
+            "ACTOR".to_owned(),
+            "HEAD".into(),
+            "NEEDED".into(),
+            "PARTIALS".into(),
+            "LAST SERVED".into(),
+            "REQUESTED".into(),
+            "ERROR".into(),
+        ]];
+        sync.extend(status.sync.iter().map(|s| {
+            let served = s.last_served.as_ref();
+            [
+                s.actor_id.to_string(),
+                s.head.map_or("-".into(), |head| head.to_string()),
+                s.needed_versions.to_string(),
+                s.incomplete_partials.to_string(),
+                served.map_or("-".into(), |ls| format!("{:.0}s ago", ls.secs_ago)),
+                served.map_or("-".into(), |ls| ls.requested_versions.to_string()),
+                served
+                    .and_then(|ls| ls.error.clone())
+                    .unwrap_or_else(|| "-".into()),
+            ]
+        }));
+        print_table(&sync);
+
+        Ok(())
+    }
+}
+
+/// Prints rows with each column padded to the widest value in it
+fn print_table<const N: usize>(rows: &[[String; N]]) {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Sends a request to the corrosion endpoints of a node's admin server,
+/// failing if the response is not successful
+///
+/// The request is a `POST` of the JSON body if there is one, otherwise a `GET`
+async fn admin_request(
+    admin_address: &str,
+    path: &str,
+    body: Option<serde_json::Value>,
+) -> crate::Result<hyper::body::Incoming> {
+    use http_body_util::{BodyExt as _, Full};
+
+    let client = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+        .build_http::<Full<bytes::Bytes>>();
+
+    let uri: hyper::Uri =
+        format!("{}/corrosion/{path}", admin_address.trim_end_matches('/')).parse()?;
+
+    let request = if let Some(body) = body {
+        hyper::Request::post(uri)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Full::new(serde_json::to_vec(&body)?.into()))?
+    } else {
+        hyper::Request::get(uri).body(Full::default())?
+    };
+
+    let response = client.request(request).await?;
+    let status = response.status();
+    let body = response.into_body();
+
+    if !status.is_success() {
+        let message = body.collect().await?.to_bytes();
+        eyre::bail!(
+            "request failed with {status}: {}",
+            String::from_utf8_lossy(&message)
+        );
+    }
+
+    Ok(body)
+}
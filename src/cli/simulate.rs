@@ -0,0 +1,615 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    fmt::Write as _,
+    io::Write as _,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use bytes::BytesMut;
+
+use crate::{
+    filters::{FilterChain, FilterError, ReadContext, WriteContext},
+    net::{
+        ClusterMap,
+        endpoint::{DynamicMetadata, Endpoint, EndpointAddress},
+    },
+};
+
+/// Runs packets through the filter chain of a configuration file without any
+/// sockets, printing how each filter changes the metadata, payload and
+/// destinations of each packet, and the error of any filter that drops one.
+///
+/// Packets sent from one of the endpoints are run through the chain's
+/// `write`, and any other packet through its `read`.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Simulate {
+    /// The configuration file with the filter chain and clusters to simulate.
+    #[clap(short, long, default_value = "quilkin.yaml")]
+    pub config: PathBuf,
+    /// The packets to simulate, either a pcap capture of UDP traffic, or a
+    /// text file with one hex encoded packet per line, optionally preceded by
+    /// the address it was sent from.
+    #[clap(short, long)]
+    pub input: PathBuf,
+    /// Endpoints to use instead of the clusters in the configuration file.
+    #[clap(long)]
+    pub to: Vec<SocketAddr>,
+    /// The address of the client, used as the source of hex encoded packets
+    /// without one, and the destination of packets sent from endpoints.
+    #[clap(long, default_value = "203.0.113.1:12345")]
+    pub client: SocketAddr,
+}
+
+impl Simulate {
+    pub fn run(&self) -> crate::Result<()> {
+        if !self.config.exists() {
+            eyre::bail!("configuration file {} not found", self.config.display());
+        }
+
+        let providers = crate::Providers::default();
+        let mut service = crate::Service::builder().udp();
+        let config = Arc::new(crate::Config::new(
+            None,
+            Default::default(),
+            &providers,
+            &mut service,
+        ));
+        config.read_config(&self.config, None)?;
+
+        let filters = config
+            .dyn_cfg
+            .filters()
+            .ok_or_else(|| eyre::eyre!("the filter chain was not configured"))?
+            .load();
+
+        let clusters = if self.to.is_empty() {
+            config
+                .dyn_cfg
+                .clusters()
+                .ok_or_else(|| eyre::eyre!("the clusters were not configured"))?
+                .clone_value()
+        } else {
+            Arc::new(ClusterMap::new_default(
+                self.to
+                    .iter()
+                    .map(|addr| Endpoint::new((*addr).into()))
+                    .collect(),
+            ))
+        };
+
+        let input = std::fs::read(&self.input)
+            .map_err(|error| eyre::eyre!("failed to read {}: {error}", self.input.display()))?;
+        let packets = if is_pcap(&input) {
+            parse_pcap(&input)?
+        } else {
+            parse_hex(std::str::from_utf8(&input)?)?
+        };
+
+        let simulation = Simulation {
+            filters: &filters,
+            clusters: &clusters,
+            client: self.client,
+        };
+
+        let mut stdout = std::io::stdout().lock();
+        let mut dropped = 0;
+        for (index, packet) in packets.iter().enumerate() {
+            let (report, forwarded) = simulation.run(index + 1, packet);
+            stdout.write_all(report.as_bytes())?;
+            dropped += usize::from(!forwarded);
+        }
+
+        writeln!(
+            stdout,
+            "{} packets simulated, {} forwarded, {dropped} dropped",
+            packets.len(),
+            packets.len() - dropped,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// A packet read from the input.
+#[derive(Debug, PartialEq, Eq)]
+struct Input {
+    /// The address the packet was sent from, if known.
+    source: Option<SocketAddr>,
+    payload: Vec<u8>,
+}
+
+/// The state of a packet in between filters, to find what each filter changed.
+struct State {
+    payload: Vec<u8>,
+    metadata: DynamicMetadata,
+    destinations: Vec<EndpointAddress>,
+}
+
+impl State {
+    fn new(payload: &impl crate::filters::Packet, metadata: &DynamicMetadata) -> Self {
+        Self {
+            payload: payload.as_slice().to_vec(),
+            metadata: metadata.clone(),
+            destinations: Vec::new(),
+        }
+    }
+
+    /// Appends a line to `report` for each change from `self` to `next`,
+    /// replacing `self` with `next`.
+    fn describe(&mut self, next: Self, report: &mut String) {
+        if next.payload != self.payload {
+            let _ = writeln!(
+                report,
+                "      payload: {} -> {} bytes: {}",
+                self.payload.len(),
+                next.payload.len(),
+                hex(&next.payload),
+            );
+        }
+
+        let mut keys: Vec<_> = self.metadata.keys().chain(next.metadata.keys()).collect();
+        keys.sort_by_key(|key| key.to_string());
+        keys.dedup();
+        for key in keys {
+            let _ = match (self.metadata.get(key), next.metadata.get(key)) {
+                (None, Some(value)) => writeln!(report, "      metadata: + {key} = {value}"),
+                (Some(_), None) => writeln!(report, "      metadata: - {key}"),
+                (Some(old), Some(new)) if old != new => {
+                    writeln!(report, "      metadata: ~ {key} = {old} -> {new}")
+                }
+                _ => Ok(()),
+            };
+        }
+
+        if next.destinations != self.destinations {
+            let _ = writeln!(report, "      destinations: {}", list(&next.destinations));
+        }
+
+        *self = next;
+    }
+}
+
+/// The filter chain and clusters packets are simulated with.
+struct Simulation<'s> {
+    filters: &'s FilterChain,
+    clusters: &'s ClusterMap,
+    client: SocketAddr,
+}
+
+impl Simulation<'_> {
+    /// Runs a packet through the chain, returning a report of what happened to
+    /// it, and whether it would have been forwarded.
+    fn run(&self, number: usize, packet: &Input) -> (String, bool) {
+        let source = EndpointAddress::from(packet.source.unwrap_or(self.client));
+        let from_endpoint = self
+            .clusters
+            .endpoints()
+            .iter()
+            .any(|endpoint| endpoint.address == source);
+
+        let mut report = String::new();
+        let contents = BytesMut::from(&packet.payload[..]);
+        let forwarded = if from_endpoint {
+            let _ = writeln!(
+                report,
+                "packet {number}: write {} bytes from {source} to {}",
+                packet.payload.len(),
+                self.client,
+            );
+            self.write(source, contents, &mut report)
+        } else {
+            let _ = writeln!(
+                report,
+                "packet {number}: read {} bytes from {source}",
+                packet.payload.len(),
+            );
+            self.read(source, contents, &mut report)
+        };
+
+        (report, forwarded)
+    }
+
+    fn read(&self, source: EndpointAddress, contents: BytesMut, report: &mut String) -> bool {
+        if !self.clusters.has_endpoints() {
+            let _ = writeln!(report, "  dropped: no upstream endpoints");
+            return false;
+        }
+
+        let mut destinations = Vec::new();
+        let mut ctx = ReadContext::new(self.clusters, source, contents, &mut destinations);
+        let mut state = State::new(&ctx.contents, &ctx.metadata);
+
+        let result = self
+            .filters
+            .read_inspect(&mut ctx, |name, label, ctx, result| {
+                filter_line(report, name, label, result);
+                let mut next = State::new(&ctx.contents, &ctx.metadata);
+                next.destinations.clone_from(&*ctx.destinations);
+                state.describe(next, report);
+            });
+
+        if result.is_err() {
+            return false;
+        }
+
+        if ctx.destinations.is_empty() {
+            let _ = writeln!(report, "  dropped: no destinations");
+            false
+        } else {
+            let _ = writeln!(
+                report,
+                "  forwarded {} bytes to {}",
+                ctx.contents.len(),
+                list(&*ctx.destinations),
+            );
+            true
+        }
+    }
+
+    fn write(&self, source: EndpointAddress, contents: BytesMut, report: &mut String) -> bool {
+        let mut ctx = WriteContext::new(source, self.client.into(), contents);
+        let mut state = State::new(&ctx.contents, &ctx.metadata);
+
+        let result = self
+            .filters
+            .write_inspect(&mut ctx, |name, label, ctx, result| {
+                filter_line(report, name, label, result);
+                state.describe(State::new(&ctx.contents, &ctx.metadata), report);
+            });
+
+        if result.is_err() {
+            return false;
+        }
+
+        let _ = writeln!(
+            report,
+            "  forwarded {} bytes to {}",
+            ctx.contents.len(),
+            ctx.dest
+        );
+        true
+    }
+}
+
+fn filter_line(
+    report: &mut String,
+    name: &str,
+    label: Option<&str>,
+    result: Result<(), &FilterError>,
+) {
+    let _ = match (label, result) {
+        (Some(label), Ok(())) => writeln!(report, "  {name} ({label})"),
+        (None, Ok(())) => writeln!(report, "  {name}"),
+        (Some(label), Err(error)) => writeln!(report, "  {name} ({label}) dropped: {error}"),
+        (None, Err(error)) => writeln!(report, "  {name} dropped: {error}"),
+    };
+}
+
+fn list(addresses: &[EndpointAddress]) -> String {
+    addresses
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Parses packets from hex, one per line, optionally preceded by the address
+/// they were sent from. Empty lines and lines starting with `#` are skipped.
+fn parse_hex(text: &str) -> crate::Result<Vec<Input>> {
+    let mut packets = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace().peekable();
+        let source = words.peek().and_then(|word| word.parse().ok());
+        if source.is_some() {
+            words.next();
+        }
+
+        let digits: Vec<u8> = words
+            .flat_map(str::bytes)
+            .filter(|byte| *byte != b':')
+            .collect();
+        let payload = digits
+            .chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .filter(|pair| pair.len() == 2)
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| eyre::eyre!("line {}: invalid hex encoded packet", index + 1))?;
+
+        packets.push(Input { source, payload });
+    }
+
+    Ok(packets)
+}
+
+const PCAP_MAGIC: [u32; 2] = [0xa1b2_c3d4, 0xa1b2_3c4d];
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const PROTOCOL_UDP: u8 = 17;
+
+fn is_pcap(bytes: &[u8]) -> bool {
+    bytes.get(..4).is_some_and(|magic| {
+        let magic: [u8; 4] = magic.try_into().unwrap();
+        PCAP_MAGIC.contains(&u32::from_be_bytes(magic))
+            || PCAP_MAGIC.contains(&u32::from_le_bytes(magic))
+    })
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+/// Parses the UDP datagrams of a pcap capture, skipping any other packet.
+fn parse_pcap(bytes: &[u8]) -> crate::Result<Vec<Input>> {
+    let big_endian = PCAP_MAGIC.contains(&u32::from_be_bytes(bytes[..4].try_into()?));
+    let u32_at = |offset: usize| -> Option<u32> {
+        let bytes: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let link_type = u32_at(20).ok_or_else(|| eyre::eyre!("truncated pcap header"))? & 0xffff;
+    let mut packets = Vec::new();
+    let mut skipped = 0;
+    let mut offset = 24;
+
+    while offset < bytes.len() {
+        let record = u32_at(offset + 8)
+            .map(|length| offset + 16..offset + 16 + length as usize)
+            .and_then(|range| bytes.get(range))
+            .ok_or_else(|| eyre::eyre!("truncated pcap record at byte {offset}"))?;
+        offset += 16 + record.len();
+
+        match udp_datagram(link_type, record) {
+            Some(packet) => packets.push(packet),
+            None => skipped += 1,
+        }
+    }
+
+    if skipped > 0 {
+        tracing::info!(skipped, "skipped packets that aren't UDP datagrams");
+    }
+
+    Ok(packets)
+}
+
+/// Extracts the source and payload of a UDP datagram from a captured frame.
+fn udp_datagram(link_type: u32, frame: &[u8]) -> Option<Input> {
+    let ip = match link_type {
+        LINKTYPE_ETHERNET => {
+            let start = if u16_at(frame, 12)? == ETHERTYPE_VLAN {
+                18
+            } else {
+                14
+            };
+            frame.get(start..)?
+        }
+        LINKTYPE_LINUX_SLL => frame.get(16..)?,
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => frame,
+        _ => return None,
+    };
+
+    let (source, udp) = match ip.first()? >> 4 {
+        4 => {
+            let header = usize::from(ip[0] & 0xf) * 4;
+            let total = usize::from(u16_at(ip, 2)?);
+            let source: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            if *ip.get(9)? != PROTOCOL_UDP {
+                return None;
+            }
+            (IpAddr::from(source), ip.get(header..total)?)
+        }
+        6 => {
+            let payload = usize::from(u16_at(ip, 4)?);
+            let source: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            if *ip.get(6)? != PROTOCOL_UDP {
+                return None;
+            }
+            (IpAddr::from(source), ip.get(40..40 + payload)?)
+        }
+        _ => return None,
+    };
+
+    let port = u16_at(udp, 0)?;
+    let length = usize::from(u16_at(udp, 4)?);
+
+    Some(Input {
+        source: Some(SocketAddr::new(source, port)),
+        payload: udp.get(8..length)?.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_input() {
+        let packets =
+            parse_hex("# comment\n\n68656c6c6f\n203.0.113.2:7000 de:ad:be:ef\n[::1]:7000 00 01\n")
+                .unwrap();
+
+        assert_eq!(
+            packets,
+            [
+                Input {
+                    source: None,
+                    payload: b"hello".to_vec(),
+                },
+                Input {
+                    source: Some("203.0.113.2:7000".parse().unwrap()),
+                    payload: vec![0xde, 0xad, 0xbe, 0xef],
+                },
+                Input {
+                    source: Some("[::1]:7000".parse().unwrap()),
+                    payload: vec![0, 1],
+                },
+            ]
+        );
+
+        assert!(parse_hex("abc").is_err());
+        assert!(parse_hex("zz").is_err());
+    }
+
+    #[test]
+    fn pcap_input() {
+        let payload = b"hello";
+        let udp_length = 8 + payload.len() as u16;
+        let ip_length = 20 + udp_length;
+
+        let mut frame = vec![0; 12];
+        frame.extend(0x0800u16.to_be_bytes());
+        frame.extend([0x45, 0, 0, 0]);
+        frame[16..18].copy_from_slice(&ip_length.to_be_bytes());
+        frame.extend([0, 0, 0, 0, 64, PROTOCOL_UDP, 0, 0]);
+        frame.extend([203, 0, 113, 2]);
+        frame.extend([10, 0, 0, 1]);
+        frame.extend(7000u16.to_be_bytes());
+        frame.extend(7777u16.to_be_bytes());
+        frame.extend(udp_length.to_be_bytes());
+        frame.extend([0, 0]);
+        frame.extend(payload);
+
+        let mut pcap = Vec::new();
+        pcap.extend(PCAP_MAGIC[0].to_le_bytes());
+        pcap.extend([2, 0, 4, 0]);
+        pcap.extend([0; 8]);
+        pcap.extend(65535u32.to_le_bytes());
+        pcap.extend(LINKTYPE_ETHERNET.to_le_bytes());
+        for _ in 0..2 {
+            pcap.extend([0; 8]);
+            pcap.extend((frame.len() as u32).to_le_bytes());
+            pcap.extend((frame.len() as u32).to_le_bytes());
+            pcap.extend(&frame);
+        }
+
+        assert!(is_pcap(&pcap));
+        assert!(!is_pcap(b"68656c6c6f"));
+        assert_eq!(
+            parse_pcap(&pcap).unwrap(),
+            [
+                Input {
+                    source: Some("203.0.113.2:7000".parse().unwrap()),
+                    payload: payload.to_vec(),
+                },
+                Input {
+                    source: Some("203.0.113.2:7000".parse().unwrap()),
+                    payload: payload.to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn simulates_chain() {
+        let filters: FilterChain = serde_json::from_value(serde_json::json!([
+            {
+                "name": "quilkin.filters.capture.v1alpha1.Capture",
+                "config": { "suffix": { "size": 3, "remove": true } },
+            },
+            {
+                "name": "quilkin.filters.token_router.v1alpha1.TokenRouter",
+                "label": "router",
+            },
+        ]))
+        .unwrap();
+
+        let endpoint: SocketAddr = "127.0.0.1:26000".parse().unwrap();
+        let clusters = ClusterMap::new_default(
+            [Endpoint::with_metadata(
+                endpoint.into(),
+                crate::net::endpoint::Metadata {
+                    tokens: vec![b"abc".to_vec()].into_iter().collect(),
+                },
+            )]
+            .into(),
+        );
+
+        let simulation = Simulation {
+            filters: &filters,
+            clusters: &clusters,
+            client: "203.0.113.1:12345".parse().unwrap(),
+        };
+
+        let (report, forwarded) = simulation.run(
+            1,
+            &Input {
+                source: None,
+                payload: b"helloabc".to_vec(),
+            },
+        );
+        assert!(forwarded, "{report}");
+        assert!(
+            report.contains("payload: 8 -> 5 bytes: 68656c6c6f"),
+            "{report}"
+        );
+        assert!(
+            report.contains("forwarded 5 bytes to 127.0.0.1:26000"),
+            "{report}"
+        );
+
+        let (report, forwarded) = simulation.run(
+            2,
+            &Input {
+                source: None,
+                payload: b"helloxyz".to_vec(),
+            },
+        );
+        assert!(!forwarded, "{report}");
+        assert!(report.contains("(router) dropped"), "{report}");
+
+        let (report, forwarded) = simulation.run(
+            3,
+            &Input {
+                source: Some(endpoint),
+                payload: b"reply".to_vec(),
+            },
+        );
+        assert!(forwarded, "{report}");
+        assert!(report.contains("write 5 bytes"), "{report}");
+    }
+}
@@ -240,6 +240,13 @@ where
         self.0.inner.clear();
     }
 
+    /// Returns an iterator over the entries of the map.
+    /// Unlike [`TtlMap::get`], this does not reset the TTL of the entries visited.
+    #[inline]
+    pub fn iter(&self) -> dashmap::iter::Iter<'_, K, Value<V>> {
+        self.0.inner.iter()
+    }
+
     /// Returns an entry for in-place updates of the specified key-value pair.
     /// Note: This acquires a write lock on the map's shard that corresponds
     /// to the entry.
@@ -14,7 +14,11 @@
  * limitations under the License.
  */
 
+mod config;
+mod corrosion;
+mod drain;
 mod health;
+mod sessions;
 
 use std::sync::{
     Arc,
@@ -43,6 +47,7 @@ pub fn serve(
     ready: Arc<AtomicBool>,
     shutdown_tx: crate::signal::ShutdownTx,
     address: Option<std::net::SocketAddr>,
+    config_writes: bool,
 ) -> std::thread::JoinHandle<()> {
     let address = address.unwrap_or_else(|| (std::net::Ipv6Addr::UNSPECIFIED, PORT).into());
     let health = Health::new(shutdown_tx);
@@ -52,6 +57,7 @@ pub fn serve(
         config,
         health,
         ready,
+        config_writes,
     }
     .router();
 
@@ -82,6 +88,8 @@ struct Admin {
     health: Health,
     config: Arc<crate::Config>,
     ready: Arc<AtomicBool>,
+    /// Whether the configuration can be modified through the admin server
+    config_writes: bool,
 }
 
 #[cfg(target_os = "linux")]
@@ -111,7 +119,30 @@ impl Admin {
             .route("/livez", axum::routing::get(live))
             .route("/ready", axum::routing::get(ready))
             .route("/readyz", axum::routing::get(ready))
-            .route("/config", axum::routing::get(config));
+            .route("/config", axum::routing::get(config))
+            .route("/config/history", axum::routing::get(config::history))
+            .route(
+                "/config/history/{id}/rollback",
+                axum::routing::post(config::rollback),
+            )
+            .route(
+                "/config/{resource}",
+                axum::routing::get(config::get)
+                    .put(config::put)
+                    .patch(config::patch),
+            )
+            .route("/drain", axum::routing::get(drain::get).post(drain::start))
+            .route("/endpoints/health", axum::routing::get(endpoint_health))
+            .route(
+                "/sessions",
+                axum::routing::get(sessions::list).delete(sessions::terminate),
+            )
+            .route("/corrosion/query", axum::routing::post(corrosion::query))
+            .route(
+                "/corrosion/subscribe",
+                axum::routing::post(corrosion::subscribe),
+            )
+            .route("/corrosion/gossip", axum::routing::get(corrosion::gossip));
 
         #[cfg(all(feature = "jemalloc", not(target_env = "msvc")))]
         {
@@ -167,7 +198,13 @@ async fn live(state: State<Admin>) -> StatusCode {
 }
 
 async fn ready(state: State<Admin>) -> StatusCode {
-    if state.ready.load(Ordering::SeqCst) {
+    let draining = state
+        .config
+        .dyn_cfg
+        .drain()
+        .is_some_and(|drain| drain.is_draining());
+
+    if state.ready.load(Ordering::SeqCst) && !draining {
         StatusCode::OK
     } else {
         StatusCode::INTERNAL_SERVER_ERROR
@@ -178,6 +215,42 @@ async fn config(state: State<Admin>) -> Json<Arc<crate::Config>> {
     Json(state.config.clone())
 }
 
+#[derive(serde::Serialize)]
+struct EndpointHealth {
+    endpoints: usize,
+    unhealthy: Vec<UnhealthyEndpoint>,
+}
+
+#[derive(serde::Serialize)]
+struct UnhealthyEndpoint {
+    address: crate::net::EndpointAddress,
+    #[serde(flatten)]
+    state: crate::net::health::Unhealthy,
+}
+
+/// Lists the endpoints that are currently excluded from routing by health checks
+async fn endpoint_health(state: State<Admin>) -> Json<EndpointHealth> {
+    let Some(clusters) = state.config.dyn_cfg.clusters() else {
+        return Json(EndpointHealth {
+            endpoints: 0,
+            unhealthy: Vec::new(),
+        });
+    };
+
+    let clusters = clusters.clone_value();
+    let mut unhealthy: Vec<_> = clusters
+        .unhealthy_endpoints()
+        .into_iter()
+        .map(|(address, state)| UnhealthyEndpoint { address, state })
+        .collect();
+    unhealthy.sort_unstable_by(|a, b| a.address.cmp(&b.address));
+
+    Json(EndpointHealth {
+        endpoints: clusters.num_of_endpoints(),
+        unhealthy,
+    })
+}
+
 fn collect_metrics() -> Response<Body> {
     use prometheus_client::encoding::text::{encode_eof, encode_registry};
     let mut text_encoding = String::new();
@@ -414,6 +487,7 @@ mod tests {
             config: crate::test::TestHelper::new_config(),
             ready: <_>::default(),
             health,
+            config_writes: false,
         };
 
         let server = axum_test::TestServer::new(admin.router()).unwrap();
@@ -427,6 +501,283 @@ mod tests {
         server.get("/live").expect_failure().await;
     }
 
+    #[tokio::test]
+    async fn sessions() {
+        let (shutdown_tx, _shutdown_rx) = crate::signal::channel();
+        let config = crate::test::TestHelper::new_config();
+        let admin = Admin {
+            config: config.clone(),
+            ready: <_>::default(),
+            health: Health::new(shutdown_tx),
+            config_writes: false,
+        };
+
+        let backend = crate::net::io::UdpBackend::default();
+        let (pending_sends, _srecv) = crate::net::queue(1, backend).unwrap();
+        let pool = crate::net::SessionPool::new(
+            vec![pending_sends],
+            crate::config::filter::FilterChainConfig::default().cached(),
+            usize::MAX,
+            backend,
+            64,
+            Default::default(),
+        );
+        config
+            .dyn_cfg
+            .active_sessions()
+            .unwrap()
+            .register_pool(&pool);
+
+        let client = |port: u16| -> std::net::SocketAddr {
+            (std::net::Ipv4Addr::new(1, 1, 1, 1), port).into()
+        };
+        let server: std::net::SocketAddr = (std::net::Ipv4Addr::new(2, 2, 2, 2), 7777).into();
+        for port in [1000, 1001, 1002] {
+            pool.get((client(port), server).into()).unwrap();
+        }
+        pool.get(((std::net::Ipv4Addr::new(3, 3, 3, 3), 1000).into(), server).into())
+            .unwrap();
+
+        let server = axum_test::TestServer::new(admin.router()).unwrap();
+
+        let page: serde_json::Value = server.get("/sessions").await.json();
+        assert_eq!(page["total"], 4);
+        assert_eq!(page["sessions"][0]["source"], "1.1.1.1:1000");
+        assert_eq!(page["sessions"][0]["destination"], "2.2.2.2:7777");
+
+        let page: serde_json::Value = server
+            .get("/sessions?source=1.1.1.1&offset=1&limit=1")
+            .await
+            .json();
+        assert_eq!(page["total"], 3);
+        assert_eq!(page["offset"], 1);
+        assert_eq!(page["sessions"].as_array().unwrap().len(), 1);
+        assert_eq!(page["sessions"][0]["source"], "1.1.1.1:1001");
+
+        // A bare delete would drop every session
+        server.delete("/sessions").expect_failure().await;
+
+        let terminated: serde_json::Value = server.delete("/sessions?source=1.1.1.1").await.json();
+        assert_eq!(terminated["terminated"], 3);
+        assert_eq!(pool.sessions().len(), 1);
+
+        let page: serde_json::Value = server.get("/sessions").await.json();
+        assert_eq!(page["total"], 1);
+        assert_eq!(page["sessions"][0]["source"], "3.3.3.3:1000");
+    }
+
+    #[tokio::test]
+    async fn drain() {
+        let (shutdown_tx, _shutdown_rx) = crate::signal::channel();
+        let admin = Admin {
+            config: crate::test::TestHelper::new_config(),
+            ready: Arc::new(AtomicBool::new(true)),
+            health: Health::new(shutdown_tx),
+            config_writes: false,
+        };
+
+        let server = axum_test::TestServer::new(admin.router()).unwrap();
+
+        server.get("/ready").expect_success().await;
+        let status: serde_json::Value = server.get("/drain").await.json();
+        assert_eq!(status["draining"], false);
+        assert_eq!(status["remaining_sessions"], 0);
+
+        let response = server.post("/drain").await;
+        response.assert_status(StatusCode::ACCEPTED);
+        assert_eq!(response.json::<serde_json::Value>()["draining"], true);
+
+        server.get("/ready").expect_failure().await;
+        let status: serde_json::Value = server.get("/drain").await.json();
+        assert_eq!(status["draining"], true);
+        assert!(status["elapsed_secs"].is_number());
+    }
+
+    #[tokio::test]
+    async fn config_resources() {
+        use serde_json::json;
+
+        let (shutdown_tx, _shutdown_rx) = crate::signal::channel();
+        let config = crate::test::TestHelper::new_config();
+        let admin = |config_writes| Admin {
+            config: config.clone(),
+            ready: <_>::default(),
+            health: Health::new(shutdown_tx.clone()),
+            config_writes,
+        };
+
+        let server = axum_test::TestServer::new(admin(false).router()).unwrap();
+        let clusters: serde_json::Value = server.get("/config/clusters").await.json();
+        assert!(clusters.is_array());
+        server
+            .put("/config/clusters")
+            .json(&json!([]))
+            .expect_failure()
+            .await
+            .assert_status(axum::http::StatusCode::FORBIDDEN);
+        server.get("/config/listeners").expect_failure().await;
+
+        let server = axum_test::TestServer::new(admin(true).router()).unwrap();
+        let endpoints = |port: u16| json!([{ "address": format!("127.0.0.1:{port}") }]);
+
+        let diff: serde_json::Value = server
+            .put("/config/clusters")
+            .json(&json!([
+                { "locality": "eu", "endpoints": endpoints(7000) },
+                { "locality": "us", "endpoints": endpoints(7001) },
+            ]))
+            .await
+            .json();
+        assert_eq!(diff["resource"], "clusters");
+        assert_eq!(diff["changes"].as_array().unwrap().len(), 2);
+
+        // Patching only touches the localities in the request
+        let diff: serde_json::Value = server
+            .patch("/config/clusters")
+            .json(&json!([{ "locality": "eu", "endpoints": endpoints(7002) }]))
+            .await
+            .json();
+        let key = |locality: &str, port: u16| json!({ "locality": locality, "address": format!("127.0.0.1:{port}") });
+        assert_eq!(diff["changes"][0]["key"], key("eu", 7002));
+        assert!(diff["changes"][0]["before"].is_null());
+        assert_eq!(diff["changes"][1]["key"], key("eu", 7000));
+        assert!(diff["changes"][1]["after"].is_null());
+        assert_eq!(diff["changes"].as_array().unwrap().len(), 2);
+
+        // Replacing removes the localities that aren't in the request
+        let diff: serde_json::Value = server
+            .put("/config/clusters")
+            .json(&json!([{ "locality": "eu", "endpoints": endpoints(7002) }]))
+            .await
+            .json();
+        assert_eq!(diff["changes"][0]["key"], key("us", 7001));
+        assert!(diff["changes"][0]["after"].is_null());
+        assert_eq!(config.dyn_cfg.clusters().unwrap().read().len(), 1);
+
+        // Including the localities applied by a remote server
+        config.dyn_cfg.clusters().unwrap().read().insert(
+            Some(std::net::Ipv4Addr::LOCALHOST.into()),
+            Some(crate::net::endpoint::Locality::with_region("asia")),
+            [crate::net::endpoint::Endpoint::new(
+                ([127, 0, 0, 1], 7003).into(),
+            )]
+            .into(),
+        );
+        let diff: serde_json::Value = server
+            .put("/config/clusters")
+            .json(&json!([{ "locality": "eu", "endpoints": endpoints(7002) }]))
+            .await
+            .json();
+        assert_eq!(diff["changes"][0]["key"], key("asia", 7003));
+        assert_eq!(config.dyn_cfg.clusters().unwrap().read().len(), 1);
+
+        server
+            .put("/config/clusters")
+            .json(&json!([{ "locality": "eu:north:a:b", "endpoints": endpoints(7002) }]))
+            .expect_failure()
+            .await
+            .assert_status(axum::http::StatusCode::BAD_REQUEST);
+
+        const DEBUG: &str = "quilkin.filters.debug.v1alpha1.Debug";
+        let debug = |label: &str, id: &str| json!({ "name": DEBUG, "label": label, "config": { "id": id } });
+        server
+            .put("/config/filters")
+            .json(&json!([debug("first", "a"), debug("second", "b")]))
+            .await
+            .assert_status_ok();
+
+        let diff: serde_json::Value = server
+            .patch("/config/filters")
+            .json(&json!([debug("second", "c")]))
+            .await
+            .json();
+        assert_eq!(diff["changes"].as_array().unwrap().len(), 1);
+        assert_eq!(diff["changes"][0]["key"], 1);
+        assert_eq!(diff["changes"][0]["after"]["config"]["id"], "c");
+
+        server
+            .patch("/config/filters")
+            .json(&json!([debug("third", "d")]))
+            .expect_failure()
+            .await
+            .assert_status(axum::http::StatusCode::NOT_FOUND);
+        server
+            .patch("/config/filters")
+            .json(&json!([{ "name": DEBUG, "config": {} }]))
+            .expect_failure()
+            .await
+            .assert_status(axum::http::StatusCode::CONFLICT);
+        server
+            .put("/config/datacenters")
+            .json(&json!([]))
+            .expect_failure()
+            .await
+            .assert_status(axum::http::StatusCode::METHOD_NOT_ALLOWED);
+
+        let history: serde_json::Value =
+            server.get("/config/history?resource=filters").await.json();
+        let history = history.as_array().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1]["source"]["kind"], "admin");
+
+        let diff: serde_json::Value = server
+            .post(&format!("/config/history/{}/rollback", history[0]["id"]))
+            .await
+            .json();
+        assert_eq!(diff["changes"][0]["after"]["config"]["id"], "b");
+        server
+            .post("/config/history/1000/rollback")
+            .expect_failure()
+            .await
+            .assert_status(axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn corrosion_unavailable() {
+        let (shutdown_tx, _shutdown_rx) = crate::signal::channel();
+        let admin = Admin {
+            config: crate::test::TestHelper::new_config(),
+            ready: <_>::default(),
+            health: Health::new(shutdown_tx.clone()),
+            config_writes: false,
+        };
+
+        let server = axum_test::TestServer::new(admin.router()).unwrap();
+        server
+            .get("/corrosion/gossip")
+            .expect_failure()
+            .await
+            .assert_status(axum::http::StatusCode::NOT_FOUND);
+
+        // The replica is only available once the corrosion service is running
+        let config = crate::Config::new_rc(
+            Some("test-server".into()),
+            Default::default(),
+            &crate::Providers::default(),
+            &mut crate::Service::builder().mds(),
+            tokio_util::sync::CancellationToken::new(),
+        );
+        let admin = Admin {
+            config,
+            ready: <_>::default(),
+            health: Health::new(shutdown_tx),
+            config_writes: false,
+        };
+
+        let server = axum_test::TestServer::new(admin.router()).unwrap();
+        server
+            .get("/corrosion/gossip")
+            .expect_failure()
+            .await
+            .assert_status(axum::http::StatusCode::SERVICE_UNAVAILABLE);
+        server
+            .post("/corrosion/query")
+            .json(&serde_json::json!({ "sql": "SELECT 1" }))
+            .expect_failure()
+            .await
+            .assert_status(axum::http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
     #[tokio::test]
     async fn collect_metrics() {
         let response = super::collect_metrics();
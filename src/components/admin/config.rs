@@ -0,0 +1,292 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Reads and modifies individual resources of the live configuration
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::Value;
+
+use super::Admin;
+use crate::config::history::{Change, Entry, Resource, Source};
+
+type Error = (StatusCode, String);
+
+#[derive(Debug, serde::Serialize)]
+pub(super) struct Diff {
+    resource: Resource,
+    changes: Vec<Change>,
+}
+
+fn not_enabled(resource: Resource) -> Error {
+    (
+        StatusCode::NOT_FOUND,
+        format!("{} are not enabled on this instance", resource.key()),
+    )
+}
+
+/// Retrieves the current value of the resource
+fn current(admin: &Admin, resource: Resource) -> Result<Value, Error> {
+    let mut config = serde_json::to_value(&*admin.config)
+        .map_err(|error| (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+
+    config
+        .get_mut(resource.key())
+        .map(Value::take)
+        .ok_or_else(|| not_enabled(resource))
+}
+
+/// Checks the resource can be modified through the admin server
+fn check_writable(admin: &Admin, resource: Resource) -> Result<(), Error> {
+    if !admin.config_writes {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "modifying the configuration is disabled, see `--admin.config-writes`".into(),
+        ));
+    }
+
+    if resource == Resource::Datacenters {
+        return Err((
+            StatusCode::METHOD_NOT_ALLOWED,
+            "datacenters are only applied from remote control planes".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Replaces the filter chain, returning what changed
+fn replace_filters(admin: &Admin, value: Value) -> Result<Json<Diff>, Error> {
+    let filters = admin
+        .config
+        .dyn_cfg
+        .filters()
+        .ok_or_else(|| not_enabled(Resource::Filters))?;
+    let chain: crate::filters::FilterChain = serde_json::from_value(value)
+        .map_err(|error| (StatusCode::BAD_REQUEST, error.to_string()))?;
+
+    // The filter chain that was replaced is returned by the swap rather than
+    // read beforehand, so the diff can't include changes made by providers
+    let before = filters.swap(chain.clone());
+    let changes = admin.config.record_filters(&before, &chain, Source::Admin);
+
+    Ok(Json(Diff {
+        resource: Resource::Filters,
+        changes,
+    }))
+}
+
+/// Replaces the localities of the clusters in the request, taking them over
+/// from whichever provider or remote applied them, and if `remove_others` is
+/// set removes every other locality, returning what changed
+fn replace_clusters(admin: &Admin, value: Value, remove_others: bool) -> Result<Json<Diff>, Error> {
+    let clusters = admin
+        .config
+        .dyn_cfg
+        .clusters()
+        .ok_or_else(|| not_enabled(Resource::Clusters))?;
+    let request: crate::net::cluster::ClusterMapDeser = serde_json::from_value(value)
+        .map_err(|error| (StatusCode::BAD_REQUEST, error.to_string()))?;
+
+    // The endpoints each locality had are returned as it's replaced or
+    // removed, rather than read beforehand, so the diff can't include changes
+    // made by providers
+    let mut replaced = Vec::new();
+    clusters.modify(|clusters| {
+        if remove_others {
+            let stale: Vec<_> = clusters
+                .iter()
+                .map(|entry| entry.key().clone())
+                .filter(|locality| {
+                    !request
+                        .endpoints
+                        .iter()
+                        .any(|cluster| cluster.locality == *locality)
+                })
+                .collect();
+
+            for locality in stale {
+                if let Some(set) = clusters.take_locality(&locality) {
+                    replaced.push((locality, set.endpoint_map().clone(), Default::default()));
+                }
+            }
+        }
+
+        for cluster in request.endpoints {
+            let set = crate::net::cluster::EndpointSet::new(cluster.endpoints);
+            let after = set.endpoint_map().clone();
+            let before = clusters.replace_locality(cluster.locality.clone(), set);
+            replaced.push((cluster.locality, before, after));
+        }
+    });
+    admin.config.apply_metrics();
+
+    Ok(Json(Diff {
+        resource: Resource::Clusters,
+        changes: admin.config.record_endpoints(&replaced, Source::Admin),
+    }))
+}
+
+/// Retrieves a single resource of the configuration
+pub(super) async fn get(
+    State(admin): State<Admin>,
+    Path(resource): Path<Resource>,
+) -> Result<Json<Value>, Error> {
+    current(&admin, resource).map(Json)
+}
+
+/// Replaces a resource of the configuration, for clusters any locality that
+/// isn't in the request is removed
+pub(super) async fn put(
+    State(admin): State<Admin>,
+    Path(resource): Path<Resource>,
+    Json(value): Json<Value>,
+) -> Result<Json<Diff>, Error> {
+    check_writable(&admin, resource)?;
+
+    match resource {
+        Resource::Filters => replace_filters(&admin, value),
+        Resource::Clusters => replace_clusters(&admin, value, true),
+        Resource::Datacenters => unreachable!("datacenters are never writable"),
+    }
+}
+
+/// Modifies part of a resource of the configuration
+///
+/// For clusters the request contains the localities to replace, leaving any
+/// other locality untouched. For filters the request contains the filters to
+/// replace, each matched to exactly one filter of the chain by its label, or
+/// its name if it has no label, leaving the order of the chain untouched.
+pub(super) async fn patch(
+    State(admin): State<Admin>,
+    Path(resource): Path<Resource>,
+    Json(value): Json<Value>,
+) -> Result<Json<Diff>, Error> {
+    check_writable(&admin, resource)?;
+
+    if resource != Resource::Filters {
+        return replace_clusters(&admin, value, false);
+    }
+
+    let Value::Array(patches) = value else {
+        return Err((StatusCode::BAD_REQUEST, "expected a list of filters".into()));
+    };
+
+    let mut chain = current(&admin, resource)?;
+    let filters = chain.as_array_mut().ok_or_else(|| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "filter chain is not a list".into(),
+        )
+    })?;
+
+    for patch in patches {
+        let (field, id) = match (patch.get("label"), patch.get("name")) {
+            (Some(Value::String(label)), _) => ("label", label.clone()),
+            (_, Some(Value::String(name))) => ("name", name.clone()),
+            _ => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "each filter must have a `label` or a `name`".into(),
+                ));
+            }
+        };
+
+        let mut matches = filters
+            .iter_mut()
+            .filter(|filter| filter.get(field).and_then(Value::as_str) == Some(id.as_str()));
+        match (matches.next(), matches.next()) {
+            (Some(filter), None) => *filter = patch,
+            (None, _) => {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    format!("no filter with the {field} `{id}`"),
+                ));
+            }
+            (Some(_), Some(_)) => {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("more than one filter with the {field} `{id}`"),
+                ));
+            }
+        }
+    }
+
+    replace_filters(&admin, chain)
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub(super) struct HistoryParams {
+    resource: Option<Resource>,
+}
+
+/// Lists the changes applied to the configuration, oldest first
+pub(super) async fn history(
+    State(admin): State<Admin>,
+    Query(params): Query<HistoryParams>,
+) -> Json<Vec<Entry>> {
+    let entries = admin
+        .config
+        .dyn_cfg
+        .history()
+        .map(|history| history.entries())
+        .unwrap_or_default();
+
+    Json(
+        entries
+            .into_iter()
+            .filter(|entry| {
+                params
+                    .resource
+                    .is_none_or(|resource| entry.resource == resource)
+            })
+            .collect(),
+    )
+}
+
+/// Restores the filter chain to its value after a history entry was applied
+pub(super) async fn rollback(
+    State(admin): State<Admin>,
+    Path(id): Path<u64>,
+) -> Result<Json<Diff>, Error> {
+    check_writable(&admin, Resource::Filters)?;
+
+    if admin
+        .config
+        .dyn_cfg
+        .history()
+        .and_then(|history| history.get(id))
+        .is_none()
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("there is no history entry {id}"),
+        ));
+    }
+
+    let changes = admin
+        .config
+        .rollback_filters(id)
+        .map_err(|error| (StatusCode::BAD_REQUEST, format!("{error:#}")))?;
+
+    Ok(Json(Diff {
+        resource: Resource::Filters,
+        changes,
+    }))
+}
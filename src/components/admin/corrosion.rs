@@ -0,0 +1,109 @@
+/*
+ * Copyright 2026 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use corrosion::{
+    db::query::{QueryError, QueryLimits, QueryOutput},
+    gossip::status::{GossipStatus, StatusError},
+};
+
+use super::Admin;
+use crate::config::{CorrosionReplica, ReplicaError};
+
+type Error = (StatusCode, String);
+
+/// The body accepted by `POST /corrosion/query` and `POST /corrosion/subscribe`
+#[derive(Debug, serde::Deserialize)]
+pub(super) struct QueryRequest {
+    /// The read-only SQL statement to run
+    sql: String,
+    /// The maximum number of rows, or events for subscriptions, to return
+    limit: Option<usize>,
+    /// The maximum time in milliseconds the query can run for, not applicable
+    /// to subscriptions
+    timeout_ms: Option<u64>,
+}
+
+fn replica(admin: &Admin) -> Result<&CorrosionReplica, Error> {
+    admin.config.dyn_cfg.corrosion_replica().ok_or((
+        StatusCode::NOT_FOUND,
+        "the corrosion service is not enabled".into(),
+    ))
+}
+
+fn to_response_error(error: ReplicaError) -> Error {
+    let status = match &error {
+        ReplicaError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        ReplicaError::GossipDisabled => StatusCode::NOT_FOUND,
+        ReplicaError::GossipStatus(StatusError::SwimStopped) => StatusCode::SERVICE_UNAVAILABLE,
+        ReplicaError::GossipStatus(StatusError::Timeout) => StatusCode::GATEWAY_TIMEOUT,
+        ReplicaError::Query(QueryError::Timeout(_)) => StatusCode::REQUEST_TIMEOUT,
+        ReplicaError::Query(qe) if qe.is_invalid_query() => StatusCode::BAD_REQUEST,
+        ReplicaError::Subscribe(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    (status, error.to_string())
+}
+
+/// Runs a bounded, read-only query against the local replica
+pub(super) async fn query(
+    State(admin): State<Admin>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<QueryOutput>, Error> {
+    let limits = QueryLimits::new(request.timeout_ms.map(Duration::from_millis), request.limit);
+
+    let output = replica(&admin)?
+        .query(request.sql, limits)
+        .await
+        .map_err(to_response_error)?;
+    Ok(Json(output))
+}
+
+/// Subscribes to a read-only query against the local replica, streaming the
+/// initial rows and every subsequent change as newline delimited JSON
+pub(super) async fn subscribe(
+    State(admin): State<Admin>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Response, Error> {
+    let limits = QueryLimits::new(None, request.limit);
+
+    let stream = replica(&admin)?
+        .subscribe(request.sql, limits.max_rows)
+        .await
+        .map_err(to_response_error)?;
+
+    use futures::StreamExt as _;
+    let body = axum::body::Body::from_stream(stream.map(Ok::<_, std::convert::Infallible>));
+
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+/// Retrieves the gossip membership of the local node, and how far behind it
+/// is in syncing from each actor
+pub(super) async fn gossip(State(admin): State<Admin>) -> Result<Json<GossipStatus>, Error> {
+    let status = replica(&admin)?
+        .gossip_status()
+        .await
+        .map_err(to_response_error)?;
+    Ok(Json(status))
+}
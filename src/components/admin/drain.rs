@@ -0,0 +1,73 @@
+/*
+ * Copyright 2026 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use axum::{extract::State, http::StatusCode, response::Json};
+
+use super::Admin;
+use crate::net::sessions::Drain;
+
+type Error = (StatusCode, &'static str);
+
+/// Whether the proxy is draining, and the sessions it's waiting on
+#[derive(Debug, serde::Serialize)]
+pub(super) struct Status {
+    draining: bool,
+    /// How long the proxy has been draining for, in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_secs: Option<f64>,
+    /// The number of sessions that have yet to expire
+    remaining_sessions: usize,
+}
+
+fn drain(admin: &Admin) -> Result<&Drain, Error> {
+    admin
+        .config
+        .dyn_cfg
+        .drain()
+        .ok_or((StatusCode::NOT_FOUND, "the UDP service is not enabled"))
+}
+
+fn status(admin: &Admin, drain: &Drain) -> Status {
+    Status {
+        draining: drain.is_draining(),
+        elapsed_secs: drain
+            .started_at()
+            .map(|started_at| started_at.elapsed().as_secs_f64()),
+        remaining_sessions: admin
+            .config
+            .dyn_cfg
+            .active_sessions()
+            .map_or(0, |sessions| sessions.all().len()),
+    }
+}
+
+/// Returns whether the proxy is draining
+pub(super) async fn get(State(admin): State<Admin>) -> Result<Json<Status>, Error> {
+    let drain = drain(&admin)?;
+    Ok(Json(status(&admin, drain)))
+}
+
+/// Starts draining, the proxy stops reporting itself as ready and creating
+/// new sessions, and shuts down once the existing sessions have expired or
+/// the termination timeout is reached
+pub(super) async fn start(State(admin): State<Admin>) -> Result<(StatusCode, Json<Status>), Error> {
+    let drain = drain(&admin)?;
+    if drain.start() {
+        tracing::info!("draining started through the admin server");
+    }
+
+    Ok((StatusCode::ACCEPTED, Json(status(&admin, drain))))
+}
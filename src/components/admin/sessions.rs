@@ -0,0 +1,88 @@
+/*
+ * Copyright 2026 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+
+use super::Admin;
+use crate::net::sessions::{ActiveSessions, SessionFilter, SessionPage, inspect};
+
+type Error = (StatusCode, &'static str);
+
+/// Query parameters accepted by `GET /sessions`
+#[derive(Debug, serde::Deserialize)]
+pub(super) struct ListParams {
+    source: Option<IpAddr>,
+    destination: Option<SocketAddr>,
+    asn: Option<u64>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub(super) struct Terminated {
+    terminated: usize,
+}
+
+fn active_sessions(admin: &Admin) -> Result<&ActiveSessions, Error> {
+    admin
+        .config
+        .dyn_cfg
+        .active_sessions()
+        .ok_or((StatusCode::NOT_FOUND, "the UDP service is not enabled"))
+}
+
+/// Lists the active sessions matching the query, one page at a time
+pub(super) async fn list(
+    State(admin): State<Admin>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<SessionPage>, Error> {
+    let filter = SessionFilter {
+        source: params.source,
+        destination: params.destination,
+        asn: params.asn,
+    };
+
+    Ok(Json(active_sessions(&admin)?.list(
+        &filter,
+        params.offset,
+        params.limit.unwrap_or(inspect::DEFAULT_PAGE_LIMIT),
+    )))
+}
+
+/// Forcibly drops every active session matching the query, at least one filter
+/// must be supplied so that a bare request can't drop every session
+pub(super) async fn terminate(
+    State(admin): State<Admin>,
+    Query(filter): Query<SessionFilter>,
+) -> Result<Json<Terminated>, Error> {
+    if filter.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "at least one of `source`, `destination`, or `asn` must be specified",
+        ));
+    }
+
+    let terminated = active_sessions(&admin)?.terminate(&filter);
+    tracing::info!(?filter, terminated, "terminated sessions");
+    Ok(Json(Terminated { terminated }))
+}
@@ -41,6 +41,7 @@ use crate::{
 
 pub use self::{
     config_type::ConfigType,
+    corro::{CorrosionReplica, ReplicaError},
     datacenter::{Datacenter, DatacenterMap},
     error::ValidationError,
     icao::{IcaoCode, NotifyingIcaoCode},
@@ -50,9 +51,12 @@ pub use self::{
 mod config_type;
 mod corro;
 mod datacenter;
+pub mod eds;
 mod error;
 pub mod filter;
+pub mod history;
 mod icao;
+pub mod loader;
 pub mod qcmp;
 mod serialization;
 pub mod watch;
@@ -145,6 +149,26 @@ impl DynamicConfig {
         self.typemap.get::<DatacenterMap>()
     }
 
+    #[inline]
+    pub fn active_sessions(&self) -> Option<&crate::net::sessions::ActiveSessions> {
+        self.typemap.get::<crate::net::sessions::ActiveSessions>()
+    }
+
+    #[inline]
+    pub fn drain(&self) -> Option<&crate::net::sessions::Drain> {
+        self.typemap.get::<crate::net::sessions::Drain>()
+    }
+
+    #[inline]
+    pub fn envoy_assignments(&self) -> Option<&eds::EnvoyAssignments> {
+        self.typemap.get::<eds::EnvoyAssignments>()
+    }
+
+    #[inline]
+    pub fn corrosion_replica(&self) -> Option<&CorrosionReplica> {
+        self.typemap.get::<CorrosionReplica>()
+    }
+
     pub(crate) fn init_leader_lock(&self) -> LeaderLock {
         self.typemap
             .get::<LeaderLock>()
@@ -243,6 +267,10 @@ impl quilkin_xds::config::Configuration for Config {
         self.delta_discovery_request(client_state)
     }
 
+    fn sotw_resource(&self, resource: prost_types::Any) -> quilkin_xds::Result<XdsResource> {
+        self.sotw_resource(resource)
+    }
+
     fn interested_resources(
         &self,
         _server_version: &str,
@@ -424,6 +452,7 @@ impl Config {
             cancellation_token: None,
         };
         insert_default::<crate::config::LeaderLock>(&mut config.dyn_cfg.typemap);
+        insert_default::<history::ConfigHistory>(&mut config.dyn_cfg.typemap);
         providers.init_config(&mut config);
         service.init_config(&mut config);
 
@@ -497,21 +526,21 @@ impl Config {
         Ok(())
     }
 
+    /// Reads the configuration from `config_path`, either a file or a
+    /// directory of files, see [`loader`], falling back to
+    /// `/etc/quilkin/quilkin.yaml` if it doesn't exist
     pub fn read_config(
         self: &Arc<Self>,
         config_path: &std::path::Path,
         locality: Option<crate::net::endpoint::Locality>,
     ) -> Result<(), eyre::Error> {
-        let paths = [config_path, std::path::Path::new(ETC_CONFIG_PATH)];
-        let mut paths = paths.iter();
-
-        let file = loop {
-            let Some(path) = paths.next() else {
-                return Ok(());
-            };
-
-            match std::fs::File::open(path) {
-                Ok(file) => break file,
+        for path in [config_path, std::path::Path::new(ETC_CONFIG_PATH)] {
+            match std::fs::metadata(path) {
+                Ok(_) => {
+                    let loaded = loader::load(path)?;
+                    self.update_from_json(loaded.config, locality)?;
+                    return Ok(());
+                }
                 Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
                     tracing::debug!(path = %path.display(), "config path not found");
                 }
@@ -520,10 +549,7 @@ impl Config {
                     eyre::bail!(err);
                 }
             }
-        };
-
-        let json = serde_yaml::from_reader(file)?;
-        self.update_from_json(json, locality)?;
+        }
 
         Ok(())
     }
@@ -684,39 +710,110 @@ impl Config {
                         break 'append;
                     };
 
-                    if client_state.subscribed.is_empty() {
-                        for cluster in clusters.read().iter() {
-                            push(cluster.key(), cluster.value())?;
-                        }
+                    // Named subscriptions are localities, which also include
+                    // every locality within them, with the empty name being the
+                    // endpoints without a locality, any other name is rejected
+                    // rather than leaving the client without its endpoints
+                    let subscriptions = if client_state.is_wildcard() {
+                        None
                     } else {
-                        for locality in client_state.subscribed.iter().filter_map(|name| {
-                            if name.is_empty() {
-                                Some(None)
-                            } else {
-                                name.parse().ok().map(Some)
-                            }
-                        }) {
-                            if let Some(cluster) = clusters.read().get(&locality) {
-                                push(cluster.key(), cluster.value())?;
-                            }
-                        }
+                        let subscriptions = client_state
+                            .subscribed
+                            .iter()
+                            .map(|name| {
+                                if name.is_empty() {
+                                    return Ok(None);
+                                }
+
+                                name.parse::<crate::net::endpoint::Locality>()
+                                    .map(Some)
+                                    .map_err(|error| {
+                                        eyre::eyre!(
+                                            "cluster subscription `{name}` is not a locality: {error}"
+                                        )
+                                    })
+                            })
+                            .collect::<crate::Result<Vec<_>>>()?;
+                        Some(subscriptions)
                     };
 
-                    // Currently, we have exactly _one_ special case for removed resources, which
-                    // is when ClusterMap::update_unlocated_endpoints is called to move the None
-                    // locality endpoints to another one, so we just detect that case manually
-                    if client_state.versions.contains_key("")
-                        && clusters.read().get(&None).is_none()
-                    {
-                        removed.insert("".into());
+                    let is_subscribed = |key: &Option<crate::net::endpoint::Locality>| {
+                        subscriptions.as_ref().is_none_or(|subs| {
+                            subs.iter().any(|sub| match (sub, key) {
+                                (None, None) => true,
+                                (Some(sub), Some(key)) => sub.contains(key),
+                                _ => false,
+                            })
+                        })
+                    };
+
+                    for cluster in clusters.read().iter() {
+                        if is_subscribed(cluster.key()) {
+                            push(cluster.key(), cluster.value())?;
+                        }
+                    }
+
+                    // Remove the localities the client has that no longer exist,
+                    // eg. when ClusterMap::update_unlocated_endpoints moves the
+                    // None locality endpoints to another one, or that the client
+                    // is no longer subscribed to
+                    for key in client_state.versions.keys() {
+                        let locality = if key.is_empty() {
+                            None
+                        } else {
+                            let Ok(locality) = key.parse() else {
+                                continue;
+                            };
+                            Some(locality)
+                        };
+
+                        if !is_subscribed(&locality) || clusters.read().get(&locality).is_none() {
+                            removed.insert(key.clone());
+                        }
                     }
                 }
+                // Envoy resources are only ever ingested, never served
+                ResourceType::ClusterLoadAssignment => {}
             }
         }
 
         Ok(DeltaDiscoveryRes { resources, removed })
     }
 
+    /// Names and versions a resource received in a SotW response the same as
+    /// [`Self::delta_discovery_request`] would, so it can be applied as a delta
+    pub fn sotw_resource(&self, any: prost_types::Any) -> crate::Result<XdsResource> {
+        let (name, version) = match crate::xds::Resource::try_decode(any.clone())? {
+            crate::xds::Resource::Cluster(cluster) => (
+                cluster
+                    .locality
+                    .map(|locality| crate::net::endpoint::Locality::from(locality).to_string())
+                    .unwrap_or_default(),
+                // Cluster versions are parsed as an `EndpointSetVersion`
+                format!("{:x}", gxhash::gxhash64(&any.value, 0xdeadbeef)),
+            ),
+            crate::xds::Resource::Datacenter(dc) => {
+                let version = format!("{}-{}", dc.icao_code, dc.qcmp_port);
+                (dc.host, version)
+            }
+            crate::xds::Resource::FilterChain(_) => (
+                "filter_chain".into(),
+                gxhash::gxhash64(&any.value, 0xdeadbeef).to_string(),
+            ),
+            crate::xds::Resource::ClusterLoadAssignment(cla) => (
+                cla.cluster_name,
+                gxhash::gxhash64(&any.value, 0xdeadbeef).to_string(),
+            ),
+        };
+
+        Ok(XdsResource {
+            name,
+            version,
+            resource: Some(any),
+            ..Default::default()
+        })
+    }
+
     #[tracing::instrument(skip_all, fields(response = type_url))]
     pub fn apply_delta(
         &self,
@@ -765,13 +862,21 @@ impl Config {
                 let fc =
                     crate::filters::FilterChain::try_create_fallible(resource.filters.into_iter())?;
 
+                let snapshot = self.snapshot(history::Resource::Filters, None);
                 filters.store(fc);
+                self.record(
+                    snapshot,
+                    history::Source::Xds { peer: remote_addr },
+                    [(res.name, res.version)].into(),
+                );
             }
             ResourceType::Datacenter => {
                 if self.dyn_cfg.datacenters().is_none() {
                     return Ok(());
                 }
 
+                let snapshot = self.snapshot(history::Resource::Datacenters, None);
+                let mut versions = std::collections::BTreeMap::new();
                 let mut bridge = corro::Bridge::new(self);
 
                 if let Some(ip) = remote_addr.filter(|_| !removed_resources.is_empty()) {
@@ -779,6 +884,7 @@ impl Config {
                 }
 
                 for res in resources {
+                    versions.insert(res.name, res.version);
                     let Some(resource) = res.resource else {
                         eyre::bail!(
                             "a datacenter resource could not be applied because it didn't contain an actual payload"
@@ -835,22 +941,45 @@ impl Config {
                     let datacenter = parse_payload()?;
                     bridge.insert_dc(host, datacenter);
                 }
+
+                self.record(
+                    snapshot,
+                    history::Source::Xds { peer: remote_addr },
+                    versions,
+                );
             }
             ResourceType::Cluster => {
                 if self.dyn_cfg.clusters().is_none() {
                     return Ok(());
                 }
 
+                let mut snapshot = self.snapshot(history::Resource::Clusters, Some(<_>::default()));
+                let mut versions = std::collections::BTreeMap::new();
                 let mut bridge = corro::Bridge::new(self);
 
-                for removed in removed_resources {
-                    let locality = if removed.is_empty() {
+                // If Envoy assignments are ingested they can contain the same
+                // localities, so the endpoints are merged with them rather
+                // than replacing them
+                let assignments = self.dyn_cfg.envoy_assignments();
+                let mut changed = Vec::new();
+                let mut removed = Vec::new();
+
+                for name in removed_resources {
+                    let locality = if name.is_empty() {
                         None
                     } else {
-                        Some(removed.parse()?)
+                        Some(name.parse()?)
                     };
 
-                    bridge.remove_locality(remote_addr, &locality);
+                    if let Some(snapshot) = &mut snapshot {
+                        snapshot.add_locality(self, &locality);
+                    }
+
+                    if assignments.is_some() {
+                        removed.push(eds::Contributor::Cluster(locality));
+                    } else {
+                        bridge.remove_locality(remote_addr, &locality);
+                    }
                 }
 
                 let icao = self
@@ -880,12 +1009,13 @@ impl Config {
                     };
 
                     let parsed_version = res.version.parse()?;
+                    versions.insert(res.name, res.version);
 
                     let endpoints = match cluster
                         .endpoints
                         .into_iter()
                         .map(crate::net::endpoint::Endpoint::try_from)
-                        .collect::<Result<_, _>>()
+                        .collect::<Result<std::collections::BTreeSet<_>, _>>()
                     {
                         Ok(eps) => eps,
                         Err(error) => {
@@ -893,15 +1023,95 @@ impl Config {
                         }
                     };
 
-                    let endpoints = crate::config::cluster::EndpointSet::with_version(
-                        endpoints,
-                        parsed_version,
+                    let locality = cluster.locality.map(crate::net::endpoint::Locality::from);
+                    if let Some(snapshot) = &mut snapshot {
+                        snapshot.add_locality(self, &locality);
+                    }
+
+                    if assignments.is_some() {
+                        changed.push((
+                            eds::Contributor::Cluster(locality.clone()),
+                            [(locality, endpoints)].into(),
+                        ));
+                    } else {
+                        let endpoints = crate::config::cluster::EndpointSet::with_version(
+                            endpoints,
+                            parsed_version,
+                        );
+                        bridge.apply_changes(remote_addr, locality, icao, endpoints);
+                    }
+                }
+
+                if let Some(assignments) = assignments {
+                    bridge.apply_merged(
+                        remote_addr,
+                        icao,
+                        assignments.apply(remote_addr, changed, removed),
                     );
+                }
 
-                    let locality = cluster.locality.map(crate::net::endpoint::Locality::from);
-                    bridge.apply_changes(remote_addr, locality, icao, endpoints);
+                self.apply_metrics();
+                self.record(
+                    snapshot,
+                    history::Source::Xds { peer: remote_addr },
+                    versions,
+                );
+            }
+            ResourceType::ClusterLoadAssignment => {
+                let Some(assignments) = self.dyn_cfg.envoy_assignments() else {
+                    eyre::bail!(
+                        "ingestion of Envoy ClusterLoadAssignment resources is not enabled"
+                    );
+                };
+
+                if self.dyn_cfg.clusters().is_none() {
+                    return Ok(());
+                }
+
+                // Map every assignment before applying any of them, so that an
+                // invalid one rejects the whole response
+                let mut changed = Vec::with_capacity(resources.len());
+                for res in resources {
+                    let Some(resource) = res.resource else {
+                        eyre::bail!(
+                            "a cluster load assignment could not be applied because it didn't contain an actual payload"
+                        );
+                    };
+
+                    let cla = match crate::xds::Resource::try_decode(resource)? {
+                        crate::xds::Resource::ClusterLoadAssignment(cla) => cla,
+                        other => {
+                            eyre::bail!(
+                                "a cluster load assignment could not be applied because the resource payload was '{}'",
+                                other.type_url()
+                            );
+                        }
+                    };
+
+                    changed.push((
+                        eds::Contributor::Assignment(res.name),
+                        eds::endpoints_by_locality(cla)?,
+                    ));
                 }
 
+                let removed = removed_resources
+                    .iter()
+                    .cloned()
+                    .map(eds::Contributor::Assignment)
+                    .collect();
+
+                let icao = self
+                    .dyn_cfg
+                    .datacenters()
+                    .zip(remote_addr)
+                    .and_then(|(dc, ra)| dc.read().get(&ra).map(|dc| dc.icao_code));
+
+                corro::Bridge::new(self).apply_merged(
+                    remote_addr,
+                    icao,
+                    assignments.apply(remote_addr, changed, removed),
+                );
+
                 self.apply_metrics();
             }
         }
@@ -87,6 +87,7 @@ impl<'m> Bridge<'m> {
             return;
         };
 
+        let locality_str = locality.to_string();
         let Some(endpoints) = wsrv.get(&Some(locality)) else {
             return;
         };
@@ -102,6 +103,8 @@ impl<'m> Bridge<'m> {
                     },
                     dc.icao_code,
                     &ep.metadata.known.tokens,
+                    &ep.metadata.unknown,
+                    Some(&locality_str),
                 );
             }
         }
@@ -162,6 +165,28 @@ impl<'m> Bridge<'m> {
         bri.statements.extend(statements);
     }
 
+    /// Applies the merged endpoints of each locality, removing the localities
+    /// that no longer have any endpoints
+    pub fn apply_merged(
+        &mut self,
+        ip: Option<IpAddr>,
+        icao: Option<quilkin_types::IcaoCode>,
+        localities: super::eds::LocalityEndpoints,
+    ) {
+        for (locality, endpoints) in localities {
+            if endpoints.is_empty() {
+                self.remove_locality(ip, &locality);
+            } else {
+                self.apply_changes(
+                    ip,
+                    locality,
+                    icao,
+                    crate::config::cluster::EndpointSet::new(endpoints),
+                );
+            }
+        }
+    }
+
     pub fn apply_changes(
         &mut self,
         ip: Option<IpAddr>,
@@ -175,6 +200,7 @@ impl<'m> Bridge<'m> {
 
         let mut rm = Vec::new();
         let mut up = Vec::new();
+        let locality_str = locality.as_ref().map(|l| l.to_string());
 
         if let Err(error) = wsrv.apply(ip, locality, es, &mut rm, &mut up) {
             tracing::error!(%error, "failed to apply xDS endpoint update");
@@ -200,8 +226,14 @@ impl<'m> Bridge<'m> {
             }
 
             if !up.is_empty() {
-                for (ep, ts) in up {
-                    stx.upsert(&ep, icao, &ts);
+                for (ep, md) in up {
+                    stx.upsert(
+                        &ep,
+                        icao,
+                        &md.known.tokens,
+                        &md.unknown,
+                        locality_str.as_deref(),
+                    );
                 }
             }
         }
@@ -209,3 +241,190 @@ impl<'m> Bridge<'m> {
         bri.statements.extend(statements);
     }
 }
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReplicaError {
+    #[error("the corrosion service is not running")]
+    Unavailable,
+    #[error(transparent)]
+    Query(#[from] corrosion::db::query::QueryError),
+    #[error(transparent)]
+    Subscribe(#[from] corrosion::pubsub::MatcherUpsertError),
+    #[error(transparent)]
+    Pool(#[from] corrosion::types::sqlite::SqlitePoolError),
+    #[error(transparent)]
+    Join(#[from] tokio::task::JoinError),
+    #[error("corrosion gossip is not enabled")]
+    GossipDisabled,
+    #[error(transparent)]
+    GossipStatus(#[from] corrosion::gossip::status::StatusError),
+}
+
+#[derive(Clone)]
+struct ReplicaInner {
+    pubsub: corrosion::pubsub::PubsubContext,
+    /// The runtime the corrosion service runs on, callers such as the admin
+    /// server run on their own runtime, but subscriptions spawn tasks that
+    /// must outlive the request
+    runtime: tokio::runtime::Handle,
+    gossip: Option<corrosion::gossip::status::Introspect>,
+}
+
+/// A handle to the local corrosion replica, allowing operators to run ad-hoc
+/// read-only queries and subscriptions against it, and inspect its gossip
+/// membership
+#[derive(Clone, Default)]
+pub struct CorrosionReplica(std::sync::Arc<parking_lot::RwLock<Option<ReplicaInner>>>);
+
+impl typemap_rev::TypeMapKey for CorrosionReplica {
+    type Value = CorrosionReplica;
+}
+
+impl std::fmt::Debug for CorrosionReplica {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CorrosionReplica")
+            .field("registered", &self.0.read().is_some())
+            .finish()
+    }
+}
+
+impl CorrosionReplica {
+    /// Registers the running corrosion service, must be called from within
+    /// the runtime it runs on
+    pub fn register(
+        &self,
+        pubsub: corrosion::pubsub::PubsubContext,
+        gossip: Option<corrosion::gossip::status::Introspect>,
+    ) {
+        *self.0.write() = Some(ReplicaInner {
+            pubsub,
+            runtime: tokio::runtime::Handle::current(),
+            gossip,
+        });
+    }
+
+    fn inner(&self) -> Result<ReplicaInner, ReplicaError> {
+        self.0.read().clone().ok_or(ReplicaError::Unavailable)
+    }
+
+    /// Runs a bounded, read-only query
+    pub async fn query(
+        &self,
+        sql: String,
+        limits: corrosion::db::query::QueryLimits,
+    ) -> Result<corrosion::db::query::QueryOutput, ReplicaError> {
+        use corrosion::db::SplitPoolReadExt as _;
+
+        let inner = self.inner()?;
+        let pool = inner.pubsub.pool.clone();
+
+        inner
+            .runtime
+            .spawn(async move {
+                let conn = pool.read_readonly().await?;
+                let output = tokio::task::spawn_blocking(move || {
+                    corrosion::db::query::query(&conn, &sql, limits)
+                })
+                .await??;
+                Ok::<_, ReplicaError>(output)
+            })
+            .await?
+    }
+
+    /// Retrieves the gossip membership and sync state
+    pub async fn gossip_status(
+        &self,
+    ) -> Result<corrosion::gossip::status::GossipStatus, ReplicaError> {
+        let inner = self.inner()?;
+        let gossip = inner.gossip.ok_or(ReplicaError::GossipDisabled)?;
+
+        Ok(inner
+            .runtime
+            .spawn(async move { gossip.status().await })
+            .await??)
+    }
+
+    /// Subscribes to a read-only query, returning a stream of each of the
+    /// query's events as newline delimited JSON
+    ///
+    /// The stream ends after `max_events`, the subscription is removed when
+    /// the stream is dropped
+    pub async fn subscribe(
+        &self,
+        sql: String,
+        max_events: usize,
+    ) -> Result<impl futures::Stream<Item = bytes::Bytes> + Send + 'static, ReplicaError> {
+        use corrosion::db::SplitPoolReadExt as _;
+
+        let inner = self.inner()?;
+
+        let sub = inner
+            .runtime
+            .spawn({
+                let pubsub = inner.pubsub.clone();
+                async move {
+                    // Subscriptions can only be created for queries, but
+                    // validate that it is read-only the same as for a single
+                    // query before creating a matcher for it
+                    {
+                        let conn = pubsub.pool.read_readonly().await?;
+                        let sql = sql.clone();
+                        tokio::task::spawn_blocking(move || {
+                            corrosion::db::query::prepare(&conn, &sql).map(drop)
+                        })
+                        .await??;
+                    }
+
+                    let params = corrosion::pubsub::SubParamsv1::new(&sql);
+                    Ok::<_, ReplicaError>(pubsub.subscribe(params).await?)
+                }
+            })
+            .await??;
+
+        /// Removes the subscription once the stream is dropped, eg. because
+        /// the client disconnected
+        struct Unsubscribe {
+            id: uuid::Uuid,
+            inner: ReplicaInner,
+        }
+
+        impl Drop for Unsubscribe {
+            fn drop(&mut self) {
+                let id = self.id;
+                let pubsub = self.inner.pubsub.clone();
+                self.inner.runtime.spawn(async move {
+                    if !pubsub.remove(&id).await {
+                        tracing::debug!(%id, "subscription already removed");
+                    }
+                });
+            }
+        }
+
+        let unsubscribe = Unsubscribe { id: sub.id, inner };
+        let mut rx = sub.rx;
+
+        Ok(async_stream::stream! {
+            let _unsubscribe = unsubscribe;
+            let mut count = 0;
+
+            while let Some(event) = rx.recv().await {
+                for event in corrosion::pubsub::SubscriptionStream::new(event.buff) {
+                    let mut line = match event.and_then(|event| serde_json::to_vec(&event)) {
+                        Ok(line) => line,
+                        Err(error) => {
+                            tracing::warn!(%error, "failed to serialize subscription event");
+                            continue;
+                        }
+                    };
+                    line.push(b'\n');
+                    yield bytes::Bytes::from(line);
+
+                    count += 1;
+                    if count >= max_events {
+                        return;
+                    }
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,398 @@
+/*
+ * Copyright 2025 Google LLC All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Ingestion of Envoy `ClusterLoadAssignment` (EDS) resources, so that
+//! endpoints can be fed to Quilkin from existing service mesh control planes
+//!
+//! Each assignment is mapped to a set of endpoints per locality. As the
+//! [`ClusterMap`](crate::net::ClusterMap) is only keyed by locality, the
+//! endpoints of every assignment and Quilkin `Cluster` resource from the same
+//! peer that contain the same locality are merged together.
+
+use crate::net::endpoint::{AddressKind, Endpoint, Locality, metadata::MetadataView};
+use quilkin_xds::generated::envoy::config::{
+    core::v3::{self as envoy_core, HealthStatus},
+    endpoint::v3::{self as envoy_endpoint, ClusterLoadAssignment},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::IpAddr,
+    sync::Arc,
+};
+
+/// The endpoints in each locality
+pub type LocalityEndpoints = BTreeMap<Option<Locality>, BTreeSet<Endpoint>>;
+
+/// A resource that contributes endpoints to the cluster map
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Contributor {
+    /// A Quilkin `Cluster` resource, which is named after its locality
+    Cluster(Option<Locality>),
+    /// An Envoy `ClusterLoadAssignment`, named after the cluster it is for
+    Assignment(String),
+}
+
+/// The endpoints each resource has contributed, keyed by the peer that sent it
+///
+/// Only present in the config if the ingestion of Envoy resources is enabled.
+#[derive(Clone, Debug, Default)]
+pub struct EnvoyAssignments(
+    Arc<parking_lot::Mutex<BTreeMap<(Option<IpAddr>, Contributor), LocalityEndpoints>>>,
+);
+
+impl typemap_rev::TypeMapKey for EnvoyAssignments {
+    type Value = EnvoyAssignments;
+}
+
+impl EnvoyAssignments {
+    /// Replaces the endpoints of the `changed` resources, and removes the
+    /// `removed` resources, returning the full set of endpoints from
+    /// `remote_addr` for each locality that was affected
+    ///
+    /// A locality with an empty set no longer has any endpoints.
+    pub fn apply(
+        &self,
+        remote_addr: Option<IpAddr>,
+        changed: Vec<(Contributor, LocalityEndpoints)>,
+        removed: Vec<Contributor>,
+    ) -> LocalityEndpoints {
+        let mut assignments = self.0.lock();
+        let mut affected = BTreeSet::new();
+
+        for contributor in removed {
+            if let Some(old) = assignments.remove(&(remote_addr, contributor)) {
+                affected.extend(old.into_keys());
+            }
+        }
+
+        for (contributor, endpoints) in changed {
+            affected.extend(endpoints.keys().cloned());
+            if let Some(old) = assignments.insert((remote_addr, contributor), endpoints) {
+                affected.extend(old.into_keys());
+            }
+        }
+
+        affected
+            .into_iter()
+            .map(|locality| {
+                let endpoints = assignments
+                    .iter()
+                    .filter(|((peer, _), _)| *peer == remote_addr)
+                    .filter_map(|(_, assignment)| assignment.get(&locality))
+                    .flatten()
+                    .cloned()
+                    .collect();
+                (locality, endpoints)
+            })
+            .collect()
+    }
+}
+
+/// Maps an Envoy `ClusterLoadAssignment` to the endpoints in each locality
+///
+/// Endpoints that Envoy would not route to, ie. those that are unhealthy,
+/// draining, or have timed out, are excluded. Endpoint metadata is read from
+/// the filter metadata, with the `quilkin.dev` namespace containing the known
+/// Quilkin metadata, the same as the Quilkin `Cluster` resource.
+///
+/// Quilkin doesn't support weighted load balancing, so load balancing weights
+/// are ignored, with a warning if they would change how traffic is routed.
+pub fn endpoints_by_locality(cla: ClusterLoadAssignment) -> crate::Result<LocalityEndpoints> {
+    use envoy_endpoint::{lb_endpoint::HostIdentifier, locality_lb_endpoints::LbConfig};
+
+    let mut localities = LocalityEndpoints::new();
+    let mut weighted = false;
+
+    for lle in cla.endpoints {
+        weighted |= lle.load_balancing_weight.is_some_and(|weight| weight != 1);
+
+        let locality = lle
+            .locality
+            .filter(|l| !l.region.is_empty() || !l.zone.is_empty() || !l.sub_zone.is_empty())
+            .map(|l| Locality::new(l.region, l.zone, l.sub_zone));
+
+        let mut lb_endpoints = lle.lb_endpoints;
+        match lle.lb_config {
+            Some(LbConfig::LoadBalancerEndpoints(list)) => lb_endpoints.extend(list.lb_endpoints),
+            Some(LbConfig::LedsClusterLocalityConfig(_)) => {
+                eyre::bail!(
+                    "cluster '{}' uses LEDS, which is not supported",
+                    cla.cluster_name
+                );
+            }
+            None => {}
+        }
+
+        let endpoints = localities.entry(locality).or_default();
+
+        for lbe in lb_endpoints {
+            weighted |= lbe.load_balancing_weight.is_some_and(|weight| weight != 1);
+
+            match HealthStatus::try_from(lbe.health_status) {
+                Ok(HealthStatus::Unknown | HealthStatus::Healthy | HealthStatus::Degraded) => {}
+                Ok(status) => {
+                    tracing::trace!(
+                        cluster = cla.cluster_name,
+                        status = status.as_str_name(),
+                        "skipping endpoint"
+                    );
+                    continue;
+                }
+                Err(_) => eyre::bail!("unknown endpoint health status {}", lbe.health_status),
+            }
+
+            let endpoint = match lbe.host_identifier {
+                Some(HostIdentifier::Endpoint(endpoint)) => endpoint,
+                Some(HostIdentifier::EndpointName(name)) => {
+                    let Some(endpoint) = cla.named_endpoints.get(&name) else {
+                        eyre::bail!(
+                            "cluster '{}' referenced unknown named endpoint '{name}'",
+                            cla.cluster_name
+                        );
+                    };
+                    endpoint.clone()
+                }
+                None => eyre::bail!(
+                    "cluster '{}' has an endpoint without an address",
+                    cla.cluster_name
+                ),
+            };
+
+            let address = match endpoint.address.and_then(|address| address.address) {
+                Some(envoy_core::address::Address::SocketAddress(address)) => address,
+                _ => eyre::bail!(
+                    "cluster '{}' has an endpoint that is not a socket address",
+                    cla.cluster_name
+                ),
+            };
+
+            let port = match address.port_specifier {
+                Some(envoy_core::socket_address::PortSpecifier::PortValue(port)) => {
+                    u16::try_from(port)
+                        .map_err(|_err| eyre::eyre!("invalid endpoint port {port}"))?
+                }
+                _ => eyre::bail!(
+                    "endpoint '{}' does not have a numeric port",
+                    address.address
+                ),
+            };
+
+            let host: AddressKind = address.address.parse()?;
+
+            let metadata: crate::net::endpoint::EndpointMetadata = lbe.metadata.map_or_else(
+                || Ok(Default::default()),
+                |metadata| {
+                    MetadataView::try_from(prost_types::Struct {
+                        fields: metadata
+                            .filter_metadata
+                            .into_iter()
+                            .map(|(namespace, value)| {
+                                (namespace, crate::codec::prost::value_from_struct(value))
+                            })
+                            .collect(),
+                    })
+                },
+            )?;
+
+            endpoints.insert(Endpoint::with_metadata((host, port).into(), metadata));
+        }
+    }
+
+    if weighted {
+        tracing::warn!(
+            cluster = cla.cluster_name,
+            "ignoring load balancing weights, traffic is spread evenly across endpoints"
+        );
+    }
+
+    Ok(localities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use envoy_endpoint::{LbEndpoint, LocalityLbEndpoints, lb_endpoint::HostIdentifier};
+
+    fn lb_endpoint(port: u32, status: HealthStatus, token: Option<&str>) -> LbEndpoint {
+        LbEndpoint {
+            health_status: status as i32,
+            metadata: token.map(|token| envoy_core::Metadata {
+                filter_metadata: [(
+                    crate::net::endpoint::metadata::KEY.into(),
+                    crate::net::endpoint::Metadata {
+                        tokens: [token.as_bytes().to_vec()].into_iter().collect(),
+                    }
+                    .into(),
+                )]
+                .into(),
+                ..Default::default()
+            }),
+            load_balancing_weight: None,
+            host_identifier: Some(HostIdentifier::Endpoint(envoy_endpoint::Endpoint {
+                address: Some(envoy_core::Address {
+                    address: Some(envoy_core::address::Address::SocketAddress(
+                        envoy_core::SocketAddress {
+                            address: "127.0.0.1".into(),
+                            port_specifier: Some(
+                                envoy_core::socket_address::PortSpecifier::PortValue(port),
+                            ),
+                            ..Default::default()
+                        },
+                    )),
+                }),
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn maps_cluster_load_assignment() {
+        let cla = ClusterLoadAssignment {
+            cluster_name: "game-servers".into(),
+            endpoints: vec![
+                LocalityLbEndpoints {
+                    locality: Some(envoy_core::Locality {
+                        region: "eu".into(),
+                        zone: "north".into(),
+                        sub_zone: String::new(),
+                    }),
+                    lb_endpoints: vec![
+                        lb_endpoint(7000, HealthStatus::Healthy, Some("abc")),
+                        lb_endpoint(7001, HealthStatus::Unhealthy, None),
+                        lb_endpoint(7002, HealthStatus::Draining, None),
+                    ],
+                    ..Default::default()
+                },
+                LocalityLbEndpoints {
+                    locality: None,
+                    lb_endpoints: vec![lb_endpoint(7003, HealthStatus::Unknown, None)],
+                    ..Default::default()
+                },
+            ],
+            named_endpoints: Default::default(),
+        };
+
+        let localities = endpoints_by_locality(cla).unwrap();
+        let eu = Some(Locality::new("eu", "north", ""));
+
+        let endpoints = &localities[&eu];
+        assert_eq!(endpoints.len(), 1);
+        let endpoint = endpoints.first().unwrap();
+        assert_eq!(endpoint.address, "127.0.0.1:7000".parse().unwrap());
+        assert!(endpoint.metadata.known.tokens.0.contains(b"abc".as_slice()));
+
+        assert_eq!(
+            localities[&None],
+            [Endpoint::new("127.0.0.1:7003".parse().unwrap())].into()
+        );
+    }
+
+    #[test]
+    fn merges_assignments() {
+        let assignments = EnvoyAssignments::default();
+        let eu = Some(Locality::with_region("eu"));
+        let endpoint = |port: u16| Endpoint::new(([127, 0, 0, 1], port).into());
+
+        let a = || Contributor::Assignment("a".into());
+        let b = || Contributor::Assignment("b".into());
+
+        let applied = assignments.apply(
+            None,
+            vec![
+                (a(), [(eu.clone(), [endpoint(1)].into())].into()),
+                (b(), [(eu.clone(), [endpoint(2)].into())].into()),
+            ],
+            Vec::new(),
+        );
+        assert_eq!(applied[&eu], [endpoint(1), endpoint(2)].into());
+
+        let applied = assignments.apply(
+            None,
+            vec![(b(), [(None, [endpoint(3)].into())].into())],
+            Vec::new(),
+        );
+        assert_eq!(applied[&eu], [endpoint(1)].into());
+        assert_eq!(applied[&None], [endpoint(3)].into());
+
+        let applied = assignments.apply(None, Vec::new(), vec![a(), b()]);
+        assert!(applied[&eu].is_empty());
+        assert!(applied[&None].is_empty());
+    }
+
+    #[test]
+    fn merges_quilkin_clusters() {
+        let assignments = EnvoyAssignments::default();
+        let eu = Some(Locality::with_region("eu"));
+        let endpoint = |port: u16| Endpoint::new(([127, 0, 0, 1], port).into());
+        let peer = Some(IpAddr::from([10, 0, 0, 1]));
+        let other = Some(IpAddr::from([10, 0, 0, 2]));
+
+        let applied = assignments.apply(
+            peer,
+            vec![(
+                Contributor::Cluster(eu.clone()),
+                [(eu.clone(), [endpoint(1)].into())].into(),
+            )],
+            Vec::new(),
+        );
+        assert_eq!(applied[&eu], [endpoint(1)].into());
+
+        // An assignment from the same peer doesn't replace the cluster's endpoints
+        let applied = assignments.apply(
+            peer,
+            vec![(
+                Contributor::Assignment("a".into()),
+                [(eu.clone(), [endpoint(2)].into())].into(),
+            )],
+            Vec::new(),
+        );
+        assert_eq!(applied[&eu], [endpoint(1), endpoint(2)].into());
+
+        // Nor does removing the cluster remove the assignment's endpoints
+        let applied = assignments.apply(peer, Vec::new(), vec![Contributor::Cluster(eu.clone())]);
+        assert_eq!(applied[&eu], [endpoint(2)].into());
+
+        // Resources from other peers are kept separate
+        let applied = assignments.apply(
+            other,
+            vec![(
+                Contributor::Assignment("a".into()),
+                [(eu.clone(), [endpoint(3)].into())].into(),
+            )],
+            Vec::new(),
+        );
+        assert_eq!(applied[&eu], [endpoint(3)].into());
+    }
+
+    #[test]
+    fn ignores_weights() {
+        let mut weighted = lb_endpoint(7000, HealthStatus::Healthy, None);
+        weighted.load_balancing_weight = Some(10);
+
+        let cla = ClusterLoadAssignment {
+            cluster_name: "weighted".into(),
+            endpoints: vec![LocalityLbEndpoints {
+                load_balancing_weight: Some(5),
+                lb_endpoints: vec![weighted, lb_endpoint(7001, HealthStatus::Healthy, None)],
+                ..Default::default()
+            }],
+            named_endpoints: Default::default(),
+        };
+
+        let localities = endpoints_by_locality(cla).unwrap();
+        assert_eq!(localities[&None].len(), 2);
+    }
+}
@@ -32,6 +32,19 @@ impl FilterChainConfig {
         let _ = self.channel.send(());
     }
 
+    /// Stores the filter chain, returning the one it replaced
+    #[inline]
+    pub fn swap(&self, new_chain: FilterChain) -> Arc<FilterChain> {
+        let new_chain = Arc::new(new_chain);
+        let old = self.chain.swap(new_chain.clone());
+        if old != new_chain {
+            tracing::debug!("sending new FilterChain notification");
+            let _ = self.channel.send(());
+        }
+
+        old
+    }
+
     #[inline]
     pub fn load(&self) -> arc_swap::Guard<Arc<FilterChain>> {
         self.chain.load()
@@ -0,0 +1,809 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A bounded, in-memory record of the changes applied to the configuration
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+    sync::Arc,
+};
+
+use serde_json::Value;
+
+use crate::net::{
+    ClusterMap,
+    endpoint::{EndpointAddress, EndpointMetadata, Locality},
+};
+
+/// The number of changes that are kept
+pub const CAPACITY: usize = 100;
+
+/// The approximate size in bytes of the changes that are kept, the oldest
+/// changes are dropped before [`CAPACITY`] is reached if this is exceeded
+pub const MAX_BYTES: usize = 8 * 1024 * 1024;
+
+/// The approximate size in bytes of the changes kept for a single entry, eg.
+/// when every endpoint of a large cluster is replaced, any further changes are
+/// omitted from the entry
+const MAX_ENTRY_BYTES: usize = MAX_BYTES / 8;
+
+/// The resources of the configuration that have their changes recorded
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resource {
+    Filters,
+    Clusters,
+    Datacenters,
+}
+
+impl Resource {
+    /// The key of the resource in the serialized configuration
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Filters => "filters",
+            Self::Clusters => "clusters",
+            Self::Datacenters => "datacenters",
+        }
+    }
+}
+
+/// Where a change to the configuration came from
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Source {
+    /// The configuration file
+    File,
+    /// The admin server
+    Admin,
+    /// An xDS management server, or an agent pushing to a relay
+    Xds { peer: Option<std::net::IpAddr> },
+    /// The Kubernetes providers, ie. the filter chain `ConfigMap` and the
+    /// Agones `GameServer`s
+    Kubernetes,
+    /// The HTTP provider
+    Http,
+    /// A corrosion server subscribed to
+    Corrosion,
+    /// The static provider
+    Static,
+    /// A roll back to the filter chain of a previous entry
+    Rollback { entry: u64 },
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File => f.write_str("file"),
+            Self::Admin => f.write_str("admin"),
+            Self::Xds { peer: Some(peer) } => write!(f, "xds({peer})"),
+            Self::Xds { peer: None } => f.write_str("xds"),
+            Self::Kubernetes => f.write_str("kubernetes"),
+            Self::Http => f.write_str("http"),
+            Self::Corrosion => f.write_str("corrosion"),
+            Self::Static => f.write_str("static"),
+            Self::Rollback { entry } => write!(f, "rollback({entry})"),
+        }
+    }
+}
+
+/// A single entry of a resource that was added, removed, or modified
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Change {
+    /// The index of a filter, the locality and address of an endpoint, or the
+    /// address of a datacenter
+    pub key: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// A change applied to the configuration
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Entry {
+    pub id: u64,
+    /// When the change was applied, in seconds since the unix epoch
+    pub timestamp: i64,
+    pub source: Source,
+    pub resource: Resource,
+    /// The versions of the xDS resources that were applied, by their name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: BTreeMap<String, String>,
+    pub changes: Vec<Change>,
+    /// The number of changes left out of [`Self::changes`] to bound the size
+    /// of the history
+    #[serde(skip_serializing_if = "is_zero")]
+    pub omitted: usize,
+    /// The entire filter chain after the change, to roll back to
+    #[serde(skip)]
+    filters: Option<Value>,
+    /// The approximate size of the entry in bytes
+    #[serde(skip)]
+    size: usize,
+}
+
+#[inline]
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    entries: VecDeque<Entry>,
+    next_id: u64,
+    /// The sum of the size of every entry
+    bytes: usize,
+}
+
+/// The most recent [`CAPACITY`] changes applied to the configuration, bounded
+/// by [`MAX_BYTES`]
+#[derive(Clone, Debug, Default)]
+pub struct ConfigHistory {
+    inner: Arc<parking_lot::Mutex<Entries>>,
+}
+
+impl typemap_rev::TypeMapKey for ConfigHistory {
+    type Value = ConfigHistory;
+}
+
+impl ConfigHistory {
+    /// Retrieves every entry, oldest first
+    pub fn entries(&self) -> Vec<Entry> {
+        self.inner.lock().entries.iter().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Entry> {
+        self.inner
+            .lock()
+            .entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .cloned()
+    }
+
+    fn push(&self, mut entry: Entry) -> u64 {
+        let mut size = entry.filters.as_ref().map_or(0, approximate_size);
+        let mut kept = 0;
+        for change in &entry.changes {
+            let change_size = approximate_size(&change.key)
+                + change.before.as_ref().map_or(0, approximate_size)
+                + change.after.as_ref().map_or(0, approximate_size);
+            if size + change_size > MAX_ENTRY_BYTES {
+                break;
+            }
+
+            size += change_size;
+            kept += 1;
+        }
+        entry.omitted = entry.changes.len() - kept;
+        entry.changes.truncate(kept);
+        entry.size = size;
+
+        let mut inner = self.inner.lock();
+        entry.id = inner.next_id;
+        inner.next_id += 1;
+
+        while inner.entries.len() >= CAPACITY || inner.bytes + size > MAX_BYTES {
+            let Some(oldest) = inner.entries.pop_front() else {
+                break;
+            };
+            inner.bytes -= oldest.size;
+        }
+        inner.bytes += size;
+        inner.entries.push_back(entry);
+        inner.next_id - 1
+    }
+
+    /// Records the changes to the resource, `filters` being the entire filter
+    /// chain after the change, returning what changed
+    pub(crate) fn record(
+        &self,
+        resource: Resource,
+        source: Source,
+        versions: BTreeMap<String, String>,
+        changes: Vec<Change>,
+        filters: Option<Value>,
+    ) -> Vec<Change> {
+        if changes.is_empty() {
+            return changes;
+        }
+
+        let id = self.push(Entry {
+            id: 0,
+            timestamp: crate::time::UtcTimestamp::now().unix(),
+            source: source.clone(),
+            resource,
+            versions: versions.clone(),
+            changes: changes.clone(),
+            omitted: 0,
+            filters,
+            size: 0,
+        });
+
+        tracing::info!(
+            id,
+            %source,
+            ?resource,
+            ?versions,
+            changes = changes.len(),
+            "configuration changed"
+        );
+        for change in &changes {
+            tracing::debug!(
+                id,
+                key = %change.key,
+                before = ?change.before,
+                after = ?change.after,
+                "configuration change"
+            );
+        }
+
+        changes
+    }
+}
+
+impl super::filter::FilterChainConfig {
+    /// Stores the filter chain, recording the change in `history`, for
+    /// providers that only have access to the filter chain
+    pub fn store_with_history(
+        &self,
+        chain: crate::filters::FilterChain,
+        history: Option<&ConfigHistory>,
+        source: Source,
+    ) {
+        let Some(history) = history else {
+            self.store(chain);
+            return;
+        };
+
+        let value = |chain: &crate::filters::FilterChain| {
+            serde_json::to_value(chain)
+                .inspect_err(|error| tracing::warn!(%error, "failed to serialize filter chain"))
+                .ok()
+        };
+
+        let before = value(&self.load());
+        self.store(chain);
+        if let Some((before, after)) = before.zip(value(&self.load())) {
+            let changes = diff(Resource::Filters, before, after.clone());
+            history.record(
+                Resource::Filters,
+                source,
+                BTreeMap::new(),
+                changes,
+                Some(after),
+            );
+        }
+    }
+}
+
+impl super::Watch<ClusterMap> {
+    /// Modifies the clusters, recording the changes to the endpoints of
+    /// `localities` in `history`, for providers that only have access to the
+    /// clusters
+    pub fn modify_with_history<R>(
+        &self,
+        history: Option<&ConfigHistory>,
+        source: Source,
+        localities: BTreeSet<Option<Locality>>,
+        modify: impl FnOnce(&super::watch::WatchGuard<'_, ClusterMap>) -> R,
+    ) -> R {
+        let Some(history) = history else {
+            return self.modify(modify);
+        };
+
+        let before = cluster_endpoints(&self.read(), Some(&localities));
+        let result = self.modify(modify);
+        let changes = cluster_changes(before, &self.read(), Some(&localities));
+        history.record(Resource::Clusters, source, BTreeMap::new(), changes, None);
+        result
+    }
+}
+
+impl super::DynamicConfig {
+    #[inline]
+    pub fn history(&self) -> Option<&ConfigHistory> {
+        self.typemap.get::<ConfigHistory>()
+    }
+}
+
+/// The endpoints of each locality
+type Endpoints = BTreeMap<Option<Locality>, BTreeMap<EndpointAddress, EndpointMetadata>>;
+
+enum Before {
+    /// The serialized filters or datacenters
+    Value(Value),
+    /// The endpoints of the clusters, which are compared without serializing
+    /// them as they can be large, and change often
+    Endpoints(Endpoints),
+}
+
+/// The value of a resource before a change is applied
+pub(crate) struct Snapshot {
+    resource: Resource,
+    /// The localities of the clusters that are compared, `None` compares every
+    /// cluster
+    localities: Option<BTreeSet<Option<Locality>>>,
+    before: Before,
+}
+
+impl Snapshot {
+    /// Adds the current value of the locality to a snapshot of only some
+    /// clusters, before it is modified
+    pub(crate) fn add_locality(&mut self, config: &super::Config, locality: &Option<Locality>) {
+        let (Some(localities), Before::Endpoints(before)) =
+            (&mut self.localities, &mut self.before)
+        else {
+            return;
+        };
+
+        if localities.insert(locality.clone())
+            && let Some(clusters) = config.dyn_cfg.clusters()
+            && let Some(set) = clusters.read().get(locality)
+        {
+            before.insert(locality.clone(), set.endpoint_map().clone());
+        }
+    }
+}
+
+/// Copies the endpoints of the `localities`, or every locality if `None`
+fn cluster_endpoints(
+    clusters: &ClusterMap,
+    localities: Option<&BTreeSet<Option<Locality>>>,
+) -> Endpoints {
+    match localities {
+        None => clusters
+            .iter()
+            .map(|set| (set.key().clone(), set.value().endpoint_map().clone()))
+            .collect(),
+        Some(localities) => localities
+            .iter()
+            .filter_map(|locality| {
+                let set = clusters.get(locality)?;
+                Some((locality.clone(), set.endpoint_map().clone()))
+            })
+            .collect(),
+    }
+}
+
+/// Compares the endpoints of the `localities`, or every locality if `None`,
+/// with their endpoints `before` a change, only serializing those that changed
+fn cluster_changes(
+    mut before: Endpoints,
+    clusters: &ClusterMap,
+    localities: Option<&BTreeSet<Option<Locality>>>,
+) -> Vec<Change> {
+    let localities = match localities {
+        Some(localities) => localities.clone(),
+        None => before
+            .keys()
+            .cloned()
+            .chain(clusters.iter().map(|set| set.key().clone()))
+            .collect(),
+    };
+
+    let empty = BTreeMap::new();
+    let mut changes = Vec::new();
+
+    for locality in localities {
+        let before = before.remove(&locality).unwrap_or_default();
+        let set = clusters.get(&locality);
+        let after = set.as_ref().map_or(&empty, |set| set.endpoint_map());
+        locality_changes(&locality, &before, after, &mut changes);
+    }
+
+    changes
+}
+
+/// Compares the endpoints of a locality `before` and `after` a change
+fn locality_changes(
+    locality: &Option<Locality>,
+    before: &BTreeMap<EndpointAddress, EndpointMetadata>,
+    after: &BTreeMap<EndpointAddress, EndpointMetadata>,
+    changes: &mut Vec<Change>,
+) {
+    if before == after {
+        return;
+    }
+
+    for (address, metadata) in after {
+        match before.get(address) {
+            Some(before) if before == metadata => {}
+            before => changes.push(endpoint_change(locality, address, before, Some(metadata))),
+        }
+    }
+
+    changes.extend(
+        before
+            .iter()
+            .filter(|(address, _)| !after.contains_key(*address))
+            .map(|(address, metadata)| endpoint_change(locality, address, Some(metadata), None)),
+    );
+}
+
+/// The change of a single endpoint, keyed by its locality and address
+pub(crate) fn endpoint_change(
+    locality: &Option<Locality>,
+    address: &EndpointAddress,
+    before: Option<&EndpointMetadata>,
+    after: Option<&EndpointMetadata>,
+) -> Change {
+    let value = |metadata: &EndpointMetadata| serde_json::to_value(metadata).unwrap_or_default();
+
+    Change {
+        key: serde_json::json!({ "locality": locality, "address": address }),
+        before: before.map(value),
+        after: after.map(value),
+    }
+}
+
+/// The approximate size of the value when serialized
+fn approximate_size(value: &Value) -> usize {
+    match value {
+        Value::Null | Value::Bool(_) | Value::Number(_) => 8,
+        Value::String(string) => string.len() + 2,
+        Value::Array(values) => values.iter().map(approximate_size).sum::<usize>() + 2,
+        Value::Object(map) => {
+            map.iter()
+                .map(|(key, value)| key.len() + 3 + approximate_size(value))
+                .sum::<usize>()
+                + 2
+        }
+    }
+}
+
+impl super::Config {
+    /// Serializes the current value of the filters or datacenters
+    fn resource_value(&self, resource: Resource) -> Option<Value> {
+        let value = match resource {
+            Resource::Filters => serde_json::to_value(&*self.dyn_cfg.filters()?.load()),
+            Resource::Datacenters => serde_json::to_value(self.dyn_cfg.datacenters()?),
+            Resource::Clusters => serde_json::to_value(self.dyn_cfg.clusters()?),
+        };
+
+        value
+            .inspect_err(|error| tracing::warn!(%error, ?resource, "failed to serialize resource"))
+            .ok()
+    }
+
+    /// Takes a snapshot of the resource before changing it, `Some` localities
+    /// only compares those clusters, with more added by [`Snapshot::add_locality`]
+    pub(crate) fn snapshot(
+        &self,
+        resource: Resource,
+        localities: Option<BTreeSet<Option<Locality>>>,
+    ) -> Option<Snapshot> {
+        self.dyn_cfg.history()?;
+
+        let before = match resource {
+            Resource::Clusters => Before::Endpoints(cluster_endpoints(
+                &self.dyn_cfg.clusters()?.read(),
+                localities.as_ref(),
+            )),
+            Resource::Filters | Resource::Datacenters => {
+                Before::Value(self.resource_value(resource)?)
+            }
+        };
+
+        Some(Snapshot {
+            resource,
+            localities,
+            before,
+        })
+    }
+
+    /// Records the change of the resource since the snapshot was taken,
+    /// returning what changed
+    pub(crate) fn record(
+        &self,
+        snapshot: Option<Snapshot>,
+        source: Source,
+        versions: BTreeMap<String, String>,
+    ) -> Vec<Change> {
+        let Some((snapshot, history)) = snapshot.zip(self.dyn_cfg.history()) else {
+            return Vec::new();
+        };
+
+        let resource = snapshot.resource;
+        let (changes, filters) = match snapshot.before {
+            Before::Endpoints(before) => {
+                let Some(clusters) = self.dyn_cfg.clusters() else {
+                    return Vec::new();
+                };
+                let changes =
+                    cluster_changes(before, &clusters.read(), snapshot.localities.as_ref());
+                (changes, None)
+            }
+            Before::Value(before) => {
+                let Some(after) = self.resource_value(resource) else {
+                    return Vec::new();
+                };
+                let changes = diff(resource, before, after.clone());
+                (changes, (resource == Resource::Filters).then_some(after))
+            }
+        };
+
+        history.record(resource, source, versions, changes, filters)
+    }
+
+    /// Records the changes to the endpoints of each locality, from the
+    /// endpoints it had before to the ones it has after, for changes that
+    /// know exactly what they replaced, returning what changed
+    pub(crate) fn record_endpoints(
+        &self,
+        replaced: &[(
+            Option<Locality>,
+            BTreeMap<EndpointAddress, EndpointMetadata>,
+            BTreeMap<EndpointAddress, EndpointMetadata>,
+        )],
+        source: Source,
+    ) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (locality, before, after) in replaced {
+            locality_changes(locality, before, after, &mut changes);
+        }
+
+        self.record_changes(Resource::Clusters, source, changes, None)
+    }
+
+    /// Records the change of the filter chain from `before` to `after`,
+    /// returning what changed
+    pub(crate) fn record_filters(
+        &self,
+        before: &crate::filters::FilterChain,
+        after: &crate::filters::FilterChain,
+        source: Source,
+    ) -> Vec<Change> {
+        let value = |chain: &crate::filters::FilterChain| {
+            serde_json::to_value(chain)
+                .inspect_err(|error| tracing::warn!(%error, "failed to serialize filter chain"))
+                .ok()
+        };
+
+        let Some((before, after)) = value(before).zip(value(after)) else {
+            return Vec::new();
+        };
+        let changes = diff(Resource::Filters, before, after.clone());
+        self.record_changes(Resource::Filters, source, changes, Some(after))
+    }
+
+    /// Records the changes in the history if it's enabled, returning them
+    fn record_changes(
+        &self,
+        resource: Resource,
+        source: Source,
+        changes: Vec<Change>,
+        filters: Option<Value>,
+    ) -> Vec<Change> {
+        match self.dyn_cfg.history() {
+            Some(history) => history.record(resource, source, BTreeMap::new(), changes, filters),
+            None => changes,
+        }
+    }
+
+    /// Restores the filter chain to its value after the history entry was
+    /// applied, returning what changed
+    pub fn rollback_filters(&self, id: u64) -> eyre::Result<Vec<Change>> {
+        let Some(entry) = self.dyn_cfg.history().and_then(|history| history.get(id)) else {
+            eyre::bail!("there is no history entry {id}");
+        };
+        let Some(chain) = entry.filters else {
+            eyre::bail!("history entry {id} is not a change to the filter chain");
+        };
+        let Some(filters) = self.dyn_cfg.filters() else {
+            eyre::bail!("the filter chain is not enabled");
+        };
+
+        let chain: crate::filters::FilterChain = serde_json::from_value(chain)?;
+        let before = filters.swap(chain.clone());
+        Ok(self.record_filters(&before, &chain, Source::Rollback { entry: id }))
+    }
+}
+
+/// Splits a resource into its entries, keyed by the index of each filter, or
+/// the address of each datacenter
+fn entries(resource: Resource, value: Value) -> BTreeMap<String, (Value, Value)> {
+    let entries: Vec<(Value, Value)> = match (resource, value) {
+        (Resource::Filters, Value::Array(filters)) => filters
+            .into_iter()
+            .enumerate()
+            .map(|(index, filter)| (Value::from(index), filter))
+            .collect(),
+        (Resource::Datacenters, mut datacenters) => {
+            match datacenters.get_mut("map").map(Value::take) {
+                Some(Value::Object(map)) => map
+                    .into_iter()
+                    .map(|(address, dc)| (Value::from(address), dc))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        }
+        _ => Vec::new(),
+    };
+
+    entries
+        .into_iter()
+        .map(|(key, entry)| {
+            // Zero padded so filters are ordered by their index
+            let sort_key = match &key {
+                Value::Number(index) => format!("{index:0>10}"),
+                key => key.to_string(),
+            };
+            (sort_key, (key, entry))
+        })
+        .collect()
+}
+
+/// Compares two values of the filters or datacenters, the clusters are
+/// compared by their endpoints instead
+pub fn diff(resource: Resource, before: Value, after: Value) -> Vec<Change> {
+    let mut before = entries(resource, before);
+    let mut changes = Vec::new();
+
+    for (sort_key, (key, after)) in entries(resource, after) {
+        match before.remove(&sort_key) {
+            Some((_, before)) if before == after => {}
+            before => changes.push(Change {
+                key,
+                before: before.map(|(_, before)| before),
+                after: Some(after),
+            }),
+        }
+    }
+
+    changes.extend(before.into_values().map(|(key, before)| Change {
+        key,
+        before: Some(before),
+        after: None,
+    }));
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn cluster_diff() {
+        let config = crate::test::TestHelper::new_config();
+        let clusters = config.dyn_cfg.clusters().unwrap();
+        let eu = Some(Locality::with_region("eu"));
+        let us = Some(Locality::with_region("us"));
+        let endpoint = |port: u16| {
+            crate::net::endpoint::Endpoint::new(EndpointAddress::from(([127, 0, 0, 1], port)))
+        };
+
+        clusters.modify(|clusters| {
+            clusters.insert(None, eu.clone(), [endpoint(1)].into());
+            clusters.insert(None, us.clone(), [endpoint(2)].into());
+        });
+
+        let snapshot = config.snapshot(Resource::Clusters, None);
+        clusters.modify(|clusters| {
+            clusters.insert(None, eu.clone(), [endpoint(1)].into());
+            clusters.insert(None, us.clone(), [endpoint(3)].into());
+            clusters.insert(None, None, [endpoint(4)].into());
+        });
+        let changes = config.record(snapshot, Source::File, Default::default());
+
+        let key = |locality: &Option<Locality>, port: u16| json!({ "locality": locality, "address": format!("127.0.0.1:{port}") });
+        let metadata = Some(json!({ "quilkin.dev": { "tokens": [] } }));
+
+        // Only the endpoints that changed are recorded
+        assert_eq!(
+            changes,
+            [
+                Change {
+                    key: key(&None, 4),
+                    before: None,
+                    after: metadata.clone(),
+                },
+                Change {
+                    key: key(&us, 3),
+                    before: None,
+                    after: metadata.clone(),
+                },
+                Change {
+                    key: key(&us, 2),
+                    before: metadata.clone(),
+                    after: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bounded_size() {
+        let history = ConfigHistory::default();
+        let large = |id: usize| Change {
+            key: Value::from(id),
+            before: None,
+            after: Some(Value::String("a".repeat(MAX_ENTRY_BYTES / 4))),
+        };
+
+        // A single change that is too large keeps only what fits
+        let changes: Vec<_> = (0..8).map(large).collect();
+        let recorded = history.record(
+            Resource::Clusters,
+            Source::File,
+            Default::default(),
+            changes,
+            None,
+        );
+        assert_eq!(recorded.len(), 8);
+        let entries = history.entries();
+        assert_eq!(entries[0].changes.len(), 3);
+        assert_eq!(entries[0].omitted, 5);
+
+        // The oldest entries are dropped once the total size is exceeded
+        for _ in 0..MAX_BYTES / MAX_ENTRY_BYTES * 2 {
+            history.record(
+                Resource::Clusters,
+                Source::File,
+                Default::default(),
+                (0..8).map(large).collect(),
+                None,
+            );
+        }
+        let entries = history.entries();
+        assert!(entries.len() < MAX_BYTES / MAX_ENTRY_BYTES * 2);
+        assert!(entries.iter().map(|entry| entry.size).sum::<usize>() <= MAX_BYTES);
+    }
+
+    #[tokio::test]
+    async fn records_and_rolls_back_filters() {
+        let config = crate::test::TestHelper::new_config();
+        let debug = |id: &str| {
+            json!({ "filters": [{
+                "name": "quilkin.filters.debug.v1alpha1.Debug",
+                "config": { "id": id },
+            }] })
+        };
+        let apply = |value: Value| {
+            config
+                .update_from_json(serde_json::from_value(value).unwrap(), None)
+                .unwrap();
+        };
+
+        apply(debug("first"));
+        apply(debug("second"));
+        // Applying the same chain again isn't a change
+        apply(debug("second"));
+
+        let history = config.dyn_cfg.history().unwrap();
+        let entries = history.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].source, Source::File);
+        assert_eq!(entries[1].resource, Resource::Filters);
+        assert_eq!(
+            entries[1].changes[0].after.as_ref().unwrap()["config"]["id"],
+            "second"
+        );
+
+        let changes = config.rollback_filters(entries[0].id).unwrap();
+        assert_eq!(changes[0].after.as_ref().unwrap()["config"]["id"], "first");
+        assert_eq!(
+            history.entries()[2].source,
+            Source::Rollback {
+                entry: entries[0].id
+            }
+        );
+        assert!(config.rollback_filters(u64::MAX).is_err());
+    }
+}
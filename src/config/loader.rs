@@ -0,0 +1,557 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Loads the configuration from a single file, or from a directory of files
+//! that are merged together.
+//!
+//! Every string value of a file has `${NAME}` replaced with the value of the
+//! environment variable `NAME` (or `${NAME:-default}` to use `default` when it
+//! isn't set) once the file is parsed, so comments and keys are left as they
+//! are, and any value tagged with `!include <path>` replaced with the contents
+//! of the file at `path`, relative to the file including it.
+//!
+//! The files of a directory are merged in the order of their paths, lists are
+//! concatenated, maps merged, and any other value replaced by the later file.
+//! Files in a `<field>.d` directory, such as `filters.d` or `clusters.d`, hold
+//! either a single item of that field's list, or a list of items.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use eyre::WrapErr as _;
+use serde_json::{Map, Value};
+
+pub(crate) const INCLUDE_TAG: &str = "include";
+
+/// The configuration loaded by [`load`].
+#[derive(Debug)]
+pub struct Loaded {
+    pub config: Map<String, Value>,
+    /// Every file read, including the ones included by another, so they can
+    /// be watched for changes.
+    pub files: BTreeSet<PathBuf>,
+}
+
+/// Loads the configuration from `path`, which is either a single file, or a
+/// directory of files to merge.
+pub fn load(path: &Path) -> crate::Result<Loaded> {
+    let mut loader = Loader::default();
+
+    let config = if path.is_dir() {
+        let mut documents = Vec::new();
+        for file in yaml_files(path)? {
+            let value = loader.file(&file)?;
+            documents.push((file, value));
+        }
+
+        let mut config = Map::new();
+        for (file, value) in documents {
+            if loader.included.contains(&file.canonicalize()?) {
+                continue;
+            }
+
+            let layer = match list_field(path, &file) {
+                Some(field) => {
+                    let items = match value {
+                        serde_yaml::Value::Sequence(items) => items,
+                        item => vec![item],
+                    };
+                    Map::from_iter([(field, to_json(serde_yaml::Value::Sequence(items))?)])
+                }
+                None => object(value, &file)?,
+            };
+
+            merge(&mut config, layer);
+        }
+
+        if let Some(Value::Array(clusters)) = config.get_mut("clusters") {
+            *clusters = combine_clusters(std::mem::take(clusters));
+        }
+
+        config
+    } else {
+        object(loader.file(path)?, path)?
+    };
+
+    Ok(Loaded {
+        config,
+        files: loader.files,
+    })
+}
+
+/// Reads a single file, substituting environment variables and resolving its
+/// includes, without merging it into any configuration.
+pub(crate) fn include(path: &Path) -> crate::Result<serde_yaml::Value> {
+    Loader::default().file(path)
+}
+
+#[derive(Default)]
+struct Loader {
+    files: BTreeSet<PathBuf>,
+    included: BTreeSet<PathBuf>,
+    /// The files currently being read, to find files including themselves.
+    stack: Vec<PathBuf>,
+}
+
+impl Loader {
+    fn file(&mut self, path: &Path) -> crate::Result<serde_yaml::Value> {
+        let canonical = path
+            .canonicalize()
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        if self.stack.contains(&canonical) {
+            eyre::bail!("{} includes itself", path.display());
+        }
+
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        let value = serde_yaml::from_str(&text)
+            .wrap_err_with(|| format!("failed to parse {}", path.display()))?;
+        let probe = serde_yaml::from_str(&expression_probe(&text)).ok();
+        let value = substitute_value(value, probe.as_ref(), &|name| std::env::var(name).ok())
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+
+        self.files.insert(canonical.clone());
+        self.stack.push(canonical);
+        let value = self.resolve(value, path.parent().unwrap_or(Path::new(".")));
+        self.stack.pop();
+
+        value.wrap_err_with(|| format!("failed to read {}", path.display()))
+    }
+
+    /// Replaces every `!include` in `value` with the file it refers to.
+    fn resolve(
+        &mut self,
+        value: serde_yaml::Value,
+        dir: &Path,
+    ) -> crate::Result<serde_yaml::Value> {
+        use serde_yaml::Value;
+
+        Ok(match value {
+            Value::Tagged(tagged) if tagged.tag == INCLUDE_TAG => {
+                let Value::String(include) = tagged.value else {
+                    eyre::bail!("`!{INCLUDE_TAG}` must be followed by a path");
+                };
+
+                let path = dir.join(include);
+                let value = self.file(&path)?;
+                self.included.insert(path.canonicalize()?);
+                value
+            }
+            Value::Tagged(tagged) => eyre::bail!("unsupported tag `{}`", tagged.tag),
+            Value::Sequence(items) => Value::Sequence(
+                items
+                    .into_iter()
+                    .map(|item| self.resolve(item, dir))
+                    .collect::<crate::Result<_>>()?,
+            ),
+            Value::Mapping(entries) => Value::Mapping(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| Ok((key, self.resolve(value, dir)?)))
+                    .collect::<crate::Result<_>>()?,
+            ),
+            value => value,
+        })
+    }
+}
+
+/// Replaces every `${NAME}` in `text` with a distinct number. Parsing the
+/// result gives the probe of [`substitute_value`], as only the unquoted values
+/// that are nothing but a `${NAME}` become numbers, quoted values being kept
+/// as strings.
+pub(crate) fn expression_probe(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut count = 0;
+
+    while let Some(start) = rest.find("${") {
+        let (before, after) = rest.split_at(start);
+        output.push_str(before);
+
+        match after.find('}') {
+            Some(end) if !before.ends_with('$') => {
+                output.push_str(&count.to_string());
+                count += 1;
+                rest = &after[end + 1..];
+            }
+            _ => {
+                output.push_str("${");
+                rest = &after[2..];
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Substitutes environment variables into every string value of `value`,
+/// including the paths of `!include`s. An unquoted value that is nothing but a
+/// single `${NAME}` becomes a boolean or number if that's what it's replaced
+/// with, so fields such as `port: ${PORT}` keep their type, while quoted
+/// values such as `id: "${ID}"` are always strings.
+///
+/// `probe` is the same document parsed from [`expression_probe`], to tell
+/// which values are unquoted, without it every value is kept as a string.
+pub(crate) fn substitute_value(
+    value: serde_yaml::Value,
+    probe: Option<&serde_yaml::Value>,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> crate::Result<serde_yaml::Value> {
+    use serde_yaml::Value;
+
+    Ok(match value {
+        Value::String(text) => {
+            let substituted = substitute(&text, lookup)?;
+            let is_expression = matches!(probe, Some(Value::Number(_)))
+                && text.starts_with("${")
+                && text.find('}') == Some(text.len() - 1);
+
+            match serde_yaml::from_str(&substituted) {
+                Ok(typed @ (Value::Bool(_) | Value::Number(_))) if is_expression => typed,
+                _ => Value::String(substituted),
+            }
+        }
+        Value::Tagged(mut tagged) => {
+            let probe = match probe {
+                Some(Value::Tagged(probe)) => Some(&probe.value),
+                _ => None,
+            };
+            tagged.value = substitute_value(tagged.value, probe, lookup)?;
+            Value::Tagged(tagged)
+        }
+        Value::Sequence(items) => {
+            let mut probes = probe.and_then(Value::as_sequence).into_iter().flatten();
+            Value::Sequence(
+                items
+                    .into_iter()
+                    .map(|item| substitute_value(item, probes.next(), lookup))
+                    .collect::<crate::Result<_>>()?,
+            )
+        }
+        Value::Mapping(entries) => {
+            // Keys containing `${NAME}` differ in the probe, so values are
+            // matched by their position
+            let mut probes = probe
+                .and_then(Value::as_mapping)
+                .into_iter()
+                .flat_map(serde_yaml::Mapping::values);
+            Value::Mapping(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| Ok((key, substitute_value(value, probes.next(), lookup)?)))
+                    .collect::<crate::Result<_>>()?,
+            )
+        }
+        value => value,
+    })
+}
+
+/// Replaces `${NAME}` in `text` with the value of `NAME` returned by `lookup`,
+/// or with `default` for `${NAME:-default}` if it isn't set. `$${` is kept
+/// as a literal `${`.
+fn substitute(text: &str, lookup: impl Fn(&str) -> Option<String>) -> crate::Result<String> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        let (before, after) = rest.split_at(start);
+        if let Some(before) = before.strip_suffix('$') {
+            output.push_str(before);
+            output.push_str("${");
+            rest = &after[2..];
+            continue;
+        }
+
+        output.push_str(before);
+        let end = after
+            .find('}')
+            .ok_or_else(|| eyre::eyre!("`${{` is never closed in `{text}`"))?;
+
+        let expression = &after[2..end];
+        let (name, default) = match expression.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expression, None),
+        };
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            eyre::bail!("`{name}` is not a valid environment variable name");
+        }
+
+        match (lookup(name), default) {
+            (Some(value), _) => output.push_str(&value),
+            (None, Some(default)) => output.push_str(default),
+            (None, None) => eyre::bail!("environment variable `{name}` is not set"),
+        }
+
+        rest = &after[end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Finds every YAML file in `dir`, ordered by their path, skipping hidden
+/// files and directories such as the ones Kubernetes uses to mount volumes.
+fn yaml_files(dir: &Path) -> crate::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = std::fs::read_dir(&dir)
+            .wrap_err_with(|| format!("failed to read {}", dir.display()))?;
+
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            {
+                continue;
+            }
+
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == "yaml" || extension == "yml")
+            {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// The field of the list `file` holds items of, if it's in a `<field>.d`
+/// directory.
+fn list_field(root: &Path, file: &Path) -> Option<String> {
+    let relative = file.strip_prefix(root).ok()?;
+    // Files directly in the root are never in a `.d` directory
+    if relative.components().count() < 2 {
+        return None;
+    }
+
+    let first = relative.components().next()?.as_os_str().to_str()?;
+    first.strip_suffix(".d").map(String::from)
+}
+
+fn to_json(value: serde_yaml::Value) -> crate::Result<Value> {
+    Ok(serde_json::to_value(value)?)
+}
+
+fn object(value: serde_yaml::Value, file: &Path) -> crate::Result<Map<String, Value>> {
+    match to_json(value)? {
+        Value::Object(map) => Ok(map),
+        // Empty files have nothing to apply
+        Value::Null => Ok(Map::new()),
+        _ => eyre::bail!("{} is not a map of configuration fields", file.display()),
+    }
+}
+
+/// Merges `layer` into `config`, concatenating lists, merging maps, and
+/// replacing any other value.
+fn merge(config: &mut Map<String, Value>, layer: Map<String, Value>) {
+    for (key, value) in layer {
+        match (config.get_mut(&key), value) {
+            (Some(Value::Array(existing)), Value::Array(items)) => existing.extend(items),
+            (Some(Value::Object(existing)), Value::Object(fields)) => merge(existing, fields),
+            (_, value) => {
+                config.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Combines clusters of the same locality from different files, as each
+/// locality can only be in the configuration once.
+fn combine_clusters(clusters: Vec<Value>) -> Vec<Value> {
+    let locality = |cluster: &Value| cluster.get("locality").filter(|l| !l.is_null()).cloned();
+    let mut combined: Vec<Value> = Vec::with_capacity(clusters.len());
+
+    for cluster in clusters {
+        let existing = combined
+            .iter_mut()
+            .find(|existing| locality(existing) == locality(&cluster));
+
+        match (existing, cluster) {
+            (Some(Value::Object(existing)), Value::Object(cluster)) => merge(existing, cluster),
+            (_, cluster) => combined.push(cluster),
+        }
+    }
+
+    combined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "PORT" => Some("7777".into()),
+            "EMPTY" => Some(String::new()),
+            "SPECIAL" => Some(": # {a} - [b] &c *d".into()),
+            _ => None,
+        }
+    }
+
+    fn substituted(yaml: &str) -> crate::Result<Value> {
+        let probe = serde_yaml::from_str(&expression_probe(yaml)).ok();
+        to_json(substitute_value(
+            serde_yaml::from_str(yaml).unwrap(),
+            probe.as_ref(),
+            &lookup,
+        )?)
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        assert_eq!(
+            substitute("127.0.0.1:${PORT}", lookup).unwrap(),
+            "127.0.0.1:7777"
+        );
+        assert_eq!(substitute("${ID:-proxy}", lookup).unwrap(), "proxy");
+        assert_eq!(substitute("$${PORT}", lookup).unwrap(), "${PORT}");
+        assert!(substitute("${ID}", lookup).is_err());
+        assert!(substitute("${ID", lookup).is_err());
+        assert!(substitute("${I D}", lookup).is_err());
+
+        assert_eq!(
+            substituted(
+                "address: 127.0.0.1:${PORT}\nport: ${PORT}\nid: ${ID:-proxy}\nliteral: $${PORT}"
+            )
+            .unwrap(),
+            json!({
+                "address": "127.0.0.1:7777",
+                "port": 7777,
+                "id": "proxy",
+                "literal": "${PORT}",
+            })
+        );
+        assert_eq!(
+            substitute_value(
+                serde_yaml::from_str("!include ${ID:-filters}.yaml").unwrap(),
+                None,
+                &lookup
+            )
+            .unwrap(),
+            serde_yaml::from_str::<serde_yaml::Value>("!include filters.yaml").unwrap(),
+        );
+    }
+
+    #[test]
+    fn keeps_quoted_values_as_strings() {
+        assert_eq!(
+            substituted(
+                "port: ${PORT}\nid: \"${PORT}\"\ntoken: '${PORT}'\nempty: ${EMPTY:-default}\n"
+            )
+            .unwrap(),
+            json!({ "port": 7777, "id": "7777", "token": "7777", "empty": "" })
+        );
+    }
+
+    #[test]
+    fn ignores_variables_in_comments() {
+        assert_eq!(
+            substituted("# listens on ${UNSET}\nid: proxy # ${ALSO_UNSET\n").unwrap(),
+            json!({ "id": "proxy" })
+        );
+    }
+
+    #[test]
+    fn keeps_special_characters_in_values() {
+        assert_eq!(
+            substituted("id: ${SPECIAL}\nversion: v1alpha1\n").unwrap(),
+            json!({ "id": ": # {a} - [b] &c *d", "version": "v1alpha1" })
+        );
+    }
+
+    #[test]
+    fn merges_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let write = |path: &str, contents: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+
+        write("base.yaml", "version: v1alpha1\nid: base\n");
+        write("override.yaml", "id: override\n");
+        write(
+            "filters.d/10-capture.yaml",
+            "name: quilkin.filters.capture.v1alpha1.Capture\nconfig: !include ../capture.yml\n",
+        );
+        write(
+            "filters.d/20-router.yaml",
+            "- name: quilkin.filters.token_router.v1alpha1.TokenRouter\n",
+        );
+        write("capture.yml", "suffix:\n  size: 3\n  remove: true\n");
+        write(
+            "clusters.d/a.yaml",
+            "endpoints:\n  - address: 127.0.0.1:26000\n",
+        );
+        write(
+            "clusters.d/b.yaml",
+            "endpoints:\n  - address: 127.0.0.1:26001\n",
+        );
+        write(".hidden/ignored.yaml", "id: hidden\n");
+
+        let loaded = load(root).unwrap();
+        assert_eq!(
+            Value::Object(loaded.config),
+            json!({
+                "version": "v1alpha1",
+                "id": "override",
+                "filters": [
+                    {
+                        "name": "quilkin.filters.capture.v1alpha1.Capture",
+                        "config": { "suffix": { "size": 3, "remove": true } },
+                    },
+                    { "name": "quilkin.filters.token_router.v1alpha1.TokenRouter" },
+                ],
+                "clusters": [
+                    {
+                        "endpoints": [
+                            { "address": "127.0.0.1:26000" },
+                            { "address": "127.0.0.1:26001" },
+                        ],
+                    },
+                ],
+            })
+        );
+        assert_eq!(loaded.files.len(), 7);
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(&path, "filters: !include config.yaml\n").unwrap();
+
+        let error = load(&path).unwrap_err();
+        assert!(
+            format!("{error:#}").contains("includes itself"),
+            "{error:#}"
+        );
+    }
+}
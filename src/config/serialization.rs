@@ -3,7 +3,31 @@ use serde::ser::SerializeMap;
 use super::*;
 
 impl Config {
+    /// Applies the configuration from a file, recording the changes to the
+    /// filters and clusters in the [`history`](DynamicConfig::history)
     pub fn update_from_json(
+        &self,
+        map: serde_json::Map<String, serde_json::Value>,
+        locality: Option<crate::net::endpoint::Locality>,
+    ) -> Result<(), eyre::Error> {
+        let snapshots: Vec<_> = [history::Resource::Filters, history::Resource::Clusters]
+            .into_iter()
+            .filter(|resource| map.contains_key(resource.key()))
+            .map(|resource| self.snapshot(resource, None))
+            .collect();
+
+        self.apply_json(map, locality)?;
+
+        for snapshot in snapshots {
+            self.record(snapshot, history::Source::File, Default::default());
+        }
+
+        Ok(())
+    }
+
+    /// Applies the configuration without recording the changes, see
+    /// [`Config::update_from_json`]
+    pub(crate) fn apply_json(
         &self,
         map: serde_json::Map<String, serde_json::Value>,
         mut locality: Option<crate::net::endpoint::Locality>,
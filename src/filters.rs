@@ -35,6 +35,7 @@ pub mod local_rate_limit;
 pub mod r#match;
 pub mod metrics;
 pub mod pass;
+pub mod proxy_protocol;
 pub mod timestamp;
 pub mod token_router;
 
@@ -63,6 +64,7 @@ pub use self::{
     local_rate_limit::LocalRateLimit,
     r#match::Match,
     pass::Pass,
+    proxy_protocol::ProxyProtocol,
     read::ReadContext,
     registry::FilterRegistry,
     set::{FilterMap, FilterSet},
@@ -90,6 +92,7 @@ pub enum FilterKind {
     LocalRateLimit,
     Pass,
     Match,
+    ProxyProtocol,
     Timestamp,
     TokenRouter,
     HashedTokenRouter,
@@ -117,6 +117,53 @@ impl FilterChain {
         })
     }
 
+    /// Runs [`Filter::read`] in the same way as the chain, calling `inspect`
+    /// with the name, label and result of each filter after it has run, so
+    /// what each filter does to a packet can be traced.
+    pub fn read_inspect<P: PacketMut>(
+        &self,
+        ctx: &mut ReadContext<'_, P>,
+        mut inspect: impl FnMut(&str, Option<&str>, &ReadContext<'_, P>, Result<(), &FilterError>),
+    ) -> Result<(), FilterError> {
+        for (id, instance) in &self.filters {
+            let result = instance.filter().read(ctx);
+            inspect(id, instance.label(), ctx, result.as_ref().copied());
+            result?;
+        }
+
+        Self::passthrough(ctx);
+
+        Ok(())
+    }
+
+    /// Runs [`Filter::write`] in the same way as the chain, calling `inspect`
+    /// with the name, label and result of each filter after it has run, so
+    /// what each filter does to a packet can be traced.
+    pub fn write_inspect<P: PacketMut>(
+        &self,
+        ctx: &mut WriteContext<P>,
+        mut inspect: impl FnMut(&str, Option<&str>, &WriteContext<P>, Result<(), &FilterError>),
+    ) -> Result<(), FilterError> {
+        for (id, instance) in self.filters.iter().rev() {
+            let result = instance.filter().write(ctx);
+            inspect(id, instance.label(), ctx, result.as_ref().copied());
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Special case to handle to allow for pass-through, if no filter
+    /// has rejected, and the destinations is empty, we passthrough to all.
+    /// Which mimics the old behaviour while avoid clones in most cases.
+    #[inline]
+    fn passthrough<P>(ctx: &mut ReadContext<'_, P>) {
+        if ctx.destinations.is_empty() {
+            ctx.destinations
+                .extend(ctx.endpoints.endpoints().into_iter().map(|ep| ep.address));
+        }
+    }
+
     /// Validates the filter configurations in the provided config and constructs
     /// a [`Self`] if all configurations are valid, including the conversion
     /// into a [`Filter`]
@@ -292,13 +339,7 @@ impl Filter for FilterChain {
             }
         }
 
-        // Special case to handle to allow for pass-through, if no filter
-        // has rejected, and the destinations is empty, we passthrough to all.
-        // Which mimics the old behaviour while avoid clones in most cases.
-        if ctx.destinations.is_empty() {
-            ctx.destinations
-                .extend(ctx.endpoints.endpoints().into_iter().map(|ep| ep.address));
-        }
+        Self::passthrough(ctx);
 
         Ok(())
     }
@@ -25,7 +25,8 @@ use std::{
 
 use crate::net::{ClusterMap, EndpointAddress};
 
-/// Chooses from a set of endpoints that a proxy is connected to.
+/// Chooses from a set of endpoints that a proxy is connected to, skipping
+/// endpoints that are marked unhealthy.
 pub trait EndpointChooser: Send + Sync {
     /// Asks for the next endpoint(s) to use.
     fn choose_endpoints(
@@ -57,14 +58,9 @@ impl EndpointChooser for RoundRobinEndpointChooser {
         _src: &EndpointAddress,
     ) {
         let count = self.next_endpoint.fetch_add(1, Ordering::Relaxed);
-        // Note: The index is guaranteed to be in range.
-        destinations.push(
-            endpoints
-                .nth_endpoint(count % endpoints.num_of_endpoints())
-                .unwrap()
-                .address
-                .clone(),
-        );
+        if let Some(endpoint) = endpoints.choose_routable_endpoint(|len| count % len) {
+            destinations.push(endpoint.address);
+        }
     }
 }
 
@@ -78,9 +74,11 @@ impl EndpointChooser for RandomEndpointChooser {
         endpoints: &ClusterMap,
         _src: &EndpointAddress,
     ) {
-        // The index is guaranteed to be in range.
-        let index = rand::rng().random_range(0..endpoints.num_of_endpoints());
-        destinations.push(endpoints.nth_endpoint(index).unwrap().address.clone());
+        if let Some(endpoint) =
+            endpoints.choose_routable_endpoint(|len| rand::rng().random_range(0..len))
+        {
+            destinations.push(endpoint.address);
+        }
     }
 }
 
@@ -96,12 +94,9 @@ impl EndpointChooser for HashEndpointChooser {
     ) {
         let mut hasher = DefaultHasher::new();
         src.hash(&mut hasher);
-        destinations.push(
-            endpoints
-                .nth_endpoint(hasher.finish() as usize % endpoints.num_of_endpoints())
-                .unwrap()
-                .address
-                .clone(),
-        );
+        let hash = hasher.finish() as usize;
+        if let Some(endpoint) = endpoints.choose_routable_endpoint(|len| hash % len) {
+            destinations.push(endpoint.address);
+        }
     }
 }
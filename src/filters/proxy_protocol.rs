@@ -0,0 +1,596 @@
+/*
+ * Copyright 2026 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::{IpAddr, SocketAddr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collections::ttl::TtlMap,
+    filters::{local_rate_limit::SESSION_TIMEOUT_SECONDS, prelude::*},
+    net::endpoint::{EndpointAddress, metadata},
+};
+
+use crate::generated::quilkin::filters::proxy_protocol::v1alpha1 as proto;
+
+/// The signature every PROXY protocol v2 header starts with
+pub const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// The magic bytes every compact header starts with
+pub const COMPACT_MAGIC: [u8; 2] = *b"QK";
+
+/// Version 2, `PROXY` command
+const V2_VERSION_COMMAND: u8 = 0x21;
+/// `AF_INET` + `SOCK_DGRAM`
+const V2_UDP4: u8 = 0x12;
+/// `AF_INET6` + `SOCK_DGRAM`
+const V2_UDP6: u8 = 0x22;
+/// Version 1 in the high nibble, the IP version in the low nibble
+const COMPACT_VERSION: u8 = 0x10;
+
+const SESSION_EXPIRY_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// A client and the endpoint it's sending to, which is `None` if the
+/// endpoints haven't been chosen yet when the filter runs
+type SessionKey = (EndpointAddress, Option<EndpointAddress>);
+
+const HEADER_TOO_LARGE: FilterError =
+    FilterError::Custom("PROXY protocol header is larger than 65535 bytes");
+
+/// The `ProxyProtocol` filter prepends a header carrying the address of the
+/// client that sent a packet, and optionally values from the dynamic metadata,
+/// to packets sent to upstream endpoints. This allows game servers to see the
+/// address of the player rather than the proxy.
+pub struct ProxyProtocol {
+    config: Config,
+    /// The sessions a header has already been sent for, when only the first
+    /// packet of a session carries a header
+    seen: Option<TtlMap<SessionKey, ()>>,
+}
+
+impl ProxyProtocol {
+    fn new(config: Config) -> Self {
+        let seen = matches!(config.frequency, Frequency::FirstPacket)
+            .then(|| TtlMap::new(SESSION_TIMEOUT_SECONDS, SESSION_EXPIRY_POLL_INTERVAL));
+
+        Self { config, seen }
+    }
+
+    pub fn testing(config: Config) -> Self {
+        Self::new(config)
+    }
+
+    /// Encodes the header for a packet received from `source`, failing if the
+    /// TLVs don't fit in the header's 16 bit length
+    fn encode(
+        &self,
+        source: SocketAddr,
+        metadata: &metadata::DynamicMetadata,
+    ) -> Result<Vec<u8>, FilterError> {
+        let mut tlvs = Vec::new();
+        for tlv in &self.config.tlvs {
+            let Some(value) = metadata.get(&tlv.key).and_then(tlv_value) else {
+                continue;
+            };
+            let Ok(len) = u16::try_from(value.len()) else {
+                continue;
+            };
+
+            tlvs.push(tlv.tlv_type);
+            tlvs.extend_from_slice(&len.to_be_bytes());
+            tlvs.extend_from_slice(&value);
+        }
+
+        let ip = source.ip().to_canonical();
+        let mut header = Vec::with_capacity(64 + tlvs.len());
+
+        match self.config.format {
+            Format::V2 => {
+                header.extend_from_slice(&V2_SIGNATURE);
+                header.push(V2_VERSION_COMMAND);
+
+                // The listening address of the proxy isn't known to filters,
+                // so the destination is always sent as unspecified
+                match ip {
+                    IpAddr::V4(ip) => {
+                        let length =
+                            u16::try_from(12 + tlvs.len()).map_err(|_e| HEADER_TOO_LARGE)?;
+                        header.push(V2_UDP4);
+                        header.extend_from_slice(&length.to_be_bytes());
+                        header.extend_from_slice(&ip.octets());
+                        header.extend_from_slice(&[0; 4]);
+                    }
+                    IpAddr::V6(ip) => {
+                        let length =
+                            u16::try_from(36 + tlvs.len()).map_err(|_e| HEADER_TOO_LARGE)?;
+                        header.push(V2_UDP6);
+                        header.extend_from_slice(&length.to_be_bytes());
+                        header.extend_from_slice(&ip.octets());
+                        header.extend_from_slice(&[0; 16]);
+                    }
+                }
+                header.extend_from_slice(&source.port().to_be_bytes());
+                header.extend_from_slice(&[0; 2]);
+            }
+            Format::Compact => {
+                header.extend_from_slice(&COMPACT_MAGIC);
+                match ip {
+                    IpAddr::V4(ip) => {
+                        header.push(COMPACT_VERSION | 4);
+                        header.extend_from_slice(&ip.octets());
+                    }
+                    IpAddr::V6(ip) => {
+                        header.push(COMPACT_VERSION | 6);
+                        header.extend_from_slice(&ip.octets());
+                    }
+                }
+                let length = u16::try_from(tlvs.len()).map_err(|_e| HEADER_TOO_LARGE)?;
+                header.extend_from_slice(&source.port().to_be_bytes());
+                header.extend_from_slice(&length.to_be_bytes());
+            }
+        }
+
+        header.extend_from_slice(&tlvs);
+        Ok(header)
+    }
+}
+
+/// Returns the sessions of a packet from `source` that haven't had a header
+/// sent yet.
+///
+/// Sessions are per client and endpoint, so a client that starts sending to
+/// another endpoint, eg. because its routing token changed, sends it a header
+/// too. If the filter runs before the endpoints are chosen only the client is
+/// known, so the header is only sent once per client.
+fn unseen_sessions(
+    seen: &TtlMap<SessionKey, ()>,
+    source: &EndpointAddress,
+    destinations: &[EndpointAddress],
+) -> Vec<SessionKey> {
+    let keys = if destinations.is_empty() {
+        vec![(source.clone(), None)]
+    } else {
+        destinations
+            .iter()
+            .map(|destination| (source.clone(), Some(destination.clone())))
+            .collect()
+    };
+
+    keys.into_iter()
+        .filter(|key| seen.get(key).is_none())
+        .collect()
+}
+
+/// The bytes a metadata value is encoded as in a TLV, lists aren't supported
+fn tlv_value(value: &metadata::Value) -> Option<Vec<u8>> {
+    match value {
+        metadata::Value::Bytes(bytes) => Some(bytes.to_vec()),
+        metadata::Value::String(string) => Some(string.as_bytes().to_vec()),
+        metadata::Value::Number(number) => Some(number.to_be_bytes().to_vec()),
+        metadata::Value::Bool(boolean) => Some(vec![u8::from(*boolean)]),
+        metadata::Value::List(_) => None,
+    }
+}
+
+/// Returns the length of the header of `format` at the start of `packet`, if
+/// there is a complete one.
+pub fn header_length(format: Format, packet: &[u8]) -> Option<usize> {
+    let length = match format {
+        Format::V2 => {
+            if !packet.starts_with(&V2_SIGNATURE) || packet.len() < 16 {
+                return None;
+            }
+            16 + usize::from(u16::from_be_bytes([packet[14], packet[15]]))
+        }
+        Format::Compact => {
+            if !packet.starts_with(&COMPACT_MAGIC) || packet.len() < 3 {
+                return None;
+            }
+            let address = match packet[2] {
+                v if v == COMPACT_VERSION | 4 => 4,
+                v if v == COMPACT_VERSION | 6 => 16,
+                _ => return None,
+            };
+            let tlvs = 3 + address + 2;
+            let tlv_length = packet.get(tlvs..tlvs + 2)?;
+            tlvs + 2 + usize::from(u16::from_be_bytes([tlv_length[0], tlv_length[1]]))
+        }
+    };
+
+    (length <= packet.len()).then_some(length)
+}
+
+impl Filter for ProxyProtocol {
+    fn read<P: PacketMut>(&self, ctx: &mut ReadContext<'_, P>) -> Result<(), FilterError> {
+        let unseen = match &self.seen {
+            Some(seen) => {
+                let unseen = unseen_sessions(seen, &ctx.source, ctx.destinations);
+                if unseen.is_empty() {
+                    return Ok(());
+                }
+                unseen
+            }
+            None => Vec::new(),
+        };
+
+        let source = ctx.source.to_socket_addr()?;
+        let header = self.encode(source, &ctx.metadata)?;
+        ctx.contents.extend_head(&header);
+
+        if let Some(seen) = &self.seen {
+            for key in unseen {
+                seen.insert(key, ());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write<P: PacketMut>(&self, ctx: &mut WriteContext<P>) -> Result<(), FilterError> {
+        if self.config.strip_replies
+            && let Some(length) = header_length(self.config.format, ctx.contents.as_slice())
+        {
+            ctx.contents.remove_head(length);
+        }
+
+        Ok(())
+    }
+}
+
+impl StaticFilter for ProxyProtocol {
+    const NAME: &'static str = "quilkin.filters.proxy_protocol.v1alpha1.ProxyProtocol";
+    type Configuration = Config;
+    type BinaryConfiguration = proto::ProxyProtocol;
+
+    fn try_from_config(config: Option<Self::Configuration>) -> Result<Self, CreationError> {
+        Ok(Self::new(config.unwrap_or_default()))
+    }
+}
+
+/// The header format prepended to packets
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize, schemars::JsonSchema,
+)]
+pub enum Format {
+    /// A [PROXY protocol v2](https://www.haproxy.org/download/3.0/doc/proxy-protocol.txt)
+    /// header, with a `SOCK_DGRAM` transport.
+    #[serde(rename = "V2")]
+    #[default]
+    V2,
+    /// A smaller Quilkin specific header, `QK`, a version and IP version byte
+    /// (`0x14` or `0x16`), the client IP and port, the length of the TLVs and
+    /// the TLVs.
+    #[serde(rename = "COMPACT")]
+    Compact,
+}
+
+/// Which packets the header is prepended to
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize, schemars::JsonSchema,
+)]
+pub enum Frequency {
+    #[serde(rename = "EVERY_PACKET")]
+    #[default]
+    EveryPacket,
+    /// Only the first packet of each client and endpoint pair, until they have
+    /// been idle for as long as a session.
+    #[serde(rename = "FIRST_PACKET")]
+    FirstPacket,
+}
+
+/// A dynamic metadata value to add to the header
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct Tlv {
+    /// The key of the value in the filter's dynamic metadata, packets without
+    /// a value for the key are sent without the TLV.
+    pub key: metadata::Key,
+    /// The type of the TLV, the PROXY protocol reserves `0xE0`-`0xEF` for
+    /// custom types.
+    #[serde(rename = "type")]
+    pub tlv_type: u8,
+}
+
+/// Config represents a [`ProxyProtocol`] filter configuration.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct Config {
+    #[serde(default)]
+    pub format: Format,
+    #[serde(default)]
+    pub frequency: Frequency,
+    /// Values from the dynamic metadata to send in the header
+    #[serde(default)]
+    pub tlvs: Vec<Tlv>,
+    /// Whether to remove a header of the same format from the start of
+    /// packets sent back by the upstream endpoints
+    #[serde(default)]
+    pub strip_replies: bool,
+}
+
+impl From<Config> for proto::ProxyProtocol {
+    fn from(config: Config) -> Self {
+        Self {
+            format: match config.format {
+                Format::V2 => proto::proxy_protocol::Format::V2,
+                Format::Compact => proto::proxy_protocol::Format::Compact,
+            }
+            .into(),
+            frequency: match config.frequency {
+                Frequency::EveryPacket => proto::proxy_protocol::Frequency::EveryPacket,
+                Frequency::FirstPacket => proto::proxy_protocol::Frequency::FirstPacket,
+            }
+            .into(),
+            tlvs: config
+                .tlvs
+                .into_iter()
+                .map(|tlv| proto::proxy_protocol::Tlv {
+                    key: tlv.key.to_string(),
+                    tlv_type: tlv.tlv_type.into(),
+                })
+                .collect(),
+            strip_replies: config.strip_replies,
+        }
+    }
+}
+
+impl TryFrom<proto::ProxyProtocol> for Config {
+    type Error = ConvertProtoConfigError;
+
+    fn try_from(p: proto::ProxyProtocol) -> Result<Self, Self::Error> {
+        let format = match proto::proxy_protocol::Format::try_from(p.format) {
+            Ok(proto::proxy_protocol::Format::V2) => Format::V2,
+            Ok(proto::proxy_protocol::Format::Compact) => Format::Compact,
+            Err(_) => {
+                return Err(ConvertProtoConfigError::new(
+                    format!("invalid format {}", p.format),
+                    Some("format".into()),
+                ));
+            }
+        };
+
+        let frequency = match proto::proxy_protocol::Frequency::try_from(p.frequency) {
+            Ok(proto::proxy_protocol::Frequency::EveryPacket) => Frequency::EveryPacket,
+            Ok(proto::proxy_protocol::Frequency::FirstPacket) => Frequency::FirstPacket,
+            Err(_) => {
+                return Err(ConvertProtoConfigError::new(
+                    format!("invalid frequency {}", p.frequency),
+                    Some("frequency".into()),
+                ));
+            }
+        };
+
+        let tlvs = p
+            .tlvs
+            .into_iter()
+            .map(|tlv| {
+                Ok(Tlv {
+                    key: metadata::Key::new(tlv.key),
+                    tlv_type: tlv.tlv_type.try_into().map_err(|_e| {
+                        ConvertProtoConfigError::new(
+                            format!("TLV type {} is larger than 255", tlv.tlv_type),
+                            Some("tlvs.tlv_type".into()),
+                        )
+                    })?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            format,
+            frequency,
+            tlvs,
+            strip_replies: p.strip_replies,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::alloc_buffer;
+
+    fn read(
+        filter: &ProxyProtocol,
+        source: &str,
+        metadata: &[(&'static str, metadata::Value)],
+    ) -> Vec<u8> {
+        let endpoints = crate::net::cluster::ClusterMap::default();
+        let mut dest = Vec::new();
+        let mut ctx = ReadContext::new(
+            &endpoints,
+            source.parse().unwrap(),
+            alloc_buffer(b"hello"),
+            &mut dest,
+        );
+        for (key, value) in metadata {
+            ctx.metadata
+                .insert(metadata::Key::from_static(key), value.clone());
+        }
+
+        filter.read(&mut ctx).unwrap();
+        ctx.contents.as_slice().to_vec()
+    }
+
+    #[test]
+    fn v2() {
+        let filter = ProxyProtocol::testing(Config {
+            tlvs: vec![Tlv {
+                key: metadata::Key::from_static("quilkin.dev/capture"),
+                tlv_type: 0xe0,
+            }],
+            ..<_>::default()
+        });
+
+        let packet = read(&filter, "1.2.3.4:5678", &[]);
+        let mut expected = V2_SIGNATURE.to_vec();
+        expected.extend_from_slice(&[0x21, 0x12, 0, 12]);
+        expected.extend_from_slice(&[1, 2, 3, 4, 0, 0, 0, 0]);
+        expected.extend_from_slice(&5678u16.to_be_bytes());
+        expected.extend_from_slice(&[0, 0]);
+        assert_eq!(header_length(Format::V2, &packet), Some(expected.len()));
+        expected.extend_from_slice(b"hello");
+        assert_eq!(packet, expected);
+
+        let packet = read(
+            &filter,
+            "[::ffff:1.2.3.4]:5678",
+            &[("quilkin.dev/capture", metadata::Value::from(&[0xab, 0xcd]))],
+        );
+        assert_eq!(&packet[13..16], &[0x12, 0, 12 + 5]);
+        assert_eq!(&packet[28..33], &[0xe0, 0, 2, 0xab, 0xcd]);
+
+        let packet = read(&filter, "[2001:db8::1]:5678", &[]);
+        assert_eq!(&packet[13..16], &[0x22, 0, 36]);
+        assert_eq!(header_length(Format::V2, &packet), Some(52));
+        assert_eq!(&packet[52..], b"hello");
+    }
+
+    #[tokio::test]
+    async fn compact() {
+        let filter = ProxyProtocol::testing(Config {
+            format: Format::Compact,
+            frequency: Frequency::FirstPacket,
+            tlvs: vec![Tlv {
+                key: metadata::Key::from_static("player"),
+                tlv_type: 0xe1,
+            }],
+            strip_replies: true,
+        });
+
+        let metadata = [("player", metadata::Value::from("p1"))];
+        let packet = read(&filter, "1.2.3.4:5678", &metadata);
+        let mut expected = vec![b'Q', b'K', 0x14, 1, 2, 3, 4];
+        expected.extend_from_slice(&5678u16.to_be_bytes());
+        expected.extend_from_slice(&[0, 5, 0xe1, 0, 2, b'p', b'1']);
+        assert_eq!(
+            header_length(Format::Compact, &packet),
+            Some(expected.len())
+        );
+        expected.extend_from_slice(b"hello");
+        assert_eq!(packet, expected);
+
+        // Only the first packet from a client has a header
+        assert_eq!(read(&filter, "1.2.3.4:5678", &metadata), b"hello");
+        assert_ne!(read(&filter, "1.2.3.4:5679", &metadata), b"hello");
+
+        let mut ctx = WriteContext::new(
+            "127.0.0.1:7000".parse().unwrap(),
+            "1.2.3.4:5678".parse().unwrap(),
+            alloc_buffer(&packet),
+        );
+        filter.write(&mut ctx).unwrap();
+        assert_eq!(ctx.contents.as_slice(), b"hello");
+
+        // Incomplete or foreign headers are left alone
+        let mut ctx = WriteContext::new(
+            "127.0.0.1:7000".parse().unwrap(),
+            "1.2.3.4:5678".parse().unwrap(),
+            alloc_buffer(&packet[..10]),
+        );
+        filter.write(&mut ctx).unwrap();
+        assert_eq!(ctx.contents.as_slice(), &packet[..10]);
+    }
+
+    #[tokio::test]
+    async fn first_packet_per_endpoint() {
+        let filter = ProxyProtocol::testing(Config {
+            frequency: Frequency::FirstPacket,
+            ..<_>::default()
+        });
+        let endpoints = crate::net::cluster::ClusterMap::default();
+
+        let has_header = |destinations: &[&str]| {
+            let mut destinations: Vec<EndpointAddress> = destinations
+                .iter()
+                .map(|destination| destination.parse().unwrap())
+                .collect();
+            let mut ctx = ReadContext::new(
+                &endpoints,
+                "1.2.3.4:5678".parse().unwrap(),
+                alloc_buffer(b"hello"),
+                &mut destinations,
+            );
+            filter.read(&mut ctx).unwrap();
+            ctx.contents.as_slice() != b"hello"
+        };
+
+        assert!(has_header(&["10.0.0.1:7777"]));
+        assert!(!has_header(&["10.0.0.1:7777"]));
+        // The client was routed to another endpoint, which needs a header too
+        assert!(has_header(&["10.0.0.2:7777"]));
+        assert!(has_header(&["10.0.0.1:7777", "10.0.0.3:7777"]));
+        assert!(!has_header(&["10.0.0.3:7777"]));
+    }
+
+    #[test]
+    fn rejects_oversized_header() {
+        let filter = ProxyProtocol::testing(Config {
+            tlvs: vec![
+                Tlv {
+                    key: metadata::Key::from_static("a"),
+                    tlv_type: 0xe0,
+                },
+                Tlv {
+                    key: metadata::Key::from_static("b"),
+                    tlv_type: 0xe1,
+                },
+            ],
+            ..<_>::default()
+        });
+
+        let endpoints = crate::net::cluster::ClusterMap::default();
+        let mut dest = Vec::new();
+        let mut ctx = ReadContext::new(
+            &endpoints,
+            "1.2.3.4:5678".parse().unwrap(),
+            alloc_buffer(b"hello"),
+            &mut dest,
+        );
+        // Each TLV fits on its own, but the header can't hold both
+        for key in ["a", "b"] {
+            ctx.metadata.insert(
+                metadata::Key::from_static(key),
+                metadata::Value::Bytes(vec![0u8; 40_000].into()),
+            );
+        }
+
+        assert!(filter.read(&mut ctx).is_err());
+        assert_eq!(ctx.contents.as_slice(), b"hello");
+    }
+
+    #[test]
+    fn convert_proto_config() {
+        let config = Config {
+            format: Format::Compact,
+            frequency: Frequency::FirstPacket,
+            tlvs: vec![Tlv {
+                key: metadata::Key::from_static("player"),
+                tlv_type: 0xe1,
+            }],
+            strip_replies: true,
+        };
+
+        let proto = proto::ProxyProtocol::from(config.clone());
+        assert_eq!(Config::try_from(proto).unwrap(), config);
+
+        let proto = proto::ProxyProtocol {
+            tlvs: vec![proto::proxy_protocol::Tlv {
+                key: "player".into(),
+                tlv_type: 256,
+            }],
+            ..<_>::default()
+        };
+        assert!(Config::try_from(proto).is_err());
+    }
+}
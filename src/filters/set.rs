@@ -34,6 +34,7 @@ pub type FilterMap = std::collections::HashMap<&'static str, Arc<DynFilterFactor
 /// - [`capture`][filters::capture]
 /// - [`token_router`][filters::token_router]
 /// - [`hashed_token_router`][filters::token_router]
+/// - [`proxy_protocol`][filters::proxy_protocol]
 #[derive(Clone)]
 pub struct FilterSet(FilterMap);
 
@@ -54,6 +55,7 @@ impl FilterSet {
                 filters::LocalRateLimit::factory(),
                 filters::Match::factory(),
                 filters::Pass::factory(),
+                filters::ProxyProtocol::factory(),
                 filters::Timestamp::factory(),
                 filters::TokenRouter::factory(),
             ]
@@ -59,12 +59,13 @@ impl Filter for TokenRouter {
                 ctx.endpoints.addresses_for_token(tok, ctx.destinations);
 
                 if ctx.destinations.is_empty() {
-                    Err(FilterError::TokenRouter(RouterError::NoEndpointMatch {
+                    return Err(FilterError::TokenRouter(RouterError::NoEndpointMatch {
                         token: token.clone(),
-                    }))
-                } else {
-                    Ok(())
+                    }));
                 }
+
+                ctx.endpoints.retain_healthy(ctx.destinations);
+                Ok(())
             }
             Some(_value) => unreachable!(
                 "this means the capture filter has regressed, it only ever captures byte slices"
@@ -305,6 +306,25 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    async fn unhealthy_endpoints() {
+        let filter = TokenRouter::from_config(None);
+        let mut dest = Vec::new();
+
+        // If every endpoint matching the token is unhealthy we still route to
+        // them rather than dropping the traffic
+        with_ctx(&mut dest, |mut ctx| {
+            ctx.endpoints.mark_unhealthy(
+                "127.0.0.1:80".parse().unwrap(),
+                crate::net::health::Reason::ActiveCheck,
+            );
+            ctx.metadata
+                .insert(CAPTURED_BYTES.into(), Value::Bytes(b"123".to_vec().into()));
+            filter.read(&mut ctx).unwrap();
+        });
+        assert_eq!(dest, ["127.0.0.1:80".parse().unwrap()]);
+    }
+
     #[tokio::test]
     async fn write() {
         let config = Config {
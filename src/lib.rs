@@ -28,6 +28,7 @@ pub mod components;
 pub mod config;
 pub mod filters;
 pub mod metrics;
+pub mod otlp;
 pub mod providers;
 pub mod service;
 pub mod signal;
@@ -15,11 +15,18 @@
  */
 
 use crate::net::maxmind_db::MetricsIpNetEntry;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use prometheus::{
     DEFAULT_BUCKETS, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter,
     IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, core::Collector,
 };
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::SocketAddr,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
+    time::{Duration, Instant},
+};
 
 pub use prometheus::Result;
 
@@ -29,8 +36,12 @@ pub const DIRECTION_LABEL: &str = "event";
 
 pub(crate) const READ: Direction = Direction::Read;
 pub(crate) const WRITE: Direction = Direction::Write;
-#[allow(dead_code)]
 pub(crate) const ASN_LABEL: &str = "asn";
+pub(crate) const PREFIX_LABEL: &str = "ip_prefix";
+
+/// Label value for the traffic of endpoints and tokens beyond the cap of
+/// [`MetricsCli::max_endpoints`] and [`MetricsCli::max_tokens`]
+pub const OTHER_LABEL: &str = "other";
 
 /// Label value for [`DIRECTION_LABEL`] for `read` events
 pub const READ_DIRECTION_LABEL: &str = "read";
@@ -107,6 +118,59 @@ pub fn register_metrics(registry: &mut prometheus_client::registry::Registry, id
     quilkin_system::register_metrics(registry);
 }
 
+#[derive(Clone, Debug, Default, clap::Parser)]
+#[command(next_help_heading = "Metrics Options")]
+pub struct MetricsCli {
+    /// Adds the `asn` and `ip_prefix` labels to the packet metrics, which
+    /// can result in a large number of series on large fleets
+    #[clap(long = "metrics.asn-labels", env = "QUILKIN_METRICS_ASN_LABELS")]
+    pub asn_labels: bool,
+    /// The maximum number of upstream endpoints that have their own packet
+    /// and byte counters, `0` disables per endpoint counters
+    #[clap(
+        long = "metrics.endpoints.max",
+        env = "QUILKIN_METRICS_ENDPOINTS_MAX",
+        default_value_t = 0
+    )]
+    pub max_endpoints: usize,
+    /// The maximum number of token hashes that have their own packet and
+    /// byte counters, `0` disables per token counters
+    #[clap(
+        long = "metrics.tokens.max",
+        env = "QUILKIN_METRICS_TOKENS_MAX",
+        default_value_t = 0
+    )]
+    pub max_tokens: usize,
+}
+
+impl MetricsCli {
+    /// Applies the label settings, this must be called before any packet is
+    /// processed, as the labels of a metric can't change once it is created
+    pub fn apply(&self) {
+        if ASN_LABELS.set(self.asn_labels).is_err() && asn_labels() != self.asn_labels {
+            tracing::warn!("packet metrics already created, ignoring `--metrics.asn-labels`");
+        }
+
+        if self.max_endpoints != 0 {
+            drop(ENDPOINT_TRAFFIC.set(CappedTraffic::new(
+                self.max_endpoints,
+                EVICTION_INTERVAL,
+                endpoint_packets_total().clone(),
+                endpoint_bytes_total().clone(),
+            )));
+        }
+
+        if self.max_tokens != 0 {
+            drop(TOKEN_TRAFFIC.set(CappedTraffic::new(
+                self.max_tokens,
+                EVICTION_INTERVAL,
+                token_packets_total().clone(),
+                token_bytes_total().clone(),
+            )));
+        }
+    }
+}
+
 /// Start the histogram bucket at a quarter of a millisecond, as number below a millisecond are
 /// what we are aiming for, but some granularity below a millisecond is useful for performance
 /// profiling.
@@ -393,6 +457,37 @@ pub(crate) fn shutdown_initiated() -> &'static IntGauge {
     &SHUTDOWN_INITATED
 }
 
+pub(crate) fn tls_certificate_expiry() -> &'static IntGauge {
+    static TLS_CERTIFICATE_EXPIRY: Lazy<IntGauge> = Lazy::new(|| {
+        prometheus::register_int_gauge_with_registry! {
+            prometheus::opts! {
+                "quilkin_tls_certificate_expiry_timestamp_seconds",
+                "The time the certificate presented by the xDS, relay and corrosion servers expires, in seconds since the UNIX epoch",
+            },
+            registry(),
+        }
+        .unwrap()
+    });
+
+    &TLS_CERTIFICATE_EXPIRY
+}
+
+pub(crate) fn tls_certificate_reloads(result: &str) -> IntCounter {
+    static TLS_CERTIFICATE_RELOADS: Lazy<IntCounterVec> = Lazy::new(|| {
+        prometheus::register_int_counter_vec_with_registry! {
+            prometheus::opts! {
+                "quilkin_tls_certificate_reloads_total",
+                "The number of times the certificate of the servers was reloaded, by whether it succeeded or failed",
+            },
+            &["result"],
+            registry(),
+        }
+        .unwrap()
+    });
+
+    TLS_CERTIFICATE_RELOADS.with_label_values(&[result])
+}
+
 pub(crate) fn game_traffic_tasks() -> &'static IntCounter {
     static GAME_TRAFFIC_TASKS: Lazy<IntCounter> = Lazy::new(|| {
         prometheus::register_int_counter_with_registry! {
@@ -563,75 +658,113 @@ pub(crate) fn processing_time(direction: Direction) -> Histogram {
     PROCESSING_TIME.with_label_values(&[direction.label()])
 }
 
-pub(crate) fn bytes_total(direction: Direction, _asn: &AsnInfo<'_>) -> IntCounter {
+static ASN_LABELS: OnceCell<bool> = OnceCell::new();
+
+/// Whether the packet metrics have the `asn` and `ip_prefix` labels, this is
+/// fixed the first time it is called
+#[inline]
+fn asn_labels() -> bool {
+    *ASN_LABELS.get_or_init(|| false)
+}
+
+/// The label names of a packet metric, with the ASN labels appended if enabled
+fn packet_label_names(names: &[&'static str]) -> Vec<&'static str> {
+    let mut names = names.to_vec();
+    if asn_labels() {
+        names.extend([ASN_LABEL, PREFIX_LABEL]);
+    }
+    names
+}
+
+/// Retrieves the metric of a packet metric vec created with
+/// [`packet_label_names`], at most two labels can be passed in addition to
+/// the ASN labels
+#[inline]
+fn with_packet_label_values<P: prometheus::core::MetricVecBuilder>(
+    vec: &prometheus::core::MetricVec<P>,
+    values: &[&str],
+    asn: &AsnInfo<'_>,
+) -> P::M {
+    if !asn_labels() {
+        return vec.with_label_values(values);
+    }
+
+    let mut labels = [""; 4];
+    labels[..values.len()].copy_from_slice(values);
+    labels[values.len()] = asn.asn;
+    labels[values.len() + 1] = asn.prefix;
+    vec.with_label_values(&labels[..values.len() + 2])
+}
+
+pub(crate) fn bytes_total(direction: Direction, asn: &AsnInfo<'_>) -> IntCounter {
     static BYTES_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
         prometheus::register_int_counter_vec_with_registry! {
             prometheus::opts! {
                 "quilkin_bytes_total",
                 "total number of bytes",
             },
-            &[Direction::LABEL],
+            &packet_label_names(&[Direction::LABEL]),
             registry(),
         }
         .unwrap()
     });
 
-    BYTES_TOTAL.with_label_values(&[direction.label()])
+    with_packet_label_values(&BYTES_TOTAL, &[direction.label()], asn)
 }
 
 #[must_use]
-pub(crate) fn errors_total(direction: Direction, display: &str, _asn: &AsnInfo<'_>) -> IntCounter {
+pub(crate) fn errors_total(direction: Direction, display: &str, asn: &AsnInfo<'_>) -> IntCounter {
     static ERRORS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
         prometheus::register_int_counter_vec_with_registry! {
             prometheus::opts! {
                 "quilkin_errors_total",
                 "total number of errors sending packets",
             },
-            &[Direction::LABEL, "display"],
+            &packet_label_names(&[Direction::LABEL, "display"]),
             registry(),
         }
         .unwrap()
     });
 
-    ERRORS_TOTAL.with_label_values(&[direction.label(), display])
+    with_packet_label_values(&ERRORS_TOTAL, &[direction.label(), display], asn)
 }
 
-pub(crate) fn packet_jitter(direction: Direction, _asn: &AsnInfo<'_>) -> IntGauge {
+pub(crate) fn packet_jitter(direction: Direction, asn: &AsnInfo<'_>) -> IntGauge {
     static PACKET_JITTER: Lazy<IntGaugeVec> = Lazy::new(|| {
         prometheus::register_int_gauge_vec_with_registry! {
             prometheus::opts! {
                 "quilkin_packet_jitter",
                 "The time between new packets",
             },
-            &[Direction::LABEL],
+            &packet_label_names(&[Direction::LABEL]),
             registry(),
         }
         .unwrap()
     });
 
-    PACKET_JITTER.with_label_values(&[direction.label()])
+    with_packet_label_values(&PACKET_JITTER, &[direction.label()], asn)
 }
 
-pub(crate) fn packets_total(direction: Direction, _asn: &AsnInfo<'_>) -> IntCounter {
+pub(crate) fn packets_total(direction: Direction, asn: &AsnInfo<'_>) -> IntCounter {
     static PACKETS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
         prometheus::register_int_counter_vec_with_registry! {
             prometheus::opts! {
                 "quilkin_packets_total",
                 "Total number of packets",
             },
-            &[Direction::LABEL],
+            &packet_label_names(&[Direction::LABEL]),
             registry(),
         }
         .unwrap()
     });
 
-    PACKETS_TOTAL.with_label_values(&[direction.label()])
+    with_packet_label_values(&PACKETS_TOTAL, &[direction.label()], asn)
 }
 
 pub(crate) fn packets_dropped_total(
     direction: Direction,
     source: &str,
-    _asn: &AsnInfo<'_>,
+    asn: &AsnInfo<'_>,
 ) -> IntCounter {
     static PACKETS_DROPPED: Lazy<IntCounterVec> = Lazy::new(|| {
         prometheus::register_int_counter_vec_with_registry! {
@@ -639,13 +772,237 @@ pub(crate) fn packets_dropped_total(
                 "quilkin_packets_dropped_total",
                 "Total number of dropped packets",
             },
-            &[Direction::LABEL, "source"],
+            &packet_label_names(&[Direction::LABEL, "source"]),
+            registry(),
+        }
+        .unwrap()
+    });
+
+    with_packet_label_values(&PACKETS_DROPPED, &[direction.label(), source], asn)
+}
+
+fn endpoint_packets_total() -> &'static IntCounterVec {
+    static ENDPOINT_PACKETS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+        prometheus::register_int_counter_vec_with_registry! {
+            prometheus::opts! {
+                "quilkin_endpoint_packets_total",
+                "Total number of packets sent to or received from each upstream endpoint",
+            },
+            &[Direction::LABEL, "endpoint"],
+            registry(),
+        }
+        .unwrap()
+    });
+
+    &ENDPOINT_PACKETS_TOTAL
+}
+
+fn endpoint_bytes_total() -> &'static IntCounterVec {
+    static ENDPOINT_BYTES_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+        prometheus::register_int_counter_vec_with_registry! {
+            prometheus::opts! {
+                "quilkin_endpoint_bytes_total",
+                "Total number of bytes sent to or received from each upstream endpoint",
+            },
+            &[Direction::LABEL, "endpoint"],
+            registry(),
+        }
+        .unwrap()
+    });
+
+    &ENDPOINT_BYTES_TOTAL
+}
+
+fn token_packets_total() -> &'static IntCounterVec {
+    static TOKEN_PACKETS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+        prometheus::register_int_counter_vec_with_registry! {
+            prometheus::opts! {
+                "quilkin_token_packets_total",
+                "Total number of packets routed with each token, labelled by the hash of the token",
+            },
+            &[Direction::LABEL, "token_hash"],
             registry(),
         }
         .unwrap()
     });
 
-    PACKETS_DROPPED.with_label_values(&[direction.label(), source])
+    &TOKEN_PACKETS_TOTAL
+}
+
+fn token_bytes_total() -> &'static IntCounterVec {
+    static TOKEN_BYTES_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+        prometheus::register_int_counter_vec_with_registry! {
+            prometheus::opts! {
+                "quilkin_token_bytes_total",
+                "Total number of bytes routed with each token, labelled by the hash of the token",
+            },
+            &[Direction::LABEL, "token_hash"],
+            registry(),
+        }
+        .unwrap()
+    });
+
+    &TOKEN_BYTES_TOTAL
+}
+
+/// How often label values without traffic are evicted once a cap is reached
+const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+static ENDPOINT_TRAFFIC: OnceCell<CappedTraffic<SocketAddr>> = OnceCell::new();
+static TOKEN_TRAFFIC: OnceCell<CappedTraffic<crate::net::cluster::Token>> = OnceCell::new();
+
+/// Records a packet sent to, or received from, an upstream endpoint, if per
+/// endpoint counters are enabled
+#[inline]
+pub(crate) fn endpoint_traffic(direction: Direction, endpoint: SocketAddr, length: usize) {
+    if let Some(traffic) = ENDPOINT_TRAFFIC.get() {
+        traffic.record(&endpoint, direction, length);
+    }
+}
+
+/// Records a packet routed with the token captured in the metadata, if per
+/// token counters are enabled
+#[inline]
+pub(crate) fn token_traffic(metadata: &crate::net::endpoint::DynamicMetadata, length: usize) {
+    use crate::net::endpoint::metadata::{Key, Value};
+
+    let Some(traffic) = TOKEN_TRAFFIC.get() else {
+        return;
+    };
+
+    if let Some(Value::Bytes(token)) =
+        metadata.get(&Key::from_static(crate::filters::capture::CAPTURED_BYTES))
+    {
+        traffic.record(&crate::net::cluster::Token::new(token), READ, length);
+    }
+}
+
+/// The packet and byte counters of a single label value, for each direction
+struct TrafficCounters {
+    /// Whether there has been traffic since the last eviction
+    active: AtomicBool,
+    packets: [IntCounter; 2],
+    bytes: [IntCounter; 2],
+}
+
+impl TrafficCounters {
+    fn new(packets: &IntCounterVec, bytes: &IntCounterVec, value: &str) -> Self {
+        let counters =
+            |vec: &IntCounterVec| [READ, WRITE].map(|d| vec.with_label_values(&[d.label(), value]));
+
+        Self {
+            active: AtomicBool::new(true),
+            packets: counters(packets),
+            bytes: counters(bytes),
+        }
+    }
+
+    #[inline]
+    fn record(&self, direction: Direction, length: usize) {
+        self.active.store(true, Relaxed);
+        self.packets[direction as usize].inc();
+        self.bytes[direction as usize].inc_by(length as u64);
+    }
+}
+
+/// Packet and byte counters labelled by a value with unbounded cardinality,
+/// such as an endpoint address, bounded to at most `max` label values
+///
+/// Once the cap is reached, the traffic of new values is recorded under
+/// [`OTHER_LABEL`], and at most once every eviction interval, the values that
+/// had no traffic during the previous interval are evicted to make room for
+/// new ones, so the labelled values converge on the active ones.
+pub(crate) struct CappedTraffic<K> {
+    max: usize,
+    eviction_interval: Duration,
+    packets: IntCounterVec,
+    bytes: IntCounterVec,
+    other: TrafficCounters,
+    values: parking_lot::RwLock<HashMap<K, TrafficCounters>>,
+    start: Instant,
+    /// The time of the last eviction, in milliseconds since `start`
+    last_eviction: AtomicU64,
+}
+
+impl<K: Clone + Eq + Hash + std::fmt::Display> CappedTraffic<K> {
+    pub(crate) fn new(
+        max: usize,
+        eviction_interval: Duration,
+        packets: IntCounterVec,
+        bytes: IntCounterVec,
+    ) -> Self {
+        Self {
+            max,
+            eviction_interval,
+            other: TrafficCounters::new(&packets, &bytes, OTHER_LABEL),
+            packets,
+            bytes,
+            values: Default::default(),
+            start: Instant::now(),
+            last_eviction: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    fn eviction_due(&self) -> bool {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        elapsed.saturating_sub(self.last_eviction.load(Relaxed))
+            >= self.eviction_interval.as_millis() as u64
+    }
+
+    pub(crate) fn record(&self, key: &K, direction: Direction, length: usize) {
+        {
+            let values = self.values.read();
+            if let Some(counters) = values.get(key) {
+                counters.record(direction, length);
+                return;
+            }
+
+            if values.len() >= self.max && !self.eviction_due() {
+                self.other.record(direction, length);
+                return;
+            }
+        }
+
+        let mut values = self.values.write();
+        if values.len() >= self.max && !values.contains_key(key) && self.eviction_due() {
+            self.evict_idle(&mut values);
+        }
+
+        if values.len() < self.max || values.contains_key(key) {
+            values
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    TrafficCounters::new(&self.packets, &self.bytes, &key.to_string())
+                })
+                .record(direction, length);
+        } else {
+            self.other.record(direction, length);
+        }
+    }
+
+    /// Removes the values, and their series, that have had no traffic since the
+    /// last eviction
+    fn evict_idle(&self, values: &mut HashMap<K, TrafficCounters>) {
+        values.retain(|key, counters| {
+            if counters.active.swap(false, Relaxed) {
+                return true;
+            }
+
+            let value = key.to_string();
+            for direction in [READ, WRITE] {
+                drop(
+                    self.packets
+                        .remove_label_values(&[direction.label(), &value]),
+                );
+                drop(self.bytes.remove_label_values(&[direction.label(), &value]));
+            }
+            false
+        });
+
+        self.last_eviction
+            .store(self.start.elapsed().as_millis() as u64, Relaxed);
+    }
 }
 
 pub(crate) fn provider_task_failures_total(provider_task: &str) -> IntCounter {
@@ -874,4 +1231,44 @@ mod tests {
         apply_clusters(&clusters);
         assert!(!has_active_endpoints_series(&label));
     }
+
+    #[test]
+    fn capped_traffic_evicts_idle_values() {
+        let vec = |name| {
+            IntCounterVec::new(Opts::new(name, "test"), &[Direction::LABEL, "value"]).unwrap()
+        };
+        let (packets, bytes) = (vec("packets"), vec("bytes"));
+        let traffic = CappedTraffic::new(2, Duration::ZERO, packets.clone(), bytes.clone());
+        let packets_of = |value: &str| {
+            packets
+                .get_metric_with_label_values(&[READ.label(), value])
+                .unwrap()
+                .get()
+        };
+
+        traffic.record(&1, READ, 10);
+        traffic.record(&2, READ, 10);
+        traffic.record(&2, WRITE, 5);
+        assert_eq!(packets_of("1"), 1);
+        assert_eq!(packets_of("2"), 1);
+        assert_eq!(bytes.with_label_values(&[WRITE.label(), "2"]).get(), 5);
+
+        // Both values had traffic since they were added, so the new value is
+        // recorded under the overflow label
+        traffic.record(&3, READ, 10);
+        assert_eq!(packets_of(OTHER_LABEL), 1);
+
+        // Only 2 has traffic since the previous eviction, so 1 is evicted
+        traffic.record(&2, READ, 10);
+        traffic.record(&3, READ, 10);
+        assert_eq!(packets_of("3"), 1);
+        assert_eq!(packets_of("2"), 2);
+        assert!(
+            !packets
+                .collect()
+                .iter()
+                .flat_map(|mf| mf.get_metric())
+                .any(|m| m.get_label().iter().any(|l| l.value() == "1"))
+        );
+    }
 }
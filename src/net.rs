@@ -17,10 +17,12 @@
 pub mod cluster;
 pub mod endpoint;
 pub mod error;
+pub mod health;
 pub mod io;
 pub(crate) mod maxmind_db;
 pub mod packet;
 pub mod phoenix;
+pub mod qos;
 pub mod servers;
 pub mod sessions;
 
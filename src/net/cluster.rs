@@ -77,7 +77,7 @@ pub(crate) fn remove_active_endpoints(cluster: &str) {
 
 pub type TokenAddressMap = gxhash::HashMap<u64, gxhash::HashSet<EndpointAddress>>;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Token(u64);
 
 impl Token {
@@ -87,6 +87,13 @@ impl Token {
     }
 }
 
+/// Displays the hash of the token, never the token itself
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct EndpointSetVersion(u64);
 
@@ -212,7 +219,6 @@ pub struct EndpointSet {
     token_map: TokenAddressMap,
     /// The hash of all of the endpoints in this set
     hash: u64,
-    change_id: ChangeId,
     cache: ClusterCache,
 }
 
@@ -249,7 +255,6 @@ impl EndpointSet {
                 .collect(),
             token_map: Default::default(),
             hash: 0,
-            change_id: ChangeId(0),
             cache: ClusterCache::default(),
         }
     }
@@ -283,6 +288,12 @@ impl EndpointSet {
             })
     }
 
+    /// The metadata of each endpoint in the set, by its address
+    #[inline]
+    pub fn endpoint_map(&self) -> &BTreeMap<EndpointAddress, EndpointMetadata> {
+        &self.endpoints
+    }
+
     #[inline]
     pub fn endpoint_iter(&self) -> impl Iterator<Item = Endpoint> {
         self.endpoints.iter().map(|(addr, md)| Endpoint {
@@ -340,12 +351,6 @@ impl EndpointSet {
         Ok(resource)
     }
 
-    /// Returns the latest change id seen from a remote server
-    #[inline]
-    pub fn change_id(&self) -> ChangeId {
-        self.change_id
-    }
-
     #[inline]
     pub fn modify(&mut self) -> EndpointSetWriteGuard<'_> {
         EndpointSetWriteGuard {
@@ -398,41 +403,29 @@ impl EndpointSet {
         &mut self,
         replacement: Self,
         removed: &mut Vec<quilkin_types::Endpoint>,
-        upserted: &mut Vec<(quilkin_types::Endpoint, quilkin_types::TokenSet)>,
+        upserted: &mut Vec<(quilkin_types::Endpoint, EndpointMetadata)>,
     ) -> (
-        usize,
+        InnerMap,
         std::collections::HashMap<u64, Option<BTreeSet<EndpointAddress>>>,
     ) {
         let old = std::mem::replace(&mut self.endpoints, replacement.endpoints);
 
         for (addr, md) in &self.endpoints {
-            if let Some(o) = old.get(addr) {
-                if md.known.tokens != o.known.tokens {
-                    upserted.push((
-                        quilkin_types::Endpoint {
-                            address: addr.host.clone(),
-                            port: addr.port,
-                        },
-                        md.known.tokens.clone(),
-                    ));
-                }
-            } else {
+            if old.get(addr) != Some(md) {
                 upserted.push((
                     quilkin_types::Endpoint {
                         address: addr.host.clone(),
                         port: addr.port,
                     },
-                    md.known.tokens.clone(),
+                    md.clone(),
                 ));
             }
         }
 
-        let old_len = old.len();
-
-        for (addr, _md) in old {
-            if !self.endpoints.contains_key(&addr) {
+        for addr in old.keys() {
+            if !self.endpoints.contains_key(addr) {
                 removed.push(quilkin_types::Endpoint {
-                    address: addr.host,
+                    address: addr.host.clone(),
                     port: addr.port,
                 });
             }
@@ -447,7 +440,7 @@ impl EndpointSet {
 
         let diff = EndpointSet::token_map_diff(&old_tm, &self.token_map);
 
-        (old_len, diff)
+        (old, diff)
     }
 
     /// Partially replace self with replacement, only replacing endpoints that match the closure
@@ -515,173 +508,80 @@ impl EndpointSet {
 }
 
 impl EndpointSet {
-    pub fn corrosion_apply(
+    /// Inserts or replaces the metadata for an endpoint from a corrosion server
+    /// row, returning the previous metadata if the endpoint was already present
+    fn corrosion_upsert(
         &mut self,
-        ss: corrosion::pubsub::SubscriptionStream,
+        address: EndpointAddress,
+        metadata: EndpointMetadata,
         token_map: &DashMap<u64, BTreeSet<EndpointAddress>>,
-        subm: &mut corrosion::persistent::SubMetrics,
-    ) -> (ChangeId, isize) {
-        use corrosion::{
-            api::{TypedQueryEvent as tqe, sqlite::ChangeType},
-            db::read::{self, FromSqlValue},
-        };
-
-        let start = self.endpoints.len() as isize;
-        let mut successful = 0;
-
-        for eve in ss {
-            subm.total_events += 1;
-
-            let eve = match eve {
-                Ok(e) => e,
-                Err(error) => {
-                    tracing::warn!(%error, "received error in server subscription stream");
-                    continue;
-                }
-            };
-
-            let (cty, srow, row_id) = match eve {
-                tqe::Change(cty, rid, row, cid) => {
-                    // It's possible? to get a change id that is smaller than the current one,
-                    // we still apply it, but we only ever keep the highest one
-                    self.change_id = ChangeId(cid.0.max(self.change_id.0));
-
-                    (cty, row, rid)
-                }
-                tqe::Row(rid, row) => (ChangeType::Insert, row, rid),
-                tqe::EndOfQuery { change_id, .. } => {
-                    if let Some(cid) = change_id {
-                        self.change_id = ChangeId(cid.0.max(self.change_id.0));
-                    }
-
-                    continue;
-                }
-                _ => continue,
-            };
-
-            let row = match read::ServerRow::from_sql(&srow) {
-                Ok(sr) => sr,
-                Err(error) => {
-                    tracing::warn!(%error, row_id = row_id.0, "failed to deserialize server row");
-                    continue;
-                }
-            };
-
-            let insert = |this: &mut TokenAddressMap, addr: &EndpointAddress, tok: &[u8]| {
-                let tok = Token::new(tok);
-
-                {
-                    let mut tm = token_map.entry(tok.0).or_default();
-                    tm.insert(addr.clone());
-                }
-
-                this.entry(tok.0).or_default().insert(addr.clone());
-            };
-
-            let remove = |this: &mut TokenAddressMap, addr: &EndpointAddress, tok: &[u8]| {
-                let tok = Token::new(tok);
-
-                let remove = if let Some(mut tm) = token_map.get_mut(&tok.0) {
-                    tm.remove(addr);
-                    tm.is_empty()
-                } else {
-                    false
-                };
-
-                if remove {
-                    token_map.remove(&tok.0);
-                }
-
-                if let Some(old) = this.get_mut(&tok.0) {
-                    old.remove(addr);
-                    if old.is_empty() {
-                        this.remove(&tok.0);
-                    }
-                }
-            };
-
-            successful += 1;
-
-            match cty {
-                ChangeType::Insert => {
-                    let address = EndpointAddress {
-                        host: row.endpoint.address,
-                        port: row.endpoint.port,
-                    };
+    ) -> Option<EndpointMetadata> {
+        let old = self.endpoints.remove(&address);
+        let empty = BTreeSet::new();
+        let old_tokens = old.as_ref().map_or(&empty, |md| &md.known.tokens.0);
+
+        // Remove old tokens
+        for old in old_tokens.difference(&metadata.known.tokens.0) {
+            self.corrosion_remove_token(&address, old, token_map);
+        }
 
-                    for tok in row.tokens.iter() {
-                        insert(&mut self.token_map, &address, tok);
-                    }
+        // Add new tokens
+        for new in metadata.known.tokens.0.difference(old_tokens) {
+            let tok = Token::new(new);
+            token_map.entry(tok.0).or_default().insert(address.clone());
+            self.token_map
+                .entry(tok.0)
+                .or_default()
+                .insert(address.clone());
+        }
 
-                    self.endpoints.insert(
-                        address,
-                        EndpointMetadata {
-                            known: Metadata { tokens: row.tokens },
-                            unknown: Default::default(),
-                        },
-                    );
-                }
-                ChangeType::Update => {
-                    let address = EndpointAddress {
-                        host: row.endpoint.address,
-                        port: row.endpoint.port,
-                    };
+        self.endpoints.insert(address, metadata);
+        old
+    }
 
-                    let Some(tokens) = self
-                        .endpoints
-                        .get_mut(&address)
-                        .map(|md| &mut md.known.tokens.0)
-                    else {
-                        for tok in row.tokens.iter() {
-                            insert(&mut self.token_map, &address, tok);
-                        }
-
-                        self.endpoints.insert(
-                            address,
-                            EndpointMetadata {
-                                known: Metadata { tokens: row.tokens },
-                                unknown: Default::default(),
-                            },
-                        );
-                        continue;
-                    };
+    /// Removes an endpoint due to a corrosion server row deletion, returning
+    /// its metadata if the endpoint was present
+    fn corrosion_remove(
+        &mut self,
+        address: &EndpointAddress,
+        token_map: &DashMap<u64, BTreeSet<EndpointAddress>>,
+    ) -> Option<EndpointMetadata> {
+        let md = self.endpoints.remove(address)?;
 
-                    // Remove old tokens
-                    for old in tokens.difference(&row.tokens.0) {
-                        remove(&mut self.token_map, &address, old);
-                    }
+        // We could pedantically check if the local token set matches
+        // the one in the deletion
+        for tok in md.known.tokens.iter() {
+            self.corrosion_remove_token(address, tok, token_map);
+        }
 
-                    // Add new tokens
-                    for new in row.tokens.0.difference(tokens) {
-                        insert(&mut self.token_map, &address, new);
-                    }
+        Some(md)
+    }
 
-                    drop(std::mem::replace(tokens, row.tokens.0));
-                }
-                ChangeType::Delete => {
-                    let address = EndpointAddress {
-                        host: row.endpoint.address,
-                        port: row.endpoint.port,
-                    };
+    fn corrosion_remove_token(
+        &mut self,
+        address: &EndpointAddress,
+        tok: &[u8],
+        token_map: &DashMap<u64, BTreeSet<EndpointAddress>>,
+    ) {
+        let tok = Token::new(tok);
 
-                    let Some(md) = self.endpoints.remove(&address) else {
-                        tracing::warn!(%address, "address not found for removal");
-                        continue;
-                    };
+        let remove = if let Some(mut tm) = token_map.get_mut(&tok.0) {
+            tm.remove(address);
+            tm.is_empty()
+        } else {
+            false
+        };
 
-                    // We could pedantically check if the local token set matches
-                    // the one in the deletion
+        if remove {
+            token_map.remove(&tok.0);
+        }
 
-                    for tok in md.known.tokens {
-                        remove(&mut self.token_map, &address, &tok);
-                    }
-                }
+        if let Some(old) = self.token_map.get_mut(&tok.0) {
+            old.remove(address);
+            if old.is_empty() {
+                self.token_map.remove(&tok.0);
             }
         }
-
-        subm.failures = subm.total_events - successful;
-
-        (self.change_id, self.endpoints.len() as isize - start)
     }
 }
 
@@ -690,6 +590,9 @@ pub struct ClusterMap<S = gxhash::GxBuildHasher> {
     map: DashMap<Option<Locality>, EndpointSet, S>,
     localities: DashMap<Option<Locality>, Option<std::net::IpAddr>>,
     token_map: DashMap<u64, BTreeSet<EndpointAddress>>,
+    /// Endpoints that have failed health checks, these are still part of the
+    /// cluster map but are excluded when routing packets
+    unhealthy: DashMap<EndpointAddress, crate::net::health::Unhealthy>,
     num_endpoints: AtomicUsize,
     version: AtomicU64,
 }
@@ -792,8 +695,34 @@ where
         locality: Option<Locality>,
         cluster: EndpointSet,
         removed: &mut Vec<quilkin_types::Endpoint>,
-        upserted: &mut Vec<(quilkin_types::Endpoint, quilkin_types::TokenSet)>,
+        upserted: &mut Vec<(quilkin_types::Endpoint, EndpointMetadata)>,
     ) -> crate::Result<()> {
+        self.replace_endpoints(remote_addr, locality, cluster, removed, upserted)?;
+        Ok(())
+    }
+
+    /// Replaces the endpoints of `locality`, taking it over from whichever
+    /// remote applied it, returning the endpoints it replaced
+    pub fn replace_locality(
+        &self,
+        locality: Option<Locality>,
+        cluster: EndpointSet,
+    ) -> BTreeMap<EndpointAddress, EndpointMetadata> {
+        self.localities.insert(locality.clone(), None);
+        self.replace_endpoints(None, locality, cluster, &mut Vec::new(), &mut Vec::new())
+            .unwrap_or_default()
+    }
+
+    /// [`Self::apply`], returning the endpoints that were replaced, which are
+    /// read while the locality is locked so they can't race with other changes
+    fn replace_endpoints(
+        &self,
+        remote_addr: Option<IpAddr>,
+        locality: Option<Locality>,
+        cluster: EndpointSet,
+        removed: &mut Vec<quilkin_types::Endpoint>,
+        upserted: &mut Vec<(quilkin_types::Endpoint, EndpointMetadata)>,
+    ) -> crate::Result<InnerMap> {
         if let Some(raddr) = self.localities.get(&locality) {
             if *raddr != remote_addr {
                 eyre::bail!(
@@ -809,7 +738,8 @@ where
         if let Some(mut current) = self.map.get_mut(&locality) {
             let current = current.value_mut();
 
-            let (old_len, token_map_diff) = current.replace(cluster, removed, upserted);
+            let (old, token_map_diff) = current.replace(cluster, removed, upserted);
+            let old_len = old.len();
 
             if new_len >= old_len {
                 self.num_endpoints.fetch_add(new_len - old_len, Relaxed);
@@ -826,20 +756,30 @@ where
                     self.token_map.remove(&token_hash);
                 }
             }
+
+            Ok(old)
         } else {
             for (token_hash, addrs) in &cluster.token_map {
                 self.token_map
                     .insert(*token_hash, addrs.iter().cloned().collect());
             }
 
-            upserted.extend(cluster.to_map());
+            upserted.extend(cluster.endpoints.iter().map(|(addr, md)| {
+                (
+                    quilkin_types::Endpoint {
+                        address: addr.host.clone(),
+                        port: addr.port,
+                    },
+                    md.clone(),
+                )
+            }));
 
             self.map.insert(locality, cluster);
             self.num_endpoints.fetch_add(new_len, Relaxed);
             self.version.fetch_add(1, Relaxed);
-        }
 
-        Ok(())
+            Ok(InnerMap::new())
+        }
     }
 
     #[inline]
@@ -933,6 +873,25 @@ where
         endpoints
     }
 
+    /// Returns every hostname that an endpoint is addressed by
+    pub fn hostnames(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+
+        for set in self.map.iter() {
+            names.extend(
+                set.value()
+                    .endpoints
+                    .keys()
+                    .filter_map(|address| match &address.host {
+                        crate::net::endpoint::AddressKind::Name(name) => Some(name.clone()),
+                        crate::net::endpoint::AddressKind::Ip(_) => None,
+                    }),
+            );
+        }
+
+        names
+    }
+
     pub fn nth_endpoint(&self, mut index: usize) -> Option<Endpoint> {
         for set in self.iter() {
             let set = &set.value().endpoints;
@@ -1006,6 +965,12 @@ where
         });
     }
 
+    /// Removes `locality`, regardless of which remote applied it
+    #[inline]
+    pub fn take_locality(&self, locality: &Option<Locality>) -> Option<EndpointSet> {
+        self.do_remove_locality(locality)
+    }
+
     #[inline]
     pub fn remove_locality(
         &self,
@@ -1029,29 +994,290 @@ where
             addrs.extend(ma.value().iter().cloned());
         }
     }
+
+    /// Marks `address` as unhealthy, excluding it from routing until it is
+    /// marked healthy again. Returns `false` if it was already unhealthy.
+    pub fn mark_unhealthy(
+        &self,
+        address: EndpointAddress,
+        reason: crate::net::health::Reason,
+    ) -> bool {
+        match self.unhealthy.entry(address) {
+            dashmap::Entry::Occupied(_) => false,
+            dashmap::Entry::Vacant(entry) => {
+                entry.insert(crate::net::health::Unhealthy {
+                    reason,
+                    since: crate::time::UtcTimestamp::now().unix(),
+                });
+                true
+            }
+        }
+    }
+
+    /// Marks `address` as healthy again if it was marked unhealthy for
+    /// `reason`, returning `true` if it was.
+    pub fn mark_healthy(
+        &self,
+        address: &EndpointAddress,
+        reason: crate::net::health::Reason,
+    ) -> bool {
+        self.unhealthy
+            .remove_if(address, |_, unhealthy| unhealthy.reason == reason)
+            .is_some()
+    }
+
+    #[inline]
+    pub fn is_healthy(&self, address: &EndpointAddress) -> bool {
+        self.unhealthy.is_empty() || !self.unhealthy.contains_key(address)
+    }
+
+    /// Returns every endpoint that is currently marked unhealthy
+    pub fn unhealthy_endpoints(&self) -> Vec<(EndpointAddress, crate::net::health::Unhealthy)> {
+        self.unhealthy
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect()
+    }
+
+    #[inline]
+    pub fn num_of_unhealthy_endpoints(&self) -> usize {
+        self.unhealthy.len()
+    }
+
+    /// Forgets the health of endpoints that have been removed
+    pub fn prune_unhealthy(&self) {
+        if self.unhealthy.is_empty() {
+            return;
+        }
+
+        self.unhealthy.retain(|address, _| {
+            self.map
+                .iter()
+                .any(|set| set.value().endpoints.contains_key(address))
+        });
+    }
+
+    /// Removes any unhealthy endpoints from `addrs`, unless every one of them
+    /// is unhealthy, in which case they are all kept so that a faulty health
+    /// check can't drop all traffic
+    #[inline]
+    pub fn retain_healthy(&self, addrs: &mut Vec<EndpointAddress>) {
+        if !self.unhealthy.is_empty()
+            && addrs
+                .iter()
+                .any(|address| !self.unhealthy.contains_key(address))
+        {
+            addrs.retain(|address| !self.unhealthy.contains_key(address));
+        }
+    }
+
+    fn num_of_healthy_endpoints(&self) -> usize {
+        self.map
+            .iter()
+            .map(|set| {
+                set.value()
+                    .endpoints
+                    .keys()
+                    .filter(|address| !self.unhealthy.contains_key(*address))
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Returns the number of endpoints packets can be routed to, which is
+    /// every healthy endpoint, or every endpoint if none of them are healthy
+    /// so that a faulty health check can't drop all traffic
+    #[inline]
+    pub fn num_of_routable_endpoints(&self) -> usize {
+        if self.unhealthy.is_empty() {
+            return self.num_of_endpoints();
+        }
+
+        match self.num_of_healthy_endpoints() {
+            0 => self.num_of_endpoints(),
+            healthy => healthy,
+        }
+    }
+
+    /// Returns the endpoint packets should be routed to, where `choose` is
+    /// given the number of routable endpoints and returns the index of the
+    /// one to use, see [`Self::num_of_routable_endpoints`]
+    ///
+    /// The number of healthy endpoints is only counted once, as this is called
+    /// for every packet.
+    pub fn choose_routable_endpoint(
+        &self,
+        choose: impl FnOnce(usize) -> usize,
+    ) -> Option<Endpoint> {
+        let healthy = if self.unhealthy.is_empty() {
+            0
+        } else {
+            self.num_of_healthy_endpoints()
+        };
+
+        if healthy == 0 {
+            return match self.num_of_endpoints() {
+                0 => None,
+                count => self.nth_endpoint(choose(count)),
+            };
+        }
+
+        let mut index = choose(healthy);
+        for set in self.iter() {
+            for (address, metadata) in set
+                .value()
+                .endpoints
+                .iter()
+                .filter(|(address, _)| !self.unhealthy.contains_key(*address))
+            {
+                if index == 0 {
+                    return Some(Endpoint {
+                        address: address.clone(),
+                        metadata: metadata.clone(),
+                    });
+                }
+
+                index -= 1;
+            }
+        }
+
+        None
+    }
 }
 
 impl<S> ClusterMap<S>
 where
     S: Default + std::hash::BuildHasher + Clone,
 {
-    /// Applies a stream of events to this cluster map
+    /// Applies a stream of events to this cluster map, returning the highest
+    /// change id seen
     ///
-    /// This adds, updates, and/or removes endpoints from a 'corrosion' locality
-    /// that is temporarily used during the transition period
+    /// Endpoints are placed in the locality stored with the server, servers
+    /// without a locality are placed in a 'corrosion' locality. The change of
+    /// each endpoint is added to `changes`, if provided.
     pub fn corrosion_apply(
         &self,
         ss: corrosion::pubsub::SubscriptionStream,
+        mut change_id: ChangeId,
         subm: &mut corrosion::persistent::SubMetrics,
+        mut changes: Option<&mut Vec<crate::config::history::Change>>,
     ) -> ChangeId {
+        use crate::config::history::endpoint_change;
+        use corrosion::{
+            api::{TypedQueryEvent as tqe, sqlite::ChangeType},
+            db::read::{self, FromSqlValue},
+        };
+
         static CORRO: std::sync::LazyLock<Locality> =
             std::sync::LazyLock::new(|| Locality::new("corrosion", "", ""));
 
-        let (id, diff) = self
-            .map
-            .entry(Some((*CORRO).clone()))
-            .or_insert_with(|| EndpointSet::new(BTreeSet::default()))
-            .corrosion_apply(ss, &self.token_map, subm);
+        let mut successful = 0;
+        let mut diff = 0isize;
+
+        for eve in ss {
+            subm.total_events += 1;
+
+            let eve = match eve {
+                Ok(e) => e,
+                Err(error) => {
+                    tracing::warn!(%error, "received error in server subscription stream");
+                    continue;
+                }
+            };
+
+            let (cty, srow, row_id) = match eve {
+                tqe::Change(cty, rid, row, cid) => {
+                    // It's possible? to get a change id that is smaller than the current one,
+                    // we still apply it, but we only ever keep the highest one
+                    change_id = ChangeId(cid.0.max(change_id.0));
+
+                    (cty, row, rid)
+                }
+                tqe::Row(rid, row) => (ChangeType::Insert, row, rid),
+                tqe::EndOfQuery { change_id: cid, .. } => {
+                    if let Some(cid) = cid {
+                        change_id = ChangeId(cid.0.max(change_id.0));
+                    }
+
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let row = match read::ServerRow::from_sql(&srow) {
+                Ok(sr) => sr,
+                Err(error) => {
+                    tracing::warn!(%error, row_id = row_id.0, "failed to deserialize server row");
+                    continue;
+                }
+            };
+
+            successful += 1;
+
+            let address = EndpointAddress {
+                host: row.endpoint.address,
+                port: row.endpoint.port,
+            };
+
+            let locality = match row.locality.as_deref().map(str::parse::<Locality>) {
+                Some(Ok(locality)) => locality,
+                Some(Err(error)) => {
+                    tracing::warn!(%error, %address, "failed to parse server locality");
+                    (*CORRO).clone()
+                }
+                None => (*CORRO).clone(),
+            };
+
+            // The locality of a server can change, so remove it from every
+            // locality other than the one it now belongs to
+            for mut es in self.map.iter_mut() {
+                if (matches!(cty, ChangeType::Delete) || es.key().as_ref() != Some(&locality))
+                    && let Some(old) = es.value_mut().corrosion_remove(&address, &self.token_map)
+                {
+                    diff -= 1;
+                    if let Some(changes) = changes.as_deref_mut() {
+                        changes.push(endpoint_change(es.key(), &address, Some(&old), None));
+                    }
+                }
+            }
+
+            if matches!(cty, ChangeType::Delete) {
+                continue;
+            }
+
+            let metadata = EndpointMetadata {
+                known: Metadata { tokens: row.tokens },
+                unknown: row.metadata,
+            };
+
+            let locality = Some(locality);
+            let recorded = changes
+                .is_some()
+                .then(|| (locality.clone(), address.clone(), metadata.clone()));
+
+            let old = self
+                .map
+                .entry(locality)
+                .or_insert_with(|| EndpointSet::new(BTreeSet::default()))
+                .corrosion_upsert(address, metadata, &self.token_map);
+            if old.is_none() {
+                diff += 1;
+            }
+
+            if let Some((changes, (locality, address, metadata))) =
+                changes.as_deref_mut().zip(recorded)
+                && old.as_ref() != Some(&metadata)
+            {
+                changes.push(endpoint_change(
+                    &locality,
+                    &address,
+                    old.as_ref(),
+                    Some(&metadata),
+                ));
+            }
+        }
+
+        subm.failures = subm.total_events - successful;
 
         // If we don't update the num_endpoints and it's 0, no filter will run!
         if diff >= 0 {
@@ -1060,7 +1286,7 @@ where
             self.num_endpoints.fetch_sub(diff.unsigned_abs(), Relaxed);
         }
 
-        id
+        change_id
     }
 }
 
@@ -1101,6 +1327,7 @@ where
             map: <DashMap<Option<Locality>, EndpointSet, S>>::default(),
             localities: Default::default(),
             token_map: Default::default(),
+            unhealthy: Default::default(),
             version: <_>::default(),
             num_endpoints: <_>::default(),
         }
@@ -1326,6 +1553,7 @@ pub enum EndpointSetUpdateAction {
 pub struct ClusterUpdateBatcher {
     cluster_map: crate::config::Watch<ClusterMap>,
     locality: Option<Locality>,
+    history: Option<crate::config::history::ConfigHistory>,
     updates: std::sync::Arc<std::sync::Mutex<Vec<EndpointSetUpdateAction>>>,
     interval: std::time::Duration,
     token: tokio_util::sync::CancellationToken,
@@ -1337,12 +1565,14 @@ impl ClusterUpdateBatcher {
     pub fn spawn(
         cluster_map: crate::config::Watch<ClusterMap>,
         locality: Option<Locality>,
+        history: Option<crate::config::history::ConfigHistory>,
         interval: std::time::Duration,
         token: tokio_util::sync::CancellationToken,
     ) -> Self {
         let cub = Self {
             cluster_map,
             locality,
+            history,
             updates: <_>::default(),
             interval,
             token,
@@ -1354,6 +1584,12 @@ impl ClusterUpdateBatcher {
         cub
     }
 
+    /// The locality endpoints are placed in
+    #[inline]
+    pub fn locality(&self) -> Option<&Locality> {
+        self.locality.as_ref()
+    }
+
     /// Test `ClusterUpdateBatcher` that doesn't require tokio but where you must call `flush()`
     /// manually
     pub fn test_batcher(
@@ -1363,6 +1599,7 @@ impl ClusterUpdateBatcher {
         Self {
             cluster_map,
             locality,
+            history: None,
             updates: <_>::default(),
             interval: std::time::Duration::from_secs(1),
             token: tokio_util::sync::CancellationToken::new(),
@@ -1422,9 +1659,7 @@ impl ClusterUpdateBatcher {
             std::mem::take(&mut *guard)
         };
         if !updates.is_empty() {
-            self.cluster_map
-                .write()
-                .batch_modify(&self.locality, updates);
+            self.modify(|clusters| clusters.batch_modify(&self.locality, updates));
         }
 
         crate::metrics::apply_clusters(&self.cluster_map);
@@ -1460,10 +1695,19 @@ impl ClusterUpdateBatcher {
         // came in before the Init after we have replaced the state in InitDone.
         self.flush();
 
-        self.cluster_map.write().partial_replace(
-            self.locality.clone(),
-            endpoint_set,
-            should_be_replaced,
+        self.modify(|clusters| {
+            clusters.partial_replace(self.locality.clone(), endpoint_set, should_be_replaced);
+        });
+    }
+
+    /// Modifies the locality of the cluster map, recording the change in the
+    /// history
+    fn modify(&self, modify: impl FnOnce(&ClusterMap)) {
+        self.cluster_map.modify_with_history(
+            self.history.as_ref(),
+            crate::config::history::Source::Kubernetes,
+            [self.locality.clone()].into(),
+            |clusters| modify(clusters),
         );
     }
 }
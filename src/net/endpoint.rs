@@ -17,6 +17,7 @@
 //! Types representing where the data is the sent.
 
 pub(crate) mod address;
+pub mod dns;
 pub mod metadata;
 
 use crate::net::cluster::proto;
@@ -21,8 +21,6 @@ use std::{
     str::FromStr,
 };
 
-use hickory_resolver::TokioResolver;
-use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
 pub use quilkin_types::AddressKind;
@@ -33,7 +31,7 @@ use crate::generated::envoy::config::core::v3::{
 
 /// A valid socket address. This differs from `std::net::SocketAddr`, in that it
 /// it supports parsing Domain Names in addition to IP addresses. Domain Names
-/// are resolved when `ToSocketAddrs::to_socket_addrs` is called.
+/// are resolved in the background by the [`super::dns`] resolver.
 #[derive(Debug, PartialEq, Clone, PartialOrd, Ord, Eq, Hash)]
 pub struct EndpointAddress {
     /// A valid name or IP address that resolves to a address.
@@ -60,62 +58,44 @@ impl EndpointAddress {
         self.port
     }
 
-    /// Returns the socket address for the endpoint, resolving any DNS entries
-    /// if present.
+    /// Returns the socket address for the endpoint.
+    ///
+    /// This never blocks, hostnames are resolved in the background (see
+    /// [`super::dns`]), so an error is returned if the hostname has not been
+    /// resolved yet, or has never resolved successfully.
     #[inline]
     pub fn to_socket_addr(&self) -> std::io::Result<SocketAddr> {
+        self.resolve(None)
+    }
+
+    /// Returns the socket address that packets from `source` should be sent
+    /// to, hostnames that resolve to multiple addresses are spread across
+    /// those addresses by `source`. Like [`Self::to_socket_addr`], this never
+    /// blocks.
+    #[inline]
+    pub fn to_socket_addr_for(&self, source: SocketAddr) -> std::io::Result<SocketAddr> {
+        self.resolve(Some(source))
+    }
+
+    #[inline]
+    fn resolve(&self, key: Option<SocketAddr>) -> std::io::Result<SocketAddr> {
         let ip = match &self.host {
-            AddressKind::Ip(ip) => SocketAddr::from((*ip, self.port)),
-            AddressKind::Name(_name) => {
-                let handle = tokio::runtime::Handle::current();
-                handle.block_on(self.to_socket_addr_async())?
-            }
+            AddressKind::Ip(ip) => *ip,
+            AddressKind::Name(name) => super::dns::select(name, key)?,
         };
 
-        Ok(ip)
+        Ok(SocketAddr::from((ip, self.port)))
     }
 
+    /// Returns the socket address for the endpoint, resolving the hostname if
+    /// it isn't already cached.
     pub async fn to_socket_addr_async(&self) -> std::io::Result<SocketAddr> {
-        static DNS: Lazy<TokioResolver> =
-            Lazy::new(|| TokioResolver::builder_tokio().unwrap().build().unwrap());
-
         let ip = match &self.host {
             AddressKind::Ip(ip) => *ip,
-            AddressKind::Name(name) => {
-                static CACHE: Lazy<crate::collections::ttl::TtlMap<String, IpAddr>> =
-                    Lazy::new(<_>::default);
-
-                if let Some(ip) = CACHE.get(name) {
-                    **ip
-                } else {
-                    let lookup_res = DNS.lookup_ip(&**name).await;
-
-                    let ip = match lookup_res {
-                        Ok(lookup) => {
-                            // Prefer v6 addresses
-                            lookup
-                                .iter()
-                                .find(|ip| ip.is_ipv6())
-                                .or_else(|| lookup.iter().next())
-                                .ok_or_else(|| {
-                                    // This should never be hit, hickory-resolver will return an error if there are no records
-                                    // for the query
-                                    std::io::Error::other("no ip address found")
-                                })?
-                        }
-                        Err(err) => {
-                            if err.is_no_records_found() {
-                                return Err(std::io::Error::other("no ip address found"));
-                            } else {
-                                return Err(std::io::Error::other(err));
-                            }
-                        }
-                    };
-
-                    CACHE.insert(name.clone(), ip);
-                    ip
-                }
-            }
+            AddressKind::Name(name) => *super::dns::resolve(name)
+                .await?
+                .first()
+                .ok_or_else(|| std::io::Error::other("no ip address found"))?,
         };
 
         Ok(SocketAddr::from((ip, self.port)))
@@ -0,0 +1,465 @@
+/*
+ * Copyright 2026 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *       http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+//! Background resolution of endpoints that are addressed by a hostname.
+//!
+//! The packet path never performs a DNS lookup itself, it only reads the last
+//! successful resolution of a hostname from the cache maintained here. The
+//! cache is kept fresh by a background task which re-resolves each hostname
+//! referenced by the cluster map when its records expire, honouring the TTL
+//! of the records themselves.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering::Relaxed},
+    },
+    time::Duration,
+};
+
+use dashmap::DashMap;
+use hickory_resolver::TokioResolver;
+use once_cell::sync::Lazy;
+use tokio::time::Instant;
+
+use crate::{config::Watch, net::ClusterMap};
+
+/// Records are never cached for less than this, regardless of their TTL.
+const MIN_TTL: Duration = Duration::from_secs(1);
+/// Records are always refreshed at least this often, regardless of their TTL.
+const MAX_TTL: Duration = Duration::from_secs(300);
+/// The maximum amount of time between attempts to resolve a failing hostname.
+const MAX_RETRY: Duration = Duration::from_secs(30);
+
+const HASH_SEED: i64 = 0xd15ea5e;
+
+static CACHE: Lazy<Cache> = Lazy::new(<_>::default);
+
+#[derive(Default)]
+struct Cache {
+    entries: DashMap<String, Entry>,
+    /// Wakes the background task when a hostname that isn't in the cache is
+    /// requested by the packet path
+    wake: tokio::sync::Notify,
+}
+
+struct Entry {
+    /// The resolved addresses, with the addresses of the preferred family first
+    addresses: Arc<[IpAddr]>,
+    /// The number of addresses of the preferred family, packets are only ever
+    /// sent to these addresses
+    preferred: usize,
+    refresh_at: Instant,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    /// Set when the entry is used, so that hostnames that are not part of the
+    /// cluster map are only kept while they are still being used
+    requested: AtomicBool,
+}
+
+impl Entry {
+    fn pending() -> Self {
+        Self {
+            addresses: Arc::new([]),
+            preferred: 0,
+            refresh_at: Instant::now(),
+            consecutive_failures: 0,
+            last_error: None,
+            requested: AtomicBool::new(true),
+        }
+    }
+
+    fn resolved(&mut self, mut addresses: Vec<IpAddr>, valid_until: Instant) {
+        // Prefer v6 addresses
+        addresses.sort_by_key(|ip| ip.is_ipv4());
+        self.preferred = if addresses.first().is_some_and(IpAddr::is_ipv6) {
+            addresses.iter().filter(|ip| ip.is_ipv6()).count()
+        } else {
+            addresses.len()
+        };
+        self.addresses = addresses.into();
+        self.refresh_at = clamp_ttl(valid_until);
+        self.consecutive_failures = 0;
+        self.last_error = None;
+    }
+
+    fn failed(&mut self, error: String) {
+        // Keep serving the last known addresses, if any, until the hostname
+        // resolves again
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        self.refresh_at = Instant::now() + retry_backoff(self.consecutive_failures);
+        self.last_error = Some(error);
+    }
+
+    #[inline]
+    fn select(&self, key: Option<SocketAddr>) -> Option<IpAddr> {
+        let index = match key {
+            Some(key) if self.preferred > 1 => {
+                let ip = match key.ip() {
+                    IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                    IpAddr::V6(ip) => ip,
+                };
+                let mut bytes = [0u8; 18];
+                bytes[..16].copy_from_slice(&ip.octets());
+                bytes[16..].copy_from_slice(&key.port().to_be_bytes());
+                (gxhash::gxhash64(&bytes, HASH_SEED) % self.preferred as u64) as usize
+            }
+            _ => 0,
+        };
+
+        self.addresses.get(index).copied()
+    }
+
+    fn resolution(&self) -> Resolution {
+        Resolution {
+            addresses: self.addresses.to_vec(),
+            healthy: self.last_error.is_none() && !self.addresses.is_empty(),
+            consecutive_failures: self.consecutive_failures,
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+#[inline]
+fn clamp_ttl(valid_until: Instant) -> Instant {
+    let now = Instant::now();
+    now + valid_until
+        .saturating_duration_since(now)
+        .clamp(MIN_TTL, MAX_TTL)
+}
+
+#[inline]
+fn retry_backoff(failures: u32) -> Duration {
+    MIN_TTL
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(MAX_RETRY)
+}
+
+/// The current resolution state of a hostname.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Resolution {
+    /// The addresses the hostname last resolved to.
+    pub addresses: Vec<IpAddr>,
+    /// Whether the last attempt to resolve the hostname succeeded.
+    pub healthy: bool,
+    /// The number of attempts to resolve the hostname that have failed since
+    /// it last succeeded.
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Returns the address that packets keyed by `key` should be sent to for
+/// `name`, without blocking.
+///
+/// Hostnames that resolve to multiple addresses are spread across them by
+/// `key`, so that the same key is always sent to the same address. If the
+/// hostname has not been resolved yet it is queued for resolution in the
+/// background and an error of kind [`std::io::ErrorKind::WouldBlock`] is
+/// returned.
+#[inline]
+pub fn select(name: &str, key: Option<SocketAddr>) -> std::io::Result<IpAddr> {
+    if let Some(entry) = CACHE.entries.get(name) {
+        if !entry.requested.load(Relaxed) {
+            entry.requested.store(true, Relaxed);
+        }
+
+        return entry.select(key).ok_or_else(|| match &entry.last_error {
+            Some(error) => std::io::Error::other(format!("failed to resolve {name}: {error}")),
+            None => std::io::Error::new(
+                std::io::ErrorKind::WouldBlock,
+                format!("{name} has not been resolved yet"),
+            ),
+        });
+    }
+
+    CACHE
+        .entries
+        .entry(name.to_owned())
+        .or_insert_with(Entry::pending);
+    CACHE.wake.notify_one();
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::WouldBlock,
+        format!("{name} has not been resolved yet"),
+    ))
+}
+
+/// Resolves `name`, returning the cached addresses if they haven't expired.
+pub async fn resolve(name: &str) -> std::io::Result<Arc<[IpAddr]>> {
+    if let Some(entry) = CACHE.entries.get(name) {
+        entry.requested.store(true, Relaxed);
+        if entry.refresh_at > Instant::now() && !entry.addresses.is_empty() {
+            return Ok(entry.addresses.clone());
+        }
+    }
+
+    refresh(name).await
+}
+
+/// Returns the current resolution state of `name`, if it is being resolved.
+pub fn resolution(name: &str) -> Option<Resolution> {
+    CACHE.entries.get(name).map(|entry| entry.resolution())
+}
+
+/// Returns the current resolution state of every hostname being resolved.
+pub fn resolutions() -> std::collections::BTreeMap<String, Resolution> {
+    CACHE
+        .entries
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.resolution()))
+        .collect()
+}
+
+async fn refresh(name: &str) -> std::io::Result<Arc<[IpAddr]>> {
+    static DNS: Lazy<TokioResolver> =
+        Lazy::new(|| TokioResolver::builder_tokio().unwrap().build().unwrap());
+
+    let result = match DNS.lookup_ip(name).await {
+        Ok(lookup) => {
+            let addresses: Vec<_> = lookup.iter().collect();
+            if addresses.is_empty() {
+                // This should never be hit, hickory-resolver will return an error if there are no records
+                // for the query
+                Err(std::io::Error::other("no ip address found"))
+            } else {
+                Ok((addresses, Instant::from_std(lookup.valid_until())))
+            }
+        }
+        Err(err) => {
+            if err.is_no_records_found() {
+                Err(std::io::Error::other("no ip address found"))
+            } else {
+                Err(std::io::Error::other(err))
+            }
+        }
+    };
+
+    let mut entry = CACHE
+        .entries
+        .entry(name.to_owned())
+        .or_insert_with(Entry::pending);
+
+    match result {
+        Ok((addresses, valid_until)) => {
+            if *entry.addresses != *addresses {
+                tracing::debug!(name, ?addresses, "resolved hostname");
+            }
+
+            entry.resolved(addresses, valid_until);
+            metrics::resolutions(true).inc();
+            metrics::healthy(name).set(1);
+            Ok(entry.addresses.clone())
+        }
+        Err(error) => {
+            tracing::warn!(name, %error, failures = entry.consecutive_failures + 1, "failed to resolve hostname");
+
+            entry.failed(error.to_string());
+            metrics::resolutions(false).inc();
+            metrics::healthy(name).set(0);
+            Err(error)
+        }
+    }
+}
+
+/// Resolves every hostname that is due, returning when the next hostname is
+/// due to be refreshed.
+async fn refresh_due(referenced: &std::collections::BTreeSet<String>) -> Instant {
+    for name in referenced {
+        if !CACHE.entries.contains_key(name) {
+            CACHE.entries.insert(name.clone(), Entry::pending());
+        }
+    }
+
+    let now = Instant::now();
+    let mut due = Vec::new();
+    CACHE.entries.retain(|name, entry| {
+        if entry.refresh_at > now {
+            return true;
+        }
+
+        // Only keep hostnames that are no longer in the cluster map while
+        // they're still being used, eg. by a filter
+        if !referenced.contains(name) && !entry.requested.swap(false, Relaxed) {
+            tracing::debug!(name, "hostname no longer in use, evicting");
+            metrics::remove_healthy(name);
+            return false;
+        }
+
+        due.push(name.clone());
+        true
+    });
+
+    futures::future::join_all(due.iter().map(|name| refresh(name))).await;
+
+    CACHE
+        .entries
+        .iter()
+        .map(|entry| entry.refresh_at)
+        .min()
+        .unwrap_or_else(|| Instant::now() + MAX_TTL)
+}
+
+/// Spawns the background task that keeps the hostnames referenced by
+/// `clusters`, or requested by the packet path, resolved.
+pub fn spawn(
+    clusters: &Watch<ClusterMap>,
+    mut shutdown_rx: crate::signal::ShutdownRx,
+) -> tokio::task::JoinHandle<()> {
+    let cm = clusters.clone_value();
+    let mut cw = clusters.watch();
+
+    tokio::spawn(async move {
+        loop {
+            let next = refresh_due(&cm.hostnames()).await;
+
+            tokio::select! {
+                _ = tokio::time::sleep_until(next) => {}
+                _ = CACHE.wake.notified() => {}
+                res = cw.changed() => {
+                    if res.is_err() {
+                        break;
+                    }
+                }
+                _ = shutdown_rx.changed() => {
+                    break;
+                }
+            }
+        }
+
+        tracing::debug!("DNS resolver task finished");
+    })
+}
+
+mod metrics {
+    use once_cell::sync::Lazy;
+    use prometheus::{IntCounter, IntCounterVec, IntGauge, IntGaugeVec};
+
+    pub(super) fn resolutions(success: bool) -> IntCounter {
+        static RESOLUTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+            prometheus::register_int_counter_vec_with_registry! {
+                prometheus::opts! {
+                    "quilkin_dns_resolutions_total",
+                    "Total number of background DNS resolutions of endpoint hostnames",
+                },
+                &["result"],
+                crate::metrics::registry(),
+            }
+            .unwrap()
+        });
+
+        RESOLUTIONS.with_label_values(&[if success { "success" } else { "error" }])
+    }
+
+    fn healthy_vec() -> &'static IntGaugeVec {
+        static HEALTHY: Lazy<IntGaugeVec> = Lazy::new(|| {
+            prometheus::register_int_gauge_vec_with_registry! {
+                prometheus::opts! {
+                    "quilkin_dns_endpoint_healthy",
+                    "Whether the last resolution of an endpoint hostname succeeded (1) or failed (0)",
+                },
+                &["host"],
+                crate::metrics::registry(),
+            }
+            .unwrap()
+        });
+
+        &HEALTHY
+    }
+
+    pub(super) fn healthy(name: &str) -> IntGauge {
+        healthy_vec().with_label_values(&[name])
+    }
+
+    pub(super) fn remove_healthy(name: &str) {
+        healthy_vec().remove_label_values(&[name]).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(name: &str, addresses: &[IpAddr]) {
+        let mut entry = Entry::pending();
+        entry.resolved(addresses.to_vec(), Instant::now() + MAX_TTL);
+        CACHE.entries.insert(name.into(), entry);
+    }
+
+    #[test]
+    fn unresolved_hostname_is_queued() {
+        let name = "unresolved.quilkin.test";
+        let err = select(name, None).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+        assert!(CACHE.entries.contains_key(name));
+        assert_eq!(resolution(name).unwrap().addresses, Vec::<IpAddr>::new());
+    }
+
+    #[test]
+    fn selects_preferred_family() {
+        let v4: IpAddr = "10.0.0.1".parse().unwrap();
+        let v6: [IpAddr; 2] = [
+            "2001:db8::1".parse().unwrap(),
+            "2001:db8::2".parse().unwrap(),
+        ];
+
+        insert("v4.quilkin.test", &[v4]);
+        insert("mixed.quilkin.test", &[v4, v6[0], v6[1]]);
+
+        assert_eq!(select("v4.quilkin.test", None).unwrap(), v4);
+
+        let mut seen = std::collections::BTreeSet::new();
+        for port in 1000..1100 {
+            let key = SocketAddr::from(([192, 168, 0, 1], port));
+            let ip = select("mixed.quilkin.test", Some(key)).unwrap();
+            assert!(v6.contains(&ip));
+            // The same key is always sent to the same address
+            assert_eq!(ip, select("mixed.quilkin.test", Some(key)).unwrap());
+            seen.insert(ip);
+        }
+
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn failures_keep_last_addresses() {
+        let ip: IpAddr = "10.0.0.2".parse().unwrap();
+        insert("flaky.quilkin.test", &[ip]);
+
+        CACHE
+            .entries
+            .get_mut("flaky.quilkin.test")
+            .unwrap()
+            .failed("timed out".into());
+
+        assert_eq!(select("flaky.quilkin.test", None).unwrap(), ip);
+        let resolution = resolution("flaky.quilkin.test").unwrap();
+        assert!(!resolution.healthy);
+        assert_eq!(resolution.consecutive_failures, 1);
+    }
+
+    #[test]
+    fn ttl_bounds() {
+        assert_eq!(retry_backoff(1), MIN_TTL);
+        assert_eq!(retry_backoff(2), MIN_TTL * 2);
+        assert_eq!(retry_backoff(64), MAX_RETRY);
+
+        let now = Instant::now();
+        assert!(clamp_ttl(now) >= now + MIN_TTL);
+        assert!(clamp_ttl(now + Duration::from_secs(86400)) <= Instant::now() + MAX_TTL);
+    }
+}
@@ -24,6 +24,7 @@ pub enum PipelineError {
     Io(std::io::Error),
     DisallowedSourceIP(std::net::IpAddr),
     SessionLimit,
+    Draining,
 }
 
 impl PipelineError {
@@ -51,6 +52,7 @@ impl PipelineError {
             Self::Io(_) => "io",
             Self::DisallowedSourceIP(_) => "disallowed source ip",
             Self::SessionLimit => "session limit",
+            Self::Draining => "draining",
         }
     }
 }
@@ -66,6 +68,7 @@ impl fmt::Display for PipelineError {
             Self::Io(io) => write!(f, "OS level error: {io}"),
             Self::DisallowedSourceIP(ip) => write!(f, "disallowed ip: {ip}"),
             Self::SessionLimit => f.write_str("session limit reached"),
+            Self::Draining => f.write_str("draining, no new sessions are accepted"),
         }
     }
 }
@@ -90,7 +93,8 @@ impl PartialEq for PipelineError {
             (Self::Io(ia), Self::Io(ib)) => ia.kind().eq(&ib.kind()),
             (Self::DisallowedSourceIP(ia), Self::DisallowedSourceIP(ib)) => ia.eq(ib),
             (Self::NoUpstreamEndpoints, Self::NoUpstreamEndpoints)
-            | (Self::SessionLimit, Self::SessionLimit) => true,
+            | (Self::SessionLimit, Self::SessionLimit)
+            | (Self::Draining, Self::Draining) => true,
             _ => false,
         }
     }
@@ -107,7 +111,7 @@ impl Hash for PipelineError {
             Self::Filter(fe) => Hash::hash(&fe, state),
             Self::Session(se) => Hash::hash(&se, state),
             Self::Io(io) => Hash::hash(&io.kind(), state),
-            Self::NoUpstreamEndpoints | Self::SessionLimit => {}
+            Self::NoUpstreamEndpoints | Self::SessionLimit | Self::Draining => {}
             Self::DisallowedSourceIP(ip) => Hash::hash(&ip, state),
         }
     }
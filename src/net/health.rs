@@ -0,0 +1,583 @@
+/*
+ * Copyright 2026 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *       http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+//! Active and passive health checking of upstream endpoints.
+//!
+//! Endpoints that fail a health check are marked unhealthy in the
+//! [`ClusterMap`], which excludes them from load balancing and token routing
+//! until they recover. Endpoints are actively checked by sending them QCMP
+//! pings, and passively checked by watching for endpoints that stop replying
+//! to the traffic that is forwarded to them.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use futures::StreamExt as _;
+use tokio::time::Instant;
+
+use crate::{
+    codec::qcmp::QcmpTransceiver,
+    config::Watch,
+    net::{
+        ClusterMap, EndpointAddress,
+        endpoint::AddressKind,
+        sessions::{ActiveSessions, SessionInfo},
+    },
+};
+
+/// How often passive checks, and the health of hostname endpoints, are evaluated.
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(1);
+/// The maximum number of QCMP pings that are in flight at once, the transceiver
+/// has a limited number of nonces available.
+const MAX_CONCURRENT_PINGS: usize = 64;
+
+/// Why an endpoint was marked unhealthy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// The endpoint failed to reply to consecutive QCMP pings.
+    ActiveCheck,
+    /// The endpoint stopped replying to the traffic forwarded to it.
+    NoReplies,
+    /// The endpoint's hostname has never been resolved successfully.
+    DnsResolution,
+}
+
+impl Reason {
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ActiveCheck => "active_check",
+            Self::NoReplies => "no_replies",
+            Self::DnsResolution => "dns_resolution",
+        }
+    }
+}
+
+/// The state of an endpoint that has been marked unhealthy.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+pub struct Unhealthy {
+    pub reason: Reason,
+    /// When the endpoint was marked unhealthy, in seconds since the unix epoch.
+    pub since: i64,
+}
+
+/// Options for health checking upstream endpoints
+#[derive(clap::Args, Clone, Debug)]
+pub struct HealthCheckOptions {
+    /// Actively health checks endpoints by sending them QCMP pings, endpoints
+    /// that fail to reply are excluded from routing until they reply again.
+    #[clap(
+        long = "service.udp.health-check.active",
+        env = "QUILKIN_SERVICE_UDP_HEALTH_CHECK_ACTIVE"
+    )]
+    pub active: bool,
+    /// The port QCMP pings are sent to, defaults to the port of the endpoint
+    #[clap(
+        long = "service.udp.health-check.qcmp-port",
+        env = "QUILKIN_SERVICE_UDP_HEALTH_CHECK_QCMP_PORT"
+    )]
+    pub qcmp_port: Option<u16>,
+    /// The time between each QCMP ping to an endpoint
+    #[clap(
+        long = "service.udp.health-check.interval",
+        env = "QUILKIN_SERVICE_UDP_HEALTH_CHECK_INTERVAL",
+        default_value = "5s"
+    )]
+    pub interval: crate::cli::Duration,
+    /// The time to wait for a reply to a QCMP ping
+    #[clap(
+        long = "service.udp.health-check.timeout",
+        env = "QUILKIN_SERVICE_UDP_HEALTH_CHECK_TIMEOUT",
+        default_value = "1s"
+    )]
+    pub timeout: crate::cli::Duration,
+    /// The number of consecutive QCMP pings an endpoint must fail to reply to
+    /// before it is marked unhealthy
+    #[clap(
+        long = "service.udp.health-check.unhealthy-threshold",
+        env = "QUILKIN_SERVICE_UDP_HEALTH_CHECK_UNHEALTHY_THRESHOLD",
+        default_value_t = 3
+    )]
+    pub unhealthy_threshold: u32,
+    /// Passively health checks endpoints, endpoints that are sent traffic but
+    /// don't reply to any of it are excluded from routing for a period of time.
+    #[clap(
+        long = "service.udp.health-check.passive",
+        env = "QUILKIN_SERVICE_UDP_HEALTH_CHECK_PASSIVE"
+    )]
+    pub passive: bool,
+    /// How long an endpoint can be sent traffic without replying before it is
+    /// marked unhealthy
+    #[clap(
+        long = "service.udp.health-check.passive.timeout",
+        env = "QUILKIN_SERVICE_UDP_HEALTH_CHECK_PASSIVE_TIMEOUT",
+        default_value = "10s"
+    )]
+    pub passive_timeout: crate::cli::Duration,
+    /// How long an endpoint marked unhealthy by the passive health check is
+    /// excluded from routing
+    #[clap(
+        long = "service.udp.health-check.passive.ejection-time",
+        env = "QUILKIN_SERVICE_UDP_HEALTH_CHECK_PASSIVE_EJECTION_TIME",
+        default_value = "30s"
+    )]
+    pub ejection_time: crate::cli::Duration,
+}
+
+impl Default for HealthCheckOptions {
+    fn default() -> Self {
+        Self {
+            active: false,
+            qcmp_port: None,
+            interval: Duration::from_secs(5).into(),
+            timeout: Duration::from_secs(1).into(),
+            unhealthy_threshold: 3,
+            passive: false,
+            passive_timeout: Duration::from_secs(10).into(),
+            ejection_time: Duration::from_secs(30).into(),
+        }
+    }
+}
+
+/// Marks `address` unhealthy, logging and recording the transition.
+fn eject(clusters: &ClusterMap, address: EndpointAddress, reason: Reason) {
+    if clusters.mark_unhealthy(address.clone(), reason) {
+        tracing::warn!(%address, reason = reason.as_str(), "endpoint marked unhealthy");
+        metrics::ejections(reason).inc();
+    }
+}
+
+/// Marks `address` healthy, if it was marked unhealthy for `reason`.
+fn restore(clusters: &ClusterMap, address: &EndpointAddress, reason: Reason) {
+    if clusters.mark_healthy(address, reason) {
+        tracing::info!(%address, reason = reason.as_str(), "endpoint marked healthy");
+    }
+}
+
+/// Pings every endpoint, marking endpoints that fail to reply to
+/// `threshold` consecutive pings as unhealthy.
+struct ActiveCheck {
+    transceiver: QcmpTransceiver,
+    qcmp_port: Option<u16>,
+    timeout: Duration,
+    threshold: u32,
+    failures: HashMap<EndpointAddress, u32>,
+}
+
+impl ActiveCheck {
+    async fn run(&mut self, clusters: &ClusterMap) {
+        let transceiver = &self.transceiver;
+        let (qcmp_port, timeout) = (self.qcmp_port, self.timeout);
+
+        let results: Vec<(EndpointAddress, bool)> =
+            futures::stream::iter(clusters.endpoints().into_iter().filter_map(|endpoint| {
+                // Hostnames that haven't been resolved are handled separately
+                let mut address = endpoint.address.to_socket_addr().ok()?;
+                if let Some(port) = qcmp_port {
+                    address.set_port(port);
+                }
+
+                Some(async move {
+                    let result = transceiver.ping(address, timeout).await;
+                    if let Err(error) = &result {
+                        tracing::debug!(%address, %error, "health check ping failed");
+                    }
+                    (endpoint.address, result.is_ok())
+                })
+            }))
+            .buffer_unordered(MAX_CONCURRENT_PINGS)
+            .collect()
+            .await;
+
+        let mut failures = HashMap::new();
+        for (address, healthy) in results {
+            metrics::active_checks(healthy).inc();
+
+            if healthy {
+                restore(clusters, &address, Reason::ActiveCheck);
+                continue;
+            }
+
+            let count = self.failures.get(&address).copied().unwrap_or_default() + 1;
+            if count >= self.threshold {
+                eject(clusters, address.clone(), Reason::ActiveCheck);
+            }
+            failures.insert(address, count);
+        }
+
+        self.failures = failures;
+    }
+}
+
+/// Watches the traffic counters of active sessions, marking endpoints that
+/// have been sent traffic without replying for `timeout` as unhealthy for
+/// `ejection_time`.
+struct PassiveCheck {
+    timeout: Duration,
+    ejection_time: Duration,
+    /// The upstream and downstream packet counts of each session at the last check
+    counters: HashMap<(SocketAddr, SocketAddr), (u64, u64)>,
+    /// When each destination that is being sent traffic stopped replying
+    silent_since: HashMap<SocketAddr, Instant>,
+    ejected: HashMap<EndpointAddress, Instant>,
+}
+
+impl PassiveCheck {
+    fn new(timeout: Duration, ejection_time: Duration) -> Self {
+        Self {
+            timeout,
+            ejection_time,
+            counters: HashMap::new(),
+            silent_since: HashMap::new(),
+            ejected: HashMap::new(),
+        }
+    }
+
+    fn run(&mut self, clusters: &ClusterMap, sessions: Vec<SessionInfo>) {
+        let now = Instant::now();
+
+        self.ejected.retain(|address, until| {
+            let keep = *until > now;
+            if !keep {
+                restore(clusters, address, Reason::NoReplies);
+            }
+            keep
+        });
+
+        // The number of packets sent to, and received from, each destination
+        // since the last check
+        let mut deltas = HashMap::<SocketAddr, (u64, u64)>::new();
+        let mut counters = HashMap::with_capacity(self.counters.len());
+        for info in sessions {
+            let key = (info.source, info.destination);
+            let (upstream, downstream) = self.counters.get(&key).copied().unwrap_or_default();
+            let delta = deltas.entry(info.destination).or_default();
+            delta.0 += info.upstream_packets.saturating_sub(upstream);
+            delta.1 += info.downstream_packets.saturating_sub(downstream);
+            counters.insert(key, (info.upstream_packets, info.downstream_packets));
+        }
+        self.counters = counters;
+
+        let mut silent = Vec::new();
+        self.silent_since.retain(|destination, _| {
+            deltas
+                .get(destination)
+                .is_some_and(|(_, downstream)| *downstream == 0)
+        });
+        for (destination, (upstream, downstream)) in deltas {
+            // Idle destinations give no indication either way
+            if downstream > 0 || upstream == 0 {
+                continue;
+            }
+
+            let since = *self.silent_since.entry(destination).or_insert(now);
+            if now.duration_since(since) >= self.timeout {
+                silent.push(destination);
+            }
+        }
+
+        if silent.is_empty() {
+            return;
+        }
+
+        let addresses = resolved_addresses(clusters);
+        for destination in silent {
+            self.silent_since.remove(&destination);
+            let destination = SocketAddr::new(destination.ip().to_canonical(), destination.port());
+            let Some(address) = addresses.get(&destination) else {
+                continue;
+            };
+
+            eject(clusters, address.clone(), Reason::NoReplies);
+            self.ejected
+                .insert(address.clone(), now + self.ejection_time);
+        }
+    }
+}
+
+/// Maps the socket addresses that packets are sent to back to the endpoint
+/// they belong to.
+fn resolved_addresses(clusters: &ClusterMap) -> HashMap<SocketAddr, EndpointAddress> {
+    let mut addresses = HashMap::new();
+    for endpoint in clusters.endpoints() {
+        match &endpoint.address.host {
+            AddressKind::Ip(ip) => {
+                addresses.insert(
+                    SocketAddr::new(ip.to_canonical(), endpoint.address.port),
+                    endpoint.address,
+                );
+            }
+            AddressKind::Name(name) => {
+                for ip in crate::net::endpoint::dns::resolution(name)
+                    .map(|resolution| resolution.addresses)
+                    .unwrap_or_default()
+                {
+                    addresses.insert(
+                        SocketAddr::new(ip.to_canonical(), endpoint.address.port),
+                        endpoint.address.clone(),
+                    );
+                }
+            }
+        }
+    }
+    addresses
+}
+
+/// Marks endpoints whose hostname has never resolved as unhealthy. Hostnames
+/// that fail to resolve after having resolved keep using their last
+/// addresses, so those endpoints are left to the other health checks.
+fn check_hostnames(clusters: &ClusterMap) {
+    let hostnames = clusters.hostnames();
+    if hostnames.is_empty() {
+        return;
+    }
+
+    let unresolved: HashSet<_> = hostnames
+        .into_iter()
+        .filter(|name| {
+            crate::net::endpoint::dns::resolution(name).is_some_and(|resolution| {
+                resolution.addresses.is_empty() && resolution.consecutive_failures > 0
+            })
+        })
+        .collect();
+
+    for endpoint in clusters.endpoints() {
+        let AddressKind::Name(name) = &endpoint.address.host else {
+            continue;
+        };
+
+        if unresolved.contains(name) {
+            eject(clusters, endpoint.address, Reason::DnsResolution);
+        } else {
+            restore(clusters, &endpoint.address, Reason::DnsResolution);
+        }
+    }
+}
+
+/// Spawns the task that health checks the endpoints in `clusters`, passive
+/// checks require the `sessions` of the UDP service.
+pub fn spawn(
+    options: &HealthCheckOptions,
+    clusters: &Watch<ClusterMap>,
+    sessions: Option<ActiveSessions>,
+    mut shutdown_rx: crate::signal::ShutdownRx,
+) -> crate::Result<tokio::task::JoinHandle<()>> {
+    let mut active = if options.active {
+        Some(ActiveCheck {
+            transceiver: QcmpTransceiver::new()?,
+            qcmp_port: options.qcmp_port,
+            timeout: *options.timeout,
+            threshold: options.unhealthy_threshold.max(1),
+            failures: HashMap::new(),
+        })
+    } else {
+        None
+    };
+
+    let mut passive = if options.passive {
+        let Some(sessions) = sessions else {
+            eyre::bail!("passive health checks require the UDP service to be enabled");
+        };
+
+        Some((
+            sessions,
+            PassiveCheck::new(*options.passive_timeout, *options.ejection_time),
+        ))
+    } else {
+        None
+    };
+
+    let interval = *options.interval;
+    let cm = clusters.clone_value();
+
+    Ok(tokio::spawn(async move {
+        let mut active_interval = tokio::time::interval(interval);
+        let mut maintenance_interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = active_interval.tick(), if active.is_some() => {
+                    if let Some(active) = &mut active {
+                        active.run(&cm).await;
+                    }
+                }
+                _ = maintenance_interval.tick() => {
+                    if let Some((sessions, passive)) = &mut passive {
+                        passive.run(&cm, sessions.all());
+                    }
+                    check_hostnames(&cm);
+                    cm.prune_unhealthy();
+                }
+                _ = shutdown_rx.changed() => {
+                    break;
+                }
+            }
+
+            metrics::unhealthy_endpoints().set(cm.num_of_unhealthy_endpoints() as i64);
+        }
+
+        tracing::debug!("health check task finished");
+    }))
+}
+
+mod metrics {
+    use once_cell::sync::Lazy;
+    use prometheus::{IntCounter, IntCounterVec, IntGauge};
+
+    use super::Reason;
+
+    pub(super) fn unhealthy_endpoints() -> &'static IntGauge {
+        static UNHEALTHY: Lazy<IntGauge> = Lazy::new(|| {
+            crate::metrics::register(
+                IntGauge::with_opts(prometheus::Opts::new(
+                    "quilkin_endpoints_unhealthy",
+                    "Number of endpoints currently excluded from routing by health checks",
+                ))
+                .unwrap(),
+            )
+        });
+
+        &UNHEALTHY
+    }
+
+    pub(super) fn ejections(reason: Reason) -> IntCounter {
+        static EJECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+            prometheus::register_int_counter_vec_with_registry! {
+                prometheus::opts! {
+                    "quilkin_endpoints_unhealthy_total",
+                    "Total number of times an endpoint was marked unhealthy",
+                },
+                &["reason"],
+                crate::metrics::registry(),
+            }
+            .unwrap()
+        });
+
+        EJECTIONS.with_label_values(&[reason.as_str()])
+    }
+
+    pub(super) fn active_checks(success: bool) -> IntCounter {
+        static CHECKS: Lazy<IntCounterVec> = Lazy::new(|| {
+            prometheus::register_int_counter_vec_with_registry! {
+                prometheus::opts! {
+                    "quilkin_health_checks_total",
+                    "Total number of active health check pings sent to endpoints",
+                },
+                &["result"],
+                crate::metrics::registry(),
+            }
+            .unwrap()
+        });
+
+        CHECKS.with_label_values(&[if success { "success" } else { "failure" }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::Endpoint;
+
+    #[test]
+    fn routing_skips_unhealthy_endpoints() {
+        let healthy = EndpointAddress::from(([10, 0, 0, 1], 7777));
+        let unhealthy = EndpointAddress::from(([10, 0, 0, 2], 7777));
+        let cm = ClusterMap::new_default(
+            [
+                Endpoint::new(healthy.clone()),
+                Endpoint::new(unhealthy.clone()),
+            ]
+            .into(),
+        );
+
+        assert_eq!(cm.num_of_routable_endpoints(), 2);
+        assert!(cm.mark_unhealthy(unhealthy.clone(), Reason::ActiveCheck));
+        assert!(!cm.mark_unhealthy(unhealthy.clone(), Reason::NoReplies));
+        assert!(!cm.is_healthy(&unhealthy));
+
+        assert_eq!(cm.num_of_routable_endpoints(), 1);
+        let chosen = cm.choose_routable_endpoint(|len| {
+            assert_eq!(len, 1);
+            0
+        });
+        assert_eq!(chosen.unwrap().address, healthy);
+
+        let mut addrs = vec![healthy.clone(), unhealthy.clone()];
+        cm.retain_healthy(&mut addrs);
+        assert_eq!(addrs, [healthy.clone()]);
+
+        // Only the check that marked the endpoint unhealthy can restore it
+        assert!(!cm.mark_healthy(&unhealthy, Reason::NoReplies));
+        assert!(cm.mark_healthy(&unhealthy, Reason::ActiveCheck));
+        assert_eq!(cm.num_of_routable_endpoints(), 2);
+
+        // If every endpoint is unhealthy we route to all of them rather than
+        // dropping all traffic
+        cm.mark_unhealthy(healthy.clone(), Reason::ActiveCheck);
+        cm.mark_unhealthy(unhealthy.clone(), Reason::ActiveCheck);
+        assert_eq!(cm.num_of_routable_endpoints(), 2);
+        assert!(cm.choose_routable_endpoint(|len| len - 1).is_some());
+
+        let mut addrs = vec![healthy.clone(), unhealthy.clone()];
+        cm.retain_healthy(&mut addrs);
+        assert_eq!(addrs, [healthy.clone(), unhealthy.clone()]);
+
+        cm.remove_locality(None, &None);
+        cm.prune_unhealthy();
+        assert_eq!(cm.num_of_unhealthy_endpoints(), 0);
+    }
+
+    #[test]
+    fn passive_check_ejects_silent_endpoints() {
+        let server = EndpointAddress::from(([10, 0, 0, 1], 7777));
+        let cm = ClusterMap::new_default([Endpoint::new(server.clone())].into());
+        let session = |upstream_packets, downstream_packets| SessionInfo {
+            source: ([1, 1, 1, 1], 4242).into(),
+            destination: server.to_socket_addr().unwrap(),
+            created_at: 0,
+            last_activity: 0,
+            upstream_packets,
+            upstream_bytes: 0,
+            downstream_packets,
+            downstream_bytes: 0,
+            asn: None,
+        };
+
+        let mut passive = PassiveCheck::new(Duration::ZERO, Duration::from_secs(60));
+
+        // Replies keep the endpoint healthy
+        passive.run(&cm, vec![session(5, 5)]);
+        assert!(cm.is_healthy(&server));
+
+        passive.run(&cm, vec![session(10, 5)]);
+        assert_eq!(
+            cm.unhealthy_endpoints()
+                .into_iter()
+                .map(|(address, unhealthy)| (address, unhealthy.reason))
+                .collect::<Vec<_>>(),
+            [(server.clone(), Reason::NoReplies)]
+        );
+
+        // The endpoint is restored once the ejection time has passed
+        passive.ejected.insert(server.clone(), Instant::now());
+        passive.run(&cm, Vec::new());
+        assert!(cm.is_healthy(&server));
+    }
+}
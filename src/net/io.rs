@@ -118,9 +118,19 @@ pub struct Listener {
     pub config: Arc<Config>,
     pub sessions: Arc<crate::net::sessions::SessionPool>,
     pub backend: UdpBackend,
+    /// Applied to the worker's socket, which downstream packets are both
+    /// received and sent on.
+    pub socket_options: crate::net::qos::SocketOptions,
 }
 
 impl Listener {
+    /// Binds the worker's socket to the listener's port.
+    pub fn bind(&self) -> std::io::Result<socket2::Socket> {
+        let socket = crate::net::raw_socket_with_reuse(self.port)?;
+        self.socket_options.apply(&socket)?;
+        Ok(socket)
+    }
+
     pub fn spawn_io_loop(
         self,
         queue: crate::net::PacketQueue,
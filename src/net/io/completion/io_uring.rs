@@ -53,9 +53,9 @@ pub fn spawn_listener(
     filter_chain: CachedFilterChain,
     recv_ring_len: u16,
 ) -> eyre::Result<()> {
+    let socket = listener.bind().context("failed to bind socket")?;
     let crate::net::io::Listener {
         worker_id,
-        port,
         config,
         sessions,
         ..
@@ -69,7 +69,7 @@ pub fn spawn_listener(
         }
     };
 
-    let socket = crate::net::DualStackLocalSocket::new(port).context("failed to bind socket")?;
+    let socket = crate::net::DualStackLocalSocket::from_raw(socket);
 
     let io_loop = IoUringLoop::new(recv_ring_len, socket)?;
     io_loop
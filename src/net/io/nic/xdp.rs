@@ -412,10 +412,19 @@ impl XdpLoop {
 /// the XDP sockets is already attached to the NIC by [`setup_xdp_io`] by this
 /// point.
 ///
+/// The `sessions` are shared between every I/O thread, and can be inspected
+/// while the loop is running. `dscp` is written into the IP header of every
+/// forwarded packet, unless overridden by a filter.
+///
 /// # Errors
 ///
 /// This can fail if threads can not be spawned for some reason (unlikely)
-pub fn spawn(workers: XdpWorkers, config: process::ConfigState) -> Result<XdpLoop, XdpSpawnError> {
+pub fn spawn(
+    workers: XdpWorkers,
+    config: process::ConfigState,
+    session_state: Arc<process::SessionState>,
+    dscp: crate::net::qos::Marking,
+) -> Result<XdpLoop, XdpSpawnError> {
     let nic = workers.nic;
     let ebpf_prog = workers.ebpf_prog;
     let xdp_link = workers.xdp_link;
@@ -423,7 +432,6 @@ pub fn spawn(workers: XdpWorkers, config: process::ConfigState) -> Result<XdpLoo
     let qcmp_port = workers.qcmp_port;
     let ipv4 = workers.ipv4;
     let ipv6 = workers.ipv6;
-    let session_state = Arc::new(process::SessionState::default());
     let shutdown = Arc::new(std::sync::atomic::AtomicBool::new(false));
 
     let queue_count = workers.workers.len();
@@ -453,6 +461,7 @@ pub fn spawn(workers: XdpWorkers, config: process::ConfigState) -> Result<XdpLoo
                     ss,
                     ipv4,
                     ipv6,
+                    dscp,
                     shutdown.clone(),
                 );
             })
@@ -490,6 +499,7 @@ fn io_loop(
     sessions: Arc<process::SessionState>,
     local_ipv4: std::net::Ipv4Addr,
     local_ipv6: std::net::Ipv6Addr,
+    dscp: crate::net::qos::Marking,
     shutdown: Arc<std::sync::atomic::AtomicBool>,
 ) {
     let quilkin_xdp::XdpWorker {
@@ -513,6 +523,7 @@ fn io_loop(
         local_ipv4,
         local_ipv6,
         last_receive: UtcTimestamp::now(),
+        dscp,
     };
 
     use xdp::slab::Slab;
@@ -5,7 +5,8 @@ use crate::{
         EndpointAddress,
         error::PipelineError,
         maxmind_db::{self, IpNetEntry},
-        sessions::inner_metrics as session_metrics,
+        qos::Dscp,
+        sessions::{SessionFilter, SessionInfo, SessionStats, inner_metrics as session_metrics},
     },
     time::UtcTimestamp,
 };
@@ -14,7 +15,7 @@ use quilkin_xdp::xdp::{
     Umem,
     packet::{
         Packet, PacketError, csum,
-        net_types::{IpAddresses, IpHdr, Ipv4Hdr, NetworkU16, UdpHdr, UdpHeaders},
+        net_types::{EthHdr, IpAddresses, IpHdr, Ipv4Hdr, NetworkU16, UdpHdr, UdpHeaders},
     },
     slab::{Slab, StackSlab},
 };
@@ -23,7 +24,7 @@ use std::{
     net::{IpAddr, SocketAddr},
     sync::{
         Arc,
-        atomic::{AtomicU16, Ordering},
+        atomic::{AtomicBool, AtomicU16, Ordering},
     },
     time::Instant,
 };
@@ -207,6 +208,8 @@ pub struct State {
     pub local_ipv4: std::net::Ipv4Addr,
     pub local_ipv6: std::net::Ipv6Addr,
     pub last_receive: UtcTimestamp,
+    /// The DSCP values written to forwarded packets in each direction
+    pub dscp: crate::net::qos::Marking,
 }
 
 impl State {
@@ -231,13 +234,15 @@ impl State {
     }
 
     /// Retrieves or creates a session, ie a mapping of a server endpoint + port
-    /// to a client endpoint
+    /// to a client endpoint, recording a packet of `data_length` bytes sent
+    /// through it, `None` if the session is new and the sessions are draining
     #[inline]
     fn session(
         &mut self,
         client_addr: SocketAddr,
         server_addr: SocketAddr,
-    ) -> (NetworkU16, AsnInfo<'_>, IpAddresses) {
+        data_length: usize,
+    ) -> Option<(NetworkU16, AsnInfo<'_>, IpAddresses)> {
         let ips = self.ips(server_addr.ip());
         let asn = self.addr_to_asn.get_or_insert_with(
             client_addr.ip(),
@@ -251,18 +256,23 @@ impl State {
             },
         );
 
-        let port = self
-            .sessions
-            .get_or_create(client_addr, server_addr, asn.map(|(ipe, _)| ipe));
+        let port = self.sessions.get_or_create(
+            client_addr,
+            server_addr,
+            asn.map(|(ipe, _)| ipe),
+            data_length,
+            self.last_receive,
+        )?;
+        metrics::endpoint_traffic(metrics::READ, server_addr, data_length);
 
-        (
+        Some((
             port,
             asn.map_or(metrics::EMPTY, |(ipe, asn)| AsnInfo {
                 prefix: &ipe.prefix,
                 asn: asn.as_str(),
             }),
             ips,
-        )
+        ))
     }
 
     #[inline]
@@ -384,6 +394,23 @@ impl PortMap {
                 .set(client_addr);
         }
     }
+
+    #[inline]
+    fn remove(&mut self, port: NetworkU16) {
+        let Some(i) = port.host().checked_sub(EPHEMERAL_RANGE_END) else {
+            return;
+        };
+        let i = i as usize;
+
+        if let Some(item) = self
+            .buckets
+            .get_mut(i / BUCKET_SIZE)
+            .and_then(|bucket| bucket.get_mut(i % BUCKET_SIZE))
+        {
+            // A zero port marks the item as uninitialized, see `get`
+            item.port = 0;
+        }
+    }
 }
 
 struct ClientInfo {
@@ -391,6 +418,7 @@ struct ClientInfo {
     created_at: Instant,
     /// The port used to identify this unique session to the IP owning this map
     port: NetworkU16,
+    stats: SessionStats,
 }
 
 struct PortMapper {
@@ -416,9 +444,15 @@ impl PortMapper {
         &self,
         client_addr: SocketAddr,
         asn: Option<&IpNetEntry>,
+        data_length: usize,
+        now: UtcTimestamp,
     ) -> Option<NetworkU16> {
         match self.client_to_port.lock().entry(client_addr) {
-            Entry::Occupied(entry) => Some(entry.get().port),
+            Entry::Occupied(entry) => {
+                let info = entry.get();
+                info.stats.record(metrics::READ, data_length, now);
+                Some(info.port)
+            }
             Entry::Vacant(entry) => {
                 let port = self.port.fetch_add(1, Ordering::Relaxed);
 
@@ -433,10 +467,13 @@ impl PortMapper {
                 self.port_to_client.write().insert(client_addr, port);
 
                 let port = port.into();
+                let stats = SessionStats::new(now);
+                stats.record(metrics::READ, data_length, now);
                 entry.insert(ClientInfo {
                     asn_info: asn.cloned(),
                     created_at: Instant::now(),
                     port,
+                    stats,
                 });
                 Some(port)
             }
@@ -447,6 +484,30 @@ impl PortMapper {
     fn get_client(&self, port: NetworkU16) -> Option<SocketAddr> {
         self.port_to_client.read().get(port)
     }
+
+    /// Removes every client of `server_addr` matching `filter`, returning the
+    /// number of clients removed
+    fn remove_clients(&self, server_addr: SocketAddr, filter: &SessionFilter) -> usize {
+        let mut clients = self.client_to_port.lock();
+        let mut port_to_client = self.port_to_client.write();
+        let now = Instant::now();
+        let before = clients.len();
+
+        clients.retain(|client_addr, client_info| {
+            if !filter.matches(*client_addr, server_addr) {
+                return true;
+            }
+
+            tracing::info!(source = %client_addr, dest = %server_addr, "session terminated");
+            port_to_client.remove(client_info.port);
+            session_metrics::active_sessions(client_info.asn_info.as_ref()).dec();
+            session_metrics::duration_secs()
+                .observe(now.duration_since(client_info.created_at).as_secs_f64());
+            false
+        });
+
+        before - clients.len()
+    }
 }
 
 impl Drop for PortMapper {
@@ -465,6 +526,8 @@ impl Drop for PortMapper {
 
 pub struct SessionState {
     sessions: crate::collections::ttl::TtlMap<SocketAddr, PortMapper>,
+    /// Whether new sessions are rejected, as the sessions are draining
+    draining: AtomicBool,
 }
 
 #[allow(clippy::derivable_impls)]
@@ -472,6 +535,7 @@ impl Default for SessionState {
     fn default() -> Self {
         Self {
             sessions: Default::default(),
+            draining: AtomicBool::new(false),
         }
     }
 }
@@ -486,30 +550,74 @@ impl SessionState {
             .and_then(|pm| pm.get_client(port))
     }
 
+    /// Records a packet of `data_length` bytes forwarded from the specified
+    /// server endpoint back to the client paired with `port`
+    #[inline]
+    fn record_downstream(
+        &self,
+        server_addr: SocketAddr,
+        port: NetworkU16,
+        data_length: usize,
+        now: UtcTimestamp,
+    ) {
+        metrics::endpoint_traffic(metrics::WRITE, server_addr, data_length);
+
+        let Some(pm) = self.sessions.get(&server_addr) else {
+            return;
+        };
+        let Some(client_addr) = pm.get_client(port) else {
+            return;
+        };
+
+        if let Some(client_info) = pm.client_to_port.lock().get(&client_addr) {
+            client_info.stats.record(metrics::WRITE, data_length, now);
+        }
+    }
+
     /// Retrieves the port used to forward packets from the specified client
     /// endpoint to the specified server endpoint, pairing the port to the client
-    /// for forwarding packets back from the server to the client
+    /// for forwarding packets back from the server to the client.
+    ///
+    /// Returns `None` if there is no session yet and the sessions are draining
     #[inline]
     fn get_or_create(
         &self,
         client_addr: SocketAddr,
         server_addr: SocketAddr,
         asn: Option<&IpNetEntry>,
-    ) -> NetworkU16 {
+        data_length: usize,
+        now: UtcTimestamp,
+    ) -> Option<NetworkU16> {
+        if self.draining.load(Ordering::Relaxed) {
+            let pm = self.sessions.get(&server_addr)?;
+            let clients = pm.client_to_port.lock();
+            let Some(info) = clients.get(&client_addr) else {
+                tracing::debug!(
+                    source = %client_addr,
+                    dest = %server_addr,
+                    "draining, dropping packet for new session"
+                );
+                return None;
+            };
+
+            info.stats.record(metrics::READ, data_length, now);
+            return Some(info.port);
+        }
+
         let port = match self.sessions.entry(server_addr) {
             crate::collections::ttl::Entry::Occupied(entry) => {
-                entry.get().get_or_alloc(client_addr, asn)
+                entry.get().get_or_alloc(client_addr, asn, data_length, now)
             }
             crate::collections::ttl::Entry::Vacant(entry) => {
                 let pm = PortMapper::new();
-                let port = pm.get_or_alloc(client_addr, asn);
+                let port = pm.get_or_alloc(client_addr, asn, data_length, now);
                 entry.insert(pm);
                 port
             }
         };
 
         if let Some(port) = port {
-            return port;
+            return Some(port);
         }
 
         // This means that this server has allocated over 4535 ports, which...could?
@@ -519,7 +627,57 @@ impl SessionState {
         // the client endpoint is any longer, or, slightly worse, a packet gets
         // redirected to a different client.
         self.sessions.remove(server_addr);
-        self.get_or_create(client_addr, server_addr, asn)
+        self.get_or_create(client_addr, server_addr, asn, data_length, now)
+    }
+
+    /// Stops creating new sessions, packets for existing sessions are still
+    /// forwarded until they expire
+    pub fn drain(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+
+    /// The number of active sessions
+    pub fn count(&self) -> usize {
+        self.sessions
+            .iter()
+            .map(|entry| entry.value().client_to_port.lock().len())
+            .sum()
+    }
+
+    /// Returns a snapshot of every active session that matches `filter`
+    pub fn session_info(&self, filter: &SessionFilter) -> Vec<SessionInfo> {
+        let mut sessions = Vec::new();
+
+        for entry in self.sessions.iter() {
+            let server_addr = *entry.key();
+            for (client_addr, client_info) in entry.value().client_to_port.lock().iter() {
+                if filter.matches(*client_addr, server_addr) {
+                    sessions.push(client_info.stats.info(
+                        *client_addr,
+                        server_addr,
+                        client_info.asn_info.as_ref(),
+                    ));
+                }
+            }
+        }
+
+        sessions
+    }
+
+    /// Forcibly removes every active session that matches `filter`, returning
+    /// the number of sessions removed.
+    ///
+    /// Packets the server sends on a removed session are dropped, as the port
+    /// no longer maps to a client
+    pub fn terminate(&self, filter: &SessionFilter) -> usize {
+        let removed: usize = self
+            .sessions
+            .iter()
+            .map(|entry| entry.value().remove_clients(*entry.key(), filter))
+            .sum();
+
+        session_metrics::terminated_total().inc_by(removed as u64);
+        removed
     }
 }
 
@@ -726,7 +884,9 @@ fn process_client_packet<const TXN: usize>(
         filters::ReadContext::new(cm, source_addr.into(), packet, &mut state.destinations);
 
     let result = filters.read(&mut ctx);
+    let dscp = Dscp::from_metadata(&ctx.metadata).or(state.dscp.upstream);
     let mut packet = filtered(result, ctx.contents)?;
+    metrics::token_traffic(&ctx.metadata, packet.headers.data_length());
 
     let Some(dest_addr) = state.destinations.pop() else {
         return Ok(Some(packet.buffer));
@@ -746,10 +906,19 @@ fn process_client_packet<const TXN: usize>(
     // a new packet for each destination, only modifying the headers
     if !state.destinations.is_empty() {
         while let Some(daddr) = state.destinations.pop() {
-            let Ok(dest_addr) = daddr.to_socket_addr() else {
+            let Ok(dest_addr) = daddr.to_socket_addr_for(source_addr) else {
+                continue;
+            };
+            let Some((source, asn, ips)) = state.session(source_addr, dest_addr, data_length)
+            else {
+                metrics::packets_dropped_total(
+                    metrics::READ,
+                    PipelineError::Draining.discriminant(),
+                    &metrics::EMPTY,
+                )
+                .inc();
                 continue;
             };
-            let (source, asn, ips) = state.session(source_addr, dest_addr);
 
             let mut headers = UdpHeaders {
                 eth,
@@ -771,7 +940,8 @@ fn process_client_packet<const TXN: usize>(
                 new_packet
             };
 
-            let res = fill_packet(&mut headers, data, data_checksum, &mut new_packet);
+            let res = fill_packet(&mut headers, data, data_checksum, &mut new_packet)
+                .map(|()| set_dscp(&mut new_packet, headers.is_ipv4(), dscp));
             push_packet(
                 metrics::Direction::Read,
                 new_packet,
@@ -784,10 +954,12 @@ fn process_client_packet<const TXN: usize>(
         }
     }
 
-    let Ok(dest_addr) = dest_addr.to_socket_addr() else {
+    let Ok(dest_addr) = dest_addr.to_socket_addr_for(source_addr) else {
         return Ok(Some(packet.buffer));
     };
-    let (source, asn, ips) = state.session(source_addr, dest_addr);
+    let Some((source, asn, ips)) = state.session(source_addr, dest_addr, data_length) else {
+        return Err((PipelineError::Draining, packet.buffer));
+    };
 
     let mut headers = UdpHeaders {
         eth,
@@ -803,7 +975,8 @@ fn process_client_packet<const TXN: usize>(
 
     headers.calc_checksum(data_checksum);
 
-    let res = modify_packet_headers(&packet.headers, &mut headers, &mut packet.buffer);
+    let res = modify_packet_headers(&packet.headers, &mut headers, &mut packet.buffer)
+        .map(|()| set_dscp(&mut packet.buffer, headers.is_ipv4(), dscp));
     push_packet(
         metrics::Direction::Read,
         packet.buffer,
@@ -840,8 +1013,16 @@ fn process_server_packet<const TXN: usize>(
     let mut ctx = filters::WriteContext::new(server_addr.into(), client_addr.into(), packet);
 
     let result = filters.write(&mut ctx);
+    let dscp = Dscp::from_metadata(&ctx.metadata).or(state.dscp.downstream);
     let mut packet = filtered(result, ctx.contents)?;
 
+    state.sessions.record_downstream(
+        server_addr,
+        packet.headers.udp.destination,
+        packet.headers.data_length(),
+        state.last_receive,
+    );
+
     let mut headers = UdpHeaders {
         eth: packet.headers.eth.swapped(),
         ip: state.ips(client_addr.ip()).with_header(&packet.headers.ip),
@@ -856,6 +1037,7 @@ fn process_server_packet<const TXN: usize>(
 
     let res = modify_packet_headers(&packet.headers, &mut headers, &mut packet.buffer);
     if res.is_ok() {
+        set_dscp(&mut packet.buffer, headers.is_ipv4(), dscp);
         let _ = packet.buffer.calc_udp_checksum();
     }
 
@@ -889,6 +1071,45 @@ fn modify_packet_headers(
     Ok(())
 }
 
+/// Rewrites the DSCP bits of the IP header already written to `packet`,
+/// preserving the ECN bits and incrementally updating the IPv4 header checksum
+/// ([RFC 1624](https://www.rfc-editor.org/rfc/rfc1624))
+#[inline]
+fn set_dscp(packet: &mut Packet, is_ipv4: bool, dscp: Option<Dscp>) {
+    let Some(dscp) = dscp else {
+        return;
+    };
+
+    const IP: usize = EthHdr::LEN;
+
+    if is_ipv4 {
+        let old = packet[IP + 1];
+        let new = dscp.with_ecn(old);
+        if old == new {
+            return;
+        }
+        packet[IP + 1] = new;
+
+        let version_ihl = packet[IP];
+        let old = u16::from_be_bytes([version_ihl, old]);
+        let new = u16::from_be_bytes([version_ihl, new]);
+        let check = u16::from_be_bytes([packet[IP + 10], packet[IP + 11]]);
+
+        let mut sum = u32::from(!check) + u32::from(!old) + u32::from(new);
+        sum = (sum & 0xffff) + (sum >> 16);
+        sum = (sum & 0xffff) + (sum >> 16);
+        let [hi, lo] = (!(sum as u16)).to_be_bytes();
+        packet[IP + 10] = hi;
+        packet[IP + 11] = lo;
+    } else {
+        // The traffic class straddles the version and flow label
+        let (first, second) = (packet[IP], packet[IP + 1]);
+        let tclass = dscp.with_ecn((first << 4) | (second >> 4));
+        packet[IP] = (first & 0xf0) | (tclass >> 4);
+        packet[IP + 1] = (second & 0x0f) | (tclass << 4);
+    }
+}
+
 #[inline]
 fn fill_packet(
     headers: &mut UdpHeaders,
@@ -1016,6 +1237,46 @@ mod test {
         assert_eq!(cache.len(), 0);
     }
 
+    #[tokio::test]
+    async fn session_state_inspection() {
+        let state = SessionState::default();
+        let now = UtcTimestamp::now();
+        let client = |last: u8| SocketAddr::from(([1, 1, 1, last], 4000));
+        let server = SocketAddr::from(([2, 2, 2, 2], 7777));
+        let other_server = SocketAddr::from(([3, 3, 3, 3], 7777));
+
+        let port = state
+            .get_or_create(client(1), server, None, 10, now)
+            .unwrap();
+        state.get_or_create(client(1), server, None, 20, now);
+        state.get_or_create(client(2), server, None, 5, now);
+        state.get_or_create(client(1), other_server, None, 5, now);
+        state.record_downstream(server, port, 100, now);
+
+        let filter = SessionFilter {
+            source: Some(client(1).ip()),
+            ..Default::default()
+        };
+        let mut sessions = state.session_info(&filter);
+        sessions.sort_unstable_by_key(|info| info.destination);
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].destination, server);
+        assert_eq!(sessions[0].upstream_packets, 2);
+        assert_eq!(sessions[0].upstream_bytes, 30);
+        assert_eq!(sessions[0].downstream_packets, 1);
+        assert_eq!(sessions[0].downstream_bytes, 100);
+
+        let filter = SessionFilter {
+            destination: Some(server),
+            ..Default::default()
+        };
+        assert_eq!(state.terminate(&filter), 2);
+        assert!(state.session_info(&filter).is_empty());
+        // The port no longer maps to the client, so replies are dropped
+        assert_eq!(state.lookup_client(server, port), None);
+        assert_eq!(state.session_info(&SessionFilter::default()).len(), 1);
+    }
+
     /// Builds an ipv4 UDP packet with `padding` trailing bytes
     fn ipv4_packet(
         data: &mut [u8; 2048],
@@ -1163,4 +1424,46 @@ mod test {
 
         assert_eq!(wrapper.as_slice(), payload);
     }
+
+    #[test]
+    fn rewrites_dscp() {
+        /// Sums the header with its checksum, which is 0 when the checksum is valid
+        fn ipv4_checksum(packet: &Packet) -> u16 {
+            let mut sum = 0u32;
+            for i in (nt::EthHdr::LEN..nt::EthHdr::LEN + nt::Ipv4Hdr::LEN).step_by(2) {
+                sum += u32::from(u16::from_be_bytes([packet[i], packet[i + 1]]));
+            }
+            while sum > 0xffff {
+                sum = (sum & 0xffff) + (sum >> 16);
+            }
+            !(sum as u16)
+        }
+
+        let ip = nt::EthHdr::LEN;
+
+        let mut data = [0u8; 2048];
+        let mut packet = ipv4_packet(&mut data, nt::IpProto::Udp, &[0xfd; 17], 0);
+        assert_eq!(ipv4_checksum(&packet), 0);
+
+        set_dscp(&mut packet, true, None);
+        assert_eq!(packet[ip + 1], 0);
+
+        for dscp in [Dscp::EF, Dscp::new(34).unwrap(), Dscp::new(0).unwrap()] {
+            set_dscp(&mut packet, true, Some(dscp));
+            assert_eq!(packet[ip + 1] >> 2, dscp.value());
+            assert_eq!(ipv4_checksum(&packet), 0, "{dscp}");
+        }
+        parse_headers(&mut packet).expect("failed to parse marked ipv4 packet");
+
+        let mut data = [0u8; 2048];
+        let mut packet = xdp::Packet::testing_new(&mut data);
+        packet.append(&[0u8; nt::EthHdr::LEN]).unwrap();
+        // version 6, traffic class 0x03 (ECN only), flow label 0xabcde
+        packet.append(&[0x60, 0x3a, 0xbc, 0xde]).unwrap();
+        set_dscp(&mut packet, false, Some(Dscp::EF));
+        assert_eq!(packet[ip] >> 4, 6);
+        assert_eq!((packet[ip] << 4) | (packet[ip + 1] >> 4), 0xbb);
+        assert_eq!(packet[ip + 1] & 0x0f, 0xa);
+        assert_eq!([packet[ip + 2], packet[ip + 3]], [0xbc, 0xde]);
+    }
 }
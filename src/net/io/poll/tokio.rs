@@ -51,10 +51,6 @@ cfg_select! {
         type PollSocket = crate::net::DualStackEpollSocket;
         type PollSocketRc = std::sync::Arc<PollSocket>;
 
-        fn new_poll_socket(port: u16) -> std::io::Result<PollSocketRc> {
-            PollSocket::new(port).map(std::sync::Arc::new)
-        }
-
         fn poll_socket_from_raw(socket: socket2::Socket) -> std::io::Result<PollSocketRc> {
             PollSocket::from_raw(socket).map(std::sync::Arc::new)
         }
@@ -80,10 +76,6 @@ cfg_select! {
         type PollSocket = crate::net::DualStackLocalSocket;
         type PollSocketRc = crate::net::DualStackLocalSocketRc;
 
-        fn new_poll_socket(port: u16) -> std::io::Result<PollSocketRc> {
-            PollSocket::new(port).map(|s| s.make_refcnt())
-        }
-
         fn poll_socket_from_raw(socket: socket2::Socket) -> std::io::Result<PollSocketRc> {
             Ok(std::sync::Arc::new(PollSocket::from_raw(socket)))
         }
@@ -135,6 +127,7 @@ fn spawn_poll_listener_impl(
     packet_queue: crate::net::PacketQueue,
     mut fc: crate::config::filter::CachedFilterChain,
 ) -> eyre::Result<()> {
+    let raw_socket = listener.bind()?;
     let crate::net::io::Listener {
         worker_id,
         port,
@@ -152,7 +145,7 @@ fn spawn_poll_listener_impl(
     let worker = uring_spawn!(thread_span, async move {
         crate::metrics::game_traffic_tasks().inc();
         let mut last_received_at = None;
-        let socket = new_poll_socket(port).unwrap();
+        let socket = poll_socket_from_raw(raw_socket).unwrap();
 
         tracing::trace!(port, "bound worker");
         let send_socket = socket.clone();
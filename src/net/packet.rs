@@ -123,6 +123,16 @@ impl<P: PacketMut> DownstreamPacket<'_, P> {
             "received packet from downstream"
         );
 
+        let _span = crate::otlp::sample_packet().then(|| {
+            tracing::info_span!(
+                "downstream_packet",
+                id = worker_id,
+                size = self.contents.len(),
+                source = %self.source,
+            )
+            .entered()
+        });
+
         let timer = metrics::processing_time(metrics::READ).start_timer();
         if let Err(error) = self.process_inner(config, sessions, destinations) {
             let discriminant = error.discriminant();
@@ -173,12 +183,16 @@ impl<P: PacketMut> DownstreamPacket<'_, P> {
             .read(&mut context)
             .map_err(PipelineError::Filter)?;
 
-        let ReadContext { contents, .. } = context;
+        let ReadContext {
+            contents, metadata, ..
+        } = context;
 
         if destinations.is_empty() {
             return Ok(());
         }
 
+        metrics::token_traffic(&metadata, contents.len());
+
         // Similar to bytes::BytesMut::freeze, we turn the mutable pool buffer
         // into an immutable one with its own internal arc so it can be cloned
         // cheaply and returned to the pool once all references are dropped
@@ -191,7 +205,7 @@ impl<P: PacketMut> DownstreamPacket<'_, P> {
         {
             let session_key = SessionKey {
                 source: self.source,
-                dest: dest.to_socket_addr()?,
+                dest: dest.to_socket_addr_for(self.source)?,
             };
 
             sessions.send(session_key, contents)?;
@@ -199,7 +213,7 @@ impl<P: PacketMut> DownstreamPacket<'_, P> {
             for epa in destinations.drain(0..) {
                 let session_key = SessionKey {
                     source: self.source,
-                    dest: epa.to_socket_addr()?,
+                    dest: epa.to_socket_addr_for(self.source)?,
                 };
 
                 sessions.send(session_key, contents.clone())?;
@@ -249,6 +263,7 @@ pub fn spawn_receivers(
     sessions: &Arc<SessionPool>,
     backend: crate::net::io::UdpBackend,
     recv_ring_len: u16,
+    socket_options: crate::net::qos::SocketOptions,
 ) -> crate::Result<()> {
     let port = crate::net::socket_port(&socket);
 
@@ -263,6 +278,7 @@ pub fn spawn_receivers(
             config: config.clone(),
             sessions: sessions.clone(),
             backend,
+            socket_options,
         };
 
         worker.spawn_io_loop(ws, pfc.clone(), recv_ring_len)?;
@@ -0,0 +1,296 @@
+/*
+ * Copyright 2026 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Quality of service marking (DSCP) and tuning of the UDP sockets used to
+//! forward traffic.
+
+use crate::net::endpoint::metadata;
+
+/// The dynamic metadata key filters can set to override the DSCP value of
+/// an individual packet, the value is either a number (`0..=63`) or one of the
+/// names accepted by [`Dscp`]'s [`FromStr`](std::str::FromStr) implementation.
+///
+/// This is currently only honoured by the XDP backend, as the other backends
+/// mark packets with the value set on the socket they are sent from.
+pub const DSCP_KEY: &str = "quilkin.dev/dscp";
+
+/// A [Differentiated Services Code Point](https://www.rfc-editor.org/rfc/rfc2474),
+/// the upper 6 bits of the IPv4 TOS and IPv6 traffic class fields.
+///
+/// The lower 2 bits of those fields are left to ECN, which is never set on
+/// the sockets and is preserved when rewriting packet headers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Dscp(u8);
+
+impl Dscp {
+    pub const MAX: u8 = 0b11_1111;
+    /// Expedited forwarding, the usual choice for latency sensitive traffic
+    pub const EF: Self = Self(46);
+
+    #[inline]
+    pub const fn new(value: u8) -> Option<Self> {
+        if value > Self::MAX {
+            None
+        } else {
+            Some(Self(value))
+        }
+    }
+
+    #[inline]
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    /// The value of the full TOS/traffic class byte, with ECN bits cleared
+    #[inline]
+    pub const fn tos(self) -> u8 {
+        self.0 << 2
+    }
+
+    /// Combines this code point with the ECN bits of an existing TOS/traffic class byte
+    #[inline]
+    pub const fn with_ecn(self, tos: u8) -> u8 {
+        self.tos() | (tos & 0b11)
+    }
+
+    /// Retrieves the per-packet override set by a filter under [`DSCP_KEY`], if any
+    pub fn from_metadata(metadata: &metadata::DynamicMetadata) -> Option<Self> {
+        match metadata.get(&metadata::Key::from_static(DSCP_KEY))? {
+            metadata::Value::Number(value) => u8::try_from(*value).ok().and_then(Self::new),
+            metadata::Value::String(name) => name.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Dscp {
+    type Err = eyre::Error;
+
+    /// Parses either a numeric value, or one of the well known names
+    /// (`default`, `ef`, `va`, `cs0`-`cs7`, `af11`-`af43`), case insensitively
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+
+        if let Ok(value) = s.parse::<u8>() {
+            return Self::new(value)
+                .ok_or_else(|| eyre::eyre!("DSCP value {value} is larger than {}", Self::MAX));
+        }
+
+        let value = match s.as_str() {
+            "default" | "be" => 0,
+            "ef" => 46,
+            "va" => 44,
+            cs if cs.len() == 3 && cs.starts_with("cs") => match cs.as_bytes()[2] {
+                class @ b'0'..=b'7' => (class - b'0') << 3,
+                _ => eyre::bail!("unknown class selector '{cs}'"),
+            },
+            af if af.len() == 4 && af.starts_with("af") => {
+                let bytes = af.as_bytes();
+                match (bytes[2], bytes[3]) {
+                    (class @ b'1'..=b'4', drop @ b'1'..=b'3') => {
+                        ((class - b'0') << 3) | ((drop - b'0') << 1)
+                    }
+                    _ => eyre::bail!("unknown assured forwarding class '{af}'"),
+                }
+            }
+            other => eyre::bail!("unknown DSCP value '{other}'"),
+        };
+
+        Ok(Self(value))
+    }
+}
+
+impl std::fmt::Display for Dscp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The DSCP values applied to each direction of traffic
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Marking {
+    /// Applied to packets sent from the proxy to the upstream endpoints
+    pub upstream: Option<Dscp>,
+    /// Applied to packets sent from the proxy back to the downstream clients
+    pub downstream: Option<Dscp>,
+}
+
+/// Options applied to a single socket when it is created
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    pub dscp: Option<Dscp>,
+    pub priority: Option<u32>,
+    pub recv_buffer_size: Option<usize>,
+    pub send_buffer_size: Option<usize>,
+}
+
+impl SocketOptions {
+    /// Applies the options to `socket`.
+    ///
+    /// The DSCP is set for both IPv4 and IPv6 as the proxy's sockets are dual
+    /// stack, failing to set the IPv4 TOS on an IPv6 only socket is not an error.
+    pub fn apply(&self, socket: &socket2::Socket) -> std::io::Result<()> {
+        if let Some(dscp) = self.dscp {
+            let tos = u32::from(dscp.tos());
+            if socket.local_addr()?.is_ipv6() {
+                #[cfg(unix)]
+                socket.set_tclass_v6(tos)?;
+                if let Err(error) = socket.set_tos_v4(tos) {
+                    tracing::debug!(%error, "unable to set IPv4 TOS on IPv6 socket");
+                }
+            } else {
+                socket.set_tos_v4(tos)?;
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(priority) = self.priority {
+            socket.set_priority(priority)?;
+        }
+
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Options for marking and tuning the UDP sockets used to forward traffic
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct QosOptions {
+    /// The DSCP value (eg. `46`, `ef`, `af41`, `cs5`) to mark packets sent to
+    /// upstream endpoints with.
+    #[clap(
+        long = "service.udp.qos.upstream-dscp",
+        env = "QUILKIN_SERVICE_UDP_QOS_UPSTREAM_DSCP"
+    )]
+    pub upstream_dscp: Option<Dscp>,
+    /// The DSCP value to mark packets sent back to downstream clients with.
+    #[clap(
+        long = "service.udp.qos.downstream-dscp",
+        env = "QUILKIN_SERVICE_UDP_QOS_DOWNSTREAM_DSCP"
+    )]
+    pub downstream_dscp: Option<Dscp>,
+    /// The `SO_PRIORITY` to set on every UDP socket, used by the Linux
+    /// traffic control layer to pick a queue. Ignored on other platforms.
+    #[clap(
+        long = "service.udp.socket.priority",
+        env = "QUILKIN_SERVICE_UDP_SOCKET_PRIORITY"
+    )]
+    pub priority: Option<u32>,
+    /// The `SO_RCVBUF` size, in bytes, of every UDP socket.
+    #[clap(
+        long = "service.udp.socket.recv-buffer-size",
+        env = "QUILKIN_SERVICE_UDP_SOCKET_RECV_BUFFER_SIZE"
+    )]
+    pub recv_buffer_size: Option<usize>,
+    /// The `SO_SNDBUF` size, in bytes, of every UDP socket.
+    #[clap(
+        long = "service.udp.socket.send-buffer-size",
+        env = "QUILKIN_SERVICE_UDP_SOCKET_SEND_BUFFER_SIZE"
+    )]
+    pub send_buffer_size: Option<usize>,
+}
+
+impl QosOptions {
+    #[inline]
+    pub fn marking(&self) -> Marking {
+        Marking {
+            upstream: self.upstream_dscp,
+            downstream: self.downstream_dscp,
+        }
+    }
+
+    /// The options for the sockets used to send traffic to upstream endpoints
+    #[inline]
+    pub fn upstream(&self) -> SocketOptions {
+        self.socket(self.upstream_dscp)
+    }
+
+    /// The options for the socket downstream clients send traffic to
+    #[inline]
+    pub fn downstream(&self) -> SocketOptions {
+        self.socket(self.downstream_dscp)
+    }
+
+    #[inline]
+    fn socket(&self, dscp: Option<Dscp>) -> SocketOptions {
+        SocketOptions {
+            dscp,
+            priority: self.priority,
+            recv_buffer_size: self.recv_buffer_size,
+            send_buffer_size: self.send_buffer_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dscp() {
+        for (input, expected) in [
+            ("0", 0),
+            ("63", 63),
+            ("default", 0),
+            ("EF", 46),
+            ("va", 44),
+            ("cs0", 0),
+            ("cs5", 40),
+            ("cs7", 56),
+            ("af11", 10),
+            ("af41", 34),
+            ("af43", 38),
+        ] {
+            assert_eq!(input.parse::<Dscp>().unwrap().value(), expected, "{input}");
+        }
+
+        for input in ["64", "cs8", "af44", "af51", "ef1", ""] {
+            assert!(input.parse::<Dscp>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn preserves_ecn() {
+        assert_eq!(Dscp::EF.tos(), 0xb8);
+        assert_eq!(Dscp::EF.with_ecn(0b01), 0xb9);
+        assert_eq!(Dscp::EF.with_ecn(0xff), 0xbb);
+    }
+
+    #[test]
+    fn metadata_override() {
+        let mut metadata = metadata::DynamicMetadata::default();
+        assert_eq!(Dscp::from_metadata(&metadata), None);
+
+        metadata.insert(metadata::Key::from_static(DSCP_KEY), 34u64.into());
+        assert_eq!(Dscp::from_metadata(&metadata), Dscp::new(34));
+
+        metadata.insert(
+            metadata::Key::from_static(DSCP_KEY),
+            metadata::Value::String("cs6".into()),
+        );
+        assert_eq!(Dscp::from_metadata(&metadata), Dscp::new(48));
+
+        metadata.insert(metadata::Key::from_static(DSCP_KEY), 64u64.into());
+        assert_eq!(Dscp::from_metadata(&metadata), None);
+    }
+}
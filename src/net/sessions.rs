@@ -18,7 +18,10 @@ use std::{
     collections::{HashMap, HashSet},
     fmt,
     net::SocketAddr,
-    sync::{Arc, atomic},
+    sync::{
+        Arc,
+        atomic::{self, AtomicI64, AtomicU64},
+    },
     time::Duration,
 };
 
@@ -39,7 +42,12 @@ use crate::{
 
 use parking_lot::RwLock;
 
+pub mod drain;
 pub(crate) mod inner_metrics;
+pub mod inspect;
+
+pub use drain::Drain;
+pub use inspect::{ActiveSessions, SessionFilter, SessionInfo, SessionPage};
 
 pub type SessionMap = crate::collections::ttl::TtlMap<SessionKey, Session>;
 
@@ -95,7 +103,10 @@ pub struct SessionPool {
     downstream_index: atomic::AtomicUsize,
     cached_filter_chain: CachedFilterChain,
     max_sessions: usize,
+    /// Whether new sessions are rejected, as the pool is draining
+    draining: atomic::AtomicBool,
     backend: crate::net::io::UdpBackend,
+    socket_options: crate::net::qos::SocketOptions,
     pub ring_buffer_len: u16,
 }
 
@@ -103,7 +114,7 @@ pub struct SessionPool {
 #[derive(Default)]
 struct SocketStorage {
     destination_to_sockets: HashMap<SocketAddr, HashSet<u16>>,
-    destination_to_sources: HashMap<(SocketAddr, u16), SocketAddr>,
+    destination_to_sources: HashMap<(SocketAddr, u16), (SocketAddr, Arc<SessionStats>)>,
     sources_to_asn_info: HashMap<SocketAddr, IpNetEntry>,
     sockets_to_destination: HashMap<u16, HashSet<SocketAddr>>,
 }
@@ -118,6 +129,7 @@ impl SessionPool {
         max_sessions: usize,
         backend: crate::net::io::UdpBackend,
         ring_buffer_len: u16,
+        socket_options: crate::net::qos::SocketOptions,
     ) -> Arc<Self> {
         const SESSION_TIMEOUT_SECONDS: Duration = Duration::from_secs(60);
         const SESSION_EXPIRY_POLL_INTERVAL: Duration = Duration::from_secs(60);
@@ -130,7 +142,9 @@ impl SessionPool {
             downstream_index: atomic::AtomicUsize::new(0),
             cached_filter_chain,
             max_sessions,
+            draining: atomic::AtomicBool::new(false),
             backend,
+            socket_options,
             ring_buffer_len,
         })
     }
@@ -139,9 +153,10 @@ impl SessionPool {
     fn create_new_session_from_new_socket(
         self: &Arc<Self>,
         key: SessionKey,
-    ) -> Result<(Option<MetricsIpNetEntry>, PacketQueueSender), super::PipelineError> {
+    ) -> Result<SessionHandle, super::PipelineError> {
         tracing::trace!(source=%key.source, dest=%key.dest, "creating new socket for session");
         let raw_socket = crate::net::raw_socket_with_reuse(0)?;
+        self.socket_options.apply(&raw_socket)?;
         let port = raw_socket
             .local_addr()?
             .as_socket()
@@ -172,16 +187,21 @@ impl SessionPool {
     ) {
         let received_at = UtcTimestamp::now();
         recv_addr.set_ip(recv_addr.ip().to_canonical());
-        let (downstream_addr, asn_info): (SocketAddr, Option<MetricsIpNetEntry>) = {
+        let (downstream_addr, stats, asn_info) = {
             let storage = self.storage.read();
-            let Some(downstream_addr) = storage.destination_to_sources.get(&(recv_addr, port))
+            let Some((downstream_addr, stats)) =
+                storage.destination_to_sources.get(&(recv_addr, port))
             else {
                 tracing::debug!(address=%recv_addr, "received traffic from a server that has no downstream");
                 return;
             };
             let asn_info = storage.sources_to_asn_info.get(downstream_addr);
 
-            (*downstream_addr, asn_info.map(MetricsIpNetEntry::from))
+            (
+                *downstream_addr,
+                stats.clone(),
+                asn_info.map(MetricsIpNetEntry::from),
+            )
         };
 
         let asn_metric_info = asn_info.as_ref().into();
@@ -193,12 +213,18 @@ impl SessionPool {
         *last_received_at = Some(received_at);
 
         let result = {
+            let _span = crate::otlp::sample_packet().then(|| {
+                tracing::info_span!("upstream_packet", source = %recv_addr, size = packet.len())
+                    .entered()
+            });
             let _timer = metrics::processing_time(metrics::WRITE).start_timer();
             Self::process_recv_packet(recv_addr, downstream_addr, asn_info, packet, filters)
         };
 
         match result {
             Ok(packet) => {
+                stats.record(metrics::WRITE, packet.data.len(), received_at);
+                metrics::endpoint_traffic(metrics::WRITE, recv_addr, packet.data.len());
                 let index = self
                     .downstream_index
                     .fetch_add(1, atomic::Ordering::Relaxed)
@@ -227,14 +253,24 @@ impl SessionPool {
     pub(crate) fn get(
         self: &Arc<Self>,
         key @ SessionKey { dest, .. }: SessionKey,
-    ) -> Result<(Option<MetricsIpNetEntry>, PacketQueueSender), super::PipelineError> {
+    ) -> Result<SessionHandle, super::PipelineError> {
         tracing::trace!(source=%key.source, dest=%key.dest, "SessionPool::get");
         // If we already have a session for the key pairing, return that session.
         if let Some(entry) = self.session_map.get(&key) {
-            return Ok((
-                entry.asn_info.as_ref().map(MetricsIpNetEntry::from),
-                entry.pending_sends.clone(),
-            ));
+            return Ok(SessionHandle {
+                asn_info: entry.asn_info.as_ref().map(MetricsIpNetEntry::from),
+                pending_sends: entry.pending_sends.clone(),
+                stats: entry.stats.clone(),
+            });
+        }
+
+        if self.draining.load(atomic::Ordering::Relaxed) {
+            tracing::debug!(
+                source = %key.source,
+                dest = %key.dest,
+                "draining, dropping packet for new session"
+            );
+            return Err(super::PipelineError::Draining);
         }
 
         if self.session_map.len() >= self.max_sessions {
@@ -300,8 +336,9 @@ impl SessionPool {
         key: SessionKey,
         pending_sends: PacketQueueSender,
         socket_port: u16,
-    ) -> Result<(Option<MetricsIpNetEntry>, PacketQueueSender), super::PipelineError> {
+    ) -> Result<SessionHandle, super::PipelineError> {
         tracing::trace!(source=%key.source, dest=%key.dest, "reusing socket for session");
+        let stats = Arc::new(SessionStats::new(UtcTimestamp::now()));
         let asn_info = {
             let mut storage = self.storage.write();
             storage
@@ -316,7 +353,7 @@ impl SessionPool {
                 .insert(key.dest);
             storage
                 .destination_to_sources
-                .insert((key.dest, socket_port), key.source);
+                .insert((key.dest, socket_port), (key.source, stats.clone()));
 
             let asn_info = crate::net::maxmind_db::MaxmindDb::lookup(key.source.ip());
 
@@ -337,11 +374,16 @@ impl SessionPool {
             socket_port,
             self.clone(),
             asn_info,
+            stats.clone(),
         );
         tracing::trace!("inserting session into map");
         self.session_map.insert(key, session);
         tracing::trace!("session inserted");
-        Ok((asn_metrics_info, pending_sends))
+        Ok(SessionHandle {
+            asn_info: asn_metrics_info,
+            pending_sends,
+            stats,
+        })
     }
 
     /// Processes a packet that is received by this session.
@@ -367,11 +409,50 @@ impl SessionPool {
         })
     }
 
+    /// Stops creating new sessions, packets for existing sessions are still
+    /// forwarded until they expire.
+    pub fn drain(&self) {
+        self.draining.store(true, atomic::Ordering::Relaxed);
+    }
+
     /// Returns a map of active sessions.
     pub fn sessions(&self) -> &SessionMap {
         &self.session_map
     }
 
+    /// Returns a snapshot of every active session that matches `filter`.
+    pub fn session_info(&self, filter: &SessionFilter) -> Vec<SessionInfo> {
+        self.session_map
+            .iter()
+            .filter(|entry| filter.matches(entry.key().source, entry.key().dest))
+            .map(|entry| entry.value().info())
+            .collect()
+    }
+
+    /// Forcibly removes every active session that matches `filter`, releasing
+    /// their sockets back to the pool. Returns the number of sessions removed.
+    pub fn terminate(&self, filter: &SessionFilter) -> usize {
+        // Collect the keys first, removing while iterating would deadlock on
+        // the map's shard locks
+        let keys: Vec<_> = self
+            .session_map
+            .iter()
+            .map(|entry| *entry.key())
+            .filter(|key| filter.matches(key.source, key.dest))
+            .collect();
+
+        keys.into_iter()
+            .filter(|key| {
+                let removed = self.session_map.remove(*key);
+                if removed {
+                    tracing::info!(source = %key.source, dest = %key.dest, "session terminated");
+                    inner_metrics::terminated_total().inc();
+                }
+                removed
+            })
+            .count()
+    }
+
     /// Sends packet data to the appropiate session based on its `key`.
     #[inline]
     pub fn send(
@@ -390,14 +471,20 @@ impl SessionPool {
         key: SessionKey,
         packet: bytes::Bytes,
     ) -> Result<PacketQueueSender, super::PipelineError> {
-        let (asn_info, sender) = self.get(key)?;
+        let SessionHandle {
+            asn_info,
+            pending_sends,
+            stats,
+        } = self.get(key)?;
 
-        sender.push(SendPacket {
+        stats.record(metrics::READ, packet.len(), UtcTimestamp::now());
+        metrics::endpoint_traffic(metrics::READ, key.dest, packet.len());
+        pending_sends.push(SendPacket {
             destination: key.dest,
             data: packet,
             asn_info,
         });
-        Ok(sender)
+        Ok(pending_sends)
     }
 
     /// Spawns a session I/O loop for the given socket, dispatching to the
@@ -501,6 +588,72 @@ impl Drop for SessionPool {
     }
 }
 
+/// The state of a session needed to forward a packet to its destination.
+pub(crate) struct SessionHandle {
+    asn_info: Option<MetricsIpNetEntry>,
+    pending_sends: PacketQueueSender,
+    stats: Arc<SessionStats>,
+}
+
+/// Traffic counters for a single session, updated from the packet path and
+/// read by the admin server.
+pub struct SessionStats {
+    created_at: i64,
+    last_activity: AtomicI64,
+    upstream_packets: AtomicU64,
+    upstream_bytes: AtomicU64,
+    downstream_packets: AtomicU64,
+    downstream_bytes: AtomicU64,
+}
+
+impl SessionStats {
+    pub(crate) fn new(created_at: UtcTimestamp) -> Self {
+        Self {
+            created_at: created_at.unix_nanos(),
+            last_activity: AtomicI64::new(created_at.unix_nanos()),
+            upstream_packets: AtomicU64::new(0),
+            upstream_bytes: AtomicU64::new(0),
+            downstream_packets: AtomicU64::new(0),
+            downstream_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a packet of `length` bytes forwarded in `direction`, with
+    /// [`metrics::READ`] being client to server.
+    #[inline]
+    pub(crate) fn record(&self, direction: metrics::Direction, length: usize, at: UtcTimestamp) {
+        let (packets, bytes) = match direction {
+            metrics::Direction::Read => (&self.upstream_packets, &self.upstream_bytes),
+            metrics::Direction::Write => (&self.downstream_packets, &self.downstream_bytes),
+        };
+
+        packets.fetch_add(1, atomic::Ordering::Relaxed);
+        bytes.fetch_add(length as u64, atomic::Ordering::Relaxed);
+        self.last_activity
+            .fetch_max(at.unix_nanos(), atomic::Ordering::Relaxed);
+    }
+
+    /// Creates a snapshot of the counters for the specified session.
+    pub(crate) fn info(
+        &self,
+        source: SocketAddr,
+        destination: SocketAddr,
+        asn: Option<&IpNetEntry>,
+    ) -> SessionInfo {
+        SessionInfo {
+            source,
+            destination,
+            created_at: self.created_at / 1_000_000_000,
+            last_activity: self.last_activity.load(atomic::Ordering::Relaxed) / 1_000_000_000,
+            upstream_packets: self.upstream_packets.load(atomic::Ordering::Relaxed),
+            upstream_bytes: self.upstream_bytes.load(atomic::Ordering::Relaxed),
+            downstream_packets: self.downstream_packets.load(atomic::Ordering::Relaxed),
+            downstream_bytes: self.downstream_bytes.load(atomic::Ordering::Relaxed),
+            asn: asn.map(inspect::SessionAsn::from),
+        }
+    }
+}
+
 /// Session encapsulates a UDP stream session
 pub struct Session {
     /// The time at which the session was created
@@ -513,6 +666,8 @@ pub struct Session {
     pending_sends: PacketQueueSender,
     /// The `GeoIP` information of the source.
     asn_info: Option<IpNetEntry>,
+    /// The traffic counters of the session.
+    stats: Arc<SessionStats>,
     /// The socket pool of the session.
     pool: Arc<SessionPool>,
 }
@@ -524,6 +679,7 @@ impl Session {
         socket_port: u16,
         pool: Arc<SessionPool>,
         asn_info: Option<IpNetEntry>,
+        stats: Arc<SessionStats>,
     ) -> Self {
         let s = Self {
             key,
@@ -531,6 +687,7 @@ impl Session {
             pool,
             socket_port,
             asn_info,
+            stats,
             created_at: Instant::now(),
         };
 
@@ -552,6 +709,12 @@ impl Session {
         s
     }
 
+    /// Creates a snapshot of the current state of the session.
+    pub fn info(&self) -> SessionInfo {
+        self.stats
+            .info(self.key.source, self.key.dest, self.asn_info.as_ref())
+    }
+
     fn active_session_metric(&self) -> prometheus::IntGauge {
         inner_metrics::active_sessions(self.asn_info.as_ref())
     }
@@ -623,6 +786,7 @@ mod tests {
                 usize::MAX,
                 backend,
                 64,
+                Default::default(),
             ),
             pending_sends,
         )
@@ -778,6 +942,7 @@ mod tests {
                 limit,
                 backend,
                 64,
+                Default::default(),
             ),
             pending_sends,
         )
@@ -836,6 +1001,65 @@ mod tests {
         assert!(pool.get(key(8081, 9000)).is_ok());
     }
 
+    #[tokio::test]
+    async fn draining_rejects_new_sessions() {
+        let (pool, _receiver) = pool_with_limit(usize::MAX);
+
+        assert!(pool.get(key(8080, 9000)).is_ok());
+        pool.drain();
+
+        // Existing sessions are still forwarded
+        assert!(pool.get(key(8080, 9000)).is_ok());
+        assert!(matches!(
+            pool.get(key(8081, 9000)),
+            Err(super::super::PipelineError::Draining)
+        ));
+        assert!(matches!(
+            pool.get(key(8080, 9001)),
+            Err(super::super::PipelineError::Draining)
+        ));
+    }
+
+    #[tokio::test]
+    async fn terminate_matching_sessions() {
+        let (pool, _receiver) = new_pool().await;
+
+        pool.get(key(8080, 9000)).unwrap();
+        pool.get(key(8081, 9000)).unwrap();
+        pool.get(key(8082, 9001)).unwrap();
+
+        let filter = SessionFilter {
+            destination: Some((std::net::Ipv4Addr::UNSPECIFIED, 9000).into()),
+            ..Default::default()
+        };
+        assert_eq!(pool.session_info(&filter).len(), 2);
+        assert_eq!(pool.terminate(&filter), 2);
+        assert!(pool.session_info(&filter).is_empty());
+
+        let remaining = pool.session_info(&SessionFilter::default());
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].source, key(8082, 9001).source);
+    }
+
+    #[tokio::test]
+    async fn session_stats() {
+        let (pool, _receiver) = new_pool().await;
+        let key = key(8080, 9000);
+
+        pool.send_inner(key, bytes::Bytes::from_static(b"hello"))
+            .unwrap();
+        pool.send_inner(key, bytes::Bytes::from_static(b"world!"))
+            .unwrap();
+
+        let info = pool.session_map.get(&key).unwrap().info();
+        assert_eq!(info.source, key.source);
+        assert_eq!(info.destination, key.dest);
+        assert_eq!(info.upstream_packets, 2);
+        assert_eq!(info.upstream_bytes, 11);
+        assert_eq!(info.downstream_packets, 0);
+        assert!(info.last_activity >= info.created_at);
+    }
+
     #[tokio::test]
     #[cfg_attr(target_os = "macos", ignore)]
     async fn send_and_recv() {
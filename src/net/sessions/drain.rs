@@ -0,0 +1,106 @@
+/*
+ * Copyright 2026 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Draining of the UDP service before it shuts down.
+//!
+//! While draining, the proxy reports itself as not ready and doesn't create
+//! any new sessions, while continuing to forward packets for the existing
+//! ones until they expire or the termination timeout is reached, at which
+//! point the proxy shuts down.
+
+use std::{sync::Arc, time::Instant};
+
+/// Whether the UDP service is draining, shared between the service and the
+/// admin server, either of which can start it.
+#[derive(Clone)]
+pub struct Drain(Arc<tokio::sync::watch::Sender<Option<Instant>>>);
+
+impl typemap_rev::TypeMapKey for Drain {
+    type Value = Drain;
+}
+
+impl Default for Drain {
+    fn default() -> Self {
+        Self(Arc::new(tokio::sync::watch::Sender::new(None)))
+    }
+}
+
+impl std::fmt::Debug for Drain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Drain")
+            .field("started_at", &*self.0.borrow())
+            .finish()
+    }
+}
+
+impl Drain {
+    /// Starts draining, returning `false` if it had already been started.
+    pub fn start(&self) -> bool {
+        self.0.send_if_modified(|started_at| {
+            if started_at.is_some() {
+                return false;
+            }
+
+            *started_at = Some(Instant::now());
+            true
+        })
+    }
+
+    /// Returns whether draining has been started.
+    #[inline]
+    pub fn is_draining(&self) -> bool {
+        self.0.borrow().is_some()
+    }
+
+    /// Returns when draining was started, if it has been.
+    #[inline]
+    pub fn started_at(&self) -> Option<Instant> {
+        *self.0.borrow()
+    }
+
+    /// Waits until draining has been started.
+    pub async fn started(&self) {
+        let mut rx = self.0.subscribe();
+        drop(rx.wait_for(Option::is_some).await);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn start_once() {
+        let drain = Drain::default();
+        assert!(!drain.is_draining());
+
+        let started = tokio::spawn({
+            let drain = drain.clone();
+            async move { drain.started().await }
+        });
+
+        assert!(drain.start());
+        let started_at = drain.started_at().unwrap();
+        assert!(!drain.start());
+        assert_eq!(drain.started_at(), Some(started_at));
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), started)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(drain.is_draining());
+    }
+}
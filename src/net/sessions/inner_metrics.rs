@@ -78,6 +78,60 @@ pub(crate) fn sessions_rejected_total() -> &'static IntCounter {
     &SESSIONS_REJECTED_TOTAL
 }
 
+pub(crate) fn terminated_total() -> &'static IntCounter {
+    static TERMINATED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+        register(
+            IntCounter::with_opts(
+                Opts::new(
+                    "terminated_total",
+                    "total number of sessions forcibly terminated through the admin server",
+                )
+                .subsystem(SUBSYSTEM)
+                .namespace("quilkin"),
+            )
+            .unwrap(),
+        )
+    });
+
+    &TERMINATED_TOTAL
+}
+
+pub(crate) fn draining() -> &'static IntGauge {
+    static DRAINING: Lazy<IntGauge> = Lazy::new(|| {
+        register(
+            IntGauge::with_opts(
+                Opts::new(
+                    "draining",
+                    "whether new sessions are rejected while the proxy drains before shutting down",
+                )
+                .subsystem(SUBSYSTEM)
+                .namespace("quilkin"),
+            )
+            .unwrap(),
+        )
+    });
+
+    &DRAINING
+}
+
+pub(crate) fn drain_remaining() -> &'static IntGauge {
+    static DRAIN_REMAINING: Lazy<IntGauge> = Lazy::new(|| {
+        register(
+            IntGauge::with_opts(
+                Opts::new(
+                    "drain_remaining",
+                    "number of sessions the proxy is waiting on to expire while draining",
+                )
+                .subsystem(SUBSYSTEM)
+                .namespace("quilkin"),
+            )
+            .unwrap(),
+        )
+    });
+
+    &DRAIN_REMAINING
+}
+
 pub(crate) fn duration_secs() -> &'static Histogram {
     static DURATION_SECS: Lazy<Histogram> = Lazy::new(|| {
         register(
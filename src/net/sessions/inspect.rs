@@ -0,0 +1,233 @@
+/*
+ * Copyright 2026 Google LLC All Rights Reserved.
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+//! Read and terminate access to the active sessions of whichever UDP backend
+//! is running, used by the admin server.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Weak},
+};
+
+use super::SessionPool;
+
+/// The default number of sessions returned in a single [`SessionPage`].
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+/// The maximum number of sessions that can be returned in a single [`SessionPage`].
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+/// A snapshot of a single session.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct SessionInfo {
+    /// The address of the client.
+    pub source: SocketAddr,
+    /// The address of the upstream endpoint (game server).
+    pub destination: SocketAddr,
+    /// When the session was created, in seconds since the unix epoch.
+    pub created_at: i64,
+    /// When a packet was last forwarded in either direction, in seconds since
+    /// the unix epoch.
+    pub last_activity: i64,
+    /// The number of packets forwarded from the client to the server.
+    pub upstream_packets: u64,
+    /// The number of bytes forwarded from the client to the server.
+    pub upstream_bytes: u64,
+    /// The number of packets forwarded from the server to the client.
+    pub downstream_packets: u64,
+    /// The number of bytes forwarded from the server to the client.
+    pub downstream_bytes: u64,
+    /// The `GeoIP` information of the client, if available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<SessionAsn>,
+}
+
+/// The autonomous system information of a session's client.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct SessionAsn {
+    pub number: u64,
+    pub organization: String,
+    pub country_code: String,
+    pub prefix: String,
+}
+
+impl From<&crate::net::maxmind_db::IpNetEntry> for SessionAsn {
+    fn from(entry: &crate::net::maxmind_db::IpNetEntry) -> Self {
+        Self {
+            number: entry.id,
+            organization: entry.as_name.clone(),
+            country_code: entry.as_cc.clone(),
+            prefix: entry.prefix.clone(),
+        }
+    }
+}
+
+/// Selects a subset of sessions, a session matches if it matches every field
+/// that is set.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize)]
+pub struct SessionFilter {
+    /// The IP address of the client, any port.
+    pub source: Option<IpAddr>,
+    /// The address of the upstream endpoint.
+    pub destination: Option<SocketAddr>,
+    /// The autonomous system number of the client.
+    pub asn: Option<u64>,
+}
+
+impl SessionFilter {
+    /// Returns whether no fields are set, ie. the filter matches every session.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.source.is_none() && self.destination.is_none() && self.asn.is_none()
+    }
+
+    /// Returns whether the session between `source` and `destination` matches
+    /// the address portion of this filter.
+    #[inline]
+    pub fn matches(&self, source: SocketAddr, destination: SocketAddr) -> bool {
+        self.source
+            .is_none_or(|ip| ip.to_canonical() == source.ip().to_canonical())
+            && self.destination.is_none_or(|dest| {
+                dest.ip().to_canonical() == destination.ip().to_canonical()
+                    && dest.port() == destination.port()
+            })
+    }
+
+    /// Returns whether `info` matches every field of this filter.
+    #[inline]
+    pub fn matches_info(&self, info: &SessionInfo) -> bool {
+        self.matches(info.source, info.destination)
+            && self
+                .asn
+                .is_none_or(|asn| info.asn.as_ref().is_some_and(|a| a.number == asn))
+    }
+}
+
+/// A single page of sessions, ordered by source and then destination address.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct SessionPage {
+    /// The total number of sessions that matched the filter.
+    pub total: usize,
+    /// The offset of the first session in this page.
+    pub offset: usize,
+    pub sessions: Vec<SessionInfo>,
+}
+
+#[derive(Clone)]
+enum Backend {
+    Pool(Weak<SessionPool>),
+    #[cfg(target_os = "linux")]
+    Xdp(Arc<crate::net::io::nic::xdp::process::SessionState>),
+}
+
+/// A handle to the sessions of the running UDP service, which is registered
+/// by the service once it has chosen its I/O backend.
+#[derive(Clone, Default)]
+pub struct ActiveSessions(Arc<parking_lot::RwLock<Option<Backend>>>);
+
+impl typemap_rev::TypeMapKey for ActiveSessions {
+    type Value = ActiveSessions;
+}
+
+impl std::fmt::Debug for ActiveSessions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ActiveSessions")
+            .field("registered", &self.0.read().is_some())
+            .finish()
+    }
+}
+
+impl ActiveSessions {
+    /// Registers the session pool used by the user space backends.
+    pub fn register_pool(&self, pool: &Arc<SessionPool>) {
+        *self.0.write() = Some(Backend::Pool(Arc::downgrade(pool)));
+    }
+
+    /// Registers the session state used by the XDP backend.
+    #[cfg(target_os = "linux")]
+    pub fn register_xdp(&self, state: Arc<crate::net::io::nic::xdp::process::SessionState>) {
+        *self.0.write() = Some(Backend::Xdp(state));
+    }
+
+    /// Returns the page of sessions matching `filter`, starting at `offset`
+    /// and containing at most `limit` sessions, capped to [`MAX_PAGE_LIMIT`].
+    pub fn list(&self, filter: &SessionFilter, offset: usize, limit: usize) -> SessionPage {
+        let mut sessions = self.matching(filter);
+        sessions.sort_unstable_by_key(|info| (info.source, info.destination));
+
+        let total = sessions.len();
+        let sessions = sessions
+            .into_iter()
+            .skip(offset)
+            .take(limit.min(MAX_PAGE_LIMIT))
+            .collect();
+
+        SessionPage {
+            total,
+            offset,
+            sessions,
+        }
+    }
+
+    /// Returns every active session, in no particular order.
+    pub fn all(&self) -> Vec<SessionInfo> {
+        self.matching(&SessionFilter::default())
+    }
+
+    fn matching(&self, filter: &SessionFilter) -> Vec<SessionInfo> {
+        let mut sessions = match self.0.read().clone() {
+            Some(Backend::Pool(pool)) => pool
+                .upgrade()
+                .map(|pool| pool.session_info(filter))
+                .unwrap_or_default(),
+            #[cfg(target_os = "linux")]
+            Some(Backend::Xdp(state)) => state.session_info(filter),
+            None => Vec::new(),
+        };
+
+        sessions.retain(|info| filter.matches_info(info));
+        sessions
+    }
+
+    /// Forcibly removes every session matching `filter`, returning the number
+    /// of sessions that were removed.
+    ///
+    /// Note that a client that keeps sending traffic will establish a new
+    /// session with its next packet.
+    pub fn terminate(&self, filter: &SessionFilter) -> usize {
+        // The ASN is only known once the session info is built, so resolve
+        // the matching sessions to exact addresses first
+        if filter.asn.is_some() {
+            return self
+                .matching(filter)
+                .into_iter()
+                .map(|info| {
+                    self.terminate(&SessionFilter {
+                        source: Some(info.source.ip()),
+                        destination: Some(info.destination),
+                        asn: None,
+                    })
+                })
+                .sum();
+        }
+
+        match self.0.read().clone() {
+            Some(Backend::Pool(pool)) => pool.upgrade().map_or(0, |pool| pool.terminate(filter)),
+            #[cfg(target_os = "linux")]
+            Some(Backend::Xdp(state)) => state.terminate(filter),
+            None => 0,
+        }
+    }
+}
@@ -0,0 +1,410 @@
+/*
+ * Copyright 2025 Google LLC All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Export of metrics and traces to an OpenTelemetry collector with OTLP/gRPC
+//!
+//! Metrics are gathered from the [`registry`](crate::metrics::registry) at a
+//! set interval, and pushed as cumulative OTLP metrics, with the static
+//! information of `quilkin_info` sent as resource attributes instead.
+//!
+//! Spans are exported by a `tracing` layer, so any span that passes the log
+//! filter, eg. provider updates, xDS streams, and corrosion syncs, is
+//! exported. As creating a span for every packet would be prohibitively
+//! expensive, packet processing spans are only created for one in every N
+//! packets.
+
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+use opentelemetry_proto::tonic::{
+    collector::metrics::v1::{
+        ExportMetricsServiceRequest, metrics_service_client::MetricsServiceClient,
+    },
+    common::v1::{AnyValue, InstrumentationScope, KeyValue, any_value},
+    metrics::v1 as otlp_metrics,
+    resource::v1::Resource,
+};
+
+const SCOPE: &str = "quilkin";
+
+#[derive(Clone, Debug, clap::Parser)]
+#[command(next_help_heading = "Telemetry Options")]
+pub struct OtlpCli {
+    /// The OTLP/gRPC endpoint of an OpenTelemetry collector to export
+    /// metrics and traces to
+    #[clap(long = "otlp.endpoint", env = "QUILKIN_OTLP_ENDPOINT")]
+    pub endpoint: Option<tonic::transport::Endpoint>,
+    /// How often metrics are exported
+    #[clap(
+        long = "otlp.metrics.interval",
+        env = "QUILKIN_OTLP_METRICS_INTERVAL",
+        default_value = "60s"
+    )]
+    pub metrics_interval: crate::cli::Duration,
+    /// The ratio of traces that are exported, from `0.0` to `1.0`
+    #[clap(
+        long = "otlp.traces.sample-ratio",
+        env = "QUILKIN_OTLP_TRACES_SAMPLE_RATIO",
+        default_value_t = 1.0
+    )]
+    pub traces_sample_ratio: f64,
+    /// Creates a span for one in every N packets processed, `0` disables
+    /// packet spans
+    #[clap(
+        long = "otlp.traces.packet-sample-rate",
+        env = "QUILKIN_OTLP_TRACES_PACKET_SAMPLE_RATE",
+        default_value_t = 0
+    )]
+    pub packet_sample_rate: u64,
+}
+
+/// Flushes any remaining spans, and stops exporting them when dropped
+pub struct TracerGuard(opentelemetry_sdk::trace::SdkTracerProvider);
+
+impl Drop for TracerGuard {
+    fn drop(&mut self) {
+        if let Err(error) = self.0.shutdown() {
+            tracing::warn!(%error, "failed to shutdown OTLP span exporter");
+        }
+    }
+}
+
+impl OtlpCli {
+    /// Creates the tracer spans are exported with, if an endpoint is set
+    ///
+    /// Must be called within a tokio runtime.
+    pub fn tracer(&self) -> crate::Result<Option<(opentelemetry_sdk::trace::Tracer, TracerGuard)>> {
+        use opentelemetry::trace::TracerProvider as _;
+        use opentelemetry_otlp::WithExportConfig as _;
+        use opentelemetry_sdk::trace::Sampler;
+
+        let Some(endpoint) = &self.endpoint else {
+            return Ok(None);
+        };
+
+        PACKET_SAMPLE_RATE.store(self.packet_sample_rate, Relaxed);
+
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint.uri().to_string())
+            .build()?;
+
+        let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                self.traces_sample_ratio,
+            ))))
+            .with_resource(
+                opentelemetry_sdk::Resource::builder()
+                    .with_service_name(SCOPE)
+                    .with_attribute(opentelemetry::KeyValue::new(
+                        "service.version",
+                        clap::crate_version!(),
+                    ))
+                    .build(),
+            )
+            .build();
+
+        Ok(Some((provider.tracer(SCOPE), TracerGuard(provider))))
+    }
+
+    /// Spawns a task to export the metrics at the set interval, if an
+    /// endpoint is set
+    pub fn spawn_metrics_exporter(&self, id: String, mut shutdown: crate::signal::ShutdownRx) {
+        let Some(endpoint) = self.endpoint.clone() else {
+            return;
+        };
+
+        let exporter = MetricsExporter::new(endpoint, id);
+        let mut interval = tokio::time::interval(*self.metrics_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        tokio::spawn(async move {
+            let mut exporter = exporter;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if let Err(error) = exporter.export().await {
+                            tracing::warn!(%error, "failed to export metrics");
+                        }
+                    }
+                    _ = shutdown.changed() => {
+                        // Flush the final values of the metrics before exiting
+                        if let Err(error) = exporter.export().await {
+                            tracing::warn!(%error, "failed to export metrics");
+                        }
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+static PACKET_SAMPLE_RATE: AtomicU64 = AtomicU64::new(0);
+static PACKET_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Checks if a span should be created for the packet being processed
+#[inline]
+pub(crate) fn sample_packet() -> bool {
+    let rate = PACKET_SAMPLE_RATE.load(Relaxed);
+    rate != 0 && PACKET_COUNT.fetch_add(1, Relaxed).is_multiple_of(rate)
+}
+
+/// Pushes the metrics in the [`registry`](crate::metrics::registry) to an
+/// OTLP collector
+pub struct MetricsExporter {
+    client: MetricsServiceClient<tonic::transport::Channel>,
+    resource: Resource,
+    start_time: u64,
+}
+
+impl MetricsExporter {
+    pub fn new(endpoint: tonic::transport::Endpoint, id: String) -> Self {
+        let attributes = [
+            ("service.name", SCOPE.to_owned()),
+            ("service.instance.id", id),
+            ("service.version", clap::crate_version!().to_owned()),
+            (
+                "vcs.ref.head.revision",
+                crate::net::endpoint::metadata::build::GIT_COMMIT_HASH
+                    .unwrap_or("none")
+                    .to_owned(),
+            ),
+        ];
+
+        Self {
+            client: MetricsServiceClient::new(endpoint.connect_lazy()),
+            resource: Resource {
+                attributes: attributes
+                    .into_iter()
+                    .map(|(key, value)| string_attribute(key, value))
+                    .collect(),
+                ..Default::default()
+            },
+            start_time: unix_nanos(),
+        }
+    }
+
+    /// Exports the current value of every metric
+    pub async fn export(&mut self) -> crate::Result<()> {
+        let request = ExportMetricsServiceRequest {
+            resource_metrics: vec![otlp_metrics::ResourceMetrics {
+                resource: Some(self.resource.clone()),
+                scope_metrics: vec![otlp_metrics::ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: SCOPE.into(),
+                        version: clap::crate_version!().into(),
+                        ..Default::default()
+                    }),
+                    metrics: convert_families(
+                        &crate::metrics::registry().gather(),
+                        self.start_time,
+                        unix_nanos(),
+                    ),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+
+        let response = self.client.export(request).await?.into_inner();
+        if let Some(partial) = response.partial_success
+            && partial.rejected_data_points > 0
+        {
+            tracing::warn!(
+                rejected = partial.rejected_data_points,
+                message = partial.error_message,
+                "collector rejected metric data points"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn unix_nanos() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+fn string_attribute(key: &str, value: String) -> KeyValue {
+    KeyValue {
+        key: key.into(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value)),
+        }),
+        ..Default::default()
+    }
+}
+
+/// Converts the gathered Prometheus metric families to their OTLP equivalents
+///
+/// Prometheus histogram buckets are cumulative, whereas OTLP buckets only
+/// contain the count of the observations that fall within each bucket, with
+/// an implicit final bucket for the observations above the last bound.
+fn convert_families(
+    families: &[prometheus::proto::MetricFamily],
+    start_time: u64,
+    time: u64,
+) -> Vec<otlp_metrics::Metric> {
+    use otlp_metrics::{AggregationTemporality, metric::Data, number_data_point::Value};
+    use prometheus::proto::MetricType;
+
+    let number = |attributes: Vec<KeyValue>, value: f64| otlp_metrics::NumberDataPoint {
+        attributes,
+        start_time_unix_nano: start_time,
+        time_unix_nano: time,
+        value: Some(Value::AsDouble(value)),
+        ..Default::default()
+    };
+
+    families
+        .iter()
+        .map(|family| {
+            let metrics = family.get_metric().iter().map(|metric| {
+                let attributes = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| string_attribute(label.name(), label.value().to_owned()))
+                    .collect::<Vec<_>>();
+                (metric, attributes)
+            });
+
+            let data = match family.get_field_type() {
+                MetricType::COUNTER => Data::Sum(otlp_metrics::Sum {
+                    data_points: metrics
+                        .map(|(metric, attributes)| {
+                            number(attributes, metric.get_counter().value())
+                        })
+                        .collect(),
+                    aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                    is_monotonic: true,
+                }),
+                MetricType::GAUGE => Data::Gauge(otlp_metrics::Gauge {
+                    data_points: metrics
+                        .map(|(metric, attributes)| number(attributes, metric.get_gauge().value()))
+                        .collect(),
+                }),
+                MetricType::UNTYPED => Data::Gauge(otlp_metrics::Gauge {
+                    data_points: metrics
+                        .map(|(metric, attributes)| {
+                            number(attributes, metric.get_untyped().value())
+                        })
+                        .collect(),
+                }),
+                MetricType::HISTOGRAM => Data::Histogram(otlp_metrics::Histogram {
+                    data_points: metrics
+                        .map(|(metric, attributes)| {
+                            let histogram = metric.get_histogram();
+                            let count = histogram.get_sample_count();
+                            let buckets = histogram
+                                .get_bucket()
+                                .iter()
+                                .filter(|bucket| bucket.upper_bound().is_finite());
+
+                            let mut explicit_bounds = Vec::new();
+                            let mut bucket_counts = Vec::new();
+                            let mut previous = 0;
+                            for bucket in buckets {
+                                explicit_bounds.push(bucket.upper_bound());
+                                bucket_counts
+                                    .push(bucket.cumulative_count().saturating_sub(previous));
+                                previous = bucket.cumulative_count();
+                            }
+                            bucket_counts.push(count.saturating_sub(previous));
+
+                            otlp_metrics::HistogramDataPoint {
+                                attributes,
+                                start_time_unix_nano: start_time,
+                                time_unix_nano: time,
+                                count,
+                                sum: Some(histogram.get_sample_sum()),
+                                bucket_counts,
+                                explicit_bounds,
+                                ..Default::default()
+                            }
+                        })
+                        .collect(),
+                    aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                }),
+                MetricType::SUMMARY => Data::Summary(otlp_metrics::Summary {
+                    data_points: metrics
+                        .map(|(metric, attributes)| {
+                            let summary = metric.get_summary();
+                            otlp_metrics::SummaryDataPoint {
+                                attributes,
+                                start_time_unix_nano: start_time,
+                                time_unix_nano: time,
+                                count: summary.get_sample_count(),
+                                sum: summary.get_sample_sum(),
+                                quantile_values: summary
+                                    .get_quantile()
+                                    .iter()
+                                    .map(|quantile| {
+                                        otlp_metrics::summary_data_point::ValueAtQuantile {
+                                            quantile: quantile.quantile(),
+                                            value: quantile.value(),
+                                        }
+                                    })
+                                    .collect(),
+                                ..Default::default()
+                            }
+                        })
+                        .collect(),
+                }),
+            };
+
+            otlp_metrics::Metric {
+                name: family.name().into(),
+                description: family.help().into(),
+                data: Some(data),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_histogram_buckets() {
+        let registry = prometheus::Registry::new();
+        let histogram = prometheus::Histogram::with_opts(
+            prometheus::HistogramOpts::new("test_histogram", "test").buckets(vec![1.0, 2.0]),
+        )
+        .unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+
+        histogram.observe(0.5);
+        histogram.observe(1.5);
+        histogram.observe(1.5);
+        histogram.observe(3.0);
+
+        let metrics = convert_families(&registry.gather(), 1, 2);
+        let Some(otlp_metrics::metric::Data::Histogram(otlp)) = &metrics[0].data else {
+            panic!("expected a histogram, got {:?}", metrics[0].data);
+        };
+
+        let point = &otlp.data_points[0];
+        assert_eq!(point.count, 4);
+        assert_eq!(point.explicit_bounds, [1.0, 2.0]);
+        assert_eq!(point.bucket_counts, [1, 2, 1]);
+        assert_eq!(point.sum, Some(6.5));
+    }
+}
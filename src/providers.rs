@@ -184,6 +184,69 @@ pub struct Providers {
         hide = true
     )]
     xds_endpoints: Vec<tonic::transport::Endpoint>,
+    /// Also subscribes to Envoy `ClusterLoadAssignment` (EDS) resources from
+    /// the xDS endpoints, adding their endpoints to the clusters
+    #[clap(
+        long = "provider.xds.envoy-eds",
+        env = "QUILKIN_PROVIDERS_XDS_ENVOY_EDS",
+        requires("xds_endpoints"),
+        default_value_t = false
+    )]
+    xds_envoy_eds: bool,
+    /// The names of the Envoy clusters to subscribe to the
+    /// `ClusterLoadAssignment` resources of, defaults to every cluster
+    #[clap(
+        long = "provider.xds.envoy-eds.clusters",
+        env = "QUILKIN_PROVIDERS_XDS_ENVOY_EDS_CLUSTERS",
+        value_delimiter = ',',
+        requires("xds_envoy_eds")
+    )]
+    xds_envoy_eds_clusters: Vec<String>,
+    /// The localities to subscribe to the endpoints of, eg. `eu` for every
+    /// locality in the `eu` region, or `eu:north` for a single zone, defaults
+    /// to every locality. An empty name subscribes to the endpoints without a
+    /// locality.
+    #[clap(
+        long = "provider.xds.localities",
+        env = "QUILKIN_PROVIDERS_XDS_LOCALITIES",
+        value_delimiter = ',',
+        requires("xds_endpoints")
+    )]
+    xds_localities: Vec<String>,
+    /// Path to a PEM encoded CA bundle used to verify the certificates of the
+    /// xDS and relay servers
+    #[clap(
+        long = "provider.grpc.tls.ca-path",
+        env = "QUILKIN_PROVIDERS_GRPC_TLS_CA_PATH",
+        hide = true
+    )]
+    grpc_tls_ca_path: Option<std::path::PathBuf>,
+    /// Path to a PEM encoded certificate presented to xDS and relay servers
+    /// that require client authentication
+    #[clap(
+        long = "provider.grpc.tls.cert-path",
+        env = "QUILKIN_PROVIDERS_GRPC_TLS_CERT_PATH",
+        requires_all(["grpc_tls_key_path", "grpc_tls_ca_path"]),
+        hide = true
+    )]
+    grpc_tls_cert_path: Option<std::path::PathBuf>,
+    /// Path to the private key for the cert
+    #[clap(
+        long = "provider.grpc.tls.key-path",
+        env = "QUILKIN_PROVIDERS_GRPC_TLS_KEY_PATH",
+        requires("grpc_tls_cert_path"),
+        hide = true
+    )]
+    grpc_tls_key_path: Option<std::path::PathBuf>,
+    /// The name the xDS and relay server certificates are verified against, if
+    /// not specified, the host of each endpoint is used
+    #[clap(
+        long = "provider.grpc.tls.domain-name",
+        env = "QUILKIN_PROVIDERS_GRPC_TLS_DOMAIN_NAME",
+        requires("grpc_tls_ca_path"),
+        hide = true
+    )]
+    grpc_tls_domain_name: Option<String>,
     /// One or more `quilkin relay` endpoints to push or pull configuration changes to/from
     #[clap(
         long = "provider.corrosion.endpoints",
@@ -197,6 +260,36 @@ pub struct Providers {
         env = "QUILKIN_PROVIDERS_CORROSION_MODE"
     )]
     corrosion_mode: Option<corrosion::CorrosionMode>,
+    /// Path to a PEM encoded certificate used to authenticate with the corrosion
+    /// servers, enables mutual TLS
+    #[clap(
+        long = "provider.corrosion.tls.cert-path",
+        env = "QUILKIN_PROVIDERS_CORROSION_TLS_CERT_PATH",
+        requires_all(["corrosion_tls_key_path", "corrosion_tls_ca_path"])
+    )]
+    corrosion_tls_cert_path: Option<std::path::PathBuf>,
+    /// Path to the private key for the corrosion certificate
+    #[clap(
+        long = "provider.corrosion.tls.key-path",
+        env = "QUILKIN_PROVIDERS_CORROSION_TLS_KEY_PATH",
+        requires("corrosion_tls_cert_path")
+    )]
+    corrosion_tls_key_path: Option<std::path::PathBuf>,
+    /// Path to a PEM encoded CA bundle used to verify the corrosion servers
+    #[clap(
+        long = "provider.corrosion.tls.ca-path",
+        env = "QUILKIN_PROVIDERS_CORROSION_TLS_CA_PATH",
+        requires("corrosion_tls_cert_path")
+    )]
+    corrosion_tls_ca_path: Option<std::path::PathBuf>,
+    /// The name the corrosion servers' certificates are verified against, if not
+    /// specified, the IP address of the server is used
+    #[clap(
+        long = "provider.corrosion.tls.server-name",
+        env = "QUILKIN_PROVIDERS_CORROSION_TLS_SERVER_NAME",
+        requires("corrosion_tls_cert_path")
+    )]
+    corrosion_tls_server_name: Option<String>,
     /// Enable the HTTP provider, which exposes a REST API for managing endpoints
     /// and the filter chain.
     #[arg(
@@ -218,6 +311,8 @@ pub struct Providers {
 pub struct FiltersAndClusters {
     pub filters: crate::config::filter::FilterChainConfig,
     pub clusters: config::Watch<crate::net::ClusterMap>,
+    /// Records the changes providers make to the filter chain
+    pub history: Option<crate::config::history::ConfigHistory>,
 }
 
 impl FiltersAndClusters {
@@ -225,6 +320,7 @@ impl FiltersAndClusters {
         Some(Self {
             filters: config.dyn_cfg.filters()?.clone(),
             clusters: config.dyn_cfg.clusters()?.clone(),
+            history: config.dyn_cfg.history().cloned(),
         })
     }
 }
@@ -249,6 +345,28 @@ impl Providers {
         ),
     ];
 
+    /// [`Self::SUBS`] with the addition of Envoy `ClusterLoadAssignment` resources
+    #[allow(clippy::type_complexity)]
+    const EDS_SUBS: &[(&str, &[(&str, Vec<String>)])] = &[
+        (
+            "9",
+            &[
+                (crate::xds::CLUSTER_TYPE, Vec::new()),
+                (crate::xds::DATACENTER_TYPE, Vec::new()),
+                (crate::xds::FILTER_CHAIN_TYPE, Vec::new()),
+                (crate::xds::CLUSTER_LOAD_ASSIGNMENT_TYPE, Vec::new()),
+            ],
+        ),
+        (
+            "",
+            &[
+                (crate::xds::CLUSTER_TYPE, Vec::new()),
+                (crate::xds::DATACENTER_TYPE, Vec::new()),
+                (crate::xds::CLUSTER_LOAD_ASSIGNMENT_TYPE, Vec::new()),
+            ],
+        ),
+    ];
+
     pub fn agones(mut self) -> Self {
         self.agones_enabled = true;
         self
@@ -304,6 +422,92 @@ impl Providers {
         self
     }
 
+    pub fn envoy_eds(mut self) -> Self {
+        self.xds_envoy_eds = true;
+        self
+    }
+
+    pub fn envoy_eds_clusters(mut self, clusters: impl Into<Vec<String>>) -> Self {
+        self.xds_envoy_eds_clusters = clusters.into();
+        self
+    }
+
+    pub fn xds_localities(mut self, localities: impl Into<Vec<String>>) -> Self {
+        self.xds_localities = localities.into();
+        self
+    }
+
+    pub fn grpc_tls_ca_path(mut self, ca: impl Into<std::path::PathBuf>) -> Self {
+        self.grpc_tls_ca_path = Some(ca.into());
+        self
+    }
+
+    pub fn grpc_tls_identity(
+        mut self,
+        cert: impl Into<std::path::PathBuf>,
+        key: impl Into<std::path::PathBuf>,
+    ) -> Self {
+        self.grpc_tls_cert_path = Some(cert.into());
+        self.grpc_tls_key_path = Some(key.into());
+        self
+    }
+
+    /// Applies the TLS options, if any, to the endpoints of the xDS or relay
+    /// servers
+    fn grpc_endpoints(
+        &self,
+        endpoints: Vec<tonic::transport::Endpoint>,
+    ) -> crate::Result<Vec<tonic::transport::Endpoint>> {
+        let Some(ca_path) = &self.grpc_tls_ca_path else {
+            return Ok(endpoints);
+        };
+
+        let mut tls = quilkin_xds::tls::ClientTls::from_file(ca_path)?;
+        if let Some((cert, key)) = self
+            .grpc_tls_cert_path
+            .as_ref()
+            .zip(self.grpc_tls_key_path.as_ref())
+        {
+            tls = tls.with_identity_files(cert, key)?;
+        }
+        if let Some(name) = &self.grpc_tls_domain_name {
+            tls = tls.with_domain_name(name);
+        }
+
+        tls.apply(endpoints)
+    }
+
+    /// The subscriptions for the xDS provider, with the resource names of the
+    /// cluster types being scoped to the configured localities and clusters
+    #[allow(clippy::type_complexity)]
+    fn xds_subscriptions(&self) -> Vec<(&'static str, Vec<(&'static str, Vec<String>)>)> {
+        let subs = if self.xds_envoy_eds {
+            Self::EDS_SUBS
+        } else {
+            Self::SUBS
+        };
+
+        subs.iter()
+            .map(|(version, types)| {
+                let types = types
+                    .iter()
+                    .map(|(type_url, names)| {
+                        let names = match *type_url {
+                            crate::xds::CLUSTER_TYPE => self.xds_localities.clone(),
+                            crate::xds::CLUSTER_LOAD_ASSIGNMENT_TYPE => {
+                                self.xds_envoy_eds_clusters.clone()
+                            }
+                            _ => names.clone(),
+                        };
+                        (*type_url, names)
+                    })
+                    .collect();
+
+                (*version, types)
+            })
+            .collect()
+    }
+
     pub fn corrosion_endpoints(mut self, endpoints: impl Into<Vec<EndpointAddress>>) -> Self {
         self.corrosion_endpoints = endpoints.into();
         self
@@ -314,6 +518,20 @@ impl Providers {
         self
     }
 
+    pub fn corrosion_tls(
+        mut self,
+        cert: impl Into<std::path::PathBuf>,
+        key: impl Into<std::path::PathBuf>,
+        ca: impl Into<std::path::PathBuf>,
+        server_name: Option<String>,
+    ) -> Self {
+        self.corrosion_tls_cert_path = Some(cert.into());
+        self.corrosion_tls_key_path = Some(key.into());
+        self.corrosion_tls_ca_path = Some(ca.into());
+        self.corrosion_tls_server_name = server_name;
+        self
+    }
+
     pub fn http(mut self) -> Self {
         self.http_enabled = true;
         self
@@ -380,7 +598,11 @@ impl Providers {
                         )?,
                         crate::filters::TokenRouter::as_filter_config(None)?,
                     ])?;
-                    config.filters.store(filter_chain);
+                    config.filters.store_with_history(
+                        filter_chain,
+                        config.history.as_ref(),
+                        crate::config::history::Source::Static,
+                    );
                 }
 
                 let count = count as u64;
@@ -422,13 +644,16 @@ impl Providers {
                         endpoint.address.host.clone(),
                         endpoint.address.port,
                     ),
-                    endpoint.metadata.known.tokens.clone(),
+                    corrosion::push::ServerDetails::new(&endpoint.metadata, locality.as_ref()),
                 );
             }
         } else {
-            config.clusters.modify(|clusters| {
-                clusters.insert(None, locality, endpoints);
-            });
+            config.clusters.modify_with_history(
+                config.history.as_ref(),
+                crate::config::history::Source::Static,
+                [locality.clone()].into(),
+                |clusters| clusters.insert(None, locality, endpoints),
+            );
         }
 
         health_check.store(true, Ordering::SeqCst);
@@ -513,6 +738,7 @@ impl Providers {
                                     client.clone(),
                                     k8s_namespace.clone(),
                                     fc.clone(),
+                                    config.dyn_cfg.history().cloned(),
                                 ),
                             ))
                         } else {
@@ -545,6 +771,7 @@ impl Providers {
                                 crate::net::cluster::ClusterUpdateBatcher::spawn(
                                     clusters.clone(),
                                     locality.clone(),
+                                    config.dyn_cfg.history().cloned(),
                                     std::time::Duration::from_millis(500),
                                     batch_token.child_token(),
                                 );
@@ -627,27 +854,31 @@ impl Providers {
         shutdown: tokio::sync::watch::Receiver<()>,
     ) -> impl Future<Output = crate::Result<()>> + 'static {
         let config = config.clone();
-        let endpoints = self.relay.clone();
+        let endpoints = self.grpc_endpoints(self.relay.clone());
         let control_plane_id = locality.map_or_else(|| config.id(), |l| l.region().to_string());
-        Self::task("mds_provider".into(), health_check.clone(), move || {
-            let config = config.clone();
-            let endpoints = endpoints.clone();
-            let control_plane_id = control_plane_id.clone();
-            let health_check = health_check.clone();
-            let shutdown = shutdown.clone();
-            async move {
-                let stream =
-                    crate::net::xds::client::MdsClient::connect(control_plane_id, endpoints)
-                        .await?
-                        .delta_stream(config.clone(), health_check.clone(), shutdown)
-                        .await
-                        .map_err(|_err| eyre::eyre!("failed to acquire delta stream"))?;
+        async move {
+            let endpoints = endpoints?;
+            Self::task("mds_provider".into(), health_check.clone(), move || {
+                let config = config.clone();
+                let endpoints = endpoints.clone();
+                let control_plane_id = control_plane_id.clone();
+                let health_check = health_check.clone();
+                let shutdown = shutdown.clone();
+                async move {
+                    let stream =
+                        crate::net::xds::client::MdsClient::connect(control_plane_id, endpoints)
+                            .await?
+                            .delta_stream(config.clone(), health_check.clone(), shutdown)
+                            .await
+                            .map_err(|_err| eyre::eyre!("failed to acquire xDS stream"))?;
 
-                health_check.store(true, Ordering::SeqCst);
+                    health_check.store(true, Ordering::SeqCst);
 
-                stream.await.wrap_err("join handle error")?
-            }
-        })
+                    stream.await.wrap_err("join handle error")?
+                }
+            })
+            .await
+        }
     }
 
     pub fn spawn_xds_provider(
@@ -657,31 +888,37 @@ impl Providers {
         notifier: Option<tokio::sync::mpsc::UnboundedSender<String>>,
     ) -> impl Future<Output = crate::Result<()>> + 'static {
         let config = config.clone();
-        let endpoints = self.xds_endpoints.clone();
+        let endpoints = self.grpc_endpoints(self.xds_endpoints.clone());
+        let subs = self.xds_subscriptions();
 
-        Self::task("xds_provider".into(), health_check.clone(), move || {
-            let config = config.clone();
-            let endpoints = endpoints.clone();
-            let health_check = health_check.clone();
-            let tx = notifier.clone();
-            async move {
-                let identifier = config.id();
-                let stream = crate::net::xds::delta_subscribe(
-                    config,
-                    identifier,
-                    endpoints,
-                    health_check.clone(),
-                    tx,
-                    Self::SUBS,
-                )
-                .await
-                .map_err(|_err| eyre::eyre!("failed to acquire delta stream"))?;
+        async move {
+            let endpoints = endpoints?;
+            Self::task("xds_provider".into(), health_check.clone(), move || {
+                let config = config.clone();
+                let endpoints = endpoints.clone();
+                let health_check = health_check.clone();
+                let tx = notifier.clone();
+                let subs = subs.clone();
+                async move {
+                    let identifier = config.id();
+                    let stream = crate::net::xds::subscribe(
+                        config,
+                        identifier,
+                        endpoints,
+                        health_check.clone(),
+                        tx,
+                        subs,
+                    )
+                    .await
+                    .map_err(|_err| eyre::eyre!("failed to acquire delta stream"))?;
 
-                health_check.store(true, Ordering::SeqCst);
+                    health_check.store(true, Ordering::SeqCst);
 
-                stream.await.wrap_err("join handle error")?
-            }
-        })
+                    stream.await.wrap_err("join handle error")?
+                }
+            })
+            .await
+        }
     }
 
     pub fn grpc_push_enabled(&self) -> bool {
@@ -738,6 +975,10 @@ impl Providers {
             insert_default::<crate::filters::FilterChain>(&mut config.dyn_cfg.typemap);
             insert_default::<crate::net::ClusterMap>(&mut config.dyn_cfg.typemap);
         }
+
+        if self.xds_envoy_eds {
+            insert_default::<crate::config::eds::EnvoyAssignments>(&mut config.dyn_cfg.typemap);
+        }
     }
 
     pub fn spawn_providers(
@@ -772,7 +1013,8 @@ impl Providers {
             providers.spawn(self.spawn_mmdb_provider());
         }
 
-        let mutator = self.maybe_spawn_corrosion(config, &health_check, &mut providers);
+        let mutator =
+            self.maybe_spawn_corrosion(config, &health_check, locality.as_ref(), &mut providers);
 
         if mutator.is_some() && self.fs_enabled() {
             tracing::error!("corrosion mutation does not work with file system data");
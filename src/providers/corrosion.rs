@@ -138,11 +138,13 @@ impl super::Providers {
     /// 1. If `[CorrosionMode::Push]`, spawns a `Mutator` and `Pusher` to mutate the local state
     ///    and send those mutations to a remote corrosion DB
     /// 1. If `[CorrosionMode::Pull]`, spawns a provider that subscribes to changes from a remote
-    ///    corrosion DB and applies events to the local state
+    ///    corrosion DB and applies events to the local state, using the filter chain scoped to
+    ///    the most specific of `locality`, the ICAO code, or the global default
     pub(super) fn maybe_spawn_corrosion(
         &self,
         config: &State,
         health_check: &HealthCheck,
+        locality: Option<&crate::net::endpoint::Locality>,
         providers: &mut tokio::task::JoinSet<crate::Result<()>>,
     ) -> Option<ServerMutator> {
         let Some(mode) = self.corrosion_mode else {
@@ -150,6 +152,14 @@ impl super::Providers {
             return None;
         };
 
+        let tls = match self.corrosion_tls_config() {
+            Ok(tls) => tls,
+            Err(error) => {
+                tracing::error!(%error, "unable to start corrosion provider, invalid TLS configuration");
+                return None;
+            }
+        };
+
         match mode {
             CorrosionMode::Pull => {
                 if self.corrosion_endpoints.is_empty() {
@@ -162,6 +172,7 @@ impl super::Providers {
                 let config = config.clone();
                 let health_check = health_check.clone();
                 let endpoints = self.corrosion_endpoints.clone();
+                let locality = locality.cloned();
 
                 // We're a proxy, subscribing to changes from a remote relay
                 providers.spawn(Self::task(
@@ -171,8 +182,12 @@ impl super::Providers {
                         let state = config.clone();
                         let endpoints = endpoints.clone();
                         let hc = health_check.clone();
+                        let tls = tls.clone();
+                        let locality = locality.clone();
 
-                        async move { pull::corrosion_subscribe(state, endpoints, hc).await }
+                        async move {
+                            pull::corrosion_subscribe(state, endpoints, hc, tls, locality).await
+                        }
                     },
                 ));
 
@@ -198,6 +213,7 @@ impl super::Providers {
                     icao,
                     self.corrosion_endpoints.clone(),
                     health_check.clone(),
+                    tls,
                 );
 
                 // We're an agent, pushing changes to a remote relay
@@ -207,6 +223,21 @@ impl super::Providers {
             }
         }
     }
+
+    fn corrosion_tls_config(&self) -> crate::Result<Option<corrosion::tls::TlsConfig>> {
+        let (Some(cert), Some(key), Some(ca)) = (
+            &self.corrosion_tls_cert_path,
+            &self.corrosion_tls_key_path,
+            &self.corrosion_tls_ca_path,
+        ) else {
+            return Ok(None);
+        };
+
+        Ok(Some(
+            corrosion::tls::TlsConfig::from_files(cert, key, ca)?
+                .with_server_name(self.corrosion_tls_server_name.clone()),
+        ))
+    }
 }
 
 use corrosion::{
@@ -222,6 +253,9 @@ enum Which {
     Servers,
     Clusters,
     Filter,
+    /// The unscoped filter chain in the `filter` table, used by clusters that
+    /// predate scoped filter chains
+    LegacyFilter,
 }
 
 impl std::fmt::Display for Which {
@@ -230,6 +264,7 @@ impl std::fmt::Display for Which {
             Self::Servers => "servers",
             Self::Clusters => "clusters",
             Self::Filter => "filter",
+            Self::LegacyFilter => "legacy_filter",
         };
 
         f.write_str(s)
@@ -237,20 +272,15 @@ impl std::fmt::Display for Which {
 }
 
 struct QuerySet<T> {
-    set: [Option<T>; 3],
+    set: [Option<T>; 4],
 }
 
 impl<T> QuerySet<T> {
     fn new() -> Self {
         Self {
-            set: [None, None, None],
+            set: [None, None, None, None],
         }
     }
-
-    fn assume_initialized(self) -> (T, T, T) {
-        let (a, b, c) = self.set.into();
-        (a.unwrap(), b.unwrap(), c.unwrap())
-    }
 }
 
 impl<T> Clone for QuerySet<T>
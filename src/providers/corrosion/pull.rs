@@ -1,6 +1,7 @@
 use super::*;
 
-use corrosion::persistent;
+use corrosion::{db::FilterScope, persistent};
+use std::collections::BTreeMap;
 
 struct Sub {
     #[allow(unused)]
@@ -10,32 +11,102 @@ struct Sub {
 
 struct SubState {
     /// The root client, we need to keep this alive as long as we have
-    /// subscriptions that use it
-    #[allow(unused)]
+    /// subscriptions that use it, and to resubscribe to changed queries
     client: client::Client,
     /// Subscription to servers
     servers: Sub,
     /// Subscription to cluster agents
     clusters: Sub,
-    /// Subscription to the filter config
-    filter: Sub,
+    /// Subscription to the filter chains that apply to this proxy, `None` if
+    /// the server's schema predates scoped filter chains
+    filter: Option<Sub>,
+    /// Subscription to the unscoped filter chain of older clusters
+    legacy_filter: Sub,
+}
+
+/// The filter chains received for each of the scopes that apply to this proxy
+struct ScopedFilters {
+    /// The scopes that apply to this proxy, ordered from most to least specific
+    scopes: Vec<FilterScope>,
+    chains: BTreeMap<FilterScope, crate::filters::FilterChain>,
+    /// The chain in the legacy `filter` table, only used if there are no
+    /// scoped chains at all
+    legacy: Option<crate::filters::FilterChain>,
+}
+
+impl ScopedFilters {
+    /// Updates the scopes that apply to this proxy, returning true if they
+    /// changed, in which case the chains received for the previous scopes
+    /// no longer apply
+    fn set_scopes(&mut self, scopes: Vec<FilterScope>) -> bool {
+        if scopes == self.scopes {
+            return false;
+        }
+
+        self.chains.clear();
+        self.scopes = scopes;
+        true
+    }
+
+    /// Stores the chain of the most specific scope that has one, falling back
+    /// to the legacy chain
+    ///
+    /// If every applicable chain has been removed we keep the current one
+    /// rather than dropping all filters
+    fn store(
+        &self,
+        fcf: &crate::config::filter::FilterChainConfig,
+        history: Option<&crate::config::history::ConfigHistory>,
+    ) {
+        use crate::config::history::Source;
+
+        if let Some((scope, chain)) = self
+            .scopes
+            .iter()
+            .find_map(|scope| self.chains.get_key_value(scope))
+        {
+            tracing::debug!(%scope, "using filter chain");
+            fcf.store_with_history(chain.clone(), history, Source::Corrosion);
+        } else if let Some(chain) = &self.legacy {
+            tracing::debug!("using legacy filter chain");
+            fcf.store_with_history(chain.clone(), history, Source::Corrosion);
+        }
+    }
 }
 
 pub(super) async fn corrosion_subscribe(
     state: State,
     endpoints: CorrosionAddrs,
     hc: HealthCheck,
+    tls: Option<corrosion::tls::TlsConfig>,
+    locality: Option<crate::net::endpoint::Locality>,
 ) -> crate::Result<()> {
     // Each query keeps track of the latest change id it has received, if we
     // disconnect from a remote server, we can send this when subscribing to
     // (hopefully) be able to catch up to the state of that server more quickly
     let mut change_ids = QuerySet::new();
+    let locality = locality.map(|l| l.colon_separated_string());
+    let mut filters = ScopedFilters {
+        scopes: Vec::new(),
+        chains: BTreeMap::new(),
+        legacy: None,
+    };
+    let mut icao_rx = state.dyn_cfg.icao_code.subscribe();
 
     loop {
+        // The ICAO code can change at runtime, in which case the filter query
+        // differs, and the previous change id and chains no longer apply
+        let scopes = FilterScope::candidates(state.dyn_cfg.icao_code.load(), locality.as_deref());
+        if filters.set_scopes(scopes) {
+            change_ids[Which::Filter] = None;
+        }
+
         let connect_to_corrosion = connect_first(&endpoints, |addr| {
             let cids = change_ids.clone();
+            let tls = tls.clone();
+            let filter_query = pubsub::scoped_filter_query(&filters.scopes);
             async move {
-                connect_and_sub(&addr, &cids)
+                connect_and_sub(&addr, &cids, &filter_query, tls.as_ref())
                     .instrument(tracing::debug_span!("connect_and_sub", address = %addr))
                     .await
             }
@@ -58,9 +129,16 @@ pub(super) async fn corrosion_subscribe(
         let _res = {
             let _metrics = crate::metrics::ActiveProviderMetrics::new(address.to_string());
 
-            process_subscription_events(&state, sstate, &mut change_ids)
-                .await
-                .instrument(tracing::debug_span!("corrosion subscription events", %address))
+            process_subscription_events(
+                &state,
+                sstate,
+                &mut change_ids,
+                &mut filters,
+                &mut icao_rx,
+                locality.as_deref(),
+            )
+            .await
+            .instrument(tracing::debug_span!("corrosion subscription events", %address))
         };
 
         tracing::info!(%address, "lost connection to corrosion server");
@@ -74,15 +152,18 @@ pub(super) async fn corrosion_subscribe(
 async fn connect_and_sub(
     addr: &crate::net::EndpointAddress,
     change_ids: &ChangeIds,
+    filter_query: &str,
+    tls: Option<&corrosion::tls::TlsConfig>,
 ) -> crate::Result<SubState> {
     tracing::debug!("connecting to corrosion server");
 
     let addr = addr.to_socket_addr_async().await?;
-    let root = client::Client::connect_insecure(
-        addr,
-        persistent::Metrics::new(crate::metrics::registry()),
-    )
-    .await
+    let metrics = persistent::Metrics::new(crate::metrics::registry());
+    let root = if let Some(tls) = tls {
+        client::Client::connect(addr, metrics, tls).await
+    } else {
+        client::Client::connect_insecure(addr, metrics).await
+    }
     .context("failed to connect")?;
 
     tracing::debug!("connected to corrosion server");
@@ -117,9 +198,10 @@ async fn connect_and_sub(
     js.spawn({
         let root = root.clone();
         let from = change_ids[Which::Filter];
+        let sp = SubParams::new(filter_query);
 
         async move {
-            let mut sp = SubParams::new(pubsub::FILTER_QUERY);
+            let mut sp = sp;
             sp.from = from;
             (
                 client::SubscriptionClient::connect(root, sp).await,
@@ -127,25 +209,56 @@ async fn connect_and_sub(
             )
         }
     });
+    js.spawn({
+        let root = root.clone();
+        let from = change_ids[Which::LegacyFilter];
+
+        async move {
+            let mut sp = SubParams::new(pubsub::FILTER_QUERY);
+            sp.from = from;
+            (
+                client::SubscriptionClient::connect(root, sp).await,
+                Which::LegacyFilter,
+            )
+        }
+    });
 
     let mut sub_set = QuerySet::new();
 
     while let Some(result) = js.join_next().await {
         let (result, which) = result.wrap_err("failed to join subscribe task")?;
-        let (client, stream) =
-            result.with_context(|| format!("failed to subscribe to '{which}' query"))?;
+        let (client, stream) = match result {
+            Ok(sub) => sub,
+            // Servers with a schema older than V3 don't have the `filters`
+            // table, in which case we only use the legacy filter chain
+            Err(error) if matches!(which, Which::Filter) => {
+                tracing::warn!(%error, "failed to subscribe to scoped filter chains, falling back to the legacy filter chain");
+                continue;
+            }
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to subscribe to '{which}' query"));
+            }
+        };
         sub_set[which] = Some(Sub { client, stream });
     }
 
     tracing::debug!("subscribed to corrosion server");
 
-    let (servers, clusters, filter) = sub_set.assume_initialized();
+    let filter = sub_set[Which::Filter].take();
+    let [servers, clusters, legacy_filter] = [Which::Servers, Which::Clusters, Which::LegacyFilter]
+        .map(|which| {
+            sub_set[which]
+                .take()
+                .expect("required subscriptions either succeed or return an error")
+        });
 
     Ok(SubState {
         client: root,
         servers,
         clusters,
         filter,
+        legacy_filter,
     })
 }
 
@@ -155,6 +268,9 @@ async fn process_subscription_events(
     state: &State,
     mut sstate: SubState,
     change_ids: &mut ChangeIds,
+    filters: &mut ScopedFilters,
+    icao_rx: &mut tokio::sync::broadcast::Receiver<crate::config::IcaoCode>,
+    locality: Option<&str>,
 ) -> crate::Result<()> {
     use corrosion::{db::read as db, persistent::SubMetrics, pubsub::SubscriptionStream};
     use pubsub::ChangeType;
@@ -168,7 +284,25 @@ async fn process_subscription_events(
             return Ok(());
         };
 
-        *cid = Some(servers.write().corrosion_apply(events, subm));
+        let history = state.dyn_cfg.history();
+        let mut changes = history.map(|_| Vec::new());
+
+        *cid = Some(servers.write().corrosion_apply(
+            events,
+            cid.unwrap_or(ChangeId(0)),
+            subm,
+            changes.as_mut(),
+        ));
+
+        if let Some((history, changes)) = history.zip(changes) {
+            history.record(
+                crate::config::history::Resource::Clusters,
+                crate::config::history::Source::Corrosion,
+                Default::default(),
+                changes,
+                None,
+            );
+        }
         Ok(())
     };
 
@@ -215,7 +349,8 @@ async fn process_subscription_events(
 
     let process_filter_events = |events: Option<SubscriptionStream>,
                                  cid: &mut Option<ChangeId>,
-                                 subm: &mut SubMetrics|
+                                 subm: &mut SubMetrics,
+                                 filters: &mut ScopedFilters|
      -> crate::Result<()> {
         let events = events.context("subscription was closed")?;
         let Some(fcf) = state.dyn_cfg.filters() else {
@@ -224,9 +359,17 @@ async fn process_subscription_events(
         };
 
         process_events(events, cid, subm, |ct, row| {
+            let column = row.first().context("missing 'scope' column")?;
+            let scope = column
+                .as_str()
+                .with_context(|| {
+                    format!("'scope' column is {:?}, not a string", column.column_type())
+                })?
+                .parse::<FilterScope>()?;
+
             match ct {
                 ChangeType::Insert | ChangeType::Update => {
-                    let column = row.first().context("missing 'filter' column")?;
+                    let column = row.get(1).context("missing 'filter' column")?;
 
                     let filter = column.as_str().with_context(|| {
                         format!(
@@ -235,19 +378,57 @@ async fn process_subscription_events(
                         )
                     })?;
 
-                    fcf.store(
+                    filters.chains.insert(
+                        scope,
                         serde_json::from_str(filter).context("failed to deserialize filter")?,
                     );
                 }
                 ChangeType::Delete => {
-                    // TODO: what do we actually want to do here?
-                    tracing::warn!("ignoring `filter` deletion event");
+                    filters.chains.remove(&scope);
+                }
+            }
+
+            Ok(())
+        });
+
+        filters.store(fcf, state.dyn_cfg.history());
+        Ok(())
+    };
+
+    let process_legacy_filter_events = |events: Option<SubscriptionStream>,
+                                        cid: &mut Option<ChangeId>,
+                                        subm: &mut SubMetrics,
+                                        filters: &mut ScopedFilters|
+     -> crate::Result<()> {
+        let events = events.context("subscription was closed")?;
+        let Some(fcf) = state.dyn_cfg.filters() else {
+            return Ok(());
+        };
+
+        process_events(events, cid, subm, |ct, row| {
+            match ct {
+                ChangeType::Insert | ChangeType::Update => {
+                    let column = row.first().context("missing 'filter' column")?;
+
+                    let filter = column.as_str().with_context(|| {
+                        format!(
+                            "'filter' column is {:?}, not a string",
+                            column.column_type()
+                        )
+                    })?;
+
+                    filters.legacy =
+                        Some(serde_json::from_str(filter).context("failed to deserialize filter")?);
+                }
+                ChangeType::Delete => {
+                    filters.legacy = None;
                 }
             }
 
             Ok(())
         });
 
+        filters.store(fcf, state.dyn_cfg.history());
         Ok(())
     };
 
@@ -269,10 +450,39 @@ async fn process_subscription_events(
                 let _s = span.enter();
                 process_cluster_events(dc, &mut change_ids[Which::Clusters], &mut subm).context("processing 'clusters' event").map(|_|"clusters")
             }
-            fc = sstate.filter.stream.rx.recv() => {
+            fc = async {
+                match &mut sstate.filter {
+                    Some(filter) => filter.stream.rx.recv().await,
+                    None => std::future::pending().await,
+                }
+            } => {
                 let span = tracing::info_span!("filter");
                 let _s = span.enter();
-                process_filter_events(fc, &mut change_ids[Which::Filter], &mut subm).context("processing 'filter' event").map(|_|"filter")
+                process_filter_events(fc, &mut change_ids[Which::Filter], &mut subm, filters).context("processing 'filter' event").map(|_|"filter")
+            }
+            lfc = sstate.legacy_filter.stream.rx.recv() => {
+                let span = tracing::info_span!("legacy_filter");
+                let _s = span.enter();
+                process_legacy_filter_events(lfc, &mut change_ids[Which::LegacyFilter], &mut subm, filters).context("processing 'legacy_filter' event").map(|_|"legacy_filter")
+            }
+            // We only care about the current code, so it doesn't matter if we
+            // lagged behind, and the sender is owned by the config we hold
+            _ = icao_rx.recv() => {
+                let scopes = FilterScope::candidates(state.dyn_cfg.icao_code.load(), locality);
+                // There's nothing to resubscribe to if the server doesn't
+                // have scoped filter chains
+                if filters.set_scopes(scopes) && sstate.filter.is_some() {
+                    tracing::info!("ICAO code changed, resubscribing to filter chains");
+                    change_ids[Which::Filter] = None;
+
+                    let sp = SubParams::new(&pubsub::scoped_filter_query(&filters.scopes));
+                    let (client, stream) = client::SubscriptionClient::connect(sstate.client.clone(), sp)
+                        .await
+                        .context("failed to resubscribe to 'filter' query")?;
+                    sstate.filter = Some(Sub { client, stream });
+                }
+
+                continue;
             }
         };
 
@@ -356,3 +566,123 @@ fn process_events(
 
     subm.failures = subm.total_events - successful;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corrosion::persistent::{mutator::BroadcastingTransactor, server};
+
+    /// Servers whose schema predates scoped filter chains can't serve the
+    /// `filters` subscription, the proxy should still connect and use the
+    /// chain in the legacy `filter` table
+    #[tokio::test]
+    async fn falls_back_to_legacy_filter_chain() {
+        static SERVER_REG: std::sync::OnceLock<prometheus::Registry> = std::sync::OnceLock::new();
+
+        let temp = tempfile::TempDir::new().expect("failed to create temp dir");
+        let root = camino::Utf8Path::from_path(temp.path()).expect("non-utf8 path");
+
+        // V2 doesn't have the `filters` table
+        let db = corrosion::db::InitializedDb::setup(
+            &root.join("db.db"),
+            &corrosion::schema::MIGRATIONS[..2],
+            None,
+        )
+        .await
+        .expect("failed to initialize DB");
+
+        {
+            let conn = db.pool.write_priority().await.unwrap();
+            conn.execute(
+                "INSERT INTO filter (id,filter) VALUES (9999,?)",
+                [r#"[{"name":"quilkin.filters.debug.v1alpha1.Debug","config":{"id":"legacy"}}]"#],
+            )
+            .unwrap();
+        }
+
+        let subs = pubsub::SubsManager::default();
+        let btx = BroadcastingTransactor::new(
+            db.actor_id,
+            db.clock.clone(),
+            db.pool.clone(),
+            subs.clone(),
+            None,
+            db.bookie,
+            db.booked,
+            None,
+        );
+
+        let (trip, _w, _s) = corrosion::Tripwire::new_simple();
+        let ctx = pubsub::PubsubContext::new(
+            subs,
+            root.join("subs"),
+            db.pool.clone(),
+            db.schema.clone(),
+            trip,
+            corrosion::types::pubsub::MatcherLoopConfig {
+                changes_threshold: 0,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let server = server::Server::new_unencrypted(
+            (std::net::Ipv6Addr::LOCALHOST, 0).into(),
+            btx,
+            ctx,
+            persistent::Metrics::new(SERVER_REG.get_or_init(prometheus::Registry::new)),
+        )
+        .unwrap();
+
+        let providers = crate::Providers::default().http();
+        let mut service = crate::Service::default();
+        let config: State = Arc::new(crate::Config::new(
+            None,
+            Default::default(),
+            &providers,
+            &mut service,
+        ));
+
+        let mut filters = ScopedFilters {
+            scopes: FilterScope::candidates(config.dyn_cfg.icao_code.load(), None),
+            chains: BTreeMap::new(),
+            legacy: None,
+        };
+
+        let sstate = connect_and_sub(
+            &server.local_addr().into(),
+            &QuerySet::new(),
+            &pubsub::scoped_filter_query(&filters.scopes),
+            None,
+        )
+        .await
+        .expect("the scoped filter subscription should be optional");
+        assert!(sstate.filter.is_none());
+
+        let mut change_ids = QuerySet::new();
+        let mut icao_rx = config.dyn_cfg.icao_code.subscribe();
+        let fcf = config.dyn_cfg.filters().unwrap();
+
+        tokio::select! {
+            res = process_subscription_events(
+                &config,
+                sstate,
+                &mut change_ids,
+                &mut filters,
+                &mut icao_rx,
+                None,
+            ) => panic!("subscription ended unexpectedly: {res:?}"),
+            () = async {
+                while fcf.load().is_empty() {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            } => {}
+            () = tokio::time::sleep(Duration::from_secs(10)) => {
+                panic!("the legacy filter chain was never applied");
+            }
+        }
+
+        assert_eq!(fcf.load().len(), 1);
+    }
+}
@@ -9,6 +9,7 @@ pub(super) fn corrosion_mutate(
     icao: &crate::config::NotifyingIcaoCode,
     endpoints: CorrosionAddrs,
     hc: HealthCheck,
+    tls: Option<corrosion::tls::TlsConfig>,
 ) -> (ServerMutator, Pusher) {
     let (tx, rx) = mpsc::unbounded_channel();
     let ls = Arc::new(LocalState::default());
@@ -29,6 +30,7 @@ pub(super) fn corrosion_mutate(
             state: ls,
             rx,
             agent_info,
+            tls,
             qcmp: qcmp.subscribe(),
             icao: icao.subscribe(),
         },
@@ -47,6 +49,45 @@ pub enum Mutation {
     Remove(Endpoint),
 }
 
+/// The details of a server published to the remote database
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerDetails {
+    pub tokens: TokenSet,
+    /// Additional metadata, eg. the name and namespace of the `GameServer`
+    pub metadata: corrosion::db::Metadata,
+    /// The locality of the server
+    pub locality: Option<crate::net::endpoint::Locality>,
+}
+
+impl ServerDetails {
+    #[inline]
+    pub fn new(
+        metadata: &crate::net::endpoint::EndpointMetadata,
+        locality: Option<&crate::net::endpoint::Locality>,
+    ) -> Self {
+        Self {
+            tokens: metadata.known.tokens.clone(),
+            metadata: metadata.unknown.clone(),
+            locality: locality.cloned(),
+        }
+    }
+
+    #[inline]
+    fn locality_str(&self) -> Option<String> {
+        self.locality.as_ref().map(|l| l.to_string())
+    }
+}
+
+impl From<TokenSet> for ServerDetails {
+    #[inline]
+    fn from(tokens: TokenSet) -> Self {
+        Self {
+            tokens,
+            ..Default::default()
+        }
+    }
+}
+
 /// Keeps track of server state, publishing changes to a remote corrosion database
 ///
 /// Unlike the xDS version of tracking, this specializes for the k8s usecase, so
@@ -69,10 +110,16 @@ impl ServerMutator {
     /// Upserts the server, updating it if it already exists and 1 or more details
     /// differ from the current state, or adding it if it doesn't exist
     #[inline]
-    pub fn upsert_server(&self, id: uuid::Uuid, endpoint: Endpoint, ts: TokenSet) {
+    pub fn upsert_server(
+        &self,
+        id: uuid::Uuid,
+        endpoint: Endpoint,
+        details: impl Into<ServerDetails>,
+    ) {
+        let details = details.into();
         match self.state.servers.entry(id) {
             dashmap::Entry::Vacant(ve) => {
-                ve.insert((endpoint, ts));
+                ve.insert((endpoint, details));
                 self.send(Mutation::Upsert(id));
             }
             dashmap::Entry::Occupied(mut oc) => {
@@ -83,12 +130,12 @@ impl ServerMutator {
                 if v.0 != endpoint {
                     tracing::warn!(%id, old = %v.0, new = %endpoint, "uuid endpoint has changed, replacing the endpoint");
                     self.send(Mutation::Remove(std::mem::replace(&mut v.0, endpoint)));
-                    v.1 = ts;
+                    v.1 = details;
                     self.send(Mutation::Upsert(id));
-                } else if v.1 == ts {
-                    tracing::debug!(%id, %endpoint, "ignoring server upsert, token set is the same");
+                } else if v.1 == details {
+                    tracing::debug!(%id, %endpoint, "ignoring server upsert, details are the same");
                 } else {
-                    v.1 = ts;
+                    v.1 = details;
                     self.send(Mutation::Update(id));
                 }
             }
@@ -101,7 +148,7 @@ impl ServerMutator {
     /// is essentially a full state reset, so this can both add, update, and
     /// delete servers
     #[inline]
-    pub fn replace(&self, set: BTreeMap<uuid::Uuid, (Endpoint, TokenSet)>) {
+    pub fn replace(&self, set: BTreeMap<uuid::Uuid, (Endpoint, ServerDetails)>) {
         // Remove servers that aren't part of the replacement set
         let mut to_remove = Vec::new();
         for entry in self.state.servers.iter() {
@@ -114,8 +161,8 @@ impl ServerMutator {
             self.remove_server(uid);
         }
 
-        for (uid, (ep, ts)) in set {
-            self.upsert_server(uid, ep, ts);
+        for (uid, (ep, details)) in set {
+            self.upsert_server(uid, ep, details);
         }
     }
 
@@ -164,16 +211,21 @@ impl Accumulator {
                     self.upserts.push(v1::ServerUpsert {
                         endpoint: server.0.clone(),
                         icao: self.icao,
-                        tokens: server.1.clone(),
+                        tokens: server.1.tokens.clone(),
+                        metadata: server.1.metadata.clone(),
+                        locality: server.1.locality_str(),
                     });
                 }
             }
             Mutation::Update(id) => {
                 if let Some(server) = state.servers.get(&id) {
+                    // An empty locality clears any locality the server previously had
                     self.updates.push(v1::ServerUpdate {
                         endpoint: server.0.clone(),
-                        tokens: Some(server.1.clone()),
+                        tokens: Some(server.1.tokens.clone()),
                         icao: None,
+                        metadata: Some(server.1.metadata.clone()),
+                        locality: Some(server.1.locality_str().unwrap_or_default()),
                     });
                 }
             }
@@ -203,7 +255,7 @@ impl Accumulator {
 
 #[derive(Default)]
 pub struct LocalState {
-    servers: dashmap::DashMap<uuid::Uuid, (Endpoint, TokenSet)>,
+    servers: dashmap::DashMap<uuid::Uuid, (Endpoint, ServerDetails)>,
 }
 
 impl LocalState {
@@ -212,7 +264,7 @@ impl LocalState {
     pub fn to_map(&self) -> BTreeMap<Endpoint, TokenSet> {
         self.servers
             .iter()
-            .map(|srv| (srv.0.clone(), srv.1.clone()))
+            .map(|srv| (srv.0.clone(), srv.1.tokens.clone()))
             .collect()
     }
 }
@@ -226,6 +278,7 @@ pub struct Pusher {
     qcmp: broadcast::Receiver<u16>,
     icao: broadcast::Receiver<IcaoCode>,
     agent_info: AgentInfo,
+    tls: Option<corrosion::tls::TlsConfig>,
 }
 
 impl Pusher {
@@ -233,7 +286,8 @@ impl Pusher {
         loop {
             let connect_to_corrosion = connect_first(&self.endpoints, |addr| {
                 let info = self.agent_info;
-                async move { connect(&addr, info).await }
+                let tls = self.tls.clone();
+                async move { connect(&addr, info, tls.as_ref()).await }
             });
 
             let (client, address) = match connect_to_corrosion
@@ -273,7 +327,9 @@ impl Pusher {
             upserts.push(v1::ServerUpsert {
                 endpoint: entry.0.clone(),
                 icao,
-                tokens: entry.1.clone(),
+                tokens: entry.1.tokens.clone(),
+                metadata: entry.1.metadata.clone(),
+                locality: entry.1.locality_str(),
             });
         }
 
@@ -421,13 +477,15 @@ impl Pusher {
 async fn connect(
     addr: &crate::net::EndpointAddress,
     info: AgentInfo,
+    tls: Option<&corrosion::tls::TlsConfig>,
 ) -> crate::Result<client::MutationClient> {
     let addr = addr.to_socket_addr_async().await?;
-    let root = client::Client::connect_insecure(
-        addr,
-        persistent::Metrics::new(crate::metrics::registry()),
-    )
-    .await
+    let metrics = persistent::Metrics::new(crate::metrics::registry());
+    let root = if let Some(tls) = tls {
+        client::Client::connect(addr, metrics, tls).await
+    } else {
+        client::Client::connect_insecure(addr, metrics).await
+    }
     .context("failed to connect")?;
 
     Ok(client::MutationClient::connect(root, info.qcmp, info.icao).await?)
@@ -14,20 +14,27 @@
  *  limitations under the License.
  */
 
-use std::sync::{
-    Arc,
-    atomic::{AtomicBool, Ordering},
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
 use notify::Watcher;
 use tracing::Instrument;
 
-use crate::Config;
+use crate::{Config, config::loader};
 
+/// Watches the configuration at `path`, either a file or a directory of files,
+/// see [`loader`], reloading all of it whenever any of its files, or any file
+/// they include, changes.
 pub async fn watch(
     config: Arc<Config>,
     health_check: Arc<AtomicBool>,
-    path: impl Into<std::path::PathBuf>,
+    path: impl Into<PathBuf>,
     locality: Option<crate::net::endpoint::Locality>,
 ) -> crate::Result<()> {
     let path = path.into();
@@ -36,10 +43,10 @@ pub async fn watch(
 
     async fn watch_inner(
         config: &Config,
-        path: &std::path::Path,
+        path: &Path,
         locality: Option<crate::net::endpoint::Locality>,
         tx: tokio::sync::mpsc::UnboundedSender<Result<notify::Event, notify::Error>>,
-    ) -> crate::Result<notify::RecommendedWatcher> {
+    ) -> crate::Result<(notify::RecommendedWatcher, BTreeSet<PathBuf>)> {
         tracing::info!("discovering configuration through filesystem");
         let mut watcher = notify::RecommendedWatcher::new(
             move |res| {
@@ -49,16 +56,17 @@ pub async fn watch(
         )
         .unwrap();
 
-        tracing::trace!("reading file");
-        let buf = tokio::fs::read(path).await?;
+        tracing::trace!("reading configuration");
+        let loaded = loader::load(path)?;
         tracing::info!("applying initial configuration");
-        config.update_from_json(serde_yaml::from_slice(&buf)?, locality)?;
+        config.update_from_json(loaded.config, locality)?;
         watcher.watch(path, notify::RecursiveMode::Recursive)?;
-        tracing::info!("watching file");
-        Ok(watcher)
+        let included = watch_included(&mut watcher, path, &BTreeSet::new(), loaded.files)?;
+        tracing::info!("watching configuration");
+        Ok((watcher, included))
     }
 
-    let _watcher = watch_inner(&config, &path, locality.clone(), tx)
+    let (mut watcher, mut included) = watch_inner(&config, &path, locality.clone(), tx)
         .instrument(span.clone())
         .await?;
 
@@ -67,27 +75,81 @@ pub async fn watch(
     while let Some(event) = rx.recv().instrument(span.clone()).await.transpose()? {
         tracing::trace!(event = ?event.kind, "new file event");
 
+        // Files being replaced, such as by editors or Kubernetes updating a
+        // mounted volume, are created or renamed rather than modified
         if !matches!(
             event.kind,
-            notify::EventKind::Modify(notify::event::ModifyKind::Data(_))
+            notify::EventKind::Create(_)
+                | notify::EventKind::Remove(_)
+                | notify::EventKind::Modify(
+                    notify::event::ModifyKind::Data(_) | notify::event::ModifyKind::Name(_)
+                )
         ) {
             continue;
         }
 
-        for path in event.paths {
-            // At least on macOS it's not always safe to
-            // immediately read file after the change, a small
-            // delay fixes that.
-            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
-            tracing::info!(path = %path.display(), "file changed, updating config");
-            let buf = tokio::fs::read(path).await?;
-            config.update_from_json(serde_yaml::from_slice(&buf)?, locality.clone())?;
+        // At least on macOS it's not always safe to
+        // immediately read file after the change, a small
+        // delay fixes that.
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        tracing::info!(paths = ?event.paths, "file changed, updating config");
+
+        // Files can be partially written, or missing while being replaced,
+        // so the current configuration is kept until they can be loaded
+        let loaded = match loader::load(&path) {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                tracing::warn!(
+                    error = format!("{error:#}"),
+                    "failed to load configuration, keeping the current configuration"
+                );
+                continue;
+            }
+        };
+
+        match watch_included(&mut watcher, &path, &included, loaded.files) {
+            Ok(files) => included = files,
+            Err(error) => tracing::warn!(%error, "failed to watch included files"),
+        }
+
+        if let Err(error) = config.update_from_json(loaded.config, locality.clone()) {
+            tracing::warn!(
+                error = format!("{error:#}"),
+                "failed to apply configuration, keeping the current configuration"
+            );
         }
     }
 
     Err(eyre::eyre!("filesystem watch unexpectedly stopped"))
 }
 
+/// Watches the files included from outside of `root`, which aren't covered
+/// by watching it, returning the files now being watched.
+fn watch_included(
+    watcher: &mut impl Watcher,
+    root: &Path,
+    watched: &BTreeSet<PathBuf>,
+    files: BTreeSet<PathBuf>,
+) -> crate::Result<BTreeSet<PathBuf>> {
+    let root = root.canonicalize()?;
+    let files: BTreeSet<_> = files
+        .into_iter()
+        .filter(|file| !file.starts_with(&root))
+        .collect();
+
+    for file in watched.difference(&files) {
+        if let Err(error) = watcher.unwatch(file) {
+            tracing::debug!(path = %file.display(), %error, "failed to stop watching file");
+        }
+    }
+
+    for file in files.difference(watched) {
+        watcher.watch(file, notify::RecursiveMode::NonRecursive)?;
+    }
+
+    Ok(files)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -146,4 +208,70 @@ mod tests {
 
         assert_eq!(source, dest);
     }
+
+    #[tokio::test]
+    async fn directory() {
+        let providers = Default::default();
+        let mut service = Default::default();
+        let config = crate::Config::new_rc(
+            Some("directory".into()),
+            Default::default(),
+            &providers,
+            &mut service,
+            tokio_util::sync::CancellationToken::new(),
+        );
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path().to_owned();
+        std::fs::create_dir(dir.join("clusters.d")).unwrap();
+        std::fs::write(
+            dir.join("clusters.d/a.yaml"),
+            "endpoints:\n  - address: 127.0.0.1:4321\n",
+        )
+        .unwrap();
+        let _handle = tokio::spawn(watch(config.clone(), <_>::default(), dir.clone(), None));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let clusters = config.dyn_cfg.clusters().unwrap();
+        assert_eq!(clusters.read().num_of_endpoints(), 1);
+
+        let mut rx = clusters.watch();
+        std::fs::write(
+            dir.join("clusters.d/b.yaml"),
+            "endpoints:\n  - address: 127.0.0.1:4322\n",
+        )
+        .unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_millis(1000), async {
+            while clusters.read().num_of_endpoints() != 2 {
+                rx.changed().await.unwrap();
+            }
+        })
+        .await
+        .unwrap();
+
+        // A file that fails to load keeps the current configuration, and the
+        // configuration is loaded again once it's fixed
+        std::fs::write(
+            dir.join("clusters.d/c.yaml"),
+            "endpoints:\n  - address: [\n",
+        )
+        .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert_eq!(clusters.read().num_of_endpoints(), 2);
+
+        std::fs::write(
+            dir.join("clusters.d/c.yaml"),
+            "endpoints:\n  - address: 127.0.0.1:4323\n",
+        )
+        .unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_millis(1000), async {
+            while clusters.read().num_of_endpoints() != 3 {
+                rx.changed().await.unwrap();
+            }
+        })
+        .await
+        .unwrap();
+    }
 }
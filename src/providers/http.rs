@@ -33,7 +33,7 @@ use axum::{
 
 use crate::{
     config,
-    config::filter::FilterChainConfig,
+    config::{filter::FilterChainConfig, history::Source},
     filters::FilterChain,
     net::{ClusterMap, endpoint::Endpoint},
     providers::FiltersAndClusters,
@@ -45,6 +45,7 @@ pub const DEFAULT_PORT: u16 = 9000;
 struct HttpState {
     filters: FilterChainConfig,
     clusters: config::Watch<ClusterMap>,
+    history: Option<config::history::ConfigHistory>,
 }
 
 impl HttpState {
@@ -178,12 +179,16 @@ async fn replace_filterchain(
     State(state): State<HttpState>,
     Json(chain): Json<FilterChain>,
 ) -> StatusCode {
-    state.filters.store(chain);
+    state
+        .filters
+        .store_with_history(chain, state.history.as_ref(), Source::Http);
     StatusCode::OK
 }
 
 async fn clear_filterchain(State(state): State<HttpState>) -> StatusCode {
-    state.filters.store(FilterChain::default());
+    state
+        .filters
+        .store_with_history(FilterChain::default(), state.history.as_ref(), Source::Http);
     StatusCode::OK
 }
 
@@ -194,6 +199,7 @@ pub fn make_router(fc: FiltersAndClusters) -> axum::Router {
     HttpState {
         filters: fc.filters,
         clusters: fc.clusters,
+        history: fc.history,
     }
     .router()
 }
@@ -208,6 +214,7 @@ pub async fn serve(
     let state = HttpState {
         filters: fc.filters,
         clusters: fc.clusters,
+        history: fc.history,
     };
 
     let router = state.router();
@@ -237,6 +244,7 @@ mod tests {
         let state = HttpState {
             filters: fc.filters,
             clusters: fc.clusters,
+            history: fc.history,
         };
         let server = TestServer::new(state.router()).unwrap();
         (server, config)
@@ -499,6 +507,32 @@ mod tests {
         server.get("/filterchain").await.assert_status_ok();
     }
 
+    #[tokio::test]
+    async fn filterchain_changes_are_recorded() {
+        let (server, cfg) = make_server();
+        let chain: FilterChain = serde_json::from_value(serde_json::json!([{
+            "name": "quilkin.filters.debug.v1alpha1.Debug",
+            "config": { "id": "http" },
+        }]))
+        .unwrap();
+        server
+            .put("/filterchain")
+            .json(&chain)
+            .await
+            .assert_status_ok();
+        server.delete("/filterchain").await.assert_status_ok();
+
+        let entries = cfg.dyn_cfg.history().unwrap().entries();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.source == Source::Http
+            && entry.resource == config::history::Resource::Filters));
+        assert_eq!(
+            entries[0].changes[0].after.as_ref().unwrap()["config"]["id"],
+            "http"
+        );
+        assert!(entries[1].changes[0].after.is_none());
+    }
+
     // ── shared state ─────────────────────────────────────────────────────────
 
     #[tokio::test]
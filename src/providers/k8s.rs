@@ -93,6 +93,7 @@ pub fn update_filters_from_configmap(
     client: kube::Client,
     namespace: impl AsRef<str>,
     filters: config::filter::FilterChainConfig,
+    history: Option<config::history::ConfigHistory>,
 ) -> impl Stream<Item = crate::Result<(), eyre::Error>> {
     async_stream::stream! {
         let mut cmap = None;
@@ -128,7 +129,11 @@ pub fn update_filters_from_configmap(
                 }
                 Event::Delete(_) => {
                     metrics::k8s::filters(false);
-                    filters.store(Default::default());
+                    filters.store_with_history(
+                        Default::default(),
+                        history.as_ref(),
+                        config::history::Source::Kubernetes,
+                    );
                     yield Ok(());
                     continue;
                 }
@@ -145,7 +150,11 @@ pub fn update_filters_from_configmap(
                     .transpose()?
             {
                 metrics::k8s::filters(true);
-                filters.store(de_filters);
+                filters.store_with_history(
+                    de_filters,
+                    history.as_ref(),
+                    config::history::Source::Kubernetes,
+                );
             }
 
             yield Ok(());
@@ -209,12 +218,16 @@ fn get_uid(res: &DeserializeGuard<GameServer>) -> Option<uuid::Uuid> {
 }
 
 #[inline]
-fn get_simple_endpoint_and_token_set(
+fn get_simple_endpoint_and_details(
     endpoint: &crate::net::endpoint::Endpoint,
-) -> (quilkin_types::Endpoint, quilkin_types::TokenSet) {
+    locality: Option<&crate::net::endpoint::Locality>,
+) -> (
+    quilkin_types::Endpoint,
+    crate::providers::corrosion::push::ServerDetails,
+) {
     (
         quilkin_types::Endpoint::new(endpoint.address.host.clone(), endpoint.address.port),
-        endpoint.metadata.known.tokens.clone(),
+        crate::providers::corrosion::push::ServerDetails::new(&endpoint.metadata, locality),
     )
 }
 
@@ -260,8 +273,11 @@ impl EventProcessor {
 
                 if let Some(mutator) = self.mutator.as_ref() {
                     if let Some(uid) = uid {
-                        let (ep, ts) = get_simple_endpoint_and_token_set(&endpoint);
-                        mutator.upsert_server(uid, ep, ts);
+                        let (ep, details) = get_simple_endpoint_and_details(
+                            &endpoint,
+                            self.cluster_update_batcher.locality(),
+                        );
+                        mutator.upsert_server(uid, ep, details);
                     } else {
                         tracing::warn!(namespace=%self.namespace, "apply event gameserverspec did not specify a valid UID");
                     }
@@ -303,7 +319,10 @@ impl EventProcessor {
                             let Some(uid) = uid else {
                                 return None;
                             };
-                            let data = get_simple_endpoint_and_token_set(ep);
+                            let data = get_simple_endpoint_and_details(
+                                ep,
+                                self.cluster_update_batcher.locality(),
+                            );
                             Some((*uid, data))
                         })
                         .collect();
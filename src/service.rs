@@ -2,6 +2,8 @@ use corrosion::persistent::mutator::BroadcastingTransactor;
 use eyre::ContextCompat;
 use std::sync::Arc;
 
+mod tls;
+
 use crate::{
     config::{Config, filter::CachedFilterChain},
     net::SessionPool,
@@ -75,6 +77,10 @@ pub struct Service {
     udp_port: u16,
     #[clap(flatten)]
     pub xdp: XdpOptions,
+    #[clap(flatten)]
+    pub health_check: crate::net::health::HealthCheckOptions,
+    #[clap(flatten)]
+    pub qos: crate::net::qos::QosOptions,
     /// Amount of UDP workers to run.
     #[clap(long = "service.udp.workers", env = "QUILKIN_SERVICE_UDP_WORKERS", default_value_t = std::num::NonZeroUsize::new(num_cpus::get()).unwrap())]
     pub udp_workers: std::num::NonZeroUsize,
@@ -166,6 +172,25 @@ pub struct Service {
         hide = true
     )]
     tls_key_path: Option<std::path::PathBuf>,
+    /// Path to a PEM encoded CA bundle, if supplied the mds and xds service(s) require
+    /// clients to present a certificate signed by this CA
+    #[clap(
+        long = "service.tls.client-ca-path",
+        env = "QUILKIN_SERVICE_TLS_CLIENT_CA_PATH",
+        hide = true
+    )]
+    tls_client_ca_path: Option<std::path::PathBuf>,
+    /// Path to a YAML file mapping client certificate identities (DNS or URI SANs, or
+    /// common name) to the identifiers they can use, the resource types they can
+    /// subscribe to, and whether they can push to a relay. If not supplied, any client
+    /// with a certificate signed by the CA is allowed
+    #[clap(
+        long = "service.tls.client-policy-path",
+        env = "QUILKIN_SERVICE_TLS_CLIENT_POLICY_PATH",
+        requires("tls_client_ca_path"),
+        hide = true
+    )]
+    tls_client_policy_path: Option<std::path::PathBuf>,
 
     // START CORROSION OPTIONS
     #[clap(
@@ -210,6 +235,17 @@ pub struct Service {
     )]
     corrosion_journal_size_limit: Option<u64>,
 
+    /// The scope this instance's filter chain is published to corrosion with,
+    /// proxies use the chain of the most specific scope that matches them
+    ///
+    /// One of `*` for all proxies, `icao:<ICAO>`, or `locality:<region[:zone[:sub_zone]]>`
+    #[clap(
+        long = "service.corrosion.filter-scope",
+        env = "QUILKIN_SERVICE_CORROSION_FILTER_SCOPE",
+        default_value = "*"
+    )]
+    corrosion_filter_scope: corrosion::db::FilterScope,
+
     /// The port the corrosion gossip service listens on
     #[clap(
         long = "service.corrosion.gossip-port",
@@ -238,6 +274,35 @@ pub struct Service {
     )]
     corrosion_gossip_endpoints: Vec<crate::net::EndpointAddress>,
 
+    /// Path to a PEM encoded CA bundle, if supplied the corrosion and gossip services
+    /// require mutual TLS, using the `service.tls` certificate and key as this node's
+    /// identity, and only accepting peers with certificates signed by this CA
+    #[clap(
+        long = "service.corrosion.tls.ca-path",
+        env = "QUILKIN_SERVICE_CORROSION_TLS_CA_PATH"
+    )]
+    corrosion_tls_ca_path: Option<std::path::PathBuf>,
+
+    /// The identities (DNS or URI SANs, or common name) of the peers allowed to connect
+    /// to the corrosion and gossip services, if empty any peer with a certificate signed
+    /// by the CA is allowed
+    #[clap(
+        long = "service.corrosion.tls.allowed-peers",
+        env = "QUILKIN_SERVICE_CORROSION_TLS_ALLOWED_PEERS",
+        value_delimiter = ',',
+        requires("corrosion_tls_ca_path")
+    )]
+    corrosion_tls_allowed_peers: Vec<String>,
+
+    /// The name gossip peers' certificates are verified against, if not specified,
+    /// the IP address of the peer is used
+    #[clap(
+        long = "service.corrosion.tls.server-name",
+        env = "QUILKIN_SERVICE_CORROSION_TLS_SERVER_NAME",
+        requires("corrosion_tls_ca_path")
+    )]
+    corrosion_tls_server_name: Option<String>,
+
     // END CORROSION
     #[clap(long = "termination-timeout")]
     termination_timeout: Option<crate::cli::Duration>,
@@ -245,6 +310,8 @@ pub struct Service {
     testing: bool,
     #[clap(skip)]
     xds_to_corrosion: Option<tokio::sync::mpsc::UnboundedReceiver<Vec<corrosion::api::Statement>>>,
+    #[clap(skip)]
+    certificates: tls::Certificates,
 }
 
 pub type Finalizer = Box<dyn FnOnce() + Send>;
@@ -279,21 +346,30 @@ impl Default for Service {
             xds_port: 7800,
             grpc_enabled: false,
             xdp: <_>::default(),
+            health_check: <_>::default(),
+            qos: <_>::default(),
             tls_cert: None,
             tls_key: None,
             tls_cert_path: None,
             tls_key_path: None,
+            tls_client_ca_path: None,
+            tls_client_policy_path: None,
             corrosion_port: 7901,
             corrosion_db_path: None,
             corrosion_server_reap: None,
             corrosion_max_page_count: None,
             corrosion_journal_size_limit: None,
+            corrosion_filter_scope: corrosion::db::FilterScope::Global,
             corrosion_gossip_enable: false,
             corrosion_gossip_port: 7902,
             corrosion_gossip_endpoints: Vec::new(),
+            corrosion_tls_ca_path: None,
+            corrosion_tls_allowed_peers: Vec::new(),
+            corrosion_tls_server_name: None,
             termination_timeout: None,
             testing: false,
             xds_to_corrosion: None,
+            certificates: Default::default(),
         }
     }
 }
@@ -408,6 +484,12 @@ impl Service {
         self
     }
 
+    /// Sets the DSCP marking and socket options of the UDP sockets.
+    pub fn qos(mut self, qos: crate::net::qos::QosOptions) -> Self {
+        self.qos = qos;
+        self
+    }
+
     pub fn testing(mut self) -> Self {
         self.testing = true;
         self
@@ -448,7 +530,14 @@ impl Service {
             insert_default::<config::DatacenterMap>(&mut config.dyn_cfg.typemap);
         }
 
+        if self.udp_enabled {
+            insert_default::<crate::net::sessions::ActiveSessions>(&mut config.dyn_cfg.typemap);
+            insert_default::<crate::net::sessions::Drain>(&mut config.dyn_cfg.typemap);
+        }
+
         if self.mds_enabled {
+            insert_default::<config::CorrosionReplica>(&mut config.dyn_cfg.typemap);
+
             let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
             config
                 .dyn_cfg
@@ -472,18 +561,96 @@ impl Service {
         self
     }
 
-    fn tls_identity(&self) -> crate::Result<Option<quilkin_xds::server::TlsIdentity>> {
-        if let Some((cert, key)) = self.tls_cert.as_ref().zip(self.tls_key.as_ref()) {
-            Ok(Some(quilkin_xds::server::TlsIdentity::from_raw(cert, key)))
+    fn tls_identity(&mut self) -> crate::Result<Option<quilkin_xds::server::TlsIdentity>> {
+        let identity = if let Some((cert, key)) = self.tls_cert.as_ref().zip(self.tls_key.as_ref())
+        {
+            quilkin_xds::server::TlsIdentity::from_raw(cert, key)?
         } else if let Some((certp, keyp)) =
             self.tls_cert_path.as_ref().zip(self.tls_key_path.as_ref())
         {
-            Ok(Some(quilkin_xds::server::TlsIdentity::from_files(
-                certp, keyp,
-            )?))
+            quilkin_xds::server::TlsIdentity::from_files(certp, keyp)?
+        } else if self.tls_client_ca_path.is_some() {
+            eyre::bail!(
+                "`service.tls.client-ca-path` requires a certificate and key to be set with `service.tls`"
+            );
         } else {
-            Ok(None)
+            return Ok(None);
+        };
+
+        self.certificates.push_xds(identity.certificate().clone());
+        Ok(Some(identity.with_client_auth(self.tls_client_auth()?)))
+    }
+
+    fn tls_client_auth(&self) -> crate::Result<Option<quilkin_xds::tls::ClientAuth>> {
+        use eyre::WrapErr as _;
+
+        let Some(ca_path) = &self.tls_client_ca_path else {
+            return Ok(None);
+        };
+
+        let client_auth = quilkin_xds::tls::ClientAuth::from_file(ca_path)?;
+        let Some(policy_path) = &self.tls_client_policy_path else {
+            return Ok(Some(client_auth));
+        };
+
+        let policies = std::fs::read(policy_path)
+            .with_context(|| format!("failed to read client policies from {policy_path:?}"))?;
+        let policies = serde_yaml::from_slice(&policies)
+            .with_context(|| format!("failed to parse client policies from {policy_path:?}"))?;
+
+        Ok(Some(client_auth.with_policies(policies)))
+    }
+
+    fn corrosion_tls(&mut self) -> crate::Result<Option<corrosion::tls::TlsConfig>> {
+        let Some(ca_path) = &self.corrosion_tls_ca_path else {
+            return Ok(None);
+        };
+
+        let tls = if let Some((cert, key)) = self.tls_cert.as_ref().zip(self.tls_key.as_ref()) {
+            use eyre::WrapErr as _;
+            let ca = std::fs::read(ca_path)
+                .with_context(|| format!("failed to read PEM CA bundle from {ca_path:?}"))?;
+            corrosion::tls::TlsConfig::from_pem(cert, key, &ca)?
+        } else if let Some((certp, keyp)) =
+            self.tls_cert_path.as_ref().zip(self.tls_key_path.as_ref())
+        {
+            corrosion::tls::TlsConfig::from_files(certp, keyp, ca_path)?
+        } else {
+            eyre::bail!(
+                "`service.corrosion.tls.ca-path` requires a certificate and key to be set with `service.tls`"
+            );
+        };
+
+        let tls = tls
+            .with_server_name(self.corrosion_tls_server_name.clone())
+            .with_allowed_peers(self.corrosion_tls_allowed_peers.clone());
+        self.certificates.push_corrosion(tls.clone());
+        Ok(Some(tls))
+    }
+
+    /// Spawns a task reloading the certificate presented by the xDS, relay and
+    /// corrosion servers whenever `service.tls.cert-path` or
+    /// `service.tls.key-path` change.
+    fn publish_certificate_reload(&mut self, shutdown: &mut ShutdownHandler) -> crate::Result<()> {
+        let certificates = std::mem::take(&mut self.certificates);
+        if certificates.is_empty() {
+            return Ok(());
         }
+
+        let Some((cert, key)) = self.tls_cert_path.clone().zip(self.tls_key_path.clone()) else {
+            if let Some(cert) = &self.tls_cert {
+                tls::record_expiry(cert);
+            }
+            return Ok(());
+        };
+
+        let task = tls::spawn(certificates, cert, key, shutdown.shutdown_rx())?;
+        let finished = shutdown.push("tls_reload");
+        tokio::spawn(async move {
+            drop(finished.send(task.await.map_err(eyre::Report::from)));
+        });
+
+        Ok(())
     }
 
     /// The main entrypoint for listening network servers.
@@ -516,6 +683,9 @@ impl Service {
             self.publish_udp(config, shutdown, &mut ports)?;
             self.publish_qcmp(config, shutdown, &mut ports)?;
             self.publish_xds(config, shutdown, &mut ports)?;
+            self.publish_dns(config, shutdown);
+            self.publish_health_checks(config, shutdown)?;
+            self.publish_certificate_reload(shutdown)?;
         }
 
         Ok((
@@ -559,6 +729,59 @@ impl Service {
         ))
     }
 
+    /// Spawns the background resolver for endpoints addressed by a hostname,
+    /// if this instance routes packets to endpoints.
+    fn publish_dns(&self, config: &Config, shutdown: &mut ShutdownHandler) {
+        if !self.udp_enabled && !self.xds_enabled && !self.grpc_enabled {
+            return;
+        }
+
+        let Some(clusters) = config.dyn_cfg.clusters() else {
+            return;
+        };
+
+        let task = crate::net::endpoint::dns::spawn(clusters, shutdown.shutdown_rx());
+        let finished = shutdown.push("dns");
+        tokio::spawn(async move {
+            drop(finished.send(task.await.map_err(eyre::Report::from)));
+        });
+    }
+
+    /// Spawns the health checking of endpoints, if enabled.
+    fn publish_health_checks(
+        &self,
+        config: &Config,
+        shutdown: &mut ShutdownHandler,
+    ) -> crate::Result<()> {
+        if !self.udp_enabled || (!self.health_check.active && !self.health_check.passive) {
+            return Ok(());
+        }
+
+        let clusters = config
+            .dyn_cfg
+            .clusters()
+            .context("health checks were enabled, but clusters were not inserted into typemap")?;
+
+        tracing::info!(
+            active = self.health_check.active,
+            passive = self.health_check.passive,
+            "starting endpoint health checks"
+        );
+
+        let task = crate::net::health::spawn(
+            &self.health_check,
+            clusters,
+            config.dyn_cfg.active_sessions().cloned(),
+            shutdown.shutdown_rx(),
+        )?;
+        let finished = shutdown.push("health_check");
+        tokio::spawn(async move {
+            drop(finished.send(task.await.map_err(eyre::Report::from)));
+        });
+
+        Ok(())
+    }
+
     /// Spawns an QCMP server if enabled, otherwise returns a future which never completes.
     fn publish_phoenix(
         &self,
@@ -646,7 +869,7 @@ impl Service {
 
     /// Spawns an xDS server if enabled
     fn publish_xds(
-        &self,
+        &mut self,
         config: &Arc<Config>,
         shutdown: &mut ShutdownHandler,
         ports: &mut ServicePorts,
@@ -741,7 +964,7 @@ impl Service {
             let explicit_kernel = matches!(self.udp_backend, crate::net::io::UdpBackend::Kernel);
             if explicit_kernel || matches!(resolved_backend, crate::net::io::UdpBackend::Kernel) {
                 match self.spawn_xdp(config.clone()) {
-                    Ok(xdp) => {
+                    Ok((xdp, sessions)) => {
                         // XDP handles QCMP in-kernel; disable the user-space QCMP service.
                         self.qcmp_enabled = false;
 
@@ -756,8 +979,28 @@ impl Service {
 
                         let finished = shutdown.push("xdp");
                         let mut srx = shutdown.shutdown_rx();
+                        let testing = self.testing;
+                        let termination_timeout = self.termination_timeout;
+                        let drain = config.dyn_cfg.drain().cloned().unwrap_or_default();
                         tokio::spawn(async move {
-                            drop(srx.changed().await);
+                            use crate::net::sessions::inner_metrics as session_metrics;
+
+                            tokio::select! {
+                                _ = srx.changed() => {}
+                                () = drain.started() => {}
+                            }
+
+                            if !testing {
+                                drain.start();
+                                sessions.drain();
+                                session_metrics::draining().set(1);
+
+                                tracing::info!(sessions = %sessions.count(), "draining, waiting for active sessions to expire");
+                                let start =
+                                    drain.started_at().unwrap_or_else(std::time::Instant::now);
+                                wait_for_sessions(|| sessions.count(), start, termination_timeout)
+                                    .await;
+                            }
 
                             tokio::task::block_in_place(|| {
                                 xdp();
@@ -879,7 +1122,11 @@ impl Service {
             self.udp_session_limit,
             backend,
             self.session_pool_ring_buffer,
+            self.qos.upstream(),
         );
+        if let Some(active_sessions) = config.dyn_cfg.active_sessions() {
+            active_sessions.register_pool(&sessions);
+        }
         crate::net::packet::spawn_receivers(
             config,
             socket,
@@ -887,44 +1134,38 @@ impl Service {
             &sessions,
             backend,
             self.udp_ring_buffer,
+            self.qos.downstream(),
         )?;
 
         let finished = shutdown.push("udp");
         let mut srx = shutdown.shutdown_rx();
         let testing = self.testing;
         let termination_timeout = self.termination_timeout;
+        let drain = config.dyn_cfg.drain().cloned().unwrap_or_default();
 
         tokio::spawn(async move {
-            drop(srx.changed().await);
+            use crate::net::sessions::inner_metrics as session_metrics;
+
+            // Draining is either started explicitly through the admin server,
+            // or on shutdown, either way the service finishes once it's done,
+            // shutting down the other services
+            tokio::select! {
+                _ = srx.changed() => {}
+                () = drain.started() => {}
+            }
 
             if testing {
                 drop(finished.send(Ok(())));
                 return;
             }
 
-            tracing::info!(sessions = %sessions.sessions().len(), "waiting for active sessions to expire");
-            let start = std::time::Instant::now();
+            drain.start();
+            sessions.drain();
+            session_metrics::draining().set(1);
 
-            let mut sessions_check = tokio::time::interval(std::time::Duration::from_millis(100));
-
-            loop {
-                sessions_check.tick().await;
-                let elapsed = start.elapsed();
-                if let Some(tt) = &termination_timeout
-                    && elapsed > **tt
-                {
-                    tracing::info!(
-                        ?elapsed,
-                        "termination timeout was reached before all sessions expired"
-                    );
-                    break;
-                }
-
-                if sessions.sessions().is_empty() {
-                    tracing::info!(shutdown_duration = ?elapsed, "all sessions expired");
-                    break;
-                }
-            }
+            tracing::info!(sessions = %sessions.sessions().len(), "draining, waiting for active sessions to expire");
+            let start = drain.started_at().unwrap_or_else(std::time::Instant::now);
+            wait_for_sessions(|| sessions.sessions().len(), start, termination_timeout).await;
 
             drop(finished.send(Ok(())));
         });
@@ -933,7 +1174,13 @@ impl Service {
     }
 
     #[cfg(target_os = "linux")]
-    fn spawn_xdp(&self, config: Arc<Config>) -> eyre::Result<Finalizer> {
+    fn spawn_xdp(
+        &self,
+        config: Arc<Config>,
+    ) -> eyre::Result<(
+        Finalizer,
+        Arc<crate::net::io::nic::xdp::process::SessionState>,
+    )> {
         use crate::net::io::nic::xdp;
         use eyre::{Context as _, ContextCompat as _};
 
@@ -947,6 +1194,7 @@ impl Service {
             .context("XDP requires a cluster map")?
             .clone();
 
+        let active_sessions = config.dyn_cfg.active_sessions().cloned();
         let config = crate::net::io::nic::xdp::process::ConfigState { filters, clusters };
 
         let udp_port = if self.udp_enabled { self.udp_port } else { 0 };
@@ -967,10 +1215,19 @@ impl Service {
         })
         .context("failed to setup XDP")?;
 
-        let io_loop = xdp::spawn(workers, config).context("failed to spawn XDP I/O loop")?;
-        Ok(Box::new(move || {
-            io_loop.shutdown(true);
-        }))
+        let sessions = Arc::new(xdp::process::SessionState::default());
+        if let Some(active_sessions) = active_sessions {
+            active_sessions.register_xdp(sessions.clone());
+        }
+
+        let io_loop = xdp::spawn(workers, config, sessions.clone(), self.qos.marking())
+            .context("failed to spawn XDP I/O loop")?;
+        Ok((
+            Box::new(move || {
+                io_loop.shutdown(true);
+            }),
+            sessions,
+        ))
     }
 
     /// Spawn corrosion server
@@ -990,6 +1247,13 @@ impl Service {
 
         tracing::info!(port = %self.corrosion_port, "starting corrosion service");
 
+        let tls = self.corrosion_tls()?;
+        if tls.is_none() {
+            tracing::warn!(
+                "corrosion services are unencrypted and unauthenticated, set `service.corrosion.tls.ca-path` to enable mutual TLS"
+            );
+        }
+
         eyre::ensure!(
             self.corrosion_gossip_endpoints.is_empty() || !self.corrosion_gossip_enable,
             "corrosion gossip endpoints were specified without enabling the corrosion gossip service"
@@ -1024,7 +1288,7 @@ impl Service {
 
         let db = corrosion::db::InitializedDb::setup(
             &db_path,
-            corrosion::schema::SCHEMA,
+            corrosion::schema::MIGRATIONS,
             Some(corrosion::db::DBMaintenance {
                 limits: corrosion::db::DBLimits {
                     max_page_count: self.corrosion_max_page_count,
@@ -1067,35 +1331,42 @@ impl Service {
 
             let btx = btx.clone();
 
-            async fn update_filters(btx: &BroadcastingTransactor, filters: &mut CachedFilterChain) {
+            let scope = self.corrosion_filter_scope.clone();
+
+            async fn update_filters(
+                btx: &BroadcastingTransactor,
+                scope: &corrosion::db::FilterScope,
+                filters: &mut CachedFilterChain,
+            ) {
                 let filters = filters.load();
                 let serialized =
                     serde_json::to_string(&filters).expect("failed to serialize filter chain");
-                let mut statement = corrosion::SmallVec::<[_; 1]>::new();
+                let mut statements = corrosion::SmallVec::<[_; 2]>::new();
                 {
-                    let mut fdb = corrosion::db::write::Filter(&mut statement);
-                    fdb.upsert(&serialized);
+                    let mut fdb = corrosion::db::write::Filter(&mut statements);
+                    fdb.upsert(scope, &serialized);
                 }
 
                 let res = btx
                     .make_broadcastable_changes(None, |tx| {
-                        corrosion::db::write::exec_single_interruptible(
-                            tx,
-                            &statement.pop().unwrap(),
-                        )
-                        .map_err(|source| {
-                            corrosion::types::agent::ChangeError::Rusqlite {
-                                source,
-                                actor_id: Some(btx.actor_id()),
-                                version: None,
-                            }
-                        })
+                        let mut rows = 0;
+                        for statement in &statements {
+                            rows += corrosion::db::write::exec_single_interruptible(tx, statement)
+                                .map_err(|source| {
+                                    corrosion::types::agent::ChangeError::Rusqlite {
+                                        source,
+                                        actor_id: Some(btx.actor_id()),
+                                        version: None,
+                                    }
+                                })?;
+                        }
+                        Ok(rows)
                     })
                     .await;
 
                 match res {
                     Ok((_, version, elapsed)) => {
-                        tracing::debug!(?version, ?elapsed, "updated filters");
+                        tracing::debug!(%scope, ?version, ?elapsed, "updated filters");
                     }
                     Err(error) => {
                         tracing::error!(%error, "failed to update filters");
@@ -1106,12 +1377,12 @@ impl Service {
             tokio::spawn(async move {
                 // Set the initial state, at this early stage we _probably_ won't
                 // have subscribers, but we do the full DB + publish just in case
-                update_filters(&btx, &mut filters).await;
+                update_filters(&btx, &scope, &mut filters).await;
 
                 loop {
                     tokio::select! {
                         _fc = filters_sub.recv() => {
-                            update_filters(&btx, &mut filters).await;
+                            update_filters(&btx, &scope, &mut filters).await;
                         }
                         _ = srx.changed() => {
                             break;
@@ -1275,19 +1546,25 @@ impl Service {
         // Tripwire is how corrosion communicates a shutdown was requested
         let trip = corrosion::pubsub::Trip::new();
 
-        if self.corrosion_gossip_enable {
+        let gossip = if self.corrosion_gossip_enable {
             use eyre::WrapErr;
 
-            self.spawn_gossip_service(
-                &db,
-                subs.clone(),
-                updates.unwrap(),
-                trip.tripwire(),
-                shutdown,
-            )
-            .await
-            .context("failed to spawn gossip service")?;
-        }
+            let introspect = self
+                .spawn_gossip_service(
+                    &db,
+                    subs.clone(),
+                    updates.unwrap(),
+                    trip.tripwire(),
+                    tls.as_ref(),
+                    shutdown,
+                )
+                .await
+                .context("failed to spawn gossip service")?;
+
+            Some(introspect)
+        } else {
+            None
+        };
 
         let ps_ctx = corrosion::pubsub::PubsubContext::new(
             subs,
@@ -1299,14 +1576,19 @@ impl Service {
         )
         .await?;
 
+        if let Some(replica) = config.dyn_cfg.corrosion_replica() {
+            replica.register(ps_ctx.clone(), gossip);
+        }
+
         // Spin up a UDP socket to receive state mutations from agents and send
         // events to proxy subscribers
-        let udp_server = corrosion::persistent::server::Server::new_unencrypted(
-            (std::net::Ipv6Addr::UNSPECIFIED, self.corrosion_port).into(),
-            btx,
-            ps_ctx,
-            corrosion::persistent::Metrics::new(crate::metrics::registry()),
-        )?;
+        let addr = (std::net::Ipv6Addr::UNSPECIFIED, self.corrosion_port).into();
+        let metrics = corrosion::persistent::Metrics::new(crate::metrics::registry());
+        let udp_server = if let Some(tls) = &tls {
+            corrosion::persistent::server::Server::new(addr, btx, ps_ctx, metrics, tls)?
+        } else {
+            corrosion::persistent::server::Server::new_unencrypted(addr, btx, ps_ctx, metrics)?
+        };
 
         let port = udp_server.local_addr().port();
         ports.corrosion = Some(port);
@@ -1338,8 +1620,9 @@ impl Service {
         subs: corrosion::types::pubsub::SubsManager,
         updates: corrosion::types::updates::UpdatesManager,
         tripwire: corrosion::Tripwire,
+        tls: Option<&corrosion::tls::TlsConfig>,
         shutdown: &mut ShutdownHandler,
-    ) -> eyre::Result<()> {
+    ) -> eyre::Result<corrosion::gossip::status::Introspect> {
         use corrosion::gossip;
         use eyre::WrapErr;
         use tokio::sync::mpsc::channel;
@@ -1374,8 +1657,17 @@ impl Service {
             members: members.clone(),
         };
 
-        let transport = gossip::transport::Transport::new_insecure(metrics, rtt_tx)
-            .context("failed to spawn client transport")?;
+        let schema = corrosion::schema::SchemaVersion {
+            version: db.schema_version,
+            min_compatible: corrosion::schema::MIN_COMPATIBLE_VERSION,
+        };
+
+        let transport = if let Some(tls) = tls {
+            gossip::transport::Transport::new(metrics, rtt_tx, schema, tls)
+        } else {
+            gossip::transport::Transport::new_insecure(metrics, rtt_tx, schema)
+        }
+        .context("failed to spawn client transport")?;
 
         // Spawn the actual SWIM handler before we start accepting remote connections or making our own
         gossip::swim::swim_loop(
@@ -1449,6 +1741,17 @@ impl Service {
         // Spawn a task to update the RTT state of the cluster members
         gossip::handler::spawn_rtt_handler(members.clone(), rtt_rx, tripwire.clone());
 
+        let peer_syncs = gossip::status::PeerSyncs::default();
+
+        let introspect = gossip::status::Introspect {
+            foca_tx: foca_tx.clone(),
+            members,
+            bookie: db.bookie.clone(),
+            peer_syncs: peer_syncs.clone(),
+            actor_id: db.actor_id,
+            cluster_id: db.cluster_id,
+        };
+
         let srv_ctx = gossip::GossipContext {
             metrics,
             foca_tx,
@@ -1459,16 +1762,54 @@ impl Service {
             actor_id: db.actor_id,
             clock: db.clock.clone(),
             cluster_id: db.cluster_id,
+            schema,
+            peer_syncs,
         };
 
-        corrosion::gossip::handler::spawn_gossipserver_unencrypted(
-            srv_ctx,
-            tripwire,
-            (std::net::Ipv6Addr::UNSPECIFIED, self.corrosion_gossip_port).into(),
-        )
+        let addr = (std::net::Ipv6Addr::UNSPECIFIED, self.corrosion_gossip_port).into();
+        if let Some(tls) = tls {
+            gossip::handler::spawn_gossipserver(srv_ctx, tripwire, addr, tls)
+        } else {
+            gossip::handler::spawn_gossipserver_unencrypted(srv_ctx, tripwire, addr)
+        }
         .context("failed to spawn gossip server")?;
 
-        Ok(())
+        Ok(introspect)
+    }
+}
+
+/// Waits for the `remaining` sessions to expire once draining has started at
+/// `start`, or until the termination timeout is reached
+async fn wait_for_sessions(
+    remaining: impl Fn() -> usize,
+    start: std::time::Instant,
+    termination_timeout: Option<crate::cli::Duration>,
+) {
+    use crate::net::sessions::inner_metrics as session_metrics;
+
+    let mut sessions_check = tokio::time::interval(std::time::Duration::from_millis(100));
+
+    loop {
+        sessions_check.tick().await;
+        let remaining = remaining();
+        session_metrics::drain_remaining().set(remaining as _);
+
+        let elapsed = start.elapsed();
+        if let Some(tt) = &termination_timeout
+            && elapsed > **tt
+        {
+            tracing::info!(
+                ?elapsed,
+                remaining,
+                "termination timeout was reached before all sessions expired"
+            );
+            break;
+        }
+
+        if remaining == 0 {
+            tracing::info!(shutdown_duration = ?elapsed, "all sessions expired");
+            break;
+        }
     }
 }
 
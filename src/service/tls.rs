@@ -0,0 +1,234 @@
+/*
+ * Copyright 2025 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Reloads the certificate presented by the xDS, relay and corrosion servers
+//! when the files it was read from change, so certificates can be rotated
+//! without restarting the servers and dropping their connections.
+
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr as _;
+
+/// How long to wait after a change before reloading, so a certificate and key
+/// that are replaced one after the other are reloaded together.
+const RELOAD_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// The certificates presented by the servers of a [`Service`](super::Service).
+#[derive(Debug, Default)]
+pub(super) struct Certificates {
+    xds: Vec<quilkin_xds::tls::ServerCertificate>,
+    corrosion: Vec<corrosion::tls::TlsConfig>,
+}
+
+impl Certificates {
+    pub(super) fn push_xds(&mut self, certificate: quilkin_xds::tls::ServerCertificate) {
+        self.xds.push(certificate);
+    }
+
+    pub(super) fn push_corrosion(&mut self, tls: corrosion::tls::TlsConfig) {
+        self.corrosion.push(tls);
+    }
+
+    #[inline]
+    pub(super) fn is_empty(&self) -> bool {
+        self.xds.is_empty() && self.corrosion.is_empty()
+    }
+
+    fn reload(&self, cert: &[u8], key: &[u8]) -> crate::Result<()> {
+        for certificate in &self.xds {
+            certificate.reload_pem(cert, key)?;
+        }
+
+        for tls in &self.corrosion {
+            tls.reload_pem(cert, key)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Records when the PEM encoded certificate `cert` expires.
+pub(super) fn record_expiry(cert: &[u8]) {
+    match quilkin_xds::tls::certificate_expiry(cert) {
+        Some(expiry) => crate::metrics::tls_certificate_expiry().set(expiry),
+        None => tracing::warn!("failed to parse the expiry of the TLS certificate"),
+    }
+}
+
+fn read(cert: &Path, key: &Path) -> crate::Result<(Vec<u8>, Vec<u8>)> {
+    let cert_pem = std::fs::read(cert)
+        .with_context(|| format!("failed to read PEM certificate from {cert:?}"))?;
+    let key_pem = std::fs::read(key).with_context(|| format!("failed to read key from {key:?}"))?;
+    Ok((cert_pem, key_pem))
+}
+
+/// Spawns a task reloading `certificates` from the `cert` and `key` files
+/// whenever they change, until shutdown.
+///
+/// The directories of the files are watched rather than the files themselves,
+/// as they are usually replaced rather than modified, such as when Kubernetes
+/// updates a mounted secret. If the new certificate or key can't be loaded,
+/// the current ones are kept.
+pub(super) fn spawn(
+    certificates: Certificates,
+    cert: PathBuf,
+    key: PathBuf,
+    mut shutdown_rx: crate::signal::ShutdownRx,
+) -> crate::Result<tokio::task::JoinHandle<()>> {
+    use notify::Watcher as _;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::RecommendedWatcher::new(
+        move |res| {
+            drop(tx.send(res));
+        },
+        Default::default(),
+    )?;
+
+    let directories = std::collections::BTreeSet::from([directory(&cert), directory(&key)]);
+    for dir in &directories {
+        watcher
+            .watch(dir, notify::RecursiveMode::NonRecursive)
+            .with_context(|| format!("failed to watch {dir:?} for certificate changes"))?;
+    }
+
+    let mut current = read(&cert, &key)?;
+    record_expiry(&current.0);
+
+    Ok(tokio::spawn(async move {
+        let _watcher = watcher;
+
+        loop {
+            tokio::select! {
+                event = rx.recv() => match event {
+                    Some(Ok(_)) => {}
+                    Some(Err(error)) => {
+                        tracing::warn!(%error, "error watching TLS certificate");
+                        continue;
+                    }
+                    None => break,
+                },
+                _ = shutdown_rx.changed() => break,
+            }
+
+            tokio::time::sleep(RELOAD_DELAY).await;
+            while rx.try_recv().is_ok() {}
+
+            let loaded = match read(&cert, &key) {
+                Ok(loaded) => loaded,
+                Err(error) => {
+                    tracing::warn!(%error, "failed to read TLS certificate, keeping the current one");
+                    crate::metrics::tls_certificate_reloads("error").inc();
+                    continue;
+                }
+            };
+
+            // Other files in the same directories may have changed instead
+            if loaded == current {
+                continue;
+            }
+
+            match certificates.reload(&loaded.0, &loaded.1) {
+                Ok(()) => {
+                    tracing::info!(cert = %cert.display(), "reloaded TLS certificate");
+                    crate::metrics::tls_certificate_reloads("success").inc();
+                    record_expiry(&loaded.0);
+                    current = loaded;
+                }
+                Err(error) => {
+                    tracing::warn!(%error, "failed to reload TLS certificate, keeping the current one");
+                    crate::metrics::tls_certificate_reloads("error").inc();
+                }
+            }
+        }
+
+        tracing::debug!("TLS certificate reload task finished");
+    }))
+}
+
+/// The directory containing `path`, which is the current directory for a
+/// bare file name.
+fn directory(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issue(name: &str) -> (String, String) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec![name.to_owned()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    #[tokio::test]
+    async fn reloads_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert = dir.path().join("tls.crt");
+        let key = dir.path().join("tls.key");
+
+        let (cert_pem, key_pem) = issue("old.quilkin.test");
+        std::fs::write(&cert, &cert_pem).unwrap();
+        std::fs::write(&key, &key_pem).unwrap();
+
+        let mut certificates = Certificates::default();
+        certificates.push_xds(
+            quilkin_xds::tls::ServerCertificate::from_pem(cert_pem.as_bytes(), key_pem.as_bytes())
+                .unwrap(),
+        );
+
+        let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(());
+        let _task = spawn(certificates, cert.clone(), key.clone(), shutdown_rx).unwrap();
+        let reloads = || crate::metrics::tls_certificate_reloads("success").get();
+        let before = reloads();
+
+        // An invalid certificate is ignored
+        std::fs::write(&cert, "not a certificate").unwrap();
+        tokio::time::sleep(RELOAD_DELAY * 2).await;
+        assert_eq!(reloads(), before);
+
+        // As is a certificate that doesn't match the current key, eg. if the
+        // certificate is written well before its key
+        let (mismatched, _key_pem) = issue("mismatched.quilkin.test");
+        std::fs::write(&cert, &mismatched).unwrap();
+        tokio::time::sleep(RELOAD_DELAY * 2).await;
+        assert_eq!(reloads(), before);
+
+        let (cert_pem, key_pem) = issue("new.quilkin.test");
+        std::fs::write(&key, &key_pem).unwrap();
+        std::fs::write(&cert, &cert_pem).unwrap();
+
+        tokio::time::timeout(RELOAD_DELAY * 10, async {
+            while reloads() == before {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            crate::metrics::tls_certificate_expiry().get(),
+            quilkin_xds::tls::certificate_expiry(cert_pem.as_bytes()).unwrap()
+        );
+    }
+}
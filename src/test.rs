@@ -313,7 +313,13 @@ impl TestHelper {
         let ready = <_>::default();
 
         if let Some(address) = with_admin {
-            crate::components::admin::serve(config.clone(), ready, shutdown_tx.clone(), address);
+            crate::components::admin::serve(
+                config.clone(),
+                ready,
+                shutdown_tx.clone(),
+                address,
+                false,
+            );
         }
 
         let shutdown = crate::signal::ShutdownHandler::new(shutdown_tx, shutdown_rx);
@@ -14,24 +14,37 @@
  *  limitations under the License.
  */
 
-use ::quilkin_xds::generated::quilkin::config::v1alpha1 as proto;
+use ::quilkin_xds::generated::{
+    envoy::config::endpoint::v3 as envoy_endpoint, quilkin::config::v1alpha1 as proto,
+};
 use prost::Message;
 use prost_types::Any;
 
 pub const CLUSTER_TYPE: &str = "type.googleapis.com/quilkin.config.v1alpha1.Cluster";
 pub const DATACENTER_TYPE: &str = "type.googleapis.com/quilkin.config.v1alpha1.Datacenter";
 pub const FILTER_CHAIN_TYPE: &str = "type.googleapis.com/quilkin.config.v1alpha1.FilterChain";
+/// Envoy's endpoint discovery (EDS) resource, which is only ingested if
+/// enabled, see [`crate::config::eds`]
+pub const CLUSTER_LOAD_ASSIGNMENT_TYPE: &str =
+    "type.googleapis.com/envoy.config.endpoint.v3.ClusterLoadAssignment";
 const PREFIX: &str = "type.googleapis.com/quilkin.config.v1alpha1.";
 
 pub enum Resource {
     Cluster(proto::Cluster),
     Datacenter(proto::Datacenter),
     FilterChain(proto::FilterChain),
+    ClusterLoadAssignment(envoy_endpoint::ClusterLoadAssignment),
 }
 
 impl Resource {
     #[inline]
     pub fn try_decode(any: Any) -> Result<Self, eyre::Error> {
+        if any.type_url == CLUSTER_LOAD_ASSIGNMENT_TYPE {
+            return Ok(Self::ClusterLoadAssignment(
+                envoy_endpoint::ClusterLoadAssignment::decode(&*any.value)?,
+            ));
+        }
+
         let Some(suffix) = any.type_url.strip_prefix(PREFIX) else {
             eyre::bail!("unknown resource type '{}'", any.type_url);
         };
@@ -62,6 +75,11 @@ impl Resource {
                 f.encode(&mut value)?;
                 (value, FILTER_CHAIN_TYPE)
             }
+            Self::ClusterLoadAssignment(cla) => {
+                let mut value = Vec::with_capacity(cla.encoded_len());
+                cla.encode(&mut value)?;
+                (value, CLUSTER_LOAD_ASSIGNMENT_TYPE)
+            }
         };
 
         Ok(Any {
@@ -76,6 +94,7 @@ impl Resource {
             Self::Cluster(_) => CLUSTER_TYPE,
             Self::Datacenter(_) => DATACENTER_TYPE,
             Self::FilterChain(_) => FILTER_CHAIN_TYPE,
+            Self::ClusterLoadAssignment(_) => CLUSTER_LOAD_ASSIGNMENT_TYPE,
         }
     }
 }
@@ -85,11 +104,16 @@ pub enum ResourceType {
     Cluster,
     Datacenter,
     FilterChain,
+    ClusterLoadAssignment,
 }
 
 impl std::str::FromStr for ResourceType {
     type Err = eyre::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == CLUSTER_LOAD_ASSIGNMENT_TYPE {
+            return Ok(Self::ClusterLoadAssignment);
+        }
+
         Ok(match s.strip_prefix(PREFIX) {
             Some("Cluster") => Self::Cluster,
             Some("Datacenter") => Self::Datacenter,
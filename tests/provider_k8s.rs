@@ -16,7 +16,7 @@ use quilkin::{
         },
     },
 };
-use quilkin_types::{Endpoint, IcaoCode};
+use quilkin_types::{Endpoint, IcaoCode, TokenSet};
 use std::sync::Arc;
 
 fn setup_tracing() {
@@ -473,7 +473,9 @@ fn accumulates_mutations() {
                 address: std::net::Ipv4Addr::from_bits(i as u32).into(),
                 port: i as u16,
             },
-            (0..i).map(|i| vec![i as u8; i as usize]).collect(),
+            (0..i)
+                .map(|i| vec![i as u8; i as usize])
+                .collect::<TokenSet>(),
         );
     }
 
@@ -490,7 +492,7 @@ fn accumulates_mutations() {
                 address: std::net::Ipv4Addr::from_bits(i as u32).into(),
                 port: i as u16,
             },
-            std::iter::once(vec![i as u8; 3]).collect(),
+            std::iter::once(vec![i as u8; 3]).collect::<TokenSet>(),
         );
     }
 
@@ -558,7 +560,7 @@ async fn applies_changes() {
     let sub_path = root.join("subs");
     let db_path = root.join("db.db");
 
-    let db = ::corrosion::db::InitializedDb::setup(&db_path, ::corrosion::schema::SCHEMA, None)
+    let db = ::corrosion::db::InitializedDb::setup(&db_path, ::corrosion::schema::MIGRATIONS, None)
         .await
         .expect("failed to initialize DB");
 
@@ -653,11 +655,13 @@ async fn applies_changes() {
     async fn apply_state(
         bss: &mut pubsub::BufferingSubStream,
         local: &quilkin::config::Watch<quilkin::net::ClusterMap>,
-    ) -> u64 {
+        mut cid: pubsub::ChangeId,
+    ) -> pubsub::ChangeId {
         fn apply(
             mut block: bytes::Bytes,
             local: &quilkin::config::Watch<quilkin::net::ClusterMap>,
-        ) -> u64 {
+            cid: pubsub::ChangeId,
+        ) -> pubsub::ChangeId {
             let ss = pubsub::SubscriptionStream::length_prefixed(&mut block).unwrap();
 
             let mut subm = ::corrosion::persistent::SubMetrics {
@@ -665,22 +669,13 @@ async fn applies_changes() {
                 failures: 0,
             };
             let cm = local.write();
-            cm.corrosion_apply(ss, &mut subm).0
+            cm.corrosion_apply(ss, cid, &mut subm, None)
         }
 
-        let mut cid = local
-            .read()
-            .get(&Some(quilkin::net::endpoint::Locality::new(
-                "corrosion",
-                "",
-                "",
-            )))
-            .map_or(0, |es| es.change_id().0);
-
         while let Ok(Some(block)) =
             tokio::time::timeout(std::time::Duration::from_millis(100), bss.next()).await
         {
-            cid = cid.max(apply(block, local));
+            cid = apply(block, local, cid);
         }
 
         cid
@@ -691,10 +686,12 @@ async fn applies_changes() {
         db: &BroadcastingTransactor,
         bss: &mut pubsub::BufferingSubStream,
         local: &quilkin::config::Watch<quilkin::net::ClusterMap>,
+        cid: &mut pubsub::ChangeId,
         mutation: impl FnOnce(&mut corrosion::ServerMutator),
     ) -> u64 {
         update_db(pusher, db, mutation).await;
-        let changes = apply_state(bss, local).await;
+        *cid = apply_state(bss, local, *cid).await;
+        let changes = cid.0;
 
         assert_eq!(
             pusher.state.to_map(),
@@ -713,9 +710,10 @@ async fn applies_changes() {
     }
 
     btx.connected(PEER, icao, PORT).await;
+    let mut cid = pubsub::ChangeId(0);
 
     // Initialize the set
-    end2end(&mut pusher, &btx, &mut bss, &local, |mutator| {
+    end2end(&mut pusher, &btx, &mut bss, &local, &mut cid, |mutator| {
         for i in 0..N {
             mutator.upsert_server(
                 uuid::Uuid::from_u128(i as _),
@@ -723,14 +721,14 @@ async fn applies_changes() {
                     address: std::net::Ipv4Addr::from_bits(i as u32).into(),
                     port: i as u16,
                 },
-                std::iter::once(vec![i as u8; 3]).collect(),
+                std::iter::once(vec![i as u8; 3]).collect::<TokenSet>(),
             );
         }
     })
     .await;
 
     // Do an update of 1/2 and remove the rest
-    end2end(&mut pusher, &btx, &mut bss, &local, |mutator| {
+    end2end(&mut pusher, &btx, &mut bss, &local, &mut cid, |mutator| {
         for i in 0..N {
             if i % 2 == 0 {
                 mutator.upsert_server(
@@ -739,7 +737,7 @@ async fn applies_changes() {
                         address: std::net::Ipv4Addr::from_bits(i as u32).into(),
                         port: i as u16,
                     },
-                    std::iter::once(vec![i as u8; 4]).collect(),
+                    std::iter::once(vec![i as u8; 4]).collect::<TokenSet>(),
                 );
             } else {
                 mutator.remove_server(uuid::Uuid::from_u128(i as _));
@@ -749,7 +747,7 @@ async fn applies_changes() {
     .await;
 
     // Upsert the ones we removed
-    let cid = end2end(&mut pusher, &btx, &mut bss, &local, |mutator| {
+    let expected = end2end(&mut pusher, &btx, &mut bss, &local, &mut cid, |mutator| {
         for i in 0..N {
             if i % 2 != 0 {
                 mutator.upsert_server(
@@ -758,7 +756,7 @@ async fn applies_changes() {
                         address: std::net::Ipv4Addr::from_bits(i as u32).into(),
                         port: i as u16,
                     },
-                    std::iter::once(vec![i as u8; 4]).collect(),
+                    std::iter::once(vec![i as u8; 4]).collect::<TokenSet>(),
                 );
             }
         }
@@ -768,16 +766,16 @@ async fn applies_changes() {
     // Pretend as if the pusher completely goes away
     btx.disconnected(PEER).await;
     assert_eq!(
-        cid,
-        end2end(&mut pusher, &btx, &mut bss, &local, |_mutator| {}).await
+        expected,
+        end2end(&mut pusher, &btx, &mut bss, &local, &mut cid, |_mutator| {}).await
     );
 
     // Redo all our upserts as if we reconnected, which should result in no
     // changes sent to subscribers since we only soft deleted the DB entries
     btx.connected(PEER, icao, PORT).await;
     assert_eq!(
-        cid,
-        end2end(&mut pusher, &btx, &mut bss, &local, |mutator| {
+        expected,
+        end2end(&mut pusher, &btx, &mut bss, &local, &mut cid, |mutator| {
             for i in 0..N {
                 mutator.upsert_server(
                     uuid::Uuid::from_u128(i as _),
@@ -785,10 +783,48 @@ async fn applies_changes() {
                         address: std::net::Ipv4Addr::from_bits(i as u32).into(),
                         port: i as u16,
                     },
-                    std::iter::once(vec![i as u8; 4]).collect(),
+                    std::iter::once(vec![i as u8; 4]).collect::<TokenSet>(),
                 );
             }
         })
         .await
     );
+
+    // A server with a locality, eg. the zone and node of the pod it runs on, is
+    // placed under that locality instead of the default corrosion locality
+    let locality = quilkin::net::endpoint::Locality::new("eu-west", "eu-west-1a", "node-1");
+    let located = Endpoint {
+        address: std::net::Ipv4Addr::new(10, 0, 0, 1).into(),
+        port: 7777,
+    };
+    update_db(&mut pusher, &btx, |mutator| {
+        mutator.upsert_server(
+            uuid::Uuid::from_u128(N as _),
+            located.clone(),
+            corrosion::push::ServerDetails {
+                tokens: std::iter::once(vec![0xff; 4]).collect(),
+                locality: Some(locality.clone()),
+                ..Default::default()
+            },
+        );
+    })
+    .await;
+    apply_state(&mut bss, &local, cid).await;
+
+    let cm = local.read();
+    let localized = cm.get(&Some(locality)).expect("locality was not created");
+    assert_eq!(
+        localized.to_map().into_keys().collect::<Vec<_>>(),
+        vec![located.clone()]
+    );
+    assert!(
+        !cm.get(&Some(quilkin::net::endpoint::Locality::new(
+            "corrosion",
+            "",
+            ""
+        )))
+        .unwrap()
+        .to_map()
+        .contains_key(&located)
+    );
 }